@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE guild_config (
+    guild_id INT NOT NULL PRIMARY KEY,
+    generator_type INT NOT NULL DEFAULT 0,
+    voice_type INT NOT NULL DEFAULT 1
+)
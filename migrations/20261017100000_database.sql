@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN reaction_threshold INT;
+ALTER TABLE guild_config ADD COLUMN reaction_window INT NOT NULL DEFAULT 60
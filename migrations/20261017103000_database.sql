@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN read_embed_title BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE guild_config ADD COLUMN read_embed_description BOOLEAN NOT NULL DEFAULT FALSE
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE dict ADD COLUMN priority INT NOT NULL DEFAULT 0
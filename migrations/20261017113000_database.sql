@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN personality TEXT NOT NULL DEFAULT "casual"
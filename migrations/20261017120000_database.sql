@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE user_config ADD COLUMN ignored BOOLEAN NOT NULL DEFAULT FALSE
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE read_filter (
+    guild_id INT NOT NULL,
+    kind TEXT NOT NULL,
+    target_id INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, kind, target_id)
+)
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN join_busy TEXT NOT NULL DEFAULT "refuse"
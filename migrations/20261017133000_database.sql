@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN message_gap_ms INT NOT NULL DEFAULT 0
@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN autojoin_channel_id INT;
+CREATE TABLE autojoin_user (
+    guild_id INT NOT NULL,
+    user_id INT NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+)
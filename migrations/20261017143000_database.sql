@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN idle_timeout_min INT
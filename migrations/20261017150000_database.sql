@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN read_name BOOLEAN NOT NULL DEFAULT TRUE
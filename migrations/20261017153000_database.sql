@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE feature_flag (
+    guild_id INT NOT NULL,
+    flag TEXT NOT NULL,
+    PRIMARY KEY (guild_id, flag)
+)
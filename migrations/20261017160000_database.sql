@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN read_reply BOOLEAN NOT NULL DEFAULT TRUE
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE sound_effect (
+    guild_id INT NOT NULL,
+    name TEXT NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY (guild_id, name)
+)
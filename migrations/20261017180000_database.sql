@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN tts_volume INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE guild_config ADD COLUMN music_volume INTEGER NOT NULL DEFAULT 30;
+ALTER TABLE guild_config ADD COLUMN ducked_volume INTEGER NOT NULL DEFAULT 5
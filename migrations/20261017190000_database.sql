@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN language TEXT NOT NULL DEFAULT 'ja';
+ALTER TABLE guild_config ADD COLUMN english_generator_type INTEGER;
+ALTER TABLE guild_config ADD COLUMN english_voice_type INTEGER
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN greeting_cooldown_sec INTEGER NOT NULL DEFAULT 0
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE user_config ADD COLUMN speed_scale REAL NOT NULL DEFAULT 1.0;
+ALTER TABLE user_config ADD COLUMN pitch_scale REAL NOT NULL DEFAULT 0.0
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN read_channel TEXT NOT NULL DEFAULT 'voice'
\ No newline at end of file
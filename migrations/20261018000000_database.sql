@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN announce_priority TEXT NOT NULL DEFAULT 'next'
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE dict_pack (
+    guild_id INT NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (guild_id, name)
+)
\ No newline at end of file
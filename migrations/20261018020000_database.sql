@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE command_permission (
+    guild_id INT NOT NULL,
+    level TEXT NOT NULL,
+    role_id INT NOT NULL,
+    PRIMARY KEY (guild_id, level, role_id)
+)
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE guild_nickname (
+    guild_id INT NOT NULL,
+    user_id INT NOT NULL,
+    read_nickname TEXT NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+)
\ No newline at end of file
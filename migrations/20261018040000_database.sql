@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN batch_window_ms INTEGER NOT NULL DEFAULT 0
\ No newline at end of file
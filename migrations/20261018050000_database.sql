@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE ng_word (
+    guild_id INT NOT NULL,
+    word TEXT NOT NULL,
+    action TEXT NOT NULL DEFAULT 'replace',
+    PRIMARY KEY (guild_id, word)
+)
\ No newline at end of file
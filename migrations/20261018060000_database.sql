@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN read_threads BOOLEAN NOT NULL DEFAULT FALSE
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN greeting_mode TEXT NOT NULL DEFAULT 'greeting'
\ No newline at end of file
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE voice_presets (
+    user_id INT NOT NULL,
+    name TEXT NOT NULL,
+    generator_type INT NOT NULL,
+    voice_type INT NOT NULL,
+    speed_scale REAL NOT NULL,
+    pitch_scale REAL NOT NULL,
+    PRIMARY KEY (user_id, name)
+)
\ No newline at end of file
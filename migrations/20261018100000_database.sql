@@ -0,0 +1,4 @@
+-- Add migration script here
+CREATE TABLE voice_remap_notice (
+    user_id INT NOT NULL PRIMARY KEY
+)
\ No newline at end of file
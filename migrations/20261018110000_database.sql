@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN name_reading TEXT NOT NULL DEFAULT 'configured'
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN read_stickers BOOLEAN NOT NULL DEFAULT TRUE
\ No newline at end of file
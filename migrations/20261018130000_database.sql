@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE usage_stats (
+    guild_id INTEGER NOT NULL,
+    day TEXT NOT NULL,
+    generator_type TEXT NOT NULL,
+    messages INTEGER NOT NULL DEFAULT 0,
+    characters INTEGER NOT NULL DEFAULT 0,
+    audio_ms INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, day, generator_type)
+)
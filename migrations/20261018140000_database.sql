@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE kana_speakers (
+    guild_id INTEGER NOT NULL,
+    generator_type TEXT NOT NULL,
+    style_id INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, generator_type, style_id)
+)
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN kana_reading BOOLEAN NOT NULL DEFAULT FALSE
\ No newline at end of file
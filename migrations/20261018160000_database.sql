@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE dict RENAME TO dict_tmp;
+CREATE TABLE dict (
+    word TEXT NOT NULL,
+    read_word TEXT NOT NULL,
+    priority INT NOT NULL DEFAULT 0,
+    user_id INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (word, user_id)
+);
+INSERT INTO dict(word,read_word,priority) SELECT word,read_word,priority FROM dict_tmp;
+DROP TABLE dict_tmp;
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE guild_config ADD COLUMN announce_channel_id INTEGER;
+CREATE TABLE changelog_announced (
+    guild_id INTEGER PRIMARY KEY NOT NULL,
+    version TEXT NOT NULL
+)
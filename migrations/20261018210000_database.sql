@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE user_config ADD COLUMN quiet_start_min INTEGER;
+ALTER TABLE user_config ADD COLUMN quiet_end_min INTEGER;
+ALTER TABLE user_config ADD COLUMN utc_offset_min INTEGER NOT NULL DEFAULT 540;
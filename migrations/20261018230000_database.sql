@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER TABLE user_config ADD COLUMN voice_chosen BOOLEAN NOT NULL DEFAULT FALSE;
+-- これまでは初めて読んだときのデフォルトボイスを保存していたので、それと違う声だけを選んだ声とみなす
+UPDATE user_config SET voice_chosen = TRUE
+WHERE NOT (generator_type = 0 AND voice_type = 1)
+    AND NOT EXISTS (
+        SELECT 1 FROM guild_config
+        WHERE guild_config.generator_type = user_config.generator_type
+            AND guild_config.voice_type = user_config.voice_type
+    );
@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE guild_dict (
+    guild_id INT NOT NULL,
+    word TEXT NOT NULL,
+    read_word TEXT NOT NULL,
+    priority INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, word)
+)
@@ -0,0 +1,1277 @@
+use anyhow::{anyhow, Result};
+use serenity::{client::Context, model::id::ChannelId};
+
+use crate::{
+    handler::{
+        get_argument, get_sub_argument, get_sub_argument_by_name, get_subcommand, ArgumentValue,
+        Command, Generators, Handler, SlashCommandTextResult,
+    },
+    lib::{
+        app_config,
+        batch::MAX_BATCH_WINDOW_MS,
+        changelog,
+        db::{
+            AutojoinDB, ChangelogDB, CommandPermissionDB, DictDB, DictPackDB, FeatureFlagDB,
+            GuildConfigDB, KanaSpeakerDB, NgWordDB, PortableUserConfig, ReadFilter, ReadFilterDB,
+            SpeakerDB, UserConfigDB, VoicePreset, VoicePresetDB,
+        },
+        dict_pack::{DictPack, PACKS},
+        feature::Feature,
+        greeting::MAX_GREETING_COOLDOWN_SEC,
+        language::Language,
+        mixer::{MixPolicy, MAX_VOLUME},
+        ng_word::{NgAction, NgWord},
+        permission::PermissionLevel,
+        personality::Personality,
+        read_delay::MAX_READ_DELAY_MS,
+        search::search_speakers,
+        text::NameReading,
+    },
+};
+
+use super::{interactions::is_admin, meta};
+
+const MAX_MESSAGE_GAP_MS: i64 = 5000;
+// /voice_favorite で保存できる数と名前の長さ
+const MAX_VOICE_PRESETS: usize = 10;
+const MAX_VOICE_PRESET_NAME_CHARS: usize = 20;
+
+pub async fn config(
+    handler: &Handler,
+    command: &Command,
+    ctx: &Context,
+) -> Result<SlashCommandTextResult> {
+    if !is_admin(command) {
+        return Err(anyhow!("サーバーの管理権限が必要だよ"));
+    }
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?;
+    let subcommand = get_subcommand(command)?;
+    let mut guild_config = handler
+        .database
+        .get_guild_config_or_default(guild_id.0 as i64)
+        .await?;
+    match subcommand.name.as_str() {
+        "voice_region" => {
+            let region = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(region) = region {
+                let region = if region == "auto" {
+                    None
+                } else {
+                    let regions = ctx.http.get_voice_regions().await?;
+                    if !regions.iter().any(|r| &r.id == region) {
+                        return Err(anyhow!(
+                            "{} というリージョンはないよ。使えるのは {} だよ",
+                            region,
+                            regions
+                                .iter()
+                                .map(|r| r.id.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ));
+                    }
+                    Some(region.to_string())
+                };
+                guild_config.voice_region = region.clone();
+                handler.database.update_guild_config(&guild_config).await?;
+                meta::apply_voice_region(ctx, guild_id, region.clone()).await;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &format!(
+                        "ボイスチャンネルのリージョンを {} にしたよ",
+                        region.as_deref().unwrap_or("自動")
+                    ),
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "reaction_summary" => {
+            let threshold = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Integer(threshold) = threshold {
+                if let Ok(ArgumentValue::Integer(window)) = get_sub_argument(subcommand, 1) {
+                    if *window <= 0 {
+                        return Err(anyhow!("集計する秒数は 1 以上にしてね"));
+                    }
+                    guild_config.reaction_window = *window;
+                }
+                guild_config.reaction_threshold = if *threshold > 0 {
+                    Some(*threshold)
+                } else {
+                    None
+                };
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = match guild_config.reaction_threshold {
+                    Some(threshold) => format!(
+                        "{} 秒以内にリアクションが {} 件ついたら読み上げるね",
+                        guild_config.reaction_window, threshold
+                    ),
+                    None => "リアクションの読み上げをやめたよ".to_string(),
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "announce_channel" => {
+            // 今の版はもう知らせたことにして、次の版から送る
+            guild_config.announce_channel_id = match get_sub_argument_by_name(subcommand, "channel")
+            {
+                Some(ArgumentValue::Channel(channel)) => {
+                    handler
+                        .database
+                        .claim_changelog(guild_id.0 as i64, changelog::VERSION)
+                        .await?;
+                    Some(channel.id.0 as i64)
+                }
+                _ => None,
+            };
+            handler.database.update_guild_config(&guild_config).await?;
+            let msg = match guild_config.announce_channel_id {
+                Some(channel_id) => format!(
+                    "新しい版になったら <#{}> で変更点を知らせるね。今の版の変更点は `/changelog` で見られるよ",
+                    channel_id
+                ),
+                None => "変更点を知らせるのをやめたよ".to_string(),
+            };
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        "transcribe" => {
+            if app_config::config().stt_url.is_none() {
+                return Err(anyhow!(
+                    "文字起こしのサーバーが設定されていないよ。bot を動かしている人に STT_URL を設定してもらってね"
+                ));
+            }
+            guild_config.transcribe_channel_id =
+                match get_sub_argument_by_name(subcommand, "channel") {
+                    Some(ArgumentValue::Channel(channel)) => Some(channel.id.0 as i64),
+                    _ => None,
+                };
+            handler.database.update_guild_config(&guild_config).await?;
+            let channel_id = guild_config
+                .transcribe_channel_id
+                .map(|id| ChannelId(id as u64));
+            meta::apply_transcription(ctx, handler, guild_id, channel_id).await?;
+            let msg = match channel_id {
+                Some(channel_id) => format!(
+                    "ボイスチャンネルで話した声を文字起こしして <#{}> に書き込むね。聞かれていることを参加している人に伝えてね",
+                    channel_id
+                ),
+                None => "文字起こしをやめたよ".to_string(),
+            };
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        "song_request" => {
+            guild_config.song_request_channel_id =
+                match get_sub_argument_by_name(subcommand, "channel") {
+                    Some(ArgumentValue::Channel(channel)) => Some(channel.id.0 as i64),
+                    _ => None,
+                };
+            handler.database.update_guild_config(&guild_config).await?;
+            let msg = match guild_config.song_request_channel_id {
+                Some(channel_id) => format!(
+                    "<#{}> に貼られた曲の URL を予約して、流し始めるときにだれのリクエストか読み上げるね",
+                    channel_id
+                ),
+                None => "曲のリクエストを受け付けるのをやめたよ".to_string(),
+            };
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        "reaction_milestone" => {
+            let count = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Integer(count) = count {
+                if *count < 0 {
+                    return Err(anyhow!("数は 0 以上にしてね"));
+                }
+                guild_config.reaction_milestone = if *count > 0 { Some(*count) } else { None };
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = match guild_config.reaction_milestone {
+                    Some(count) => format!(
+                        "読み上げチャンネルのメッセージに同じリアクションが {} 個ついたら、だれのメッセージか読み上げるね",
+                        count
+                    ),
+                    None => "リアクションの数の読み上げをやめたよ".to_string(),
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "read_embed" => {
+            let title = get_sub_argument(subcommand, 0)?;
+            let description = get_sub_argument(subcommand, 1)?;
+            if let (ArgumentValue::Boolean(title), ArgumentValue::Boolean(description)) =
+                (title, description)
+            {
+                guild_config.read_embed_title = *title;
+                guild_config.read_embed_description = *description;
+                handler.database.update_guild_config(&guild_config).await?;
+                let on_off = |b: bool| if b { "読む" } else { "読まない" };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &format!(
+                        "埋め込みのタイトルは{}、説明は{}ようにしたよ",
+                        on_off(*title),
+                        on_off(*description)
+                    ),
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "personality" => {
+            let personality = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(personality) = personality {
+                let bundle = Personality::try_from(personality.as_str())?.bundle();
+                guild_config.personality = personality.to_string();
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str(bundle.join))
+            } else {
+                unreachable!()
+            }
+        }
+        "join_busy" => {
+            let mode = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(mode) = mode {
+                guild_config.join_busy = mode.to_string();
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = match mode.as_str() {
+                    "move" => "使用中でも、確認してから移動するね",
+                    "queue" => "使用中なら、空くまで待ってから行くね",
+                    _ => "使用中なら、断るね",
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "read_channel" => {
+            let mode = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(mode) = mode {
+                guild_config.read_channel = mode.to_string();
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = match mode.as_str() {
+                    "command" => "これからは /join を送ったチャンネルを読み上げるね",
+                    _ => "これからはボイスチャンネルのチャットを読み上げるね",
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "read_edits" => {
+            let enabled = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Boolean(enabled) = enabled {
+                guild_config.read_edits = *enabled;
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    if *enabled {
+                        "読み上げたメッセージが編集されたら、訂正を読むね"
+                    } else {
+                        "メッセージが編集されても読まないね"
+                    },
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "kana_reading" => {
+            let enabled = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Boolean(enabled) = enabled {
+                guild_config.kana_reading = *enabled;
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    if *enabled {
+                        "COEIROINK の話者にも、VOICEVOX で読みを決めてから読ませるね"
+                    } else {
+                        "それぞれのエンジンの読みで読ませるね"
+                    },
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "read_stickers" => {
+            let enabled = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Boolean(enabled) = enabled {
+                guild_config.read_stickers = *enabled;
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    if *enabled {
+                        "スタンプや GIF が送られたら知らせるね"
+                    } else {
+                        "スタンプや GIF は知らせないね"
+                    },
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "read_threads" => {
+            let enabled = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Boolean(enabled) = enabled {
+                guild_config.read_threads = *enabled;
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    if *enabled {
+                        "読み上げるチャンネルのスレッドも読むね"
+                    } else {
+                        "スレッドは /read_thread したものだけ読むね"
+                    },
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "announce_priority" => {
+            let mode = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(mode) = mode {
+                guild_config.announce_priority = mode.to_string();
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = match mode.as_str() {
+                    "queue" => "これからはあいさつや返事もメッセージと同じ順番で読むね",
+                    "interrupt" => {
+                        "これからはあいさつや返事を、読んでいるメッセージを止めて先に読むね"
+                    }
+                    _ => "これからはあいさつや返事を、読んでいるメッセージの次に読むね",
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "message_gap" => {
+            let ms = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Integer(ms) = ms {
+                if !(0..=MAX_MESSAGE_GAP_MS).contains(ms) {
+                    return Err(anyhow!(
+                        "間隔は 0 から {} ミリ秒にしてね",
+                        MAX_MESSAGE_GAP_MS
+                    ));
+                }
+                guild_config.message_gap_ms = *ms;
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &format!("メッセージの間を {} ミリ秒あけるね", ms),
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "batch_window" => {
+            let ms = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Integer(ms) = ms {
+                if !(0..=MAX_BATCH_WINDOW_MS).contains(ms) {
+                    return Err(anyhow!(
+                        "待つ時間は 0 から {} ミリ秒にしてね",
+                        MAX_BATCH_WINDOW_MS
+                    ));
+                }
+                guild_config.batch_window_ms = *ms;
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = if *ms == 0 {
+                    "短いメッセージもひとつずつ読むね".to_string()
+                } else {
+                    format!("{} ミリ秒以内に続いた短いメッセージはまとめて読むね", ms)
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "read_delay" => {
+            let ms = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Integer(ms) = ms {
+                if !(0..=MAX_READ_DELAY_MS).contains(ms) {
+                    return Err(anyhow!(
+                        "待つ時間は 0 から {} ミリ秒にしてね",
+                        MAX_READ_DELAY_MS
+                    ));
+                }
+                guild_config.read_delay_ms = *ms;
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = if *ms == 0 {
+                    "メッセージはすぐに読むね".to_string()
+                } else {
+                    format!(
+                        "{} ミリ秒待ってから読むね。その間に消したり編集したりしたメッセージは読まないよ",
+                        ms
+                    )
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "greeting" => {
+            let mode = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(mode) = mode {
+                guild_config.greeting_mode = mode.to_string();
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = match mode.as_str() {
+                    "announce" => "あいさつを決めていない人は、参加したことだけ知らせるね",
+                    "off" => "入退出のあいさつをしないね",
+                    _ => "入退出のときにあいさつするね",
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "greeting_cooldown" => {
+            let seconds = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Integer(seconds) = seconds {
+                if !(0..=MAX_GREETING_COOLDOWN_SEC).contains(seconds) {
+                    return Err(anyhow!("0 から {} 秒にしてね", MAX_GREETING_COOLDOWN_SEC));
+                }
+                guild_config.greeting_cooldown_sec = *seconds;
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = if *seconds > 0 {
+                    format!("あいさつしたあと {} 秒は次のあいさつをしないね", seconds)
+                } else {
+                    "あいさつの間隔をあけないね".to_string()
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "idle_leave" => {
+            let minutes = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Integer(minutes) = minutes {
+                guild_config.idle_timeout_min = if *minutes > 0 { Some(*minutes) } else { None };
+                handler.database.update_guild_config(&guild_config).await?;
+                let msg = match guild_config.idle_timeout_min {
+                    Some(minutes) => format!("{} 分読み上げがなかったら抜けるね", minutes),
+                    None => "読み上げがなくても抜けないようにしたよ".to_string(),
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "read_name" => {
+            let enabled = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Boolean(enabled) = enabled {
+                guild_config.read_name = *enabled;
+                // /set_name_reading none のあとに読むことにしたら、決めた読みに戻す
+                if *enabled && NameReading::of(&guild_config) == NameReading::None {
+                    guild_config.name_reading = <&str>::from(NameReading::Configured).to_string();
+                }
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    if *enabled {
+                        "メッセージの前に送った人の名前を読むね"
+                    } else {
+                        "送った人の名前は読まないね"
+                    },
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "read_reply" => {
+            let enabled = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Boolean(enabled) = enabled {
+                guild_config.read_reply = *enabled;
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    if *enabled {
+                        "返信のときは誰への返信かを読むね"
+                    } else {
+                        "誰への返信かは読まないね"
+                    },
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "language" => {
+            let language = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(language) = language {
+                let language = Language::try_from(language.as_str())?;
+                guild_config.language = <&str>::from(language).to_string();
+                let mut msg = match language {
+                    Language::Japanese => "日本語で読み上げるね".to_string(),
+                    Language::English => "I'll read in English".to_string(),
+                };
+                if let Some(ArgumentValue::String(query)) =
+                    get_sub_argument_by_name(subcommand, "speaker")
+                {
+                    let speakers = handler.database.get_all_speakers().await?;
+                    let speaker = search_speakers(&speakers, query)
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| anyhow!("{} という話者はいないよ", query))?;
+                    let generator_type = Generators::try_from(speaker.generator_type.as_str())?;
+                    guild_config.english_generator_type = Some(generator_type as i64);
+                    guild_config.english_voice_type = Some(speaker.style_id);
+                    msg = format!(
+                        "{}\n英語は {} ({}) で読むね",
+                        msg, speaker.name, speaker.style_name
+                    );
+                }
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "kana_speaker" => {
+            let query = match get_sub_argument_by_name(subcommand, "speaker") {
+                Some(ArgumentValue::String(query)) => query,
+                _ => unreachable!(),
+            };
+            let enabled = matches!(
+                get_sub_argument_by_name(subcommand, "enabled"),
+                Some(ArgumentValue::Boolean(true))
+            );
+            let speakers = handler.database.get_all_speakers().await?;
+            let speaker = search_speakers(&speakers, query)
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("{} という話者はいないよ", query))?;
+            handler
+                .database
+                .set_kana_speaker(
+                    guild_id.0 as i64,
+                    &speaker.generator_type,
+                    speaker.style_id,
+                    enabled,
+                )
+                .await?;
+            let msg = if enabled {
+                format!(
+                    "{} ({}) には、漢字をカタカナの読みに直してから読ませるね",
+                    speaker.name, speaker.style_name
+                )
+            } else {
+                format!(
+                    "{} ({}) には、そのまま読ませるね",
+                    speaker.name, speaker.style_name
+                )
+            };
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        "english_kana" => {
+            let enabled = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::Boolean(enabled) = enabled {
+                guild_config.english_kana = *enabled;
+                handler.database.update_guild_config(&guild_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    if *enabled {
+                        "知らない英単語もカタカナにして読むね"
+                    } else {
+                        "知らない英単語は一文字ずつ読むね"
+                    },
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "volume" => {
+            let target = get_sub_argument(subcommand, 0)?;
+            let percent = get_sub_argument(subcommand, 1)?;
+            if let (ArgumentValue::String(target), ArgumentValue::Integer(percent)) =
+                (target, percent)
+            {
+                if !(0..=MAX_VOLUME).contains(percent) {
+                    return Err(anyhow!("音量は 0 から {} % にしてね", MAX_VOLUME));
+                }
+                let name = match target.as_str() {
+                    "tts" => {
+                        guild_config.tts_volume = *percent;
+                        "読み上げ"
+                    }
+                    "music" => {
+                        guild_config.music_volume = *percent;
+                        "音楽"
+                    }
+                    _ => {
+                        guild_config.ducked_volume = *percent;
+                        "読み上げ中の音楽"
+                    }
+                };
+                handler.database.update_guild_config(&guild_config).await?;
+                handler
+                    .music
+                    .set_policy(guild_id, MixPolicy::from_guild_config(&guild_config))
+                    .await;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &format!("{}の音量を {} % にしたよ", name, percent),
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "beta" => {
+            let flag = get_sub_argument(subcommand, 0)?;
+            let enabled = get_sub_argument(subcommand, 1)?;
+            if let (ArgumentValue::String(flag), ArgumentValue::Boolean(enabled)) = (flag, enabled)
+            {
+                let feature = Feature::try_from(flag.as_str())?;
+                handler
+                    .database
+                    .set_feature_flag(guild_id.0 as i64, feature.into(), *enabled)
+                    .await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &format!(
+                        "{} を{}にしたよ",
+                        flag,
+                        if *enabled { "有効" } else { "無効" }
+                    ),
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn read_filter_label(read_filter: &ReadFilter) -> String {
+    match read_filter.kind.as_str() {
+        "bots" => "bot".to_string(),
+        "webhooks" => "webhook".to_string(),
+        "user" => format!("<@{}>", read_filter.target_id),
+        "role" => format!("<@&{}>", read_filter.target_id),
+        kind => kind.to_string(),
+    }
+}
+
+pub async fn read_filter(handler: &Handler, command: &Command) -> Result<SlashCommandTextResult> {
+    if !is_admin(command) {
+        return Err(anyhow!("サーバーの管理権限が必要だよ"));
+    }
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?
+        .0 as i64;
+    let subcommand = get_subcommand(command)?;
+    match subcommand.name.as_str() {
+        "add" | "remove" => {
+            let kind = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(kind) = kind {
+                let target_id = match kind.as_str() {
+                    "user" => match get_sub_argument_by_name(subcommand, "user") {
+                        Some(ArgumentValue::User(user, _)) => user.id.0 as i64,
+                        _ => return Err(anyhow!("user に対象のユーザーを指定してね")),
+                    },
+                    "role" => match get_sub_argument_by_name(subcommand, "role") {
+                        Some(ArgumentValue::Role(role)) => role.id.0 as i64,
+                        _ => return Err(anyhow!("role に対象のロールを指定してね")),
+                    },
+                    _ => 0,
+                };
+                let read_filter = ReadFilter {
+                    guild_id,
+                    kind: kind.to_string(),
+                    target_id,
+                };
+                let label = read_filter_label(&read_filter);
+                let msg = if subcommand.name == "add" {
+                    handler.database.add_read_filter(&read_filter).await?;
+                    format!("{} のメッセージは読み上げないね", label)
+                } else if handler.database.remove_read_filter(&read_filter).await? > 0 {
+                    format!("{} のメッセージも読み上げるね", label)
+                } else {
+                    format!("{} は登録されてないよ", label)
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "list" => {
+            let read_filters = handler.database.get_read_filters(guild_id).await?;
+            let msg = if read_filters.is_empty() {
+                "読み上げない対象はないよ".to_string()
+            } else {
+                read_filters
+                    .iter()
+                    .map(read_filter_label)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub async fn bot_perm(handler: &Handler, command: &Command) -> Result<SlashCommandTextResult> {
+    if !is_admin(command) {
+        return Err(anyhow!("サーバーの管理権限が必要だよ"));
+    }
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?
+        .0 as i64;
+    let subcommand = get_subcommand(command)?;
+    match subcommand.name.as_str() {
+        "add" | "remove" => {
+            let (level, role) = match (
+                get_sub_argument_by_name(subcommand, "level"),
+                get_sub_argument_by_name(subcommand, "role"),
+            ) {
+                (Some(ArgumentValue::String(level)), Some(ArgumentValue::Role(role))) => {
+                    (PermissionLevel::try_from(level.as_str())?, role)
+                }
+                _ => unreachable!(),
+            };
+            let level_name: &str = level.into();
+            let msg = if subcommand.name == "add" {
+                handler
+                    .database
+                    .add_permission_role(guild_id, level_name, role.id.0 as i64)
+                    .await?;
+                format!(
+                    "{} のコマンドを {} のロールの人が使えるようにしたよ",
+                    permission_label(level),
+                    role.name
+                )
+            } else if handler
+                .database
+                .remove_permission_role(guild_id, level_name, role.id.0 as i64)
+                .await?
+                > 0
+            {
+                format!(
+                    "{} のコマンドを使えるロールから {} を外したよ",
+                    permission_label(level),
+                    role.name
+                )
+            } else {
+                format!("{} は登録されてないよ", role.name)
+            };
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        "list" => {
+            let roles = handler.database.get_permission_roles(guild_id).await?;
+            let msg = PermissionLevel::ALL
+                .iter()
+                .map(|level| {
+                    let level_name: &str = (*level).into();
+                    let roles = roles
+                        .iter()
+                        .filter(|(name, _)| name == level_name)
+                        .map(|(_, role_id)| format!("<@&{}>", role_id))
+                        .collect::<Vec<_>>();
+                    format!(
+                        "{}: {}",
+                        permission_label(*level),
+                        if roles.is_empty() {
+                            "だれでも使える".to_string()
+                        } else {
+                            roles.join(" ")
+                        }
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn permission_label(level: PermissionLevel) -> &'static str {
+    match level {
+        PermissionLevel::Admin => "辞書の編集や退出などの管理",
+        PermissionLevel::User => "参加や自分の設定などふだん使う",
+    }
+}
+
+pub async fn dict_pack(handler: &Handler, command: &Command) -> Result<SlashCommandTextResult> {
+    if !is_admin(command) {
+        return Err(anyhow!("サーバーの管理権限が必要だよ"));
+    }
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?
+        .0 as i64;
+    let subcommand = get_subcommand(command)?;
+    match subcommand.name.as_str() {
+        "enable" | "disable" => {
+            let name = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(name) = name {
+                let pack = DictPack::find(name)?;
+                let enabled = subcommand.name == "enable";
+                let changed = handler
+                    .database
+                    .set_dict_pack(guild_id, pack.name, enabled)
+                    .await?
+                    > 0;
+                let msg = match (enabled, changed) {
+                    (true, true) => format!("{} の辞書パックを使うね", pack.description),
+                    (true, false) => format!("{} の辞書パックはもう使ってるよ", pack.description),
+                    (false, true) => format!("{} の辞書パックを外したよ", pack.description),
+                    (false, false) => format!("{} の辞書パックは使ってないよ", pack.description),
+                };
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &msg, false, false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "list" => {
+            let enabled = handler.database.get_dict_packs(guild_id).await?;
+            let msg = PACKS
+                .iter()
+                .map(|pack| {
+                    let mark = if enabled.iter().any(|name| name == pack.name) {
+                        "✅"
+                    } else {
+                        "⬜"
+                    };
+                    format!("{} {} ({})", mark, pack.name, pack.description)
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub async fn set_name_reading(
+    handler: &Handler,
+    command: &Command,
+) -> Result<SlashCommandTextResult> {
+    if !is_admin(command) {
+        return Err(anyhow!("サーバーの管理権限が必要だよ"));
+    }
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?
+        .0 as i64;
+    let name_reading = match get_argument(command, 0)? {
+        ArgumentValue::String(mode) => NameReading::try_from(mode.as_str())?,
+        _ => unreachable!(),
+    };
+    let mut guild_config = handler
+        .database
+        .get_guild_config_or_default(guild_id)
+        .await?;
+    guild_config.name_reading = <&str>::from(name_reading).to_string();
+    // /config read_name と同じ設定なので、読まないときはそちらも切り替える
+    guild_config.read_name = name_reading != NameReading::None;
+    handler.database.update_guild_config(&guild_config).await?;
+    let msg = match name_reading {
+        NameReading::Configured => "決めた読みで名前を読むね",
+        NameReading::Nickname => "サーバーのニックネームで名前を読むね",
+        NameReading::Username => "ユーザー名で名前を読むね",
+        NameReading::None => "送った人の名前は読まないね",
+    };
+    Ok(SlashCommandTextResult::from_str_and_flags(
+        msg, false, false,
+    ))
+}
+
+pub async fn ngword(handler: &Handler, command: &Command) -> Result<SlashCommandTextResult> {
+    if !is_admin(command) {
+        return Err(anyhow!("サーバーの管理権限が必要だよ"));
+    }
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?
+        .0 as i64;
+    let subcommand = get_subcommand(command)?;
+    let msg = match subcommand.name.as_str() {
+        "add" => {
+            let word = match get_sub_argument_by_name(subcommand, "word") {
+                Some(ArgumentValue::String(word)) => word.trim().to_string(),
+                _ => unreachable!(),
+            };
+            if word.is_empty() {
+                return Err(anyhow!("単語が空だよ"));
+            }
+            let action = match get_sub_argument_by_name(subcommand, "action") {
+                Some(ArgumentValue::String(action)) => NgAction::try_from(action.as_str())?,
+                _ => NgAction::Replace,
+            };
+            handler
+                .database
+                .add_ng_word(
+                    guild_id,
+                    &NgWord {
+                        word: word.clone(),
+                        action,
+                    },
+                )
+                .await?;
+            match action {
+                NgAction::Replace => format!("{} をピー音に置き換えるね", word),
+                NgAction::Skip => format!("{} を含むメッセージは読まないね", word),
+            }
+        }
+        "remove" => {
+            let word = match get_sub_argument_by_name(subcommand, "word") {
+                Some(ArgumentValue::String(word)) => word.trim().to_string(),
+                _ => unreachable!(),
+            };
+            if handler.database.remove_ng_word(guild_id, &word).await? > 0 {
+                format!("{} を NG ワードから外したよ", word)
+            } else {
+                format!("{} は NG ワードに入ってないよ", word)
+            }
+        }
+        "list" => {
+            let ng_words = handler.database.get_ng_words(guild_id).await?;
+            if ng_words.is_empty() {
+                "NG ワードはないよ".to_string()
+            } else {
+                ng_words
+                    .iter()
+                    .map(|ng_word| match ng_word.action {
+                        NgAction::Replace => format!("{} (ピー音)", ng_word.word),
+                        NgAction::Skip => format!("{} (読まない)", ng_word.word),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+        _ => unreachable!(),
+    };
+    Ok(SlashCommandTextResult::from_str_and_flags(
+        &msg, false, false,
+    ))
+}
+
+pub async fn autojoin(handler: &Handler, command: &Command) -> Result<SlashCommandTextResult> {
+    if !is_admin(command) {
+        return Err(anyhow!("サーバーの管理権限が必要だよ"));
+    }
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?
+        .0 as i64;
+    let subcommand = get_subcommand(command)?;
+    let user_id = match get_sub_argument_by_name(subcommand, "user") {
+        Some(ArgumentValue::User(user, _)) => Some(user.id.0 as i64),
+        _ => None,
+    };
+    let mut guild_config = handler
+        .database
+        .get_guild_config_or_default(guild_id)
+        .await?;
+    let msg = match subcommand.name.as_str() {
+        "set" => {
+            let channel_id = match get_sub_argument_by_name(subcommand, "channel") {
+                Some(ArgumentValue::Channel(channel)) => channel.id,
+                _ => return Err(anyhow!("channel に読み上げるチャンネルを指定してね")),
+            };
+            guild_config.autojoin_channel_id = Some(channel_id.0 as i64);
+            handler.database.update_guild_config(&guild_config).await?;
+            if let Some(user_id) = user_id {
+                handler
+                    .database
+                    .add_autojoin_user(guild_id, user_id)
+                    .await?;
+            }
+            let users = handler.database.get_autojoin_users(guild_id).await?;
+            if users.is_empty() {
+                format!(
+                    "誰かがボイスチャンネルに入ったら、自動で入って <#{}> を読み上げるね",
+                    channel_id
+                )
+            } else {
+                format!(
+                    "{} がボイスチャンネルに入ったら、自動で入って <#{}> を読み上げるね",
+                    users
+                        .iter()
+                        .map(|user_id| format!("<@{}>", user_id))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    channel_id
+                )
+            }
+        }
+        "off" => match user_id {
+            Some(user_id) => {
+                if handler
+                    .database
+                    .remove_autojoin_user(guild_id, user_id)
+                    .await?
+                    > 0
+                {
+                    format!("<@{}> が入っても自動では入らないね", user_id)
+                } else {
+                    format!("<@{}> は登録されてないよ", user_id)
+                }
+            }
+            None => {
+                guild_config.autojoin_channel_id = None;
+                handler.database.update_guild_config(&guild_config).await?;
+                handler.database.clear_autojoin_users(guild_id).await?;
+                "自動で入るのをやめたよ".to_string()
+            }
+        },
+        _ => unreachable!(),
+    };
+    Ok(SlashCommandTextResult::from_str_and_flags(
+        &msg, false, false,
+    ))
+}
+
+// 自分の設定を JSON で書き出したり、書き出したものを読み込んだりする
+pub async fn my_config(handler: &Handler, command: &Command) -> Result<SlashCommandTextResult> {
+    let user_id = command.user.id.0 as i64;
+    let guild_id = command.guild_id.map(|guild_id| guild_id.0 as i64);
+    let subcommand = get_subcommand(command)?;
+    let mut user_config = handler
+        .database
+        .get_user_config_or_default(user_id, guild_id)
+        .await?;
+    match subcommand.name.as_str() {
+        "export" => {
+            let voice_name = match Generators::try_from(user_config.generator_type as u8) {
+                Ok(generator) => handler
+                    .database
+                    .speaker_id_to_name(generator, user_config.voice_type as u32)
+                    .await
+                    .ok(),
+                Err(_) => None,
+            };
+            let json = serde_json::to_string(&PortableUserConfig::new(&user_config, voice_name))?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &format!(
+                    "`/my_config import` にこれを貼ると設定を移せるよ\n```json\n{}\n```",
+                    json
+                ),
+                false,
+                false,
+            ))
+        }
+        "import" => {
+            let json = get_sub_argument(subcommand, 0)?;
+            if let ArgumentValue::String(json) = json {
+                let json = json.trim().trim_matches('`').trim_start_matches("json");
+                let portable: PortableUserConfig = serde_json::from_str(json)
+                    .map_err(|_| anyhow!("`/my_config export` で書き出した JSON を貼ってね"))?;
+                let (generator_type, voice_type) = verify_voice(handler, &portable).await?;
+                portable.apply(&mut user_config, generator_type, voice_type);
+                handler.database.update_user_config(&user_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    "設定を読み込んだよ",
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub async fn voice_favorite(
+    handler: &Handler,
+    command: &Command,
+) -> Result<SlashCommandTextResult> {
+    let user_id = command.user.id.0 as i64;
+    let guild_id = command.guild_id.map(|guild_id| guild_id.0 as i64);
+    let subcommand = get_subcommand(command)?;
+    let name = match get_sub_argument_by_name(subcommand, "name") {
+        Some(ArgumentValue::String(name)) => name.trim().to_string(),
+        _ => String::new(),
+    };
+    let mut user_config = handler
+        .database
+        .get_user_config_or_default(user_id, guild_id)
+        .await?;
+    let msg = match subcommand.name.as_str() {
+        "add" => {
+            if name.is_empty() || name.chars().count() > MAX_VOICE_PRESET_NAME_CHARS {
+                return Err(anyhow!(
+                    "名前は 1 から {} 文字にしてね",
+                    MAX_VOICE_PRESET_NAME_CHARS
+                ));
+            }
+            let presets = handler.database.get_voice_presets(user_id).await?;
+            if presets.len() >= MAX_VOICE_PRESETS
+                && !presets.iter().any(|preset| preset.name == name)
+            {
+                return Err(anyhow!(
+                    "お気に入りは {} 個までだよ。`/voice_favorite remove` で消してね",
+                    MAX_VOICE_PRESETS
+                ));
+            }
+            handler
+                .database
+                .save_voice_preset(&VoicePreset::new(&user_config, &name))
+                .await?;
+            format!("いまの声を {} で保存したよ", name)
+        }
+        "use" => {
+            let preset = handler
+                .database
+                .get_voice_preset(user_id, &name)
+                .await?
+                .ok_or_else(|| anyhow!("{} というお気に入りはないよ", name))?;
+            preset.apply(&mut user_config);
+            handler.database.update_user_config(&user_config).await?;
+            format!("{} の声で読むね", name)
+        }
+        "remove" => {
+            if handler.database.remove_voice_preset(user_id, &name).await? > 0 {
+                format!("お気に入りの {} を消したよ", name)
+            } else {
+                format!("{} というお気に入りはないよ", name)
+            }
+        }
+        "list" => {
+            let presets = handler.database.get_voice_presets(user_id).await?;
+            if presets.is_empty() {
+                "お気に入りの声はないよ".to_string()
+            } else {
+                let mut lines = Vec::new();
+                for preset in presets {
+                    let voice_name = match Generators::try_from(preset.generator_type as u8) {
+                        Ok(generator) => handler
+                            .database
+                            .speaker_id_to_name(generator, preset.voice_type as u32)
+                            .await
+                            .unwrap_or_else(|_| "不明な声".to_string()),
+                        Err(_) => "不明な声".to_string(),
+                    };
+                    lines.push(format!(
+                        "{}: {} (速さ {} 、高さ {})",
+                        preset.name, voice_name, preset.speed_scale, preset.pitch_scale
+                    ));
+                }
+                lines.join("\n")
+            }
+        }
+        _ => unreachable!(),
+    };
+    Ok(SlashCommandTextResult::from_str_and_flags(
+        &msg, false, false,
+    ))
+}
+
+pub async fn mydict(handler: &Handler, command: &Command) -> Result<SlashCommandTextResult> {
+    let user_id = command.user.id.0 as i64;
+    let subcommand = get_subcommand(command)?;
+    let word = match get_sub_argument_by_name(subcommand, "word") {
+        Some(ArgumentValue::String(word)) if !word.is_empty() => word.to_string(),
+        _ => return Err(anyhow!("単語を入力してね")),
+    };
+    let msg = match subcommand.name.as_str() {
+        "add" => {
+            let read = match get_sub_argument_by_name(subcommand, "read") {
+                Some(ArgumentValue::String(read)) => read.to_string(),
+                _ => unreachable!(),
+            };
+            handler
+                .database
+                .update_user_dict(user_id, &word, &read)
+                .await?;
+            format!("あなたのメッセージの {} は {} と読むね", word, read)
+        }
+        "rem" => {
+            if handler.database.remove_user_dict(user_id, &word).await? > 0 {
+                format!("あなたの辞書から {} を忘れたよ", word)
+            } else {
+                format!("{} はあなたの辞書に登録されてないよ", word)
+            }
+        }
+        _ => unreachable!(),
+    };
+    Ok(SlashCommandTextResult::from_str_and_flags(
+        &msg, false, false,
+    ))
+}
+
+// 読み込む声がこの bot にあるか確かめる。ID で見つからなければ名前で探す
+async fn verify_voice(handler: &Handler, portable: &PortableUserConfig) -> Result<(i64, i64)> {
+    if let Ok(generator) = Generators::try_from(portable.generator_type as u8) {
+        if handler
+            .database
+            .speaker_id_to_name(generator, portable.voice_type as u32)
+            .await
+            .is_ok()
+        {
+            return Ok((portable.generator_type, portable.voice_type));
+        }
+    }
+    let voice_name = portable
+        .voice_name
+        .as_deref()
+        .ok_or_else(|| anyhow!("その声はこの bot にはないよ"))?;
+    let speaker = handler
+        .database
+        .get_all_speakers()
+        .await?
+        .into_iter()
+        .find(|speaker| format!("{} {}", speaker.name, speaker.style_name) == voice_name)
+        .ok_or_else(|| anyhow!("{} はこの bot にはないよ", voice_name))?;
+    Ok((
+        Generators::try_from(speaker.generator_type.as_str())? as i64,
+        speaker.style_id,
+    ))
+}
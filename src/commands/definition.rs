@@ -1,230 +1,1497 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use serde_json::json;
 use serenity::{
+    builder::CreateApplicationCommands,
     http::Http,
-    model::interactions::application_command::{self, ApplicationCommand},
+    model::{
+        id::GuildId,
+        interactions::application_command::{self, ApplicationCommand},
+    },
 };
-pub async fn set_application_commands(http: &Http) -> Result<Vec<ApplicationCommand>> {
-    let v = ApplicationCommand::set_global_application_commands(http, |commands| {
-        commands
-            .create_application_command(|command| {
-                command.name("join").description("VCに参加します")
-            })
-            .create_application_command(|command| {
-                command.name("leave").description("VCから抜けます")
-            })
-            .create_application_command(|command| {
-                command
-                    .name("add")
-                    .create_option(|option| {
-                        option
-                            .kind(application_command::ApplicationCommandOptionType::String)
-                            .required(true)
-                            .name("before")
-                            .description("string")
-                    })
-                    .create_option(|option| {
-                        option
-                            .kind(application_command::ApplicationCommandOptionType::String)
-                            .required(true)
-                            .description("string")
-                            .name("after")
-                    })
-                    .description("before を after と読むようにします")
-            })
-            .create_application_command(|command| {
-                command
-                    .name("rem")
-                    .create_option(|option| {
-                        option
-                            .kind(application_command::ApplicationCommandOptionType::String)
-                            .required(true)
-                            .name("word")
-                            .description("string")
-                    })
-                    .description("word の読み方を忘れます")
-            })
-            .create_application_command(|command| {
-                command.name("mute").description("botをミュートします")
-            })
-            .create_application_command(|command| {
-                command
-                    .name("unmute")
-                    .description("botのミュートを解除します")
-            })
-            .create_application_command(|command| {
-                command
-                    .name("hello")
-                    .description("入った時のあいさつを変えます")
-                    .create_option(|option| {
-                        option
-                            .kind(application_command::ApplicationCommandOptionType::String)
-                            .required(true)
-                            .name("greet")
-                            .description("string")
-                    })
-            })
-            .create_application_command(|command| {
-                command
-                    .name("bye")
-                    .description("出た時のあいさつを変えます")
-                    .create_option(|option| {
-                        option
-                            .kind(application_command::ApplicationCommandOptionType::String)
-                            .required(true)
-                            .name("greet")
-                            .description("string")
-                    })
-            })
-            .create_application_command(|command| {
-                command
-                    .name("set_voice_type")
-                    .description("ボイスタイプを変えます")
-            })
-            .create_application_command(|command| {
-                command
-                    .name("set_nickname")
-                    .description("呼ぶ名前を設定します")
-                    .create_option(|option| {
-                        option
-                            .kind(application_command::ApplicationCommandOptionType::String)
-                            .required(true)
-                            .name("nick")
-                            .description("string")
-                    })
-            })
-            .create_application_command(|command| {
-                command
-                    .name("rand_member")
-                    .description("VC内の人をランダムに選びます")
-            })
-            .create_application_command(|command| {
-                command
-                    .name("walpha")
-                    .description("計算などをしてくれます")
-                    .create_option(|option| {
-                        option
-                            .kind(application_command::ApplicationCommandOptionType::String)
-                            .required(true)
-                            .name("input")
-                            .description("string")
-                    })
-            })
-            .create_application_command(|command| {
-                command.name("info").description("設定を表示します")
-            })
-            .create_application_command(|command| command.name("help").description("ヘルプです"))
-    })
-    .await?;
-    Ok(v)
-    // GuildId::set_application_commands(&guild_id, http, |commands| {
-    //     commands
-    //         .create_application_command(|command| {
-    //             command.name("join").description("VCに参加します")
-    //         })
-    //         .create_application_command(|command| {
-    //             command.name("leave").description("VCから抜けます")
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("add")
-    //                 .create_option(|option| {
-    //                     option
-    //                         .kind(application_command::ApplicationCommandOptionType::String)
-    //                         .required(true)
-    //                         .name("before")
-    //                         .description("string")
-    //                 })
-    //                 .create_option(|option| {
-    //                     option
-    //                         .kind(application_command::ApplicationCommandOptionType::String)
-    //                         .required(true)
-    //                         .description("string")
-    //                         .name("after")
-    //                 })
-    //                 .description("before を after と読むようにします")
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("rem")
-    //                 .create_option(|option| {
-    //                     option
-    //                         .kind(application_command::ApplicationCommandOptionType::String)
-    //                         .required(true)
-    //                         .name("word")
-    //                         .description("string")
-    //                 })
-    //                 .description("word の読み方を忘れます")
-    //         })
-    //         .create_application_command(|command| {
-    //             command.name("mute").description("botをミュートします")
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("unmute")
-    //                 .description("botのミュートを解除します")
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("hello")
-    //                 .description("入った時のあいさつを変えます")
-    //                 .create_option(|option| {
-    //                     option
-    //                         .kind(application_command::ApplicationCommandOptionType::String)
-    //                         .required(true)
-    //                         .name("greet")
-    //                         .description("string")
-    //                 })
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("bye")
-    //                 .description("出た時のあいさつを変えます")
-    //                 .create_option(|option| {
-    //                     option
-    //                         .kind(application_command::ApplicationCommandOptionType::String)
-    //                         .required(true)
-    //                         .name("greet")
-    //                         .description("string")
-    //                 })
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("set_voice_type")
-    //                 .description("ボイスタイプを変えます")
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("set_nickname")
-    //                 .description("呼ぶ名前を設定します。")
-    //                 .create_option(|option| {
-    //                     option
-    //                         .kind(application_command::ApplicationCommandOptionType::String)
-    //                         .required(true)
-    //                         .name("nick")
-    //                         .description("string")
-    //                 })
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("rand_member")
-    //                 .description("VC内の人をランダムに選びます")
-    //         })
-    //         .create_application_command(|command| {
-    //             command
-    //                 .name("walpha")
-    //                 .description("計算などをしてくれます")
-    //                 .create_option(|option| {
-    //                     option
-    //                         .kind(application_command::ApplicationCommandOptionType::String)
-    //                         .required(true)
-    //                         .name("input")
-    //                         .description("string")
-    //                 })
-    //         })
-    //         .create_application_command(|command| {
-    //             command.name("info").description("設定を表示します")
-    //         })
-    // })
-    // .await
-    // .map_err(|e| e.into())
+use tracing::info;
+
+use crate::lib::{app_config::config, dict_pack::PACKS, language::Language};
+
+// コマンドをどこに登録するか。command_registration で選ぶ
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Registration {
+    // すべてのサーバーに登録する。反映に時間がかかることがある
+    Global,
+    // bot がいるサーバーごとに登録する。すぐ反映されるので開発向け
+    Guild,
+}
+
+impl TryFrom<&str> for Registration {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "global" => Ok(Self::Global),
+            "guild" => Ok(Self::Guild),
+            _ => Err(anyhow!("no such registration mode")),
+        }
+    }
+}
+
+impl Registration {
+    pub fn from_config() -> Self {
+        Self::try_from(config().command_registration.as_str()).unwrap_or(Self::Global)
+    }
+}
+
+// 一覧で上書きするので、ここから消したコマンドは discord からも消える
+// もう一方の登録先に残っているコマンドも消して、二重に出ないようにする
+pub async fn set_application_commands(
+    http: &Http,
+    registration: Registration,
+    guilds: &[GuildId],
+) -> Result<Vec<ApplicationCommand>> {
+    match registration {
+        Registration::Global => {
+            let v = ApplicationCommand::set_global_application_commands(http, define).await?;
+            for guild_id in guilds {
+                if let Err(e) = guild_id.set_application_commands(http, |c| c).await {
+                    info!("could not clear commands of {}: {}", guild_id, e);
+                }
+            }
+            Ok(v)
+        }
+        Registration::Guild => {
+            let mut v = Vec::new();
+            for guild_id in guilds {
+                match guild_id.set_application_commands(http, define).await {
+                    Ok(commands) => v = commands,
+                    Err(e) => info!("could not register commands to {}: {}", guild_id, e),
+                }
+            }
+            ApplicationCommand::set_global_application_commands(http, |c| c).await?;
+            Ok(v)
+        }
+    }
+}
+
+// 日本語の表示で使うコマンド名。説明はもともと日本語なので名前だけ
+const JA_NAMES: &[(&str, &str)] = &[
+    ("join", "参加"),
+    ("read_thread", "スレッド読み上げ"),
+    ("leave", "退出"),
+    ("add", "辞書登録"),
+    ("rem", "辞書削除"),
+    ("dict_edit", "辞書編集"),
+    ("dict", "辞書"),
+    ("migrate_legacy_dict", "旧辞書取り込み"),
+    ("dict_reload", "辞書再読み込み"),
+    ("prune", "古い記録の削除"),
+    ("mute", "ミュート"),
+    ("unmute", "ミュート解除"),
+    ("hello", "入室あいさつ"),
+    ("bye", "退室あいさつ"),
+    ("set_greeting_template", "あいさつの形"),
+    ("skip", "スキップ"),
+    ("flush", "読み上げ取り消し"),
+    ("panel", "パネル"),
+    ("set_voice_type", "声の設定"),
+    ("voice", "声を探す"),
+    ("set_guild_default_voice", "サーバーの声"),
+    ("set_nickname", "呼び方"),
+    ("set_server_nickname", "サーバーでの呼び方"),
+    ("set_name_reading", "名前の読み方"),
+    ("ignore_me", "読み上げない"),
+    ("set_speed", "速さ"),
+    ("set_pitch", "高さ"),
+    ("quiet_hours", "静かな時間"),
+    ("rand_member", "ランダム"),
+    ("walpha", "計算"),
+    ("say", "読み上げ"),
+    ("info", "設定表示"),
+    ("stats", "読み上げ量"),
+    ("setup", "初期設定"),
+    ("config", "サーバー設定"),
+    ("my_config", "自分の設定"),
+    ("mydict", "自分の辞書"),
+    ("voice_favorite", "お気に入りの声"),
+    ("autojoin", "自動参加"),
+    ("play", "曲を流す"),
+    ("np", "再生中の曲"),
+    ("queue", "曲の予約"),
+    ("skip_song", "曲を飛ばす"),
+    ("stop", "曲を止める"),
+    ("se", "効果音"),
+    ("read_filter", "読み上げフィルター"),
+    ("dict_pack", "辞書パック"),
+    ("ngword", "ngワード"),
+    ("bot_perm", "コマンド権限"),
+    ("reload_speakers", "話者の取り直し"),
+    ("status", "状態"),
+    ("shard_info", "シャード"),
+    ("ping", "応答速度"),
+    ("changelog", "変更点"),
+    ("help", "ヘルプ"),
+];
+
+// language で表示するコマンド名
+pub fn localized_name(name: &str, language: Language) -> &str {
+    match language {
+        Language::Japanese => JA_NAMES
+            .iter()
+            .find(|(en, _)| *en == name)
+            .map_or(name, |(_, ja)| ja),
+        Language::English => name,
+    }
+}
+
+// 日本語の名前を discord に登録して、日本語の discord では日本語の名前で探せるようにする
+// 実行されたときの名前はもとの名前のままなので、処理する側は変えなくていい
+fn localize(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    for command in commands.0.iter_mut() {
+        if let Some(command) = command.as_object_mut() {
+            let name = command.get("name").and_then(|name| name.as_str());
+            if let Some(name) = name.map(|name| localized_name(name, Language::Japanese)) {
+                let name_localizations = json!({ "ja": name });
+                command.insert("name_localizations".to_string(), name_localizations);
+            }
+        }
+    }
+    commands
+}
+
+fn define(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    let commands = commands
+        .create_application_command(|command| command.name("join").description("VCに参加します"))
+        .create_application_command(|command| {
+            command
+                .name("read_thread")
+                .description("このスレッドも読み上げます。もう一度使うとやめます")
+        })
+        .create_application_command(|command| {
+            command
+                .name("leave")
+                .description("VCから抜けます")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::Boolean)
+                        .required(false)
+                        .name("finish")
+                        .description("残っている読み上げを読み終わってから抜けます")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("add")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("before")
+                        .description("string")
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .description("string")
+                        .name("after")
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::Integer)
+                        .required(false)
+                        .description("大きいほど先に置き換えます (デフォルト 0)")
+                        .name("priority")
+                })
+                .description("before を after と読むようにします")
+        })
+        .create_application_command(|command| {
+            command
+                .name("rem")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("word")
+                        .description("string")
+                })
+                .description("word の読み方を忘れます")
+        })
+        .create_application_command(|command| {
+            command
+                .name("dict_edit")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("word")
+                        .description("登録済みの単語")
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("after")
+                        .description("新しい読み")
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::Integer)
+                        .required(false)
+                        .name("priority")
+                        .description("新しい優先度 (省略するとそのまま)")
+                })
+                .description("登録済みの word の読みを書き換えます")
+        })
+        .create_application_command(|command| {
+            command
+                .name("dict")
+                .description("辞書を表示します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("list")
+                        .description("辞書を置き換える順にページに分けて表示します。ボタンで単語を消せます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(false)
+                                .name("page")
+                                .description("最初に開くページ (デフォルト 1)")
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("migrate_legacy_dict")
+                .description("read_dict.json を指定したサーバーの辞書に取り込みます (オーナー専用)")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("guild")
+                        .description("取り込む先のサーバーの ID")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("dict_reload")
+                .description("書き換えた read_dict.json を指定したサーバーの辞書に反映します (オーナー専用)")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("guild")
+                        .description("取り込む先のサーバーの ID")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("prune")
+                .description("残す日数を過ぎた記録を消し、残りの行数を表示します (オーナー専用)")
+        })
+        .create_application_command(|command| {
+            command.name("mute").description("botをミュートします")
+        })
+        .create_application_command(|command| {
+            command
+                .name("unmute")
+                .description("botのミュートを解除します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("hello")
+                .description("入った時のあいさつを変えます")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("greet")
+                        .description("{time} に時間帯のあいさつ、{count} に今日何回目の入室かが入ります")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("bye")
+                .description("出た時のあいさつを変えます")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("greet")
+                        .description("{time} に時間帯のあいさつ、{count} に今日何回目の入室かが入ります")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("set_greeting_template")
+                .description("サーバーのあいさつの形を変えます (要サーバー管理権限)")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(false)
+                        .name("template")
+                        .description("{name} に名前、{greeting} にあいさつが入ります。省略すると元に戻します")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("skip")
+                .description("いま読んでいるメッセージを飛ばします")
+        })
+        .create_application_command(|command| {
+            command
+                .name("flush")
+                .description("まだ読んでいないメッセージを全部取り消します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("panel")
+                .description("参加・退出・スキップ・ミュートのボタンを出します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("set_voice_type")
+                .description("ボイスタイプを変えます")
+        })
+        .create_application_command(|command| {
+            command
+                .name("voice")
+                .description("声を探します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("search")
+                        .description("名前で声を探して、試しに聞いてから選べます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("キャラクター名やスタイル名 (例: ずんだもん ささやき)")
+                                .set_autocomplete(true)
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("set_guild_default_voice")
+                .description("サーバーのデフォルトのボイスタイプを変えます")
+        })
+        .create_application_command(|command| {
+            command
+                .name("set_nickname")
+                .description("呼ぶ名前を設定します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("nick")
+                        .description("string")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("set_server_nickname")
+                .description("このサーバーだけで呼ぶ名前を設定します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(false)
+                        .name("nick")
+                        .description("省くと /set_nickname の名前に戻します")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("set_name_reading")
+                .description("メッセージの前に読む名前を、サーバー全体で選びます")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("mode")
+                        .description("読む名前")
+                        .add_string_choice("決めた読み (なければニックネーム)", "configured")
+                        .add_string_choice("サーバーのニックネーム", "nickname")
+                        .add_string_choice("ユーザー名", "username")
+                        .add_string_choice("読まない", "none")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("ignore_me")
+                .description("自分のメッセージを読み上げないようにします")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("mode")
+                        .description("on で読み上げなくなります")
+                        .add_string_choice("on", "on")
+                        .add_string_choice("off", "off")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("set_speed")
+                .description("自分のメッセージを読む速さを設定します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::Number)
+                        .required(true)
+                        .name("speed")
+                        .description("0.5 から 2.0 (デフォルト 1.0)")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("quiet_hours")
+                .description("入退出のあいさつとメッセージの前の名前を読まない時間帯を設定します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("hours")
+                        .description("23:00-07:00 のように書きます。off で解除します")
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(false)
+                        .name("utc_offset")
+                        .description("時刻の UTC からの時差 (デフォルト +09:00)")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("set_pitch")
+                .description("自分のメッセージを読む声の高さを設定します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::Number)
+                        .required(true)
+                        .name("pitch")
+                        .description("-0.15 から 0.15 (デフォルト 0.0)")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("rand_member")
+                .description("VC内の人をランダムに選びます")
+        })
+        .create_application_command(|command| {
+            command
+                .name("walpha")
+                .description("計算などをしてくれます")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("input")
+                        .description("string")
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::Boolean)
+                        .required(false)
+                        .name("read")
+                        .description("短い答えを読み上げます")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("say")
+                .description("好きな文章を読み上げます。自分の声の設定は変わりません")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("text")
+                        .description("読み上げる文章")
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(false)
+                        .name("voice")
+                        .description("この文章だけ使う声。指定しなければ自分の声で読みます")
+                        .set_autocomplete(true)
+                })
+        })
+        .create_application_command(|command| command.name("info").description("設定を表示します"))
+        .create_application_command(|command| {
+            command
+                .name("stats")
+                .description("このサーバーで今日とこれまでに読み上げた量を表示します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("setup")
+                .description("読み上げるチャンネルや声などの設定を順に選んでまとめて保存します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("config")
+                .description("サーバーの設定を変えます")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("voice_region")
+                        .description("ボイスチャンネルのリージョンを固定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("region")
+                                .description("リージョン ID (auto で自動に戻します)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("reaction_summary")
+                        .description("短時間にたくさんついたリアクションを読み上げます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(true)
+                                .name("threshold")
+                                .description("読み上げるリアクションの数 (0 で読み上げません)")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(false)
+                                .name("window")
+                                .description("集計する秒数")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("announce_channel")
+                        .description("新しい版の変更点を知らせるチャンネルを設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Channel)
+                                .required(false)
+                                .name("channel")
+                                .description("知らせるテキストチャンネル (省略すると知らせません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("transcribe")
+                        .description("ボイスチャンネルで話した声を文字起こしするチャンネルを設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Channel)
+                                .required(false)
+                                .name("channel")
+                                .description("書き込むテキストチャンネル (省略すると文字起こししません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("song_request")
+                        .description("貼られた曲の URL を予約するリクエストのチャンネルを設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Channel)
+                                .required(false)
+                                .name("channel")
+                                .description("リクエストを受け付けるテキストチャンネル (省略すると受け付けません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("reaction_milestone")
+                        .description("同じリアクションが決まった数ついたメッセージを、送った人の名前と一緒に読み上げます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(true)
+                                .name("count")
+                                .description("読み上げるリアクションの数 (0 で読み上げません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("read_embed")
+                        .description("メッセージの埋め込みを読み上げるか設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("title")
+                                .description("タイトルを読むか")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("description")
+                                .description("説明を途中まで読むか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("personality")
+                        .description("bot の口調を変えます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("personality")
+                                .description("口調")
+                                .add_string_choice("ふつう", "casual")
+                                .add_string_choice("ていねい", "polite")
+                                .add_string_choice("キャラクター", "character")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("join_busy")
+                        .description(
+                            "別のボイスチャンネルで使用中に /join されたときの動きを設定します",
+                        )
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("mode")
+                                .description("動き")
+                                .add_string_choice("断る", "refuse")
+                                .add_string_choice("確認して移動する", "move")
+                                .add_string_choice("空くまで待つ", "queue")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("read_channel")
+                        .description("/join したときに読み上げるチャンネルを設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("mode")
+                                .description("読み上げるチャンネル")
+                                .add_string_choice("ボイスチャンネルのチャット", "voice")
+                                .add_string_choice("/join を送ったチャンネル", "command")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("read_edits")
+                        .description("読み上げたメッセージが編集されたら訂正を読むか設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("訂正を読むか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("read_stickers")
+                        .description("スタンプや GIF のリンクを、名前や「GIF」と読むか設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("スタンプや GIF を読むか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("read_threads")
+                        .description("読み上げるチャンネルの下のスレッドも読み上げるか設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("スレッドも読むか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("announce_priority")
+                        .description("あいさつやコマンドの返事を読む順番を設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("mode")
+                                .description("読む順番")
+                                .add_string_choice("メッセージと同じ順番", "queue")
+                                .add_string_choice("読んでいるメッセージの次", "next")
+                                .add_string_choice("読んでいるメッセージを止めて先に読む", "interrupt")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("message_gap")
+                        .description("続けて読み上げるメッセージの間をあけます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(true)
+                                .name("ms")
+                                .description("あけるミリ秒 (0 であけません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("batch_window")
+                        .description("続けて来た短いメッセージをまとめて読みます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(true)
+                                .name("ms")
+                                .description("まとめるまで待つミリ秒 (0 でまとめません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("read_delay")
+                        .description("メッセージを読む前に少し待ち、その間に消されたり編集されたりしたら読みません")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(true)
+                                .name("ms")
+                                .description("読む前に待つミリ秒 (0 で待ちません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("greeting")
+                        .description("入退出のあいさつの読み方を設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("mode")
+                                .description("読み方")
+                                .add_string_choice("各自のあいさつ", "greeting")
+                                .add_string_choice("参加・退出を知らせる", "announce")
+                                .add_string_choice("あいさつしない", "off")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("greeting_cooldown")
+                        .description("あいさつのあと、次のあいさつまであける秒数を設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(true)
+                                .name("seconds")
+                                .description("あける秒数 (0 であけません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("idle_leave")
+                        .description("読み上げがしばらくなかったらボイスチャンネルから抜けます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(true)
+                                .name("minutes")
+                                .description("抜けるまでの分数 (0 で抜けません)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("read_name")
+                        .description("メッセージの前に送った人の名前を読むか設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("名前を読むか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("read_reply")
+                        .description("返信のとき、誰への返信かを読むか設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("誰への返信かを読むか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("volume")
+                        .description("読み上げと /play の音楽の音量を設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("target")
+                                .description("設定する音量")
+                                .add_string_choice("読み上げ", "tts")
+                                .add_string_choice("音楽", "music")
+                                .add_string_choice("読み上げ中の音楽", "ducked")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Integer)
+                                .required(true)
+                                .name("percent")
+                                .description("音量 (%)")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("kana_reading")
+                        .description("COEIROINK の話者にも、VOICEVOX の形態素解析で読みを決めてから読ませます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("読みを直すか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("kana_speaker")
+                        .description("漢字を読み違える話者に、VOICEVOX で読みをカタカナに直してから読ませます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("speaker")
+                                .description("話者の名前")
+                                .set_autocomplete(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("カタカナに直すか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("language")
+                        .description("読み上げる言語と、英語のときに使う話者を設定します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("language")
+                                .description("言語")
+                                .add_string_choice("日本語", "ja")
+                                .add_string_choice("English", "en")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(false)
+                                .name("speaker")
+                                .description("英語のときに全員の読み上げに使う話者")
+                                .set_autocomplete(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("english_kana")
+                        .description(
+                            "読み方のわからない英単語をつづりからカタカナにして読むか設定します",
+                        )
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("カタカナにするか")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("beta")
+                        .description("実験的な機能をこのサーバーで試します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("flag")
+                                .description("機能")
+                                .add_string_choice(
+                                    "メッセージの先頭のタグで話速や声を変える",
+                                    "inline_tags",
+                                )
+                                .add_string_choice(
+                                    "「> 名前: セリフ」の引用をその人の声で読む",
+                                    "dialogue",
+                                )
+                                .add_string_choice(
+                                    "声を決めていない人に別々の声を順番に配る",
+                                    "voice_rotation",
+                                )
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                                .name("enabled")
+                                .description("有効にするか")
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("my_config")
+                .description("自分の設定を書き出したり読み込んだりします")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("export")
+                        .description("設定を JSON で書き出します")
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("import")
+                        .description("書き出した JSON から設定を読み込みます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("json")
+                                .description("/my_config export で書き出した JSON")
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("mydict")
+                .description("自分のメッセージにだけ当てる読み方を登録します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("add")
+                        .description("word を read と読むようにします")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("word")
+                                .description("string")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("read")
+                                .description("string")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("rem")
+                        .description("word の読み方を忘れます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("word")
+                                .description("string")
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("voice_favorite")
+                .description("声と速さ・高さをお気に入りに保存して切り替えます")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("add")
+                        .description("いまの声と速さ・高さを保存します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("お気に入りの名前")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("use")
+                        .description("保存した声に切り替えます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("お気に入りの名前")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("remove")
+                        .description("お気に入りを消します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("お気に入りの名前")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("list")
+                        .description("お気に入りの一覧を表示します")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("autojoin")
+                .description("ボイスチャンネルに人が入ったら自動で入ります")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("set")
+                        .description("自動で入るようにします")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Channel)
+                                .required(true)
+                                .name("channel")
+                                .description("読み上げるテキストチャンネル")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::User)
+                                .required(false)
+                                .name("user")
+                                .description("この人が入ったときだけ入るようにします")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("off")
+                        .description("自動で入るのをやめます")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::User)
+                                .required(false)
+                                .name("user")
+                                .description("この人だけ登録から外します")
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("play")
+                .description("url の曲を予約して流します。読み上げの間は音量を下げます")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::String)
+                        .required(true)
+                        .name("url")
+                        .description("曲の url")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("np")
+                .description("いま流れている曲を表示します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("queue")
+                .description("予約されている曲を表示します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("skip_song")
+                .description("いま流れている曲を飛ばします")
+        })
+        .create_application_command(|command| {
+            command
+                .name("stop")
+                .description("音楽を止めて予約を全部消します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("se")
+                .description("効果音を登録して鳴らします")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("add")
+                        .description("url の音声を効果音として登録します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("効果音の名前")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("url")
+                                .description("音声ファイルの url")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("remove")
+                        .description("効果音を削除します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("効果音の名前")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("play")
+                        .description("効果音を鳴らします")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("効果音の名前")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("list")
+                        .description("登録されている効果音を表示します")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("read_filter")
+                .description("読み上げないメッセージを設定します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("add")
+                        .description("読み上げない対象を追加します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("kind")
+                                .description("読み上げない対象")
+                                .add_string_choice("bot", "bots")
+                                .add_string_choice("webhook", "webhooks")
+                                .add_string_choice("ユーザー", "user")
+                                .add_string_choice("ロール", "role")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::User)
+                                .required(false)
+                                .name("user")
+                                .description("kind がユーザーのときの対象")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Role)
+                                .required(false)
+                                .name("role")
+                                .description("kind がロールのときの対象")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("remove")
+                        .description("読み上げない対象を削除します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("kind")
+                                .description("読み上げない対象")
+                                .add_string_choice("bot", "bots")
+                                .add_string_choice("webhook", "webhooks")
+                                .add_string_choice("ユーザー", "user")
+                                .add_string_choice("ロール", "role")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::User)
+                                .required(false)
+                                .name("user")
+                                .description("kind がユーザーのときの対象")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Role)
+                                .required(false)
+                                .name("role")
+                                .description("kind がロールのときの対象")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("list")
+                        .description("読み上げない対象の一覧を表示します")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("dict_pack")
+                .description("同梱の辞書パック (ネットスラング・ゲーム用語など) をこのサーバーで使います")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("enable")
+                        .description("辞書パックを使います")
+                        .create_sub_option(|option| {
+                            let option = option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("辞書パック");
+                            for pack in PACKS.iter() {
+                                option.add_string_choice(pack.description, pack.name);
+                            }
+                            option
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("disable")
+                        .description("辞書パックを外します")
+                        .create_sub_option(|option| {
+                            let option = option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("name")
+                                .description("辞書パック");
+                            for pack in PACKS.iter() {
+                                option.add_string_choice(pack.description, pack.name);
+                            }
+                            option
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("list")
+                        .description("辞書パックの一覧と、このサーバーで使っているかを表示します")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("ngword")
+                .description("読み上げない単語を設定します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("add")
+                        .description("読み上げない単語を追加します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("word")
+                                .description("単語")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(false)
+                                .name("action")
+                                .description("単語を含むメッセージの扱い (初期値: ピー音に置き換える)")
+                                .add_string_choice("ピー音に置き換えて読む", "replace")
+                                .add_string_choice("メッセージごと読まない", "skip")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("remove")
+                        .description("読み上げない単語を削除します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("word")
+                                .description("単語")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("list")
+                        .description("読み上げない単語の一覧を表示します")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("bot_perm")
+                .description("コマンドを使えるロールを設定します")
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("add")
+                        .description("コマンドを使えるロールを追加します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("level")
+                                .description("コマンドの種類")
+                                .add_string_choice("辞書の編集や退出などの管理", "admin")
+                                .add_string_choice("参加や自分の設定などふだん使う", "user")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Role)
+                                .required(true)
+                                .name("role")
+                                .description("ロール")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("remove")
+                        .description("コマンドを使えるロールを削除します")
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::String)
+                                .required(true)
+                                .name("level")
+                                .description("コマンドの種類")
+                                .add_string_choice("辞書の編集や退出などの管理", "admin")
+                                .add_string_choice("参加や自分の設定などふだん使う", "user")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .kind(application_command::ApplicationCommandOptionType::Role)
+                                .required(true)
+                                .name("role")
+                                .description("ロール")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .kind(application_command::ApplicationCommandOptionType::SubCommand)
+                        .name("list")
+                        .description("コマンドを使えるロールの一覧を表示します")
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("reload_speakers")
+                .description("音声合成エンジンから話者の一覧を取り直します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("status")
+                .description("ボイスチャンネルの接続数とバックグラウンドのタスクの状態を表示します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("shard_info")
+                .description("シャードごとの状態・応答速度・サーバー数を表示します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("ping")
+                .description("bot の応答速度を表示します")
+        })
+        .create_application_command(|command| {
+            command
+                .name("changelog")
+                .description("この版の nap-chan の変更点を表示します")
+        })
+        .create_application_command(|command| command.name("help").description("ヘルプです"));
+    localize(commands)
+}
+
+#[test]
+fn registration_test() {
+    assert_eq!(
+        Registration::try_from("global").unwrap(),
+        Registration::Global
+    );
+    assert_eq!(
+        Registration::try_from("guild").unwrap(),
+        Registration::Guild
+    );
+    assert!(Registration::try_from("both").is_err());
+}
+
+#[test]
+fn localize_test() {
+    let mut commands = CreateApplicationCommands::default();
+    define(&mut commands);
+    let mut ja_names = std::collections::HashSet::new();
+    for command in &commands.0 {
+        let name = command["name"].as_str().unwrap();
+        let ja = command["name_localizations"]["ja"].as_str().unwrap();
+        assert_ne!(ja, name, "{} has no japanese name", name);
+        // discord のコマンド名の決まり: 32 文字まで、小文字、空白なし
+        assert!(ja.chars().count() <= 32);
+        assert_eq!(ja.to_lowercase(), ja);
+        assert!(ja
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_'));
+        assert!(ja_names.insert(ja), "{} is used twice", ja);
+    }
+    assert_eq!(ja_names.len(), JA_NAMES.len());
+    assert_eq!(localized_name("join", Language::Japanese), "参加");
+    assert_eq!(localized_name("join", Language::English), "join");
 }
@@ -1,12 +1,45 @@
 use crate::{
-    handler::{get_argument, ArgumentValue, Command, Handler, SlashCommandTextResult},
-    lib::db::{DictDB, UserConfigDB},
+    handler::{
+        get_argument, ArgumentValue, Command, Handler, SlashCommandTextResult, JOIN_MOVE_PREFIX,
+        LEAVE_PREFIX, PANEL_PREFIX,
+    },
+    lib::{
+        changelog,
+        db::{DictDB, GuildConfigDB, GuildNicknameDB, SpeakerDB, UserConfigDB, VoiceType},
+        dict_check::{check_dict_add, DICT_ADD_PREFIX},
+        engine::pool_statuses,
+        engine::{PITCH_RANGE, SPEED_RANGE},
+        legacy::{legacy_dict_path, migrate_legacy_dict, parse_guild_id, reload_legacy_dict},
+        personality::{check_greeting_template, fill, fill_greeting, guild_bundle, Bundle},
+        quiet_hours::{format_utc_offset, parse_utc_offset, QuietHours},
+        retention::{prune, prune_summary},
+        text::clean_display_name,
+        voice_remap::reconcile_voices,
+    },
     Dict,
 };
 use anyhow::{anyhow, Result};
-use serenity::client::Context;
+use serenity::{client::Context, model::id::GuildId};
+use tracing::info;
 
-use super::{meta, util};
+use super::{config, meta, meta::JoinOutcome, util};
+
+// discord のメッセージは 2000 文字まで
+const MESSAGE_LIMIT: usize = 1900;
+
+// 旧辞書を取り込む先のサーバー。bot が入っていないサーバーには取り込まない
+async fn legacy_dict_guild(ctx: &Context, command: &Command) -> Result<(i64, String)> {
+    let guild_id = match get_argument(command, 0)? {
+        ArgumentValue::String(value) => GuildId(parse_guild_id(value)?),
+        _ => return Err(anyhow!("サーバーの ID を指定してね")),
+    };
+    let name = ctx
+        .cache
+        .guild_field(guild_id, |guild| guild.name.clone())
+        .await
+        .ok_or_else(|| anyhow!("そのサーバーには入っていないよ"))?;
+    Ok((guild_id.0 as i64, name))
+}
 
 pub fn get_display_name(command: &Command) -> String {
     command
@@ -19,30 +52,145 @@ pub fn get_display_name(command: &Command) -> String {
         .to_string()
 }
 
+// サーバーの管理権限を持っているか
+pub fn is_admin(command: &Command) -> bool {
+    command
+        .member
+        .as_ref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild())
+}
+
+// bot のオーナーかどうか
+pub async fn is_owner(ctx: &Context, command: &Command) -> bool {
+    ctx.http
+        .get_current_application_info()
+        .await
+        .is_ok_and(|info| info.owner.id == command.user.id)
+}
+
+// /join や操作パネルの参加ボタンの結果を返信にする
+pub fn join_result(outcome: JoinOutcome, bundle: &Bundle) -> SlashCommandTextResult {
+    match outcome {
+        JoinOutcome::Joined => SlashCommandTextResult::from_str(bundle.join),
+        JoinOutcome::ConfirmMove { busy, connect_to } => {
+            SlashCommandTextResult::from_str_and_flags(
+                &format!("{}。こっちに移動してもいい？", busy),
+                false,
+                false,
+            )
+            .with_button(&format!("{}{}", JOIN_MOVE_PREFIX, connect_to), "移動する")
+        }
+        JoinOutcome::Queued { busy, position } => SlashCommandTextResult::from_str_and_flags(
+            &format!("{}。{} 番目に予約したから、空いたら行くね", busy, position),
+            false,
+            false,
+        ),
+    }
+}
+
+// 読み上げが残っていれば、破棄してよいか確認してから抜ける。finish なら読み終わってから抜ける
+pub async fn leave_result(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    finish: bool,
+    bundle: &Bundle,
+) -> Result<SlashCommandTextResult> {
+    let pending = handler.playback.len(guild_id).await;
+    if pending == 0 {
+        meta::leave(ctx, guild_id)
+            .await
+            .map(|_| SlashCommandTextResult::from_str(bundle.leave))
+    } else if finish {
+        meta::leave_after_drain(ctx, handler, guild_id).await?;
+        Ok(SlashCommandTextResult::from_str_and_flags(
+            &format!("残り{}件を読み終わったら抜けるね", pending),
+            false,
+            false,
+        ))
+    } else {
+        Ok(SlashCommandTextResult::from_str_and_flags(
+            &format!("残り{}件の読み上げを破棄します", pending),
+            false,
+            false,
+        )
+        .with_button(&format!("{}confirm", LEAVE_PREFIX), "破棄して抜ける")
+        .with_button(&format!("{}finish", LEAVE_PREFIX), "読み終わってから抜ける")
+        .with_button(&format!("{}cancel", LEAVE_PREFIX), "やめる"))
+    }
+}
+
 pub async fn interaction_create_with_text(
     handler: &Handler,
     command: &Command,
     ctx: &Context,
     command_name: &str,
 ) -> Result<SlashCommandTextResult> {
+    let guild_id = command.guild_id.map(|guild_id| guild_id.0 as i64);
+    let bundle = guild_bundle(&handler.database, guild_id).await;
     match command_name {
-        "join" => meta::join(ctx, command, &handler.read_channel_id)
-            .await
-            .map(|_| SlashCommandTextResult::from_str("おはよ！")),
-        "leave" => meta::leave(ctx, command.guild_id.unwrap())
-            .await
-            .map(|_| SlashCommandTextResult::from_str("ばいばい")),
+        "join" => Ok(join_result(
+            meta::join(ctx, command, handler).await?,
+            bundle,
+        )),
+        "leave" => {
+            let guild_id = command
+                .guild_id
+                .ok_or_else(|| anyhow!("guild id not found"))?;
+            let finish = matches!(get_argument(command, 0), Ok(ArgumentValue::Boolean(true)));
+            leave_result(ctx, handler, guild_id, finish, bundle).await
+        }
+        "panel" => Ok(SlashCommandTextResult::from_str_and_flags(
+            "読み上げの操作パネルだよ。ピン留めしておくと便利だよ",
+            false,
+            false,
+        )
+        .with_button(&format!("{}join", PANEL_PREFIX), "参加")
+        .with_button(&format!("{}leave", PANEL_PREFIX), "退出")
+        .with_button(&format!("{}skip", PANEL_PREFIX), "スキップ")
+        .with_button(&format!("{}mute", PANEL_PREFIX), "ミュート切り替え")),
         "add" => {
             let before = get_argument(command, 0)?;
             let after = get_argument(command, 1)?;
             if let (ArgumentValue::String(before), ArgumentValue::String(after)) = (before, after) {
+                let priority = match get_argument(command, 2) {
+                    Ok(ArgumentValue::Integer(priority)) => *priority,
+                    _ => 0,
+                };
                 let dict = Dict {
                     word: before.to_string(),
                     read_word: after.to_string(),
+                    priority,
                 };
+                // 上書きや置き換えの循環があれば、知らせてからボタンで確かめる
+                let conflicts = check_dict_add(&handler.database.get_dict_all().await?, &dict);
+                if !conflicts.is_empty() {
+                    let msg = conflicts
+                        .iter()
+                        .map(|conflict| conflict.message(&dict))
+                        .chain(std::iter::once("このまま登録する？".to_string()))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    handler
+                        .dict_adds
+                        .insert(command.id, command.user.id, dict)
+                        .await;
+                    return Ok(
+                        SlashCommandTextResult::from_str_and_flags(&msg, false, false)
+                            .with_button(
+                                &format!("{}confirm:{}", DICT_ADD_PREFIX, command.id.0),
+                                "登録する",
+                            )
+                            .with_button(
+                                &format!("{}cancel:{}", DICT_ADD_PREFIX, command.id.0),
+                                "やめる",
+                            ),
+                    );
+                }
                 handler.database.update_dict(&dict).await?;
                 Ok(SlashCommandTextResult::from_str_and_flags(
-                    &format!("これからは、{} を {} って読むね", before, after),
+                    &fill(bundle.add, &[before, after]),
                     true,
                     false,
                 ))
@@ -54,35 +202,78 @@ pub async fn interaction_create_with_text(
             let word = get_argument(command, 0)?;
             if let ArgumentValue::String(word) = word {
                 if (handler.database.remove(word).await).is_ok() {
-                    Ok(SlashCommandTextResult::from_str(&format!(
-                        "これからは {} って読むね",
-                        word
-                    )))
+                    Ok(SlashCommandTextResult::from_str(&fill(bundle.rem, &[word])))
                 } else {
-                    Err(anyhow!("その単語は登録されてないよ！"))
+                    Err(anyhow!(bundle.rem_not_found))
                 }
             } else {
                 unreachable!()
             }
         }
+        "dict_edit" => {
+            let word = get_argument(command, 0)?;
+            let after = get_argument(command, 1)?;
+            if let (ArgumentValue::String(word), ArgumentValue::String(after)) = (word, after) {
+                let priority = match get_argument(command, 2) {
+                    Ok(ArgumentValue::Integer(priority)) => Some(*priority),
+                    _ => None,
+                };
+                let before = handler.database.edit_dict(word, after, priority).await?;
+                info!(
+                    "dict edited by {}: {} {} -> {}",
+                    command.user.id, word, before, after
+                );
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    &format!("{} の読みを {} から {} に変えたよ", word, before, after),
+                    true,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
         "mute" => meta::mute(ctx, command)
             .await
-            .map(|_| SlashCommandTextResult::from_str("ミュートしたよ")),
+            .map(|_| SlashCommandTextResult::from_str(bundle.mute)),
         "unmute" => meta::unmute(ctx, command)
             .await
-            .map(|_| SlashCommandTextResult::from_str("ミュート解除したよ")),
+            .map(|_| SlashCommandTextResult::from_str(bundle.unmute)),
+        "skip" => {
+            let guild_id = command
+                .guild_id
+                .ok_or_else(|| anyhow!("guild id not found"))?;
+            meta::skip(ctx, handler, guild_id).await?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                "読み上げをひとつ飛ばしたよ",
+                false,
+                false,
+            ))
+        }
+        "flush" => {
+            let guild_id = command
+                .guild_id
+                .ok_or_else(|| anyhow!("guild id not found"))?;
+            let count = meta::flush(ctx, handler, guild_id).await?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &format!("{} 件の読み上げを取り消したよ", count),
+                false,
+                false,
+            ))
+        }
         "hello" => {
             let greet = get_argument(command, 0)?;
             if let ArgumentValue::String(greet) = greet {
                 let user_id = command.member.as_ref().unwrap().user.id.0 as i64;
 
-                let mut user_config = handler.database.get_user_config_or_default(user_id).await?;
+                let mut user_config = handler
+                    .database
+                    .get_user_config_or_default(user_id, guild_id)
+                    .await?;
                 user_config.hello = greet.to_string();
                 handler.database.update_user_config(&user_config).await?;
-                Ok(SlashCommandTextResult::from_str(&format!(
-                    "{}さん、これから{}ってあいさつするね",
-                    get_display_name(command),
-                    greet
+                Ok(SlashCommandTextResult::from_str(&fill(
+                    bundle.greet_changed,
+                    &[&get_display_name(command), greet],
                 )))
             } else {
                 unreachable!()
@@ -92,39 +283,191 @@ pub async fn interaction_create_with_text(
             let greet = get_argument(command, 0)?;
             if let ArgumentValue::String(greet) = greet {
                 let user_id = command.member.as_ref().unwrap().user.id.0 as i64;
-                let mut user_config = handler.database.get_user_config_or_default(user_id).await?;
+                let mut user_config = handler
+                    .database
+                    .get_user_config_or_default(user_id, guild_id)
+                    .await?;
                 user_config.bye = greet.to_string();
                 handler.database.update_user_config(&user_config).await?;
-                Ok(SlashCommandTextResult::from_str(&format!(
-                    "{}さん、これから{}ってあいさつするね",
-                    get_display_name(command),
-                    greet
+                Ok(SlashCommandTextResult::from_str(&fill(
+                    bundle.greet_changed,
+                    &[&get_display_name(command), greet],
                 )))
             } else {
                 unreachable!()
             }
         }
+        "set_greeting_template" => {
+            if !is_admin(command) {
+                return Err(anyhow!("サーバーの管理権限が必要だよ"));
+            }
+            let guild_id = guild_id.ok_or_else(|| anyhow!("guild id not found"))?;
+            let mut guild_config = handler
+                .database
+                .get_guild_config_or_default(guild_id)
+                .await?;
+            let msg = match get_argument(command, 0) {
+                Ok(ArgumentValue::String(template)) => {
+                    check_greeting_template(template)?;
+                    guild_config.greeting_template = Some(template.to_string());
+                    format!(
+                        "これからは「{}」ってあいさつするね",
+                        fill_greeting(template, &get_display_name(command), bundle.default_hello)
+                    )
+                }
+                _ => {
+                    guild_config.greeting_template = None;
+                    "あいさつの形を元に戻したよ".to_string()
+                }
+            };
+            handler.database.update_guild_config(&guild_config).await?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
         "set_nickname" => {
             let nickname = get_argument(command, 0)?;
             if let ArgumentValue::String(nickname) = nickname {
                 let user_id = command.member.as_ref().unwrap().user.id.0 as i64;
-                let mut user_config = handler.database.get_user_config_or_default(user_id).await?;
+                let mut user_config = handler
+                    .database
+                    .get_user_config_or_default(user_id, guild_id)
+                    .await?;
                 user_config.read_nickname = Some(nickname.to_string());
                 tracing::info!("{:?}", user_config);
                 handler.database.update_user_config(&user_config).await?;
-                Ok(SlashCommandTextResult::from_str(&format!(
-                    "{}さん、これからは{}って呼ぶね",
-                    get_display_name(command),
-                    nickname
+                Ok(SlashCommandTextResult::from_str(&fill(
+                    bundle.nickname_changed,
+                    &[&get_display_name(command), nickname],
                 )))
             } else {
                 unreachable!()
             }
         }
+        "set_server_nickname" => {
+            let guild_id = guild_id.ok_or_else(|| anyhow!("guild id not found"))?;
+            let user_id = command.user.id.0 as i64;
+            match command
+                .data
+                .options
+                .first()
+                .and_then(|option| option.resolved.as_ref())
+            {
+                Some(ArgumentValue::String(nickname)) => {
+                    handler
+                        .database
+                        .set_guild_nickname(guild_id, user_id, Some(nickname))
+                        .await?;
+                    Ok(SlashCommandTextResult::from_str(&fill(
+                        bundle.nickname_changed,
+                        &[&get_display_name(command), nickname],
+                    )))
+                }
+                _ => {
+                    handler
+                        .database
+                        .set_guild_nickname(guild_id, user_id, None)
+                        .await?;
+                    Ok(SlashCommandTextResult::from_str_and_flags(
+                        "このサーバーだけの呼び方を消したよ",
+                        false,
+                        false,
+                    ))
+                }
+            }
+        }
+        "ignore_me" => {
+            let mode = get_argument(command, 0)?;
+            if let ArgumentValue::String(mode) = mode {
+                let user_id = command.user.id.0 as i64;
+                let mut user_config = handler
+                    .database
+                    .get_user_config_or_default(user_id, guild_id)
+                    .await?;
+                user_config.ignored = mode == "on";
+                handler.database.update_user_config(&user_config).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    if user_config.ignored {
+                        "これからは読み上げないね"
+                    } else {
+                        "これからは読み上げるね"
+                    },
+                    false,
+                    false,
+                ))
+            } else {
+                unreachable!()
+            }
+        }
+        "set_speed" | "set_pitch" => {
+            let value = match get_argument(command, 0)? {
+                ArgumentValue::Number(value) => *value,
+                _ => unreachable!(),
+            };
+            let range = if command_name == "set_speed" {
+                SPEED_RANGE
+            } else {
+                PITCH_RANGE
+            };
+            if !(range.0..=range.1).contains(&value) {
+                return Err(anyhow!("{} から {} で指定してね", range.0, range.1));
+            }
+            let mut user_config = handler
+                .database
+                .get_user_config_or_default(command.user.id.0 as i64, guild_id)
+                .await?;
+            if command_name == "set_speed" {
+                user_config.speed_scale = value;
+            } else {
+                user_config.pitch_scale = value;
+            }
+            handler.database.update_user_config(&user_config).await?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &format!(
+                    "速さ {} 、高さ {} で読むね",
+                    user_config.speed_scale, user_config.pitch_scale
+                ),
+                false,
+                false,
+            ))
+        }
+        "quiet_hours" => {
+            let hours = match get_argument(command, 0)? {
+                ArgumentValue::String(hours) => hours,
+                _ => unreachable!(),
+            };
+            let mut user_config = handler
+                .database
+                .get_user_config_or_default(command.user.id.0 as i64, guild_id)
+                .await?;
+            if let Ok(ArgumentValue::String(offset)) = get_argument(command, 1) {
+                user_config.utc_offset_min = parse_utc_offset(offset)?;
+            }
+            let msg = if hours == "off" {
+                user_config.quiet_start_min = None;
+                user_config.quiet_end_min = None;
+                "いつでもあいさつと名前を読むね".to_string()
+            } else {
+                let quiet_hours = QuietHours::parse(hours)?;
+                user_config.quiet_start_min = Some(quiet_hours.start_min);
+                user_config.quiet_end_min = Some(quiet_hours.end_min);
+                format!(
+                    "{} ({}) は入退出のあいさつとメッセージの前の名前を読まないね",
+                    quiet_hours,
+                    format_utc_offset(user_config.utc_offset_min)
+                )
+            };
+            handler.database.update_user_config(&user_config).await?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
         "rand_member" => util::rand_member(command, ctx).await.map(|member| {
-            SlashCommandTextResult::from_str(&format!(
-                "でけでけでけでけ・・・でん！{}",
-                &member.nick.unwrap_or(member.user.name)
+            SlashCommandTextResult::from_str(&fill(
+                bundle.rand_member,
+                &[&clean_display_name(
+                    member.nick.as_ref().unwrap_or(&member.user.name),
+                )],
             ))
         }),
         "walpha" => {
@@ -138,6 +481,175 @@ pub async fn interaction_create_with_text(
                 unreachable!()
             }
         }
+        "reload_speakers" => {
+            if !is_admin(command) {
+                return Err(anyhow!("サーバーの管理権限が必要だよ"));
+            }
+            let diff = handler.database.reload_speakers().await?;
+            let remapped = reconcile_voices(&handler.database).await?;
+            let voice_names = |voices: &[VoiceType]| {
+                voices
+                    .iter()
+                    .map(|v| format!("{} {} {}", v.generator_type, v.name, v.style_name))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            let mut msg = if diff.added.is_empty() && diff.removed.is_empty() {
+                "話者に変化はなかったよ".to_string()
+            } else {
+                let mut msg = String::new();
+                if !diff.added.is_empty() {
+                    msg += &format!("増えた声:\n{}\n", voice_names(&diff.added));
+                }
+                if !diff.removed.is_empty() {
+                    msg += &format!("なくなった声:\n{}\n", voice_names(&diff.removed));
+                }
+                if remapped > 0 {
+                    msg += &format!("なくなった声を使っていた {} 人の声を戻したよ\n", remapped);
+                }
+                msg
+            };
+            if msg.chars().count() > MESSAGE_LIMIT {
+                msg = msg.chars().take(MESSAGE_LIMIT).collect::<String>() + "\n…";
+            }
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &msg, false, false,
+            ))
+        }
+        "status" => {
+            let statuses = handler.supervisor.statuses().await;
+            let manager = songbird::get(ctx)
+                .await
+                .ok_or_else(|| anyhow!("Songbird Voice client placed in at initialisation."))?;
+            let connections = format!(
+                "ボイス接続: {}{} (満員で断った回数 {} 回)",
+                meta::connection_count(ctx, &manager).await,
+                handler
+                    .hosting
+                    .max_connections
+                    .map_or_else(String::new, |max| format!(" / {}", max)),
+                handler.hosting.refused()
+            );
+            let database = if handler.settings.is_degraded() {
+                "データベース: 読めないので覚えている設定で動いているよ"
+            } else {
+                "データベース: 正常"
+            };
+            let tasks = if statuses.is_empty() {
+                "動いているタスクはないよ".to_string()
+            } else {
+                statuses
+                    .iter()
+                    .map(|(name, status)| {
+                        format!(
+                            "{}: {} (再起動 {} 回{})",
+                            name,
+                            if status.running {
+                                "動作中"
+                            } else {
+                                "再起動待ち"
+                            },
+                            status.restarts,
+                            status
+                                .last_error
+                                .as_ref()
+                                .map_or_else(String::new, |e| format!("、最後のエラー: {}", e))
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            // VOICEVOX のエンジンを複数使っているときだけ、それぞれの状態を出す
+            let pool = pool_statuses();
+            let database = if pool.len() > 1 {
+                let instances = pool
+                    .iter()
+                    .map(|(base_url, alive)| {
+                        format!("{}: {}", base_url, if *alive { "正常" } else { "停止中" })
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("{}\n{}", database, instances)
+            } else {
+                database.to_string()
+            };
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &format!("{}\n{}\n{}", connections, database, tasks),
+                false,
+                false,
+            ))
+        }
+        "shard_info" => Ok(SlashCommandTextResult::from_str_and_flags(
+            &util::shard_info(ctx).await,
+            false,
+            false,
+        )),
+        "config" => config::config(handler, command, ctx).await,
+        "read_filter" => config::read_filter(handler, command).await,
+        "dict_pack" => config::dict_pack(handler, command).await,
+        "ngword" => config::ngword(handler, command).await,
+        "set_name_reading" => config::set_name_reading(handler, command).await,
+        "read_thread" => meta::read_thread(ctx, handler, command).await,
+        "say" => meta::say(ctx, handler, command).await,
+        "bot_perm" => config::bot_perm(handler, command).await,
+        "se" => meta::sound_effect(ctx, handler, command).await,
+        "play" | "np" | "queue" | "skip_song" | "stop" => {
+            meta::music(ctx, handler, command, command_name).await
+        }
+        "autojoin" => config::autojoin(handler, command).await,
+        "my_config" => config::my_config(handler, command).await,
+        "voice_favorite" => config::voice_favorite(handler, command).await,
+        "mydict" => config::mydict(handler, command).await,
+        "changelog" => Ok(SlashCommandTextResult::from_str_and_flags(
+            &changelog::message().unwrap_or_else(|| "この版の変更点は書いていないよ".to_string()),
+            false,
+            false,
+        )),
+        "migrate_legacy_dict" => {
+            if !is_owner(ctx, command).await {
+                return Err(anyhow!("bot のオーナーしか使えないよ"));
+            }
+            let (guild_id, guild_name) = legacy_dict_guild(ctx, command).await?;
+            let count = migrate_legacy_dict(&handler.database, guild_id).await?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &format!(
+                    "{} から {} 件の単語を {} の辞書に取り込んだよ",
+                    legacy_dict_path(),
+                    count,
+                    guild_name
+                ),
+                false,
+                false,
+            ))
+        }
+        "dict_reload" => {
+            if !is_owner(ctx, command).await {
+                return Err(anyhow!("bot のオーナーしか使えないよ"));
+            }
+            let (guild_id, guild_name) = legacy_dict_guild(ctx, command).await?;
+            let count = reload_legacy_dict(&handler.database, guild_id).await?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &format!(
+                    "{} の {} 件の単語を {} の辞書に反映したよ",
+                    legacy_dict_path(),
+                    count,
+                    guild_name
+                ),
+                false,
+                false,
+            ))
+        }
+        "prune" => {
+            if !is_owner(ctx, command).await {
+                return Err(anyhow!("bot のオーナーしか使えないよ"));
+            }
+            let pruned = prune(&handler.database).await?;
+            Ok(SlashCommandTextResult::from_str_and_flags(
+                &prune_summary(&pruned),
+                false,
+                false,
+            ))
+        }
 
         _ => unreachable!(),
     }
@@ -1,22 +1,207 @@
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use crate::TrackEndNotifier;
 use anyhow::{anyhow, Result};
 use serenity::{
+    async_trait,
     client::Context,
+    http::Http,
     model::{
-        id::{ChannelId, GuildId},
+        id::{ChannelId, GuildId, MessageId, UserId},
         interactions::application_command::ApplicationCommandInteraction,
+        prelude::VoiceState,
     },
 };
-use songbird::{Event, TrackEvent};
-use tokio::sync::Mutex;
+use songbird::{
+    events::context_data::DisconnectReason, Call, CoreEvent, Event, EventContext, Songbird,
+};
+use tracing::info;
+
+use crate::{
+    handler::{
+        get_argument, get_sub_argument_by_name, get_subcommand, ArgumentValue, Command, Generators,
+        Handler, SlashCommandTextResult,
+    },
+    lib::{
+        app_config::config,
+        db::{AutojoinDB, GuildConfigDB, SoundEffectDB, SpeakerDB},
+        join_queue::JoinRequest,
+        mixer::MixPolicy,
+        personality::{fill, guild_bundle},
+        read_channel::ReadChannels,
+        search::search_speakers,
+        sound_effect,
+        startup::backoff,
+        text::TextMessage,
+        transcribe::{self, Transcriber},
+        voice::play_announcement,
+    },
+};
+
+use super::interactions::is_admin;
+
+// songbird が自分でつなぎ直すのをあきらめたあと、入り直しを試す回数
+const MAX_REJOIN_ATTEMPTS: u32 = 5;
+
+// 接続が切れている間にキューが流れてしまわないよう一時停止し、つなぎ直したら再開する
+// リージョンの変更や回線の瞬断で切れたときは、同じボイスチャンネルに入り直す
+#[derive(Clone)]
+struct DriverEventNotifier {
+    manager: Arc<Songbird>,
+    guild_id: GuildId,
+    http: Arc<Http>,
+    read_channels: ReadChannels,
+    // 入り直している途中なら true。同じ接続の 3 つの通知で共有する
+    rejoining: Arc<AtomicBool>,
+}
+
+// 切れた理由から、入り直すべきか決める。reason が None なら抜けたか移動したので入り直さない
+fn should_rejoin(reason: Option<DisconnectReason>) -> bool {
+    !matches!(reason, None | Some(DisconnectReason::AttemptDiscarded))
+}
+
+impl DriverEventNotifier {
+    // 待ちながら入り直し、だめなら読み上げチャンネルに知らせて抜ける
+    // songbird が自分でつなぎ直せたら rejoining が false になるので、そこでやめる
+    async fn rejoin(self, channel_id: songbird::id::ChannelId) {
+        let guild_id = self.guild_id;
+        for attempt in 0..MAX_REJOIN_ATTEMPTS {
+            tokio::time::sleep(backoff(attempt)).await;
+            // 待っている間に抜けていたらやめる
+            if !self.rejoining.load(Ordering::SeqCst) || self.manager.get(guild_id).is_none() {
+                return;
+            }
+            match self.manager.join(guild_id, channel_id).await {
+                (_, Ok(())) => {
+                    info!("rejoined {} after {} attempts", guild_id, attempt + 1);
+                    self.rejoining.store(false, Ordering::SeqCst);
+                    return;
+                }
+                (_, Err(e)) => info!("could not rejoin {}: {}", guild_id, e),
+            }
+        }
+        self.rejoining.store(false, Ordering::SeqCst);
+        if let Some(text_channel_id) = self.read_channels.get(guild_id).await {
+            text_channel_id
+                .say(
+                    &self.http,
+                    "ボイスチャンネルとの接続が切れて、つなぎ直せなかったよ。`/join` で呼び直してね",
+                )
+                .await
+                .ok();
+        }
+        self.manager.remove(guild_id).await.ok();
+    }
+}
+
+#[async_trait]
+impl songbird::EventHandler for DriverEventNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let handler_lock = self.manager.get(self.guild_id)?;
+        let handler = handler_lock.lock().await;
+        match ctx {
+            EventContext::DriverDisconnect(data) => {
+                info!("driver disconnected: {:?} {:?}", data.kind, data.reason);
+                handler.queue().pause().ok();
+                if let Some(channel_id) = data.channel_id.filter(|_| should_rejoin(data.reason)) {
+                    if !self.rejoining.swap(true, Ordering::SeqCst) {
+                        tokio::spawn(self.clone().rejoin(channel_id));
+                    }
+                }
+            }
+            EventContext::DriverConnect(data) | EventContext::DriverReconnect(data) => {
+                info!("driver connected: {:?}", data.channel_id);
+                self.rejoining.store(false, Ordering::SeqCst);
+                handler.queue().resume().ok();
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+// bot が入っているボイスチャンネルのリージョンを設定する。権限がなければ何もしない
+pub async fn apply_voice_region(ctx: &Context, guild_id: GuildId, region: Option<String>) {
+    let bot_id = ctx.cache.current_user_id().await;
+    let channel_id = match guild_id
+        .to_guild_cached(&ctx.cache)
+        .await
+        .and_then(|guild| guild.voice_states.get(&bot_id)?.channel_id)
+    {
+        Some(channel_id) => channel_id,
+        None => return,
+    };
+    let current = ctx
+        .cache
+        .guild_channel(channel_id)
+        .await
+        .and_then(|channel| channel.rtc_region);
+    if current == region {
+        return;
+    }
+    if let Err(e) = channel_id
+        .edit(&ctx.http, |channel| channel.voice_region(region))
+        .await
+    {
+        info!("could not set voice region: {}", e);
+    }
+}
+
+// bot が別のボイスチャンネルで使われているときの /join の結果
+pub enum JoinOutcome {
+    Joined,
+    // 移動してよいか確認する
+    ConfirmMove { busy: String, connect_to: ChannelId },
+    // 空いたら入る
+    Queued { busy: String, position: usize },
+}
+
+// そのボイスチャンネルにいる bot 以外の人の名前
+pub async fn channel_member_names(ctx: &Context, channel_id: ChannelId) -> Vec<String> {
+    let members = match ctx.cache.guild_channel(channel_id).await {
+        Some(channel) => channel.members(&ctx.cache).await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+    members
+        .iter()
+        .filter(|member| !member.user.bot)
+        .map(|member| member.display_name().to_string())
+        .collect()
+}
+
+// bot が connect_to 以外のボイスチャンネルで使われていたら、誰が使っているかを返す
+async fn busy_message(ctx: &Context, guild_id: GuildId, connect_to: ChannelId) -> Option<String> {
+    let bot_id = ctx.cache.current_user_id().await;
+    let current = guild_id
+        .to_guild_cached(&ctx.cache)
+        .await?
+        .voice_states
+        .get(&bot_id)?
+        .channel_id?;
+    if current == connect_to {
+        return None;
+    }
+    let names = channel_member_names(ctx, current).await;
+    if names.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "いま <#{}> で {} さんが使ってるよ",
+        current,
+        names.join("さん、")
+    ))
+}
 
 pub async fn join(
     ctx: &Context,
     command: &ApplicationCommandInteraction,
-    read_channel_id: &Arc<Mutex<Option<ChannelId>>>,
-) -> Result<()> {
+    handler: &Handler,
+) -> Result<JoinOutcome> {
     let guild_id = command
         .guild_id
         .ok_or_else(|| anyhow!("guild id not found"))?;
@@ -26,7 +211,17 @@ pub async fn join(
         .ok_or_else(|| anyhow!("member not found"))?
         .user
         .id;
-    let text_channel_id = command.channel_id;
+    join_as(ctx, handler, guild_id, author_id, command.channel_id).await
+}
+
+// author_id の人がいるボイスチャンネルに入る。読み上げるのは text_channel_id
+pub async fn join_as(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    author_id: UserId,
+    text_channel_id: ChannelId,
+) -> Result<JoinOutcome> {
     let channel_id = guild_id
         .to_guild_cached(&ctx.cache)
         .await
@@ -36,19 +231,311 @@ pub async fn join(
         .ok_or_else(|| anyhow!("author not found"))?
         .channel_id
         .ok_or_else(|| anyhow!("channel id not found"))?;
-    let connect_to = channel_id;
+    let text_channel_id = read_channel_for(handler, guild_id, channel_id, text_channel_id).await?;
+    if let Some(busy) = busy_message(ctx, guild_id, channel_id).await {
+        let guild_config = handler
+            .database
+            .get_guild_config_or_default(guild_id.0 as i64)
+            .await?;
+        return match guild_config.join_busy.as_str() {
+            "move" => Ok(JoinOutcome::ConfirmMove {
+                busy,
+                connect_to: channel_id,
+            }),
+            "queue" => {
+                let position = handler
+                    .join_queue
+                    .push(
+                        guild_id,
+                        JoinRequest {
+                            voice_channel_id: channel_id,
+                            text_channel_id,
+                        },
+                    )
+                    .await;
+                Ok(JoinOutcome::Queued { busy, position })
+            }
+            _ => Err(anyhow!("{}", busy)),
+        };
+    }
+    connect(ctx, handler, guild_id, channel_id, text_channel_id).await?;
+    Ok(JoinOutcome::Joined)
+}
+
+// /config read_channel に従って、ボイスチャンネルのチャットか、コマンドを送ったチャンネルを返す
+// ボイスチャンネルのチャットはボイスチャンネルと同じ ID で届く
+pub async fn read_channel_for(
+    handler: &Handler,
+    guild_id: GuildId,
+    voice_channel_id: ChannelId,
+    command_channel_id: ChannelId,
+) -> Result<ChannelId> {
+    let guild_config = handler
+        .database
+        .get_guild_config_or_default(guild_id.0 as i64)
+        .await?;
+    Ok(match guild_config.read_channel.as_str() {
+        "command" => command_channel_id,
+        _ => voice_channel_id,
+    })
+}
+
+// bot がいま入っているボイスチャンネルの数
+pub async fn connection_count(ctx: &Context, manager: &Songbird) -> usize {
+    let mut count = 0;
+    for guild_id in ctx.cache.guilds().await {
+        if is_connected(manager, guild_id).await {
+            count += 1;
+        }
+    }
+    count
+}
+
+async fn is_connected(manager: &Songbird, guild_id: GuildId) -> bool {
+    match manager.get(guild_id) {
+        Some(call) => call.lock().await.current_channel().is_some(),
+        None => false,
+    }
+}
+
+// 接続の通知を登録し直す。transcribe_channel があって stt_url を決めていれば、声を聞いて文字起こしする
+async fn register_events(
+    ctx: &Context,
+    handler: &Handler,
+    manager: &Arc<Songbird>,
+    guild_id: GuildId,
+    handle: &mut Call,
+    transcribe_channel: Option<ChannelId>,
+) -> Result<()> {
+    let transcriber =
+        transcribe_channel
+            .zip(config().stt_url.clone())
+            .map(|(channel_id, stt_url)| {
+                Transcriber::new(
+                    guild_id,
+                    channel_id,
+                    stt_url,
+                    ctx.http.clone(),
+                    ctx.cache.clone(),
+                )
+            });
+    handle.deafen(transcriber.is_none()).await?;
+    // 移動したときに通知が二重に登録されないようにする
+    handle.remove_all_global_events();
+    let rejoining = Arc::new(AtomicBool::new(false));
+    for event in [
+        CoreEvent::DriverConnect,
+        CoreEvent::DriverReconnect,
+        CoreEvent::DriverDisconnect,
+    ] {
+        handle.add_global_event(
+            Event::Core(event),
+            DriverEventNotifier {
+                manager: manager.clone(),
+                guild_id,
+                http: ctx.http.clone(),
+                read_channels: handler.read_channels.clone(),
+                rejoining: rejoining.clone(),
+            },
+        );
+    }
+    if let Some(transcriber) = transcriber {
+        for event in transcribe::EVENTS {
+            handle.add_global_event(Event::Core(event), transcriber.clone());
+        }
+    }
+    Ok(())
+}
+
+// 入っているボイスチャンネルで、文字起こしを始めたりやめたりする。入っていなければ次に入ったときから
+pub async fn apply_transcription(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    channel_id: Option<ChannelId>,
+) -> Result<()> {
+    let manager = match songbird::get(ctx).await {
+        Some(manager) => manager,
+        None => return Ok(()),
+    };
+    let handle_lock = match manager.get(guild_id) {
+        Some(handle_lock) => handle_lock,
+        None => return Ok(()),
+    };
+    let mut handle = handle_lock.lock().await;
+    register_events(ctx, handler, &manager, guild_id, &mut handle, channel_id).await
+}
+
+pub async fn connect(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    connect_to: ChannelId,
+    text_channel_id: ChannelId,
+) -> Result<()> {
     let manager = songbird::get(ctx)
         .await
         .ok_or_else(|| anyhow!("Songbird Voice client placed in at initialisation."))?
         .clone();
+    let already = is_connected(&manager, guild_id).await;
+    handler
+        .hosting
+        .check(guild_id, connection_count(ctx, &manager).await, already)?;
+    let guild_config = handler
+        .database
+        .get_guild_config_or_default(guild_id.0 as i64)
+        .await?;
     let (handle_lock, _) = manager.join(guild_id, connect_to).await;
     let mut handle = handle_lock.lock().await;
-    handle.deafen(true).await?;
-    handle.add_global_event(Event::Track(TrackEvent::End), TrackEndNotifier);
-    *read_channel_id.lock().await = Some(text_channel_id);
+    register_events(
+        ctx,
+        handler,
+        &manager,
+        guild_id,
+        &mut handle,
+        guild_config
+            .transcribe_channel_id
+            .map(|id| ChannelId(id as u64)),
+    )
+    .await?;
+    drop(handle);
+    handler.playback.begin_session(guild_id).await;
+    let previous = handler.read_channels.set(guild_id, text_channel_id).await;
+    handler.activity.touch(guild_id).await;
+    // 入り直したら会話モードの声を配り直す
+    if !already {
+        handler.rotation.reset(guild_id).await;
+        handler.threads.reset(guild_id).await;
+    }
+    // 入ったまま読み上げるチャンネルだけが変わったら、聞いている人にも知らせる
+    if let Some(previous) = previous.filter(|previous| already && *previous != text_channel_id) {
+        announce_read_channel(ctx, handler, guild_id, previous, text_channel_id).await;
+    }
+    if guild_config.voice_region.is_some() {
+        apply_voice_region(ctx, guild_id, guild_config.voice_region).await;
+    }
     Ok(())
 }
 
+// 読み上げるチャンネルが変わったことを、前のチャンネルと声で知らせる
+async fn announce_read_channel(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    previous: ChannelId,
+    text_channel_id: ChannelId,
+) {
+    let bundle = guild_bundle(&handler.database, Some(guild_id.0 as i64)).await;
+    previous
+        .say(
+            &ctx.http,
+            fill(
+                bundle.read_channel_changed,
+                &[&format!("<#{}>", text_channel_id)],
+            ),
+        )
+        .await
+        .ok();
+    let name = text_channel_id
+        .name(&ctx.cache)
+        .await
+        .unwrap_or_else(|| "このチャンネル".to_string());
+    let guild_config = match handler
+        .database
+        .get_guild_config_or_default(guild_id.0 as i64)
+        .await
+    {
+        Ok(guild_config) => guild_config,
+        Err(_) => return,
+    };
+    let text = fill(bundle.read_channel_changed, &[&name])
+        .make_read_text(&handler.database, bundle.language)
+        .await;
+    if let Err(e) = play_announcement(
+        ctx,
+        handler,
+        &text,
+        guild_config.voice_type as u32,
+        guild_config.generator_type as u8,
+        guild_id,
+    )
+    .await
+    {
+        info!("{}", e);
+    }
+}
+
+// 待っている /join のうち、まだ人がいるチャンネルに移る。移ったらその予約を返す
+pub async fn join_next_in_queue(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+) -> Option<JoinRequest> {
+    while let Some(request) = handler.join_queue.pop(guild_id).await {
+        if channel_member_names(ctx, request.voice_channel_id)
+            .await
+            .is_empty()
+        {
+            continue;
+        }
+        match connect(
+            ctx,
+            handler,
+            guild_id,
+            request.voice_channel_id,
+            request.text_channel_id,
+        )
+        .await
+        {
+            Ok(()) => return Some(request),
+            Err(e) => info!("could not join queued channel: {}", e),
+        }
+    }
+    None
+}
+
+// bot がどこにも入っていないときに、自動で入る設定の人がボイスチャンネルに入ったらついていく
+pub async fn autojoin(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    new: &VoiceState,
+) -> Result<bool> {
+    let channel_id = match new.channel_id {
+        Some(channel_id) => channel_id,
+        None => return Ok(false),
+    };
+    if new.member.as_ref().is_some_and(|member| member.user.bot) {
+        return Ok(false);
+    }
+    let bot_id = ctx.cache.current_user_id().await;
+    let bot_in_voice = guild_id
+        .to_guild_cached(&ctx.cache)
+        .await
+        .and_then(|guild| guild.voice_states.get(&bot_id)?.channel_id)
+        .is_some();
+    if bot_in_voice {
+        return Ok(false);
+    }
+    let guild_config = handler
+        .database
+        .get_guild_config_or_default(guild_id.0 as i64)
+        .await?;
+    let text_channel_id = match guild_config.autojoin_channel_id {
+        Some(text_channel_id) => ChannelId(text_channel_id as u64),
+        None => return Ok(false),
+    };
+    let users = handler
+        .database
+        .get_autojoin_users(guild_id.0 as i64)
+        .await?;
+    if !users.is_empty() && !users.contains(&(new.user_id.0 as i64)) {
+        return Ok(false);
+    }
+    connect(ctx, handler, guild_id, channel_id, text_channel_id).await?;
+    Ok(true)
+}
+
 pub async fn leave(ctx: &Context, guild_id: GuildId) -> Result<()> {
     let manager = songbird::get(ctx)
         .await
@@ -64,6 +551,43 @@ pub async fn leave(ctx: &Context, guild_id: GuildId) -> Result<()> {
     }
 }
 
+// 読み終わってから抜けるときに待つ最長の時間。過ぎたら残りを捨てて抜ける
+const LEAVE_DRAIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+// 積んである読み上げを読み終えてから抜ける。待つのは裏で行う
+pub async fn leave_after_drain(ctx: &Context, handler: &Handler, guild_id: GuildId) -> Result<()> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    if manager.get(guild_id).is_none() {
+        return Err(anyhow!("ボイスチャンネルに入ってないよ"));
+    }
+    let (ctx, playback) = (ctx.clone(), handler.playback.clone());
+    let session = playback.session(guild_id).await;
+    tokio::spawn(async move {
+        if !playback.wait_drained(guild_id, LEAVE_DRAIN_TIMEOUT).await {
+            info!(
+                "gave up waiting for the read queue to drain in {}",
+                guild_id
+            );
+        }
+        // 待っている間に /join し直されていたら、新しい接続の読み上げを捨てずにそのまま残る
+        if playback.session(guild_id).await != session {
+            info!("{} rejoined while draining, not leaving", guild_id);
+            return;
+        }
+        if let Some(handler_lock) = manager.get(guild_id) {
+            let call = handler_lock.lock().await;
+            playback.flush(guild_id, call.queue()).await;
+        }
+        if let Err(e) = leave(&ctx, guild_id).await {
+            info!("{}", e);
+        }
+    });
+    Ok(())
+}
+
 pub async fn mute(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
     let guild_id = command
         .guild_id
@@ -104,3 +628,301 @@ pub async fn unmute(ctx: &Context, command: &ApplicationCommandInteraction) -> R
         Ok(())
     }
 }
+
+// ミュートを切り替え、切り替えたあとミュートしているかを返す
+pub async fn toggle_mute(ctx: &Context, guild_id: GuildId) -> Result<bool> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let handler_lock = manager
+        .get(guild_id)
+        .ok_or_else(|| anyhow!("ボイスチャンネルに入ってないよ"))?;
+    let mut handler = handler_lock.lock().await;
+    let mute = !handler.is_mute();
+    handler.mute(mute).await?;
+    Ok(mute)
+}
+
+pub async fn skip(ctx: &Context, handler: &Handler, guild_id: GuildId) -> Result<()> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let handler_lock = manager
+        .get(guild_id)
+        .ok_or_else(|| anyhow!("ボイスチャンネルに入ってないよ"))?;
+    let call = handler_lock.lock().await;
+    handler.playback.skip(guild_id, call.queue()).await
+}
+
+// 消されたメッセージの読み上げを取り消す
+pub async fn cancel_message(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    message_id: MessageId,
+) -> Result<usize> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    // 入っていなければ読んでいるメッセージもない
+    let handler_lock = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock,
+        None => return Ok(0),
+    };
+    let call = handler_lock.lock().await;
+    Ok(handler
+        .playback
+        .cancel_message(guild_id, message_id, call.queue())
+        .await)
+}
+
+pub async fn flush(ctx: &Context, handler: &Handler, guild_id: GuildId) -> Result<usize> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let handler_lock = manager
+        .get(guild_id)
+        .ok_or_else(|| anyhow!("ボイスチャンネルに入ってないよ"))?;
+    let call = handler_lock.lock().await;
+    Ok(handler.playback.flush(guild_id, call.queue()).await)
+}
+
+// /se add|remove|play|list
+// /read_thread を送ったスレッドも読み上げる。もう一度送るとやめる
+pub async fn read_thread(
+    ctx: &Context,
+    handler: &Handler,
+    command: &Command,
+) -> Result<SlashCommandTextResult> {
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?;
+    let manager = songbird::get(ctx)
+        .await
+        .ok_or_else(|| anyhow!("Songbird Voice client placed in at initialisation."))?;
+    if !is_connected(&manager, guild_id).await {
+        return Err(anyhow!("ボイスチャンネルに入ってから使ってね"));
+    }
+    let thread_id = command.channel_id;
+    if handler.threads.parent_of(ctx, thread_id).await.is_none() {
+        return Err(anyhow!("スレッドの中で使ってね"));
+    }
+    let msg = if handler.threads.toggle(guild_id, thread_id).await {
+        thread_id.join_thread(&ctx.http).await?;
+        "このスレッドも読み上げるね"
+    } else {
+        "このスレッドを読み上げるのをやめるね"
+    };
+    Ok(SlashCommandTextResult::from_str_and_flags(
+        msg, false, false,
+    ))
+}
+
+// 話者の id (voice の候補から選んだとき) か名前で声を決めて、返信を読み上げる
+pub async fn say(
+    ctx: &Context,
+    handler: &Handler,
+    command: &Command,
+) -> Result<SlashCommandTextResult> {
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?;
+    let manager = songbird::get(ctx)
+        .await
+        .ok_or_else(|| anyhow!("Songbird Voice client placed in at initialisation."))?;
+    if !is_connected(&manager, guild_id).await {
+        return Err(anyhow!("ボイスチャンネルに入ってから使ってね"));
+    }
+    let text = match get_argument(command, 0)? {
+        ArgumentValue::String(text) => text,
+        _ => return Err(anyhow!("text not found")),
+    };
+    let res = SlashCommandTextResult::from_str(text);
+    let voice = match get_argument(command, 1) {
+        Ok(ArgumentValue::String(voice)) => voice,
+        _ => return Ok(res),
+    };
+    let speaker = match voice.parse() {
+        Ok(id) => handler.database.get_speaker(id).await.ok(),
+        Err(_) => None,
+    };
+    let speaker = match speaker {
+        Some(speaker) => speaker,
+        None => search_speakers(&handler.database.get_all_speakers().await?, voice)
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("{} に合う声は見つからなかったよ", voice))?,
+    };
+    let generator_type = Generators::try_from(speaker.generator_type.as_str())? as u8;
+    Ok(res.with_voice(speaker.style_id as u32, generator_type))
+}
+
+pub async fn sound_effect(
+    ctx: &Context,
+    handler: &Handler,
+    command: &Command,
+) -> Result<SlashCommandTextResult> {
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?;
+    let subcommand = get_subcommand(command)?;
+    let name = match get_sub_argument_by_name(subcommand, "name") {
+        Some(ArgumentValue::String(name)) => name.as_str(),
+        _ => "",
+    };
+    let text = |msg: &str| SlashCommandTextResult::from_str_and_flags(msg, false, false);
+    match subcommand.name.as_str() {
+        "add" => {
+            if !is_admin(command) {
+                return Err(anyhow!("サーバーの管理権限が必要だよ"));
+            }
+            let url = match get_sub_argument_by_name(subcommand, "url") {
+                Some(ArgumentValue::String(url)) => url,
+                _ => unreachable!(),
+            };
+            sound_effect::add(&handler.database, guild_id.0 as i64, name, url).await?;
+            handler
+                .opus_cache
+                .remove(&sound_effect::cache_key(guild_id, name))
+                .await;
+            Ok(text(&format!("効果音 {} を登録したよ", name)))
+        }
+        "remove" => {
+            if !is_admin(command) {
+                return Err(anyhow!("サーバーの管理権限が必要だよ"));
+            }
+            if handler
+                .database
+                .remove_sound_effect(guild_id.0 as i64, name)
+                .await?
+                == 0
+            {
+                return Err(anyhow!("{} という効果音はないよ", name));
+            }
+            handler
+                .opus_cache
+                .remove(&sound_effect::cache_key(guild_id, name))
+                .await;
+            Ok(text(&format!("効果音 {} を消したよ", name)))
+        }
+        "play" => {
+            sound_effect::play(ctx, handler, guild_id, name).await?;
+            Ok(text(&format!("{} を鳴らすよ", name)))
+        }
+        _ => {
+            let sizes = handler
+                .database
+                .get_sound_effect_sizes(guild_id.0 as i64)
+                .await?;
+            if sizes.is_empty() {
+                return Ok(text("効果音は登録されていないよ"));
+            }
+            let used: i64 = sizes.iter().map(|(_, size)| size).sum();
+            Ok(text(&format!(
+                "{}\n使用量: {} KB / {} KB",
+                sizes
+                    .iter()
+                    .map(|(name, size)| format!("{} ({} KB)", name, size / 1024))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                used / 1024,
+                sound_effect::GUILD_QUOTA_BYTES / 1024
+            )))
+        }
+    }
+}
+
+fn format_position(position: Duration) -> String {
+    let secs = position.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+// /play, /np, /queue, /skip_song, /stop
+pub async fn music(
+    ctx: &Context,
+    handler: &Handler,
+    command: &Command,
+    command_name: &str,
+) -> Result<SlashCommandTextResult> {
+    let guild_id = command
+        .guild_id
+        .ok_or_else(|| anyhow!("guild id not found"))?;
+    let text = |msg: &str| SlashCommandTextResult::from_str_and_flags(msg, false, false);
+    match command_name {
+        "play" => {
+            let url = match get_argument(command, 0)? {
+                ArgumentValue::String(url) => url,
+                _ => unreachable!(),
+            };
+            let manager = songbird::get(ctx)
+                .await
+                .expect("Songbird Voice client placed in at initialisation.")
+                .clone();
+            let guild_config = handler
+                .settings
+                .guild_config(&handler.database, guild_id.0 as i64)
+                .await;
+            let position = handler
+                .music
+                .push(
+                    &manager,
+                    guild_id,
+                    url,
+                    MixPolicy::from_guild_config(&guild_config),
+                )
+                .await?;
+            Ok(text(&if position == 0 {
+                format!("{} を流すね", url)
+            } else {
+                format!("{} を {} 番目に予約したよ", url, position)
+            }))
+        }
+        "np" => match handler.music.now_playing(guild_id).await {
+            Some((song, position)) => Ok(text(&format!(
+                "いま流れているのは {}{}\n{}",
+                song.title,
+                position.map_or_else(String::new, |position| format!(
+                    " ({})",
+                    format_position(position)
+                )),
+                song.url
+            ))),
+            None => Ok(text("曲は流れていないよ")),
+        },
+        "queue" => {
+            let queue = handler.music.queue(guild_id).await;
+            if queue.is_empty() {
+                return Ok(text("予約されている曲はないよ"));
+            }
+            Ok(text(
+                &queue
+                    .iter()
+                    .enumerate()
+                    .map(|(i, song)| format!("{}. {}", i + 1, song.title))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ))
+        }
+        "skip_song" => {
+            let song = handler.music.skip(guild_id).await?;
+            Ok(text(&format!("{} を飛ばしたよ", song.title)))
+        }
+        _ => {
+            let count = handler.music.stop(guild_id).await;
+            Ok(text(&format!("音楽を止めて、{} 曲の予約を消したよ", count)))
+        }
+    }
+}
+
+#[test]
+fn should_rejoin_test() {
+    // 抜けたときや移動したときは入り直さない
+    assert!(!should_rejoin(None));
+    assert!(!should_rejoin(Some(DisconnectReason::AttemptDiscarded)));
+    assert!(should_rejoin(Some(DisconnectReason::TimedOut)));
+    assert!(should_rejoin(Some(DisconnectReason::Io)));
+}
@@ -1,3 +1,4 @@
+pub mod config;
 pub mod definition;
 pub mod interactions;
 pub mod meta;
@@ -1,27 +1,89 @@
 use anyhow::{anyhow, Result};
-use serenity::{client::Context, http::Http, model::guild::Member};
-use std::io::Write;
+use serenity::{
+    client::{bridge::gateway::ShardId, Context},
+    http::Http,
+    model::{guild::Member, id::GuildId, interactions::InteractionResponseType},
+};
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
 
-use crate::handler::Command;
+use crate::{
+    commands::definition::localized_name,
+    handler::{Command, Handler},
+    lib::{app_config::config, language::Language, temp::clean_dir, voice::create_voice},
+    ShardManagerContainer,
+};
 
-pub async fn simple_wolfram_alpha(input: &str) -> Result<String> {
-    dotenv::dotenv().ok();
-    let url = "http://api.wolframalpha.com/v2/simple";
-    let app_id = std::env::var("WOLFRAM_ALPHA_APP_ID")?;
+// 同じ問い合わせの結果を置いておく。temp と違って終了しても消さない
+const WOLFRAM_CACHE_DIR: &str = "cache/wolfram";
+// 時刻や天気のように変わる答えもあるので、この時間より古い結果は使わずに消す
+const WOLFRAM_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// 大文字小文字や前後の空白だけ違う問い合わせは同じ結果を使う
+// ファイル名にするので、Rust の版で変わらない FNV-1a で決める
+fn wolfram_cache_key(input: &str) -> String {
+    let hash = input
+        .trim()
+        .to_lowercase()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
+
+fn is_fresh(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified.elapsed().unwrap_or_default() < WOLFRAM_CACHE_TTL)
+}
+
+async fn wolfram_alpha_cached(url: &str, input: &str, extension: &str) -> Result<String> {
+    let path = format!(
+        "{}/{}.{}",
+        WOLFRAM_CACHE_DIR,
+        wolfram_cache_key(input),
+        extension
+    );
+    if is_fresh(Path::new(&path)) {
+        return Ok(path);
+    }
+    let app_id = config()
+        .wolfram_alpha_app_id
+        .clone()
+        .ok_or_else(|| anyhow!("wolfram_alpha_app_id is not configured"))?;
 
     let params = [("i", input), ("appid", &app_id)];
     let client = reqwest::Client::new();
-    let res = client.get(url).query(&params).send().await?;
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)?
-        .as_nanos()
-        .to_string();
-    let path = format!("temp/{}.gif", now);
+    // 答えられなかったときの結果は残さない
+    let res = client
+        .get(url)
+        .query(&params)
+        .send()
+        .await?
+        .error_for_status()?;
+    let bytes = res.bytes().await?;
+    std::fs::create_dir_all(WOLFRAM_CACHE_DIR)?;
+    clean_dir(Path::new(WOLFRAM_CACHE_DIR), WOLFRAM_CACHE_TTL);
     let mut file = std::fs::File::create(&path)?;
-    file.write(&res.bytes().await?).ok();
+    file.write_all(&bytes)?;
     Ok(path)
 }
 
+// 結果の画像のパスを返す
+pub async fn simple_wolfram_alpha(input: &str) -> Result<String> {
+    wolfram_alpha_cached("http://api.wolframalpha.com/v2/simple", input, "gif").await
+}
+
+// 読み上げ用の短い答え
+pub async fn short_wolfram_alpha(input: &str) -> Result<String> {
+    let path = wolfram_alpha_cached("http://api.wolframalpha.com/v1/result", input, "txt").await?;
+    Ok(std::fs::read_to_string(path)?)
+}
+
 pub async fn rand_member(command: &Command, ctx: &Context) -> Result<Member> {
     let guild_id = command
         .guild_id
@@ -44,9 +106,17 @@ pub async fn rand_member(command: &Command, ctx: &Context) -> Result<Member> {
 
 pub async fn help(http: &Http, command: &Command) -> Result<()> {
     let global_commands = http.get_global_application_commands().await?;
+    // 使った人の discord の表示言語に合わせたコマンド名で並べる
+    let language = Language::of_locale(&command.locale);
     let embed_fields = global_commands
         .iter()
-        .map(|global_command| (&global_command.name, &global_command.description, true))
+        .map(|global_command| {
+            (
+                localized_name(&global_command.name, language),
+                &global_command.description,
+                true,
+            )
+        })
         .collect::<Vec<_>>();
 
     command
@@ -57,3 +127,130 @@ pub async fn help(http: &Http, command: &Command) -> Result<()> {
         .await?;
     Ok(())
 }
+
+fn format_latency(latency: Option<Duration>) -> String {
+    latency.map_or_else(|| "-".to_string(), |d| format!("{} ms", d.as_millis()))
+}
+
+// discord がサーバーを割り振るシャード
+fn shard_of(guild_id: GuildId, shard_count: u64) -> u64 {
+    (guild_id.0 >> 22) % shard_count.max(1)
+}
+
+// シャードごとの状態、応答速度、サーバー数
+pub async fn shard_info(ctx: &Context) -> String {
+    let shard_count = ctx.cache.shard_count().await;
+    let mut guild_counts = vec![0; shard_count.max(1) as usize];
+    for guild_id in ctx.cache.guilds().await {
+        guild_counts[shard_of(guild_id, shard_count) as usize] += 1;
+    }
+    let runners = match ctx.data.read().await.get::<ShardManagerContainer>() {
+        Some(shard_manager) => shard_manager
+            .lock()
+            .await
+            .runners
+            .lock()
+            .await
+            .iter()
+            .map(|(id, runner)| (id.0, runner.stage, runner.latency))
+            .collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+    let mut msg = format!("シャード数: {}\n", shard_count);
+    for (id, stage, latency) in runners {
+        msg.push_str(&format!(
+            "{}shard {}: {} / {} / {} サーバー\n",
+            if id == ctx.shard_id { "▶ " } else { "" },
+            id,
+            stage,
+            format_latency(latency),
+            guild_counts.get(id as usize).unwrap_or(&0)
+        ));
+    }
+    msg
+}
+
+pub async fn ping(ctx: &Context, command: &Command, handler: &Handler) -> Result<()> {
+    // 合成まで測ると 3 秒を超えることがあるので、先に返事を待ってもらう
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+        })
+        .await?;
+    let gateway = match ctx.data.read().await.get::<ShardManagerContainer>() {
+        Some(shard_manager) => shard_manager
+            .lock()
+            .await
+            .runners
+            .lock()
+            .await
+            .get(&ShardId(ctx.shard_id))
+            .and_then(|runner| runner.latency),
+        None => None,
+    };
+
+    let now = Instant::now();
+    let rest = ctx
+        .http
+        .get_current_user()
+        .await
+        .ok()
+        .map(|_| now.elapsed());
+
+    let now = Instant::now();
+    let database = sqlx::query("SELECT 1")
+        .execute(&handler.database)
+        .await
+        .ok()
+        .map(|_| now.elapsed());
+
+    let user_config = handler
+        .settings
+        .user_config(
+            &handler.database,
+            command.user.id.0 as i64,
+            command.guild_id.map(|guild_id| guild_id.0 as i64),
+        )
+        .await;
+    let now = Instant::now();
+    let engine = create_voice(
+        "ぴん",
+        user_config.voice_type as u32,
+        user_config.generator_type as u8,
+        user_config.synthesis_params(),
+    )
+    .await
+    .ok()
+    .map(|_| now.elapsed());
+
+    command
+        .edit_original_interaction_response(&ctx.http, |response| {
+            response.create_embed(|emb| {
+                emb.title("ぽん！").fields([
+                    ("gateway", format_latency(gateway), true),
+                    ("REST", format_latency(rest), true),
+                    ("engine", format_latency(engine), true),
+                    ("database", format_latency(database), true),
+                ])
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+#[test]
+fn shard_of_test() {
+    // (guild_id >> 22) % shard_count
+    assert_eq!(shard_of(GuildId(81384788765712384), 1), 0);
+    assert_eq!(shard_of(GuildId(81384788765712384), 16), 2);
+    assert_eq!(shard_of(GuildId(81384788765712384), 0), 0);
+}
+
+#[test]
+fn wolfram_cache_key_test() {
+    assert_eq!(wolfram_cache_key("1+1"), wolfram_cache_key(" 1+1 "));
+    assert_eq!(wolfram_cache_key("Pi"), wolfram_cache_key("pi"));
+    assert_ne!(wolfram_cache_key("1+1"), wolfram_cache_key("1+2"));
+    // ファイル名なので、ビルドし直しても変わらない
+    assert_eq!(wolfram_cache_key("1+1"), "458e3818183c8aec");
+}
@@ -2,42 +2,91 @@ use anyhow::{anyhow, Result};
 
 use serenity::{
     async_trait,
-    builder::CreateSelectMenu,
+    builder::{CreateComponents, CreateInteractionResponseData, CreateSelectMenu},
     client::{Context, EventHandler},
     model::{
-        channel::Message,
-        id::GuildId,
+        channel::{GuildChannel, Message, Reaction},
+        event::MessageUpdateEvent,
+        id::{ChannelId, GuildId, InteractionId, MessageId},
         interactions::{
             application_command::{
-                ApplicationCommandInteraction, ApplicationCommandInteractionDataOptionValue,
+                ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+                ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType,
             },
-            message_component::ComponentType,
-            Interaction, InteractionResponseType,
+            autocomplete::AutocompleteInteraction,
+            message_component::{ButtonStyle, ComponentType, MessageComponentInteraction},
+            Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
         },
         prelude::{Ready, VoiceState},
     },
 };
-use std::{convert::TryInto, sync::Arc};
-use tokio::sync::Mutex;
+use std::{
+    convert::TryInto,
+    time::{Duration, Instant},
+};
 use tracing::info;
 
 use crate::{
     commands::{
         definition,
-        interactions::{get_display_name, interaction_create_with_text},
+        interactions::{
+            get_display_name, interaction_create_with_text, is_admin, join_result, leave_result,
+        },
         meta, util,
     },
     lib::{
-        db::{SpeakerDB, UserConfigDB},
-        text::TextMessage,
-        voice::{play_raw_voice, play_voice},
+        activity::ActivityTracker,
+        batch::SynthesisBatcher,
+        changelog,
+        correction::RecentMessages,
+        db::{
+            DictDB, GuildConfigDB, JoinCountDB, ReadFilter, ReadFilterDB, SetupDB, SpeakerDB,
+            UsageDB, UserConfigDB, VoiceRemapDB, VoiceType, DEFAULT_BYE, DEFAULT_HELLO,
+        },
+        dict_check::{PendingDictAdds, DICT_ADD_PREFIX},
+        follow::MoveTracker,
+        greeting::{
+            fill_variables, has_variables, Greeting, GreetingLimiter, GreetingMode, BATCH_WINDOW,
+        },
+        health::EngineHealth,
+        hosting::{HostingLimits, Quota},
+        join_queue::JoinQueue,
+        language::Language,
+        long_read::{LongReadConfirm, LONG_READ_PREFIX},
+        music::Music,
+        opus_cache::OpusCache,
+        permission::check_permission,
+        personality::{fill, fill_greeting, guild_bundle},
+        playback::Playback,
+        quiet_hours::is_quiet,
+        reaction::{emoji_name, ReactionCounter},
+        read_channel::ReadChannels,
+        read_delay::ReadDelay,
+        rotation::VoiceRotation,
+        search::{rank_speakers, AUTOCOMPLETE_LIMIT, SEARCH_LIMIT},
+        settings::SettingsCache,
+        setup::{self, SetupAction, SetupDraft, SetupStep, SETUP_PREFIX},
+        song_request::{request_songs, request_urls},
+        sound_effect::{self, se_trigger},
+        supervisor::Supervisor,
+        text::{clean_display_name, NameReading, TextMessage, VoiceOverrides},
+        thread::ThreadReader,
+        usage::usage_summary,
+        voice::{play_announcement, play_raw_voice, play_voice},
     },
+    Dict,
 };
 
-#[derive(Clone, Copy, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
 pub enum Generators {
     COEIROINK = 0,
     VOICEVOX = 1,
+    // エンジンを立てずに手元の open_jtalk で読む。ほかのエンジンが落ちたときの予備
+    OPENJTALK = 2,
+}
+impl Generators {
+    pub const ALL: [Generators; 3] = [Self::COEIROINK, Self::VOICEVOX, Self::OPENJTALK];
 }
 impl TryFrom<&str> for Generators {
     type Error = anyhow::Error;
@@ -46,6 +95,7 @@ impl TryFrom<&str> for Generators {
         match value {
             "COEIROINK" => Ok(Self::COEIROINK),
             "VOICEVOX" => Ok(Self::VOICEVOX),
+            "OpenJTalk" => Ok(Self::OPENJTALK),
             _ => Err(anyhow!("no such generator_type")),
         }
     }
@@ -58,26 +108,48 @@ impl TryFrom<u8> for Generators {
         match value {
             0 => Ok(Self::COEIROINK),
             1 => Ok(Self::VOICEVOX),
+            2 => Ok(Self::OPENJTALK),
             _ => Err(anyhow!("no such generator_type")),
         }
     }
 }
 
-impl Into<&str> for Generators {
-    fn into(self) -> &'static str {
-        match self {
-            Self::COEIROINK => "COEIROINK",
-            Self::VOICEVOX => "VOICEVOX",
+impl From<Generators> for &str {
+    fn from(generator: Generators) -> Self {
+        match generator {
+            Generators::COEIROINK => "COEIROINK",
+            Generators::VOICEVOX => "VOICEVOX",
+            Generators::OPENJTALK => "OpenJTalk",
         }
     }
 }
 
 pub struct Handler {
     pub database: sqlx::SqlitePool,
-    pub read_channel_id: Arc<Mutex<Option<serenity::model::id::ChannelId>>>,
+    pub read_channels: ReadChannels,
+    pub engine_health: EngineHealth,
+    pub reaction_counter: ReactionCounter,
+    pub join_queue: JoinQueue,
+    pub supervisor: Supervisor,
+    pub activity: ActivityTracker,
+    pub playback: Playback,
+    pub music: Music,
+    pub greetings: GreetingLimiter,
+    pub moves: MoveTracker,
+    pub rotation: VoiceRotation,
+    pub threads: ThreadReader,
+    pub recent: RecentMessages,
+    pub batches: SynthesisBatcher,
+    pub hosting: HostingLimits,
+    pub long_read: LongReadConfirm,
+    pub dict_adds: PendingDictAdds,
+    pub opus_cache: OpusCache,
+    pub read_delay: ReadDelay,
+    pub settings: SettingsCache,
 }
 pub type Command = ApplicationCommandInteraction;
 pub type ArgumentValue = ApplicationCommandInteractionDataOptionValue;
+pub type ArgumentOption = ApplicationCommandInteractionDataOption;
 #[derive(Clone)]
 pub struct SlashCommandTextResult {
     msg: String,
@@ -85,6 +157,8 @@ pub struct SlashCommandTextResult {
     format: bool,
     voice_type: Option<u32>,
     generator_type: Option<u8>,
+    // (custom_id, label) のボタンを返信につける。最初のボタンを目立たせる
+    buttons: Vec<(String, String)>,
 }
 
 impl SlashCommandTextResult {
@@ -95,6 +169,7 @@ impl SlashCommandTextResult {
             format: true,
             voice_type: None,
             generator_type: None,
+            buttons: Vec::new(),
         }
     }
     pub fn from_str_and_flags(str: &str, read: bool, format: bool) -> Self {
@@ -104,7 +179,41 @@ impl SlashCommandTextResult {
             format,
             voice_type: None,
             generator_type: None,
+            buttons: Vec::new(),
+        }
+    }
+    // 返信を読むときの声を、使った人の声から変える
+    pub fn with_voice(mut self, voice_type: u32, generator_type: u8) -> Self {
+        self.voice_type = Some(voice_type);
+        self.generator_type = Some(generator_type);
+        self
+    }
+    pub fn with_button(mut self, custom_id: &str, label: &str) -> Self {
+        self.buttons
+            .push((custom_id.to_string(), label.to_string()));
+        self
+    }
+    fn add_buttons(&self, message: &mut CreateInteractionResponseData) {
+        if self.buttons.is_empty() {
+            return;
         }
+        message.components(|c| {
+            c.create_action_row(|row| {
+                for (i, (custom_id, label)) in self.buttons.iter().enumerate() {
+                    row.create_button(|button| {
+                        button
+                            .style(if i == 0 {
+                                ButtonStyle::Primary
+                            } else {
+                                ButtonStyle::Secondary
+                            })
+                            .custom_id(custom_id)
+                            .label(label)
+                    });
+                }
+                row
+            })
+        });
     }
 }
 
@@ -118,12 +227,893 @@ pub fn get_argument(command: &Command, index: usize) -> Result<&ArgumentValue> {
         .as_ref()
         .ok_or_else(|| anyhow!("could not parse"))
 }
-impl Handler {}
+
+pub fn get_subcommand(command: &Command) -> Result<&ArgumentOption> {
+    command
+        .data
+        .options
+        .first()
+        .filter(|option| option.kind == ApplicationCommandOptionType::SubCommand)
+        .ok_or_else(|| anyhow!("subcommand not found"))
+}
+
+pub fn get_sub_argument(option: &ArgumentOption, index: usize) -> Result<&ArgumentValue> {
+    option
+        .options
+        .get(index)
+        .ok_or_else(|| anyhow!("index out of range"))?
+        .resolved
+        .as_ref()
+        .ok_or_else(|| anyhow!("could not parse"))
+}
+
+// 入力中の引数の値。サブコマンドの引数も探す
+fn focused_value(options: &[ArgumentOption]) -> Option<&str> {
+    options.iter().find_map(|option| {
+        if option.focused {
+            option.value.as_ref()?.as_str()
+        } else {
+            focused_value(&option.options)
+        }
+    })
+}
+
+pub fn get_sub_argument_by_name<'a>(
+    option: &'a ArgumentOption,
+    name: &str,
+) -> Option<&'a ArgumentValue> {
+    option
+        .options
+        .iter()
+        .find(|option| option.name == name)?
+        .resolved
+        .as_ref()
+}
+
+// /join で別のチャンネルへ移るか確認するボタンの custom_id の接頭辞。後ろに移動先のチャンネル ID をつける
+pub const JOIN_MOVE_PREFIX: &str = "join_move:";
+// /leave で読み上げが残っているときの確認ボタンの custom_id の接頭辞。後ろに confirm, finish, cancel をつける
+pub const LEAVE_PREFIX: &str = "leave:";
+// 操作パネルのボタンの custom_id の接頭辞。後ろに join, leave, skip, mute をつける
+pub const PANEL_PREFIX: &str = "panel:";
+
+// set_guild_default_voice の select menu の custom_id につける接頭辞
+const GUILD_DEFAULT_PREFIX: &str = "guild_default:";
+
+// 試聴した声に決める決定ボタンの custom_id の接頭辞
+const VOICE_CONFIRM_PREFIX: &str = "voice_confirm:";
+
+// 声を選ぶメッセージのページを送るボタンの custom_id の接頭辞
+const VOICE_PAGE_PREFIX: &str = "voice_page:";
+
+// /voice search の結果のボタンの custom_id の接頭辞。後ろに 話者の id:検索語 をつける
+const VOICE_SEARCH_PREFIX: &str = "voice_search:";
+// custom_id は 100 文字まで
+const SEARCH_QUERY_LIMIT: usize = 60;
+const BUTTONS_PER_ROW: usize = 5;
+
+// /dict list のページを送るボタンの custom_id の接頭辞。後ろにページ番号をつける
+const DICT_PAGE_PREFIX: &str = "dict_page:";
+// /dict list で消す単語を選ぶ select menu を出すボタンの custom_id の接頭辞。後ろにページ番号をつける
+const DICT_DELETE_PREFIX: &str = "dict_delete:";
+// 消す単語を選んだ select menu の custom_id の接頭辞。後ろにページ番号をつける
+const DICT_REMOVE_PREFIX: &str = "dict_remove:";
+const DICT_ENTRIES_PER_PAGE: usize = 10;
+// select menu の選択肢の文字数の上限
+const SELECT_OPTION_LIMIT: usize = 100;
+
+impl Handler {
+    // 選んだ声でお試しの一文を読み上げ、決定ボタンを出す
+    async fn preview_voice(&self, ctx: &Context, msg: &MessageComponentInteraction) -> Result<()> {
+        info!("{:?}", msg.data.values);
+        let id: usize = msg
+            .data
+            .values
+            .first()
+            .ok_or_else(|| anyhow!("no value selected"))?
+            .parse()?;
+        let speaker = self.database.get_speaker(id).await?;
+        // select menu の custom_id は [GUILD_DEFAULT_PREFIX]ページ:番号
+        let (prefix, rest) = split_guild_default(&msg.data.custom_id);
+        let page = rest
+            .split(':')
+            .next()
+            .and_then(|page| page.parse().ok())
+            .unwrap_or(0);
+        let select_page = self.voice_select_page(prefix, page).await?;
+        msg.create_interaction_response(&ctx.http, |res| {
+            res.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message
+                        .content(format!(
+                            "{} {} を試しに読むね。この声にするなら決定を押してね",
+                            speaker.name, speaker.style_name
+                        ))
+                        .components(|c| {
+                            select_page.add_components(c, Some(id));
+                            c
+                        })
+                })
+        })
+        .await?;
+        if let Some(guild_id) = msg.guild_id {
+            self.play_sample(ctx, guild_id, &speaker).await?;
+        }
+        Ok(())
+    }
+
+    // 話者を試しに聞くための一文を読み上げる
+    async fn play_sample(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        speaker: &VoiceType,
+    ) -> Result<()> {
+        let generator_type = Generators::try_from(speaker.generator_type.as_str())? as u8;
+        play_raw_voice(
+            ctx,
+            self,
+            &format!("{}、{}の声です", speaker.name, speaker.style_name),
+            speaker.style_id as u32,
+            generator_type,
+            guild_id,
+            &VoiceOverrides::default(),
+        )
+        .await
+    }
+
+    // 操作パネルのボタンが押されたときの処理
+    async fn panel_action(
+        &self,
+        ctx: &Context,
+        msg: &MessageComponentInteraction,
+        action: &str,
+    ) -> Result<SlashCommandTextResult> {
+        let guild_id = msg.guild_id.ok_or_else(|| anyhow!("guild id not found"))?;
+        let bundle = guild_bundle(&self.database, Some(guild_id.0 as i64)).await;
+        match action {
+            "join" => Ok(join_result(
+                meta::join_as(ctx, self, guild_id, msg.user.id, msg.channel_id).await?,
+                bundle,
+            )),
+            "leave" => leave_result(ctx, self, guild_id, false, bundle).await,
+            "skip" => {
+                meta::skip(ctx, self, guild_id).await?;
+                Ok(SlashCommandTextResult::from_str_and_flags(
+                    "読み上げをひとつ飛ばしたよ",
+                    false,
+                    false,
+                ))
+            }
+            "mute" => {
+                let mute = meta::toggle_mute(ctx, guild_id).await?;
+                Ok(SlashCommandTextResult::from_str(if mute {
+                    bundle.mute
+                } else {
+                    bundle.unmute
+                }))
+            }
+            _ => Err(anyhow!("unknown panel action")),
+        }
+    }
+
+    // Wolfram Alpha の短い答えを、コマンドを使った人の声で読み上げる
+    async fn read_wolfram_alpha(
+        &self,
+        ctx: &Context,
+        command: &Command,
+        guild_id: GuildId,
+        input: &str,
+    ) -> Result<()> {
+        let answer = util::short_wolfram_alpha(input).await?;
+        let user_config = self
+            .settings
+            .user_config(
+                &self.database,
+                command.user.id.0 as i64,
+                Some(guild_id.0 as i64),
+            )
+            .await;
+        play_raw_voice(
+            ctx,
+            self,
+            &answer,
+            user_config.voice_type as u32,
+            user_config.generator_type as u8,
+            guild_id,
+            &VoiceOverrides::default(),
+        )
+        .await
+    }
+
+    // 1 日の文字数を使い切っていれば読まない。使い切ったときに一度だけ知らせる
+    async fn within_quota(&self, ctx: &Context, guild_id: GuildId, channel_id: ChannelId) -> bool {
+        match self.hosting.check_quota(&self.database, guild_id).await {
+            Quota::Available => true,
+            Quota::Reached => {
+                channel_id
+                    .say(
+                        &ctx.http,
+                        "今日読み上げられる文字数を使い切ったよ。日付が変わるまで読み上げを止めるね",
+                    )
+                    .await
+                    .ok();
+                false
+            }
+            Quota::Exhausted => false,
+        }
+    }
+
+    // 動いているエンジンの話者から、近い順に limit 人まで検索する
+    async fn search_available_speakers(&self, query: &str, limit: usize) -> Result<Vec<VoiceType>> {
+        let mut speakers = Vec::new();
+        for speaker in self.database.get_all_speakers().await? {
+            if self
+                .engine_health
+                .is_available(Generators::try_from(speaker.generator_type.as_str())?)
+                .await
+            {
+                speakers.push(speaker);
+            }
+        }
+        Ok(rank_speakers(&speakers, query, limit))
+    }
+
+    // 話者の名前を入れる引数に、入力中の名前に合う話者を出す
+    // /say は話者の id を、名前で検索するコマンドは「名前 スタイル名」を値にする
+    async fn autocomplete_voice(
+        &self,
+        ctx: &Context,
+        interaction: &AutocompleteInteraction,
+    ) -> Result<()> {
+        let query = focused_value(&interaction.data.options).unwrap_or_default();
+        let speakers = self
+            .search_available_speakers(query, AUTOCOMPLETE_LIMIT)
+            .await?;
+        let by_id = interaction.data.name == "say";
+        interaction
+            .create_autocomplete_response(&ctx.http, |res| {
+                for speaker in &speakers {
+                    let name = format!("{} {}", speaker.name, speaker.style_name);
+                    if by_id {
+                        res.add_string_choice(name, speaker.id);
+                    } else {
+                        res.add_string_choice(&name, &name);
+                    }
+                }
+                res
+            })
+            .await?;
+        Ok(())
+    }
+
+    // 検索結果の話者のボタンが押されたら、試しに読み上げて決定ボタンを出す。selection は 話者の id:検索語
+    async fn preview_search_result(
+        &self,
+        ctx: &Context,
+        msg: &MessageComponentInteraction,
+        selection: &str,
+    ) -> Result<()> {
+        let (id, query) = selection
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid custom id"))?;
+        let id: usize = id.parse()?;
+        let speaker = self.database.get_speaker(id).await?;
+        let results = self.search_available_speakers(query, SEARCH_LIMIT).await?;
+        msg.create_interaction_response(&ctx.http, |res| {
+            res.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message
+                        .content(format!(
+                            "{} {} を試しに読むね。この声にするなら決定を押してね",
+                            speaker.name, speaker.style_name
+                        ))
+                        .components(|c| {
+                            add_search_result_components(c, &results, query, Some(id));
+                            c
+                        })
+                })
+        })
+        .await?;
+        if let Some(guild_id) = msg.guild_id {
+            self.play_sample(ctx, guild_id, &speaker).await?;
+        }
+        Ok(())
+    }
+
+    // 決定ボタンが押されたら設定を保存する。selection は [GUILD_DEFAULT_PREFIX]話者の id
+    async fn confirm_voice(
+        &self,
+        ctx: &Context,
+        msg: &MessageComponentInteraction,
+        selection: &str,
+    ) -> Result<()> {
+        let (prefix, id) = split_guild_default(selection);
+        let is_guild_default = prefix == GUILD_DEFAULT_PREFIX;
+        let speaker = self.database.get_speaker(id.parse()?).await?;
+        let generator_type = Generators::try_from(speaker.generator_type.as_str())? as i64;
+        let guild_id = msg.guild_id.map(|guild_id| guild_id.0 as i64);
+        if is_guild_default {
+            let mut guild_config = self
+                .database
+                .get_guild_config_or_default(guild_id.ok_or_else(|| anyhow!("guild id not found"))?)
+                .await?;
+            guild_config.generator_type = generator_type;
+            guild_config.voice_type = speaker.style_id;
+            self.database.update_guild_config(&guild_config).await?;
+        } else {
+            let mut user_config = self
+                .database
+                .get_user_config_or_default(msg.user.id.0 as i64, guild_id)
+                .await?;
+            user_config.set_voice(generator_type, speaker.style_id);
+            self.database.update_user_config(&user_config).await?;
+        }
+        msg.create_interaction_response(&ctx.http, |res| {
+            res.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message
+                        .content(format!(
+                            "{} {} に決めたよ",
+                            speaker.name, speaker.style_name
+                        ))
+                        .components(|c| c)
+                })
+        })
+        .await?;
+        Ok(())
+    }
+
+    // 前へ・次へのボタン。selection は [GUILD_DEFAULT_PREFIX]ページ
+    async fn turn_voice_page(
+        &self,
+        ctx: &Context,
+        msg: &MessageComponentInteraction,
+        selection: &str,
+    ) -> Result<()> {
+        let (prefix, page) = split_guild_default(selection);
+        let select_page = self.voice_select_page(prefix, page.parse()?).await?;
+        msg.create_interaction_response(&ctx.http, |res| {
+            res.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message.content(select_page.content()).components(|c| {
+                        select_page.add_components(c, None);
+                        c
+                    })
+                })
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn voice_select_page(
+        &self,
+        custom_id_prefix: &str,
+        page: usize,
+    ) -> Result<VoiceSelectPage> {
+        let speakers = self.database.get_all_speakers().await?;
+        info!("{:?}", &speakers);
+        let mut generators = Vec::new();
+        for generator in Generators::ALL {
+            if self.engine_health.is_available(generator).await {
+                generators.push(Into::<&str>::into(generator));
+            }
+        }
+        let chunks = chunk_speakers(speakers, &generators);
+        let pages = chunks.len().div_ceil(MENUS_PER_PAGE).max(1);
+        let page = page.min(pages - 1);
+        let menus = chunks
+            .iter()
+            .enumerate()
+            .skip(page * MENUS_PER_PAGE)
+            .take(MENUS_PER_PAGE)
+            .map(|(n, chunk)| {
+                CreateSelectMenu::default()
+                    .options(|os| {
+                        for speaker in chunk {
+                            os.create_option(|o| {
+                                o.label(format!("{} {}", speaker.name, speaker.style_name))
+                                    .value(speaker.id)
+                            });
+                        }
+                        os
+                    })
+                    .custom_id(format!("{}{}:{}", custom_id_prefix, page, n))
+                    .clone()
+            })
+            .collect();
+        Ok(VoiceSelectPage {
+            menus,
+            prefix: custom_id_prefix.to_string(),
+            page,
+            pages,
+        })
+    }
+
+    // リアクションがついたメッセージを送った人の、読み上げるときの名前
+    async fn reaction_author_name(
+        &self,
+        ctx: &Context,
+        reaction: &Reaction,
+        guild_id: GuildId,
+        reading: NameReading,
+    ) -> Option<String> {
+        if reading == NameReading::None {
+            return None;
+        }
+        let msg = reaction.message(&ctx.http).await.ok()?;
+        let nick = msg.author_nick(ctx).await;
+        let read_nickname = self
+            .settings
+            .user_config(
+                &self.database,
+                msg.author.id.0 as i64,
+                Some(guild_id.0 as i64),
+            )
+            .await
+            .read_nickname;
+        reading.author_name(read_nickname, nick.as_deref(), &msg.author.name)
+    }
+
+    // /setup の手順 step の文と選択肢
+    async fn setup_page(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        step: SetupStep,
+        draft: &SetupDraft,
+    ) -> Result<(String, CreateComponents)> {
+        let speakers = if step == SetupStep::Voice {
+            self.search_available_speakers("", SELECT_MENU_LIMIT)
+                .await?
+        } else {
+            Vec::new()
+        };
+        let roles = match guild_id.to_guild_cached(&ctx.cache).await {
+            Some(guild) if step == SetupStep::Permission => {
+                // @everyone と bot などが持つロールは選ばせない。上のロールから並べる
+                let mut roles = guild
+                    .roles
+                    .values()
+                    .filter(|role| role.id.0 != guild_id.0 && !role.managed)
+                    .collect::<Vec<_>>();
+                roles.sort_by_key(|role| std::cmp::Reverse(role.position));
+                roles
+                    .into_iter()
+                    .map(|role| (role.id, role.name.clone()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        let voice_name = self.setup_voice_name(draft).await;
+        let mut components = CreateComponents::default();
+        setup::add_components(&mut components, step, draft, &speakers, &roles);
+        Ok((
+            setup::content(step, draft, voice_name.as_deref()),
+            components,
+        ))
+    }
+
+    // /setup で選んだ声の名前
+    async fn setup_voice_name(&self, draft: &SetupDraft) -> Option<String> {
+        let (generator_type, voice_type) = draft.voice?;
+        self.database
+            .speaker_id_to_name(
+                Generators::try_from(generator_type as u8).ok()?,
+                voice_type as u32,
+            )
+            .await
+            .ok()
+    }
+
+    // /setup の select menu やボタンが押されたら次の手順へ進む。保存を押したらまとめて書き込む
+    async fn setup_component(
+        &self,
+        ctx: &Context,
+        msg: &MessageComponentInteraction,
+        action: &str,
+    ) -> Result<()> {
+        let guild_id = msg.guild_id.ok_or_else(|| anyhow!("guild id not found"))?;
+        let is_admin = msg
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| permissions.manage_guild());
+        if !is_admin {
+            msg.create_interaction_response(&ctx.http, |res| {
+                res.interaction_response_data(|message| {
+                    message
+                        .content("サーバーの管理権限が必要だよ")
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+            })
+            .await?;
+            return Ok(());
+        }
+        let (content, components) = match SetupAction::parse(action)? {
+            SetupAction::Select(step, draft) => {
+                let value = msg
+                    .data
+                    .values
+                    .first()
+                    .ok_or_else(|| anyhow!("nothing selected"))?;
+                let draft = draft.select(step, value)?;
+                self.setup_page(ctx, guild_id, step.next(), &draft).await?
+            }
+            SetupAction::Save(draft) => {
+                self.database.apply_setup(guild_id.0 as i64, &draft).await?;
+                let voice_name = self.setup_voice_name(&draft).await;
+                (
+                    format!(
+                        "設定を保存したよ。あとから `/config` などで変えられるよ\n{}",
+                        draft.summary(voice_name.as_deref())
+                    ),
+                    CreateComponents::default(),
+                )
+            }
+            SetupAction::Cancel => (
+                "セットアップをやめたよ".to_string(),
+                CreateComponents::default(),
+            ),
+        };
+        msg.create_interaction_response(&ctx.http, |res| {
+            res.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message.content(content).set_components(components)
+                })
+        })
+        .await?;
+        Ok(())
+    }
+
+    // /dict list のボタンと select menu。ページを送るか、単語を選ばせるか、選んだ単語を消す
+    async fn dict_component(&self, ctx: &Context, msg: &MessageComponentInteraction) -> Result<()> {
+        let custom_id = msg.data.custom_id.as_str();
+        let (page, deleting, note) = if let Some(page) = custom_id.strip_prefix(DICT_PAGE_PREFIX) {
+            (page.parse()?, false, None)
+        } else if let Some(page) = custom_id.strip_prefix(DICT_DELETE_PREFIX) {
+            (page.parse()?, true, None)
+        } else if let Some(page) = custom_id.strip_prefix(DICT_REMOVE_PREFIX) {
+            let word = msg
+                .data
+                .values
+                .first()
+                .ok_or_else(|| anyhow!("no word selected"))?;
+            let bundle = guild_bundle(&self.database, msg.guild_id.map(|id| id.0 as i64)).await;
+            let note = match self.database.remove(word).await {
+                Ok(()) => fill(bundle.rem, &[word]),
+                Err(_) => bundle.rem_not_found.to_string(),
+            };
+            (page.parse()?, false, Some(note))
+        } else {
+            return Err(anyhow!("unknown custom_id: {}", custom_id));
+        };
+        let dict_page = DictPage::new(self.database.get_dict_all().await?, page);
+        msg.create_interaction_response(&ctx.http, |res| {
+            res.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    dict_page.add_to(message, deleting, note.as_deref());
+                    message
+                })
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+// /dict list の 1 ページ分
+struct DictPage {
+    entries: Vec<Dict>,
+    page: usize,
+    pages: usize,
+    total: usize,
+}
+
+impl DictPage {
+    // 辞書は置き換える順に並んでいるものを渡す。page は最後のページまでに丸める
+    fn new(dict: Vec<Dict>, page: usize) -> Self {
+        let total = dict.len();
+        let pages = total.div_ceil(DICT_ENTRIES_PER_PAGE).max(1);
+        let page = page.min(pages - 1);
+        let entries = dict
+            .into_iter()
+            .skip(page * DICT_ENTRIES_PER_PAGE)
+            .take(DICT_ENTRIES_PER_PAGE)
+            .collect();
+        DictPage {
+            entries,
+            page,
+            pages,
+            total,
+        }
+    }
+
+    fn description(&self) -> String {
+        if self.entries.is_empty() {
+            return "辞書にはまだなにも登録されてないよ".to_string();
+        }
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, w)| {
+                format!(
+                    "{}. {} → {} (優先度 {})",
+                    self.page * DICT_ENTRIES_PER_PAGE + i + 1,
+                    w.word,
+                    w.read_word,
+                    w.priority
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // deleting なら、このページの単語から消すものを選ぶ select menu を出す
+    fn add_to(
+        &self,
+        message: &mut CreateInteractionResponseData,
+        deleting: bool,
+        note: Option<&str>,
+    ) {
+        let (page, pages) = (self.page, self.pages);
+        message
+            .content(note.unwrap_or_default())
+            .create_embed(|embed| {
+                embed
+                    .title(format!("辞書 ({}/{})", page + 1, pages))
+                    .description(self.description())
+                    .footer(|footer| {
+                        footer.text(format!("全 {} 件。この順番で置き換えるよ", self.total))
+                    })
+            })
+            .components(|c| {
+                // 選択肢の値は 100 文字までなので、長すぎる単語は /rem で消してもらう
+                let removable = self
+                    .entries
+                    .iter()
+                    .filter(|w| w.word.chars().count() <= SELECT_OPTION_LIMIT)
+                    .collect::<Vec<_>>();
+                if deleting && !removable.is_empty() {
+                    c.create_action_row(|row| {
+                        row.create_select_menu(|menu| {
+                            menu.custom_id(format!("{}{}", DICT_REMOVE_PREFIX, page))
+                                .placeholder("消す単語を選んでね")
+                                .options(|os| {
+                                    for w in removable.iter() {
+                                        os.create_option(|o| {
+                                            o.label(
+                                                format!("{} → {}", w.word, w.read_word)
+                                                    .chars()
+                                                    .take(SELECT_OPTION_LIMIT)
+                                                    .collect::<String>(),
+                                            )
+                                            .value(&w.word)
+                                        });
+                                    }
+                                    os
+                                })
+                        })
+                    });
+                }
+                let previous = (page > 0).then(|| (page - 1, "前へ"));
+                let next = (page + 1 < pages).then(|| (page + 1, "次へ"));
+                let delete = (!deleting && !removable.is_empty()).then_some(page);
+                let back = (deleting && !removable.is_empty()).then_some(page);
+                if previous.is_none() && next.is_none() && delete.is_none() && back.is_none() {
+                    return c;
+                }
+                c.create_action_row(|row| {
+                    for (page, label) in previous.into_iter().chain(next) {
+                        row.create_button(|button| {
+                            button
+                                .style(ButtonStyle::Secondary)
+                                .custom_id(format!("{}{}", DICT_PAGE_PREFIX, page))
+                                .label(label)
+                        });
+                    }
+                    if let Some(page) = delete {
+                        row.create_button(|button| {
+                            button
+                                .style(ButtonStyle::Danger)
+                                .custom_id(format!("{}{}", DICT_DELETE_PREFIX, page))
+                                .label("削除")
+                        });
+                    }
+                    if let Some(page) = back {
+                        row.create_button(|button| {
+                            button
+                                .style(ButtonStyle::Secondary)
+                                .custom_id(format!("{}{}", DICT_PAGE_PREFIX, page))
+                                .label("やめる")
+                        });
+                    }
+                    row
+                })
+            });
+    }
+}
+
+// select menu 1 つに入る選択肢の数
+const SELECT_MENU_LIMIT: usize = 25;
+// 1 ページに並べる select menu の数。残りの 1 行はボタンに使う
+const MENUS_PER_PAGE: usize = 4;
+
+// 話者をエンジンごとに分け、select menu に入る数ずつ区切る
+fn chunk_speakers(speakers: Vec<VoiceType>, generators: &[&str]) -> Vec<Vec<VoiceType>> {
+    generators
+        .iter()
+        .flat_map(|&gen| {
+            speakers
+                .iter()
+                .filter(|x| x.generator_type == gen)
+                .cloned()
+                .collect::<Vec<_>>()
+                .chunks(SELECT_MENU_LIMIT)
+                .map(|chunk| chunk.to_vec())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// 声を選ぶメッセージの 1 ページ分
+struct VoiceSelectPage {
+    menus: Vec<CreateSelectMenu>,
+    prefix: String,
+    page: usize,
+    pages: usize,
+}
+
+impl VoiceSelectPage {
+    fn content(&self) -> String {
+        format!("声を選んでね ({}/{})", self.page + 1, self.pages)
+    }
+
+    // select menu と、前後のページ・決定のボタンを並べる。confirm は試聴中の話者の id
+    fn add_components(self, c: &mut CreateComponents, confirm: Option<usize>) {
+        for menu in self.menus {
+            c.create_action_row(|row| row.add_select_menu(menu));
+        }
+        if self.pages <= 1 && confirm.is_none() {
+            return;
+        }
+        let prefix = self.prefix;
+        let (page, pages) = (self.page, self.pages);
+        c.create_action_row(|row| {
+            if page > 0 {
+                row.create_button(|button| {
+                    button
+                        .style(ButtonStyle::Secondary)
+                        .custom_id(format!("{}{}{}", VOICE_PAGE_PREFIX, prefix, page - 1))
+                        .label("前へ")
+                });
+            }
+            if page + 1 < pages {
+                row.create_button(|button| {
+                    button
+                        .style(ButtonStyle::Secondary)
+                        .custom_id(format!("{}{}{}", VOICE_PAGE_PREFIX, prefix, page + 1))
+                        .label("次へ")
+                });
+            }
+            if let Some(id) = confirm {
+                row.create_button(|button| {
+                    button
+                        .style(ButtonStyle::Primary)
+                        .custom_id(format!("{}{}{}", VOICE_CONFIRM_PREFIX, prefix, id))
+                        .label("決定")
+                });
+            }
+            row
+        });
+    }
+}
+
+// /voice search の結果を 1 つずつボタンにして並べる。custom_id の長さの上限を超えないよう検索語は切り詰める
+fn add_search_result_components(
+    c: &mut CreateComponents,
+    results: &[VoiceType],
+    query: &str,
+    confirm: Option<usize>,
+) {
+    let query = query.chars().take(SEARCH_QUERY_LIMIT).collect::<String>();
+    for row_results in results.chunks(BUTTONS_PER_ROW) {
+        c.create_action_row(|row| {
+            for speaker in row_results {
+                row.create_button(|button| {
+                    button
+                        .style(if confirm == Some(speaker.id as usize) {
+                            ButtonStyle::Success
+                        } else {
+                            ButtonStyle::Secondary
+                        })
+                        .custom_id(format!("{}{}:{}", VOICE_SEARCH_PREFIX, speaker.id, query))
+                        .label(format!("{} {}", speaker.name, speaker.style_name))
+                });
+            }
+            row
+        });
+    }
+    if let Some(id) = confirm {
+        c.create_action_row(|row| {
+            row.create_button(|button| {
+                button
+                    .style(ButtonStyle::Primary)
+                    .custom_id(format!("{}{}", VOICE_CONFIRM_PREFIX, id))
+                    .label("決定")
+            })
+        });
+    }
+}
+
+// どれかの条件に当たるメッセージは読まない。webhook の投稿には member がないのでロールはなし
+fn read_filtered(read_filters: &[ReadFilter], msg: &Message) -> bool {
+    let roles = msg
+        .member
+        .as_ref()
+        .map(|member| member.roles.iter().map(|role| role.0).collect::<Vec<_>>())
+        .unwrap_or_default();
+    read_filters.iter().any(|read_filter| {
+        read_filter.matches(
+            msg.author.id.0,
+            msg.author.bot,
+            msg.webhook_id.is_some(),
+            &roles,
+        )
+    })
+}
+
+// ボタンや select menu を押したときに、使ってよいか調べるコマンドの名前
+// パネルは押したボタンごとに調べ、長文の確認は送った人しか答えられないので None
+fn component_command(custom_id: &str) -> Option<&'static str> {
+    let voice_selection = [VOICE_SEARCH_PREFIX, VOICE_PAGE_PREFIX, VOICE_CONFIRM_PREFIX]
+        .iter()
+        .find_map(|prefix| custom_id.strip_prefix(prefix));
+    if custom_id.starts_with(JOIN_MOVE_PREFIX) {
+        Some("join")
+    } else if custom_id.starts_with(LEAVE_PREFIX) {
+        Some("leave")
+    } else if custom_id.starts_with(PANEL_PREFIX) || custom_id.starts_with(LONG_READ_PREFIX) {
+        None
+    } else if custom_id.starts_with(DICT_ADD_PREFIX) {
+        Some("add")
+    } else if [DICT_PAGE_PREFIX, DICT_DELETE_PREFIX, DICT_REMOVE_PREFIX]
+        .iter()
+        .any(|prefix| custom_id.starts_with(prefix))
+    {
+        Some("dict")
+    } else if custom_id.starts_with(SETUP_PREFIX) {
+        Some("setup")
+    } else if split_guild_default(voice_selection.unwrap_or(custom_id)).0 == GUILD_DEFAULT_PREFIX {
+        Some("set_guild_default_voice")
+    } else if custom_id.starts_with(VOICE_SEARCH_PREFIX) {
+        Some("voice")
+    } else {
+        Some("set_voice_type")
+    }
+}
+
+// custom_id の [GUILD_DEFAULT_PREFIX] を取り除き、(接頭辞, 残り) に分ける
+fn split_guild_default(custom_id: &str) -> (&'static str, &str) {
+    match custom_id.strip_prefix(GUILD_DEFAULT_PREFIX) {
+        Some(rest) => (GUILD_DEFAULT_PREFIX, rest),
+        None => ("", custom_id),
+    }
+}
 
 #[async_trait]
 impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, ready: Ready) {
-        let commands = definition::set_application_commands(&ctx.http).await;
+        let guilds = ready
+            .guilds
+            .iter()
+            .map(|guild| guild.id())
+            .collect::<Vec<_>>();
+        let commands = definition::set_application_commands(
+            &ctx.http,
+            definition::Registration::from_config(),
+            &guilds,
+        )
+        .await;
         match commands {
             Ok(commands) => {
                 for c in commands {
@@ -134,11 +1124,8 @@ impl EventHandler for Handler {
                 tracing::info!("{}", e.to_string())
             }
         }
-        /*let old_global_commands = ctx.http.get_global_application_commands().await.unwrap();
-        for command in old_global_commands {
-            dbg!(command.name);
-            ctx.http.delete_global_application_command(command.id.0).await;
-        }*/
+
+        changelog::announce(&ctx.http, &self.database, &guilds).await;
 
         tracing::info!("{} is connected!", ready.user.name);
     }
@@ -149,7 +1136,26 @@ impl EventHandler for Handler {
         old: Option<VoiceState>,
         new: VoiceState,
     ) {
+        if let Some(guild_id) = guild_id {
+            match meta::autojoin(&ctx, self, guild_id, &new).await {
+                Ok(true) => {
+                    let bundle = guild_bundle(&self.database, Some(guild_id.0 as i64)).await;
+                    if let Some(text_channel_id) = self.read_channels.get(guild_id).await {
+                        text_channel_id.say(&ctx.http, bundle.join).await.ok();
+                    }
+                    return;
+                }
+                Ok(false) => {}
+                Err(e) => info!("autojoin failed: {}", e),
+            }
+        }
         let bot_id = &ctx.cache.current_user_id().await;
+        // 抜けたり切断されたりしたら、そのサーバーの読み上げチャンネルを忘れる
+        if let Some(guild_id) = guild_id {
+            if new.user_id == *bot_id && new.channel_id.is_none() {
+                self.read_channels.remove(guild_id).await;
+            }
+        }
         let _ = async move {
             let bot_channel_id = guild_id?
                 .to_guild_cached(&ctx.cache)
@@ -170,8 +1176,45 @@ impl EventHandler for Handler {
                 .filter(|member| member.user.id.0 != bot_id.0)
                 .count();
 
+            // bot のチャンネルから出た人の行き先を覚えておく
+            if new.user_id != *bot_id
+                && old.as_ref().and_then(|old| old.channel_id) == Some(bot_channel_id)
+                && new.channel_id != Some(bot_channel_id)
+            {
+                self.moves
+                    .record(guild_id?, new.user_id, new.channel_id, Instant::now())
+                    .await;
+            }
+
             if members_count == 0 {
-                meta::leave(&ctx, guild_id?).await.ok();
+                // みんなで別のチャンネルへ移ったなら、同じ読み上げチャンネルのままついていく
+                if let Some(destination) =
+                    self.moves.take_destination(guild_id?, Instant::now()).await
+                {
+                    if let Some(text_channel_id) = self.read_channels.get(guild_id?).await {
+                        if !meta::channel_member_names(&ctx, destination)
+                            .await
+                            .is_empty()
+                        {
+                            match meta::connect(&ctx, self, guild_id?, destination, text_channel_id)
+                                .await
+                            {
+                                Ok(()) => return Some(()),
+                                Err(e) => info!("could not follow members: {}", e),
+                            }
+                        }
+                    }
+                }
+                if let Some(request) = meta::join_next_in_queue(&ctx, self, guild_id?).await {
+                    let bundle = guild_bundle(&self.database, Some(guild_id?.0 as i64)).await;
+                    request
+                        .text_channel_id
+                        .say(&ctx.http, format!("お待たせ！{}", bundle.join))
+                        .await
+                        .ok();
+                } else {
+                    meta::leave(&ctx, guild_id?).await.ok();
+                }
                 return Some(());
             }
 
@@ -209,25 +1252,128 @@ impl EventHandler for Handler {
                 return Some(());
             };
 
+            let guild_config = self
+                .settings
+                .guild_config(&self.database, guild_id?.0 as i64)
+                .await;
+            let mode = GreetingMode::of(&guild_config);
+            if mode == GreetingMode::Off {
+                return Some(());
+            }
+
+            // 入り直しが続くときはあいさつしない
+            if !self
+                .greetings
+                .admit_move(guild_id?, user_id, Instant::now())
+                .await
+            {
+                return Some(());
+            }
+
             let uid = user_id.0 as i64;
-            let user_config = self.database.get_user_config_or_default(uid).await.unwrap();
+            let user_config = self
+                .settings
+                .user_config(&self.database, uid, Some(guild_id?.0 as i64))
+                .await;
+            // 静かな時間帯の人はあいさつしない
+            if is_quiet(&user_config) {
+                return Some(());
+            }
             let nickname = user_config
                 .read_nickname
-                .unwrap_or_else(|| user_name.to_string());
+                .unwrap_or_else(|| clean_display_name(user_name));
+            let bundle = guild_bundle(&self.database, Some(guild_id?.0 as i64)).await;
+            if greeting_type == 0 {
+                if let Err(e) = self.database.record_join(guild_id?.0 as i64, uid).await {
+                    info!("{}", e);
+                }
+            }
             let greet_text = match greeting_type {
-                0 => user_config.hello,
-                1 => user_config.bye,
+                0 if user_config.hello == DEFAULT_HELLO => None,
+                0 => Some(user_config.hello),
+                1 if user_config.bye == DEFAULT_BYE => None,
+                1 => Some(user_config.bye),
                 _ => unreachable!(),
             };
-            let text = format!("{}さん、{}", nickname, greet_text)
-                .make_read_text(&self.database)
-                .await;
-            let voice_type = user_config.voice_type.try_into().unwrap();
-            if let Err(e) = play_raw_voice(
+            // {time} と {count} はあいさつするときに埋める
+            let greet_text = match greet_text {
+                Some(greet_text) if has_variables(&greet_text) => {
+                    match self.database.get_join_count(guild_id?.0 as i64, uid).await {
+                        Ok((count, hour)) => {
+                            Some(fill_variables(&greet_text, hour, count, bundle.language))
+                        }
+                        Err(e) => {
+                            info!("{}", e);
+                            Some(greet_text)
+                        }
+                    }
+                }
+                greet_text => greet_text,
+            };
+            // announce では、あいさつを決めていない人は参加・退出したことだけ知らせる
+            let text = match greet_text {
+                None if mode == GreetingMode::Announce => fill(
+                    if greeting_type == 0 {
+                        bundle.joined
+                    } else {
+                        bundle.left
+                    },
+                    &[&nickname],
+                ),
+                greet_text => {
+                    let greet_text = greet_text.unwrap_or_else(|| {
+                        if greeting_type == 0 {
+                            bundle.default_hello.to_string()
+                        } else {
+                            bundle.default_bye.to_string()
+                        }
+                    });
+                    match &guild_config.greeting_template {
+                        Some(template) => fill_greeting(template, &nickname, &greet_text),
+                        None => fill(bundle.greeting, &[&nickname, &greet_text]),
+                    }
+                }
+            }
+            .make_read_text(&self.database, bundle.language)
+            .await;
+            let greeting = Greeting {
+                text,
+                generator_type: user_config.generator_type.try_into().unwrap(),
+                voice_type: user_config.voice_type.try_into().unwrap(),
+            };
+            // 続けて入ってきた人はまとめて人数だけ読む
+            let greeting = if greeting_type == 0 {
+                if !self.greetings.push_join(guild_id?, greeting).await {
+                    return Some(());
+                }
+                tokio::time::sleep(BATCH_WINDOW).await;
+                let mut batch = self.greetings.take_batch(guild_id?).await;
+                if batch.len() > 1 {
+                    Greeting {
+                        text: fill(bundle.many_joined, &[&batch.len().to_string()]),
+                        generator_type: guild_config.generator_type as u8,
+                        voice_type: guild_config.voice_type as u32,
+                    }
+                } else {
+                    batch.pop()?
+                }
+            } else {
+                greeting
+            };
+            let cooldown = Duration::from_secs(guild_config.greeting_cooldown_sec as u64);
+            if !self
+                .greetings
+                .try_speak(guild_id?, cooldown, Instant::now())
+                .await
+            {
+                return Some(());
+            }
+            if let Err(e) = play_announcement(
                 &ctx,
-                &text,
-                voice_type,
-                user_config.generator_type.try_into().unwrap(),
+                self,
+                &greeting.text,
+                greeting.voice_type,
+                greeting.generator_type,
                 guild_id?,
             )
             .await
@@ -247,11 +1393,112 @@ impl EventHandler for Handler {
             .get(&bot_id)
             .and_then(|voice_states| voice_states.channel_id);
         let text_channel_id = msg.channel_id;
-        let read_channel_id = *self.read_channel_id.lock().await;
+        let read_channel_id = self.read_channels.get(guild.id).await;
         info!("msg = {:?}", &msg);
-        if read_channel_id == Some(text_channel_id) {
+        let reads = match read_channel_id {
+            Some(read_channel_id) if voice_channel_id.is_some() => {
+                let read_threads = self
+                    .settings
+                    .guild_config(&self.database, guild.id.0 as i64)
+                    .await
+                    .read_threads;
+                self.threads
+                    .reads(
+                        &ctx,
+                        guild.id,
+                        text_channel_id,
+                        read_channel_id,
+                        read_threads,
+                    )
+                    .await
+            }
+            _ => false,
+        };
+        // !se 名前 で効果音を鳴らす。読み上げはしない
+        // 読み上げるチャンネルで人が送ったときだけ鳴らし、ほかの bot には鳴らさせない
+        if let Some(name) = msg.content.strip_prefix(se_trigger().as_str()) {
+            if reads && !msg.author.bot {
+                // /se と同じロールで使えるか調べる。キャッシュのメンバーには権限がないので足す
+                let mut member = guild.members.get(&msg.author.id).cloned();
+                if let Some(member) = member.as_mut() {
+                    member.permissions = guild.member_permissions(&ctx, msg.author.id).await.ok();
+                }
+                if let Err(e) =
+                    check_permission(&self.database, Some(guild.id), member.as_ref(), "se").await
+                {
+                    info!("{}: {}", msg.author.id, e);
+                    return;
+                }
+                if let Err(e) = sound_effect::play(&ctx, self, guild.id, name.trim()).await {
+                    info!("{}", e);
+                }
+            }
+            return;
+        }
+        // 曲のリクエストのチャンネルに貼られた URL は読まずに予約する
+        let song_request_channel_id = self
+            .settings
+            .guild_config(&self.database, guild.id.0 as i64)
+            .await
+            .song_request_channel_id;
+        if voice_channel_id.is_some()
+            && !msg.author.bot
+            && song_request_channel_id == Some(text_channel_id.0 as i64)
+            && !request_urls(&msg.content).is_empty()
+        {
+            let mut member = guild.members.get(&msg.author.id).cloned();
+            if let Some(member) = member.as_mut() {
+                member.permissions = guild.member_permissions(&ctx, msg.author.id).await.ok();
+            }
+            if let Err(e) =
+                check_permission(&self.database, Some(guild.id), member.as_ref(), "play").await
+            {
+                info!("{}: {}", msg.author.id, e);
+                return;
+            }
+            if let Err(e) = request_songs(&ctx, self, &guild, &msg).await {
+                info!("{}", e);
+            }
+            return;
+        }
+        if reads {
             if let Some(_voice_channel_id) = voice_channel_id {
-                if msg.author.id != bot_id {
+                let ignored = self
+                    .settings
+                    .user_config(
+                        &self.database,
+                        msg.author.id.0 as i64,
+                        Some(guild.id.0 as i64),
+                    )
+                    .await
+                    .ignored;
+                let filtered = read_filtered(
+                    &self
+                        .database
+                        .get_read_filters(guild.id.0 as i64)
+                        .await
+                        .unwrap_or_default(),
+                    &msg,
+                );
+                if msg.author.id != bot_id
+                    && !ignored
+                    && !filtered
+                    && self.within_quota(&ctx, guild.id, msg.channel_id).await
+                {
+                    let read_delay_ms = self
+                        .settings
+                        .guild_config(&self.database, guild.id.0 as i64)
+                        .await
+                        .read_delay_ms;
+                    if read_delay_ms > 0
+                        && !self
+                            .read_delay
+                            .wait(msg.id, Duration::from_millis(read_delay_ms as u64))
+                            .await
+                    {
+                        return;
+                    }
+                    self.recent.remember(guild.id, &msg).await;
                     if let Err(e) = play_voice(&ctx, msg, self).await {
                         info!("{}", e)
                     };
@@ -260,12 +1507,271 @@ impl EventHandler for Handler {
         }
     }
 
+    async fn message_update(
+        &self,
+        ctx: Context,
+        _old_if_available: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        let _ = async move {
+            // 読むのを待っている間に編集されたら、そのメッセージは読まない
+            // リンクの埋め込みがついただけの更新では edited_timestamp が入らない
+            if event.edited_timestamp.is_some() && self.read_delay.cancel(event.id).await {
+                return None;
+            }
+            let guild_id = event.guild_id?;
+            let content = event.content?;
+            let guild_config = self
+                .settings
+                .guild_config(&self.database, guild_id.0 as i64)
+                .await;
+            if !guild_config.read_edits {
+                return None;
+            }
+            let mut msg = self.recent.edit(guild_id, event.id, &content).await?;
+            // もう抜けていれば読まない
+            let bot_id = ctx.cache.current_user_id().await;
+            guild_id
+                .to_guild_cached(&ctx.cache)
+                .await?
+                .voice_states
+                .get(&bot_id)?
+                .channel_id?;
+            let bundle = guild_bundle(&self.database, Some(guild_id.0 as i64)).await;
+            msg.content = fill(bundle.corrected, &[&msg.content]);
+            // 埋め込みは前に読んだので、訂正では本文だけ読む
+            msg.embeds.clear();
+            if let Err(e) = play_voice(&ctx, msg, self).await {
+                info!("{}", e);
+            }
+            Some(())
+        }
+        .await;
+    }
+
+    // 読み上げる前や読んでいる間にメッセージが消されたら、読むのをやめる
+    async fn message_delete(
+        &self,
+        ctx: Context,
+        _channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        guild_id: Option<GuildId>,
+    ) {
+        if self.read_delay.cancel(deleted_message_id).await {
+            return;
+        }
+        if let Some(guild_id) = guild_id {
+            if let Err(e) = meta::cancel_message(&ctx, self, guild_id, deleted_message_id).await {
+                info!("{}", e);
+            }
+        }
+    }
+
+    async fn thread_create(&self, ctx: Context, thread: GuildChannel) {
+        self.threads
+            .remember_parent(thread.id, thread.category_id)
+            .await;
+        // スレッドには入らないとメッセージが届かないので、読み上げるチャンネルにできたスレッドに入っておく
+        if thread.category_id.is_none()
+            || self.read_channels.get(thread.guild_id).await != thread.category_id
+        {
+            return;
+        }
+        let read_threads = self
+            .settings
+            .guild_config(&self.database, thread.guild_id.0 as i64)
+            .await
+            .read_threads;
+        if read_threads {
+            if let Err(e) = thread.id.join_thread(&ctx.http).await {
+                info!("{}", e);
+            }
+        }
+    }
+
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        let _ = async move {
+            let guild_id = reaction.guild_id?;
+            if self.read_channels.get(guild_id).await != Some(reaction.channel_id) {
+                return None;
+            }
+            let guild_config = self
+                .database
+                .get_guild_config_or_default(guild_id.0 as i64)
+                .await
+                .ok()?;
+            let emoji = emoji_name(&reaction.emoji);
+            let language = Language::of(&guild_config);
+            let mut texts = Vec::new();
+            if let Some(threshold) = guild_config.reaction_threshold {
+                let count = self
+                    .reaction_counter
+                    .add(
+                        reaction.message_id,
+                        &emoji,
+                        threshold as usize,
+                        Duration::from_secs(guild_config.reaction_window as u64),
+                    )
+                    .await;
+                if let Some(count) = count {
+                    texts.push(match language {
+                        Language::Japanese => {
+                            format!("さっきのメッセージに{}が{}件つきました", emoji, count)
+                        }
+                        Language::English => {
+                            format!("{} {} reactions on that message", count, emoji)
+                        }
+                    });
+                }
+            }
+            if let Some(milestone) = guild_config.reaction_milestone {
+                if self
+                    .reaction_counter
+                    .add_total(reaction.message_id, &emoji, milestone as usize)
+                    .await
+                {
+                    // 名前を読まない設定や、メッセージが取れないときは「さっきのメッセージ」と読む
+                    let name = self
+                        .reaction_author_name(
+                            &ctx,
+                            &reaction,
+                            guild_id,
+                            NameReading::of(&guild_config),
+                        )
+                        .await;
+                    texts.push(match (language, name) {
+                        (Language::Japanese, Some(name)) => format!(
+                            "{}さんのメッセージに{}が{}個つきました",
+                            name, emoji, milestone
+                        ),
+                        (Language::Japanese, None) => {
+                            format!("さっきのメッセージに{}が{}個つきました", emoji, milestone)
+                        }
+                        (Language::English, Some(name)) => {
+                            format!("{} {} reactions on {}'s message", milestone, emoji, name)
+                        }
+                        (Language::English, None) => {
+                            format!("{} {} reactions on that message", milestone, emoji)
+                        }
+                    });
+                }
+            }
+            for text in texts {
+                let text = text.make_read_text(&self.database, language).await;
+                if let Err(e) = play_announcement(
+                    &ctx,
+                    self,
+                    &text,
+                    guild_config.voice_type as u32,
+                    guild_config.generator_type as u8,
+                    guild_id,
+                )
+                .await
+                {
+                    info!("{}", e);
+                }
+            }
+            Some(())
+        }
+        .await;
+    }
+
+    async fn reaction_remove(&self, _ctx: Context, reaction: Reaction) {
+        self.reaction_counter
+            .remove_total(reaction.message_id, &emoji_name(&reaction.emoji))
+            .await;
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         if let Interaction::ApplicationCommand(command) = interaction {
+            // /bot_perm で決めたロールを持っていなければ、どのコマンドも実行せずに断る
+            if let Err(e) = check_permission(
+                &self.database,
+                command.guild_id,
+                command.member.as_ref(),
+                &command.data.name,
+            )
+            .await
+            {
+                let res = command
+                    .create_interaction_response(&ctx.http, |response| {
+                        response.interaction_response_data(|message| {
+                            message
+                                .content(e.to_string())
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                    })
+                    .await;
+                info!("{:?}", res);
+                return;
+            }
+            // 起動時に声を戻した人には、次にコマンドを使ったときに知らせる
+            if self
+                .database
+                .take_voice_remap_notice(command.user.id.0 as i64)
+                .await
+                .unwrap_or(false)
+            {
+                let res = command
+                    .channel_id
+                    .say(
+                        &ctx.http,
+                        format!(
+                            "<@{}> 使っていた声がエンジンの更新でなくなったので、デフォルトの声に戻したよ。`/voice` で選び直してね",
+                            command.user.id
+                        ),
+                    )
+                    .await;
+                info!("{:?}", res);
+            }
             match command.data.name.as_str() {
                 // respond instantly with text
-                "add" | "rem" | "hello" | "bye" | "join" | "leave" | "mute" | "unmute"
-                | "rand_member" | "set_nickname" => {
+                "add"
+                | "rem"
+                | "hello"
+                | "bye"
+                | "join"
+                | "leave"
+                | "mute"
+                | "unmute"
+                | "rand_member"
+                | "set_nickname"
+                | "set_server_nickname"
+                | "set_name_reading"
+                | "config"
+                | "dict_edit"
+                | "migrate_legacy_dict"
+                | "dict_reload"
+                | "prune"
+                | "ignore_me"
+                | "set_speed"
+                | "set_pitch"
+                | "quiet_hours"
+                | "reload_speakers"
+                | "status"
+                | "shard_info"
+                | "autojoin"
+                | "my_config"
+                | "voice_favorite"
+                | "mydict"
+                | "skip"
+                | "flush"
+                | "panel"
+                | "se"
+                | "play"
+                | "np"
+                | "queue"
+                | "skip_song"
+                | "stop"
+                | "set_greeting_template"
+                | "read_filter"
+                | "dict_pack"
+                | "ngword"
+                | "read_thread"
+                | "say"
+                | "changelog"
+                | "bot_perm" => {
                     let content =
                         interaction_create_with_text(self, &command, &ctx, &command.data.name)
                             .await;
@@ -274,6 +1780,9 @@ impl EventHandler for Handler {
                             response
                                 .kind(InteractionResponseType::ChannelMessageWithSource)
                                 .interaction_response_data(|message| {
+                                    if let Ok(content) = content.as_ref() {
+                                        content.add_buttons(message);
+                                    }
                                     message.content(match content.as_ref() {
                                         Ok(content) => content.msg.clone(),
                                         Err(error) => error.to_string(),
@@ -286,23 +1795,36 @@ impl EventHandler for Handler {
                     } else if let Ok(content) = content {
                         if content.read {
                             let msg = if content.format {
-                                content.msg.make_read_text(&self.database).await
+                                let language = match command.guild_id {
+                                    Some(guild_id) => Language::of(
+                                        &self
+                                            .settings
+                                            .guild_config(&self.database, guild_id.0 as i64)
+                                            .await,
+                                    ),
+                                    None => Language::Japanese,
+                                };
+                                content.msg.make_read_text(&self.database, language).await
                             } else {
                                 content.msg
                             };
                             let user_id = command.user.id.0;
                             let user_config = self
-                                .database
-                                .get_user_config_or_default(user_id as i64)
-                                .await
-                                .unwrap();
+                                .settings
+                                .user_config(
+                                    &self.database,
+                                    user_id as i64,
+                                    command.guild_id.map(|guild_id| guild_id.0 as i64),
+                                )
+                                .await;
                             let voice_type =
                                 content.voice_type.unwrap_or(user_config.voice_type as u32);
                             let generator_type = content
                                 .generator_type
                                 .unwrap_or(user_config.generator_type as u8);
-                            if let Err(e) = play_raw_voice(
+                            if let Err(e) = play_announcement(
                                 &ctx,
+                                self,
                                 &msg,
                                 voice_type,
                                 generator_type,
@@ -318,18 +1840,21 @@ impl EventHandler for Handler {
                 "info" => {
                     let user_id = command.user.id.0 as i64;
                     let user_config = self
-                        .database
-                        .get_user_config_or_default(user_id)
-                        .await
-                        .unwrap();
-                    let voice_name = self
-                        .database
-                        .speaker_id_to_name(
-                            (user_config.generator_type as u8).try_into().unwrap(),
-                            user_config.voice_type as u32,
+                        .settings
+                        .user_config(
+                            &self.database,
+                            user_id,
+                            command.guild_id.map(|guild_id| guild_id.0 as i64),
                         )
-                        .await
-                        .unwrap();
+                        .await;
+                    let voice_name = match (user_config.generator_type as u8).try_into() {
+                        Ok(generator) => self
+                            .database
+                            .speaker_id_to_name(generator, user_config.voice_type as u32)
+                            .await
+                            .unwrap_or_else(|_| "不明".to_string()),
+                        Err(_) => "不明".to_string(),
+                    };
                     command
                         .create_interaction_response(&ctx.http, |response| {
                             response
@@ -355,41 +1880,103 @@ impl EventHandler for Handler {
                         .await
                         .ok();
                 }
-                "set_voice_type" => {
-                    let speakers = self.database.get_all_speakers().await.unwrap();
-                    info!("{:?}", &speakers);
-                    let generators = ["COEIROINK", "VOICEVOX"];
-                    let menus = generators
-                        .iter()
-                        .filter(|&&gen| speakers.iter().any(|x| x.generator_type == gen))
-                        .map(|&gen| {
-                            CreateSelectMenu::default()
-                                .options(|os| {
-                                    for speaker in
-                                        speakers.iter().filter(|x| x.generator_type == gen)
-                                    {
-                                        os.create_option(|o| {
-                                            o.label(format!(
-                                                "{} {}",
-                                                speaker.name, speaker.style_name
-                                            ))
-                                            .value(speaker.id)
-                                        });
+                "stats" => {
+                    let usage = match command.guild_id {
+                        Some(guild_id) => self.database.get_usage(guild_id.0 as i64).await,
+                        None => Err(anyhow!("サーバーの中で使ってね")),
+                    };
+                    command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|msg| match &usage {
+                                    Ok((today, all_time)) => msg.create_embed(|emb| {
+                                        emb.title("読み上げの統計").fields([
+                                            ("今日", usage_summary(today), false),
+                                            ("これまで", usage_summary(all_time), false),
+                                        ])
+                                    }),
+                                    Err(e) => msg.content(e.to_string()),
+                                })
+                        })
+                        .await
+                        .ok();
+                }
+                "setup" => {
+                    let page = async {
+                        if !is_admin(&command) {
+                            return Err(anyhow!("サーバーの管理権限が必要だよ"));
+                        }
+                        let guild_id = command
+                            .guild_id
+                            .ok_or_else(|| anyhow!("サーバーの中で使ってね"))?;
+                        self.setup_page(
+                            &ctx,
+                            guild_id,
+                            SetupStep::ReadChannel,
+                            &SetupDraft::default(),
+                        )
+                        .await
+                    }
+                    .await;
+                    let e = command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|msg| match page {
+                                    Ok((content, components)) => {
+                                        msg.content(content).set_components(components)
                                     }
-                                    os
+                                    Err(e) => msg.content(e.to_string()),
                                 })
-                                .custom_id(gen)
-                                .clone()
-                        });
+                        })
+                        .await;
+                    if e.is_err() {
+                        info!("{:?}", e);
+                    }
+                    return;
+                }
+                "set_voice_type" | "set_guild_default_voice" => {
+                    let is_guild_default = command.data.name == "set_guild_default_voice";
+                    if is_guild_default && !is_admin(&command) {
+                        command
+                            .create_interaction_response(&ctx.http, |response| {
+                                response
+                                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                                    .interaction_response_data(|msg| {
+                                        msg.content("サーバーの管理権限が必要だよ")
+                                    })
+                            })
+                            .await
+                            .ok();
+                        return;
+                    }
+                    let prefix = if is_guild_default {
+                        GUILD_DEFAULT_PREFIX
+                    } else {
+                        ""
+                    };
+                    let select_page = match self.voice_select_page(prefix, 0).await {
+                        Ok(select_page) => select_page,
+                        Err(e) => {
+                            info!("{}", e);
+                            command
+                                .create_interaction_response(&ctx.http, |response| {
+                                    response
+                                        .interaction_response_data(|msg| msg.content(e.to_string()))
+                                })
+                                .await
+                                .ok();
+                            return;
+                        }
+                    };
                     let e = command
                         .create_interaction_response(&ctx.http, |response| {
                             response
                                 .kind(InteractionResponseType::ChannelMessageWithSource)
                                 .interaction_response_data(|msg| {
-                                    msg.components(|c| {
-                                        for menu in menus {
-                                            c.create_action_row(|row| row.add_select_menu(menu));
-                                        }
+                                    msg.content(select_page.content()).components(|c| {
+                                        select_page.add_components(c, None);
                                         c
                                     })
                                 })
@@ -400,6 +1987,73 @@ impl EventHandler for Handler {
                     }
                     return;
                 }
+                "dict" => {
+                    let dict_page = async {
+                        let subcommand = get_subcommand(&command)?;
+                        let page = match get_sub_argument_by_name(subcommand, "page") {
+                            Some(ArgumentValue::Integer(page)) => (*page).max(1) as usize - 1,
+                            _ => 0,
+                        };
+                        Ok::<_, anyhow::Error>(DictPage::new(
+                            self.database.get_dict_all().await?,
+                            page,
+                        ))
+                    }
+                    .await;
+                    let e = command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|msg| {
+                                    match &dict_page {
+                                        Ok(dict_page) => dict_page.add_to(msg, false, None),
+                                        Err(error) => {
+                                            msg.content(error.to_string());
+                                        }
+                                    }
+                                    msg
+                                })
+                        })
+                        .await;
+                    if e.is_err() {
+                        info!("{:?}", e);
+                    }
+                }
+                "voice" => {
+                    let content = async {
+                        let subcommand = get_subcommand(&command)?;
+                        let query = match get_sub_argument(subcommand, 0)? {
+                            ArgumentValue::String(query) => query.clone(),
+                            _ => unreachable!(),
+                        };
+                        let results = self.search_available_speakers(&query, SEARCH_LIMIT).await?;
+                        Ok::<_, anyhow::Error>((query, results))
+                    }
+                    .await;
+                    let e = command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|msg| match content {
+                                    Ok((query, results)) if !results.is_empty() => msg
+                                        .content(format!(
+                                            "{} で見つかった声だよ。押すと試しに読むね",
+                                            query
+                                        ))
+                                        .components(|c| {
+                                            add_search_result_components(c, &results, &query, None);
+                                            c
+                                        }),
+                                    Ok((query, _)) => msg
+                                        .content(format!("{} に合う声は見つからなかったよ", query)),
+                                    Err(error) => msg.content(error.to_string()),
+                                })
+                        })
+                        .await;
+                    if e.is_err() {
+                        info!("{:?}", e);
+                    }
+                }
                 "walpha" => {
                     let input = get_argument(&command, 0).unwrap();
                     if let ArgumentValue::String(input) = input {
@@ -413,45 +2067,356 @@ impl EventHandler for Handler {
                             .await
                             .ok();
                         if let Ok(file_path) = util::simple_wolfram_alpha(input).await {
+                            let file_name = file_path.rsplit('/').next().unwrap_or_default();
                             let _ = command
                                 .channel_id
-                                .send_files(&ctx.http, vec![file_path.as_str()], |m| m.content(""))
+                                .send_message(&ctx.http, |m| {
+                                    m.add_file(file_path.as_str())
+                                        .embed(|e| e.title(input).attachment(file_name))
+                                })
                                 .await;
                         };
+                        let read =
+                            matches!(get_argument(&command, 1), Ok(ArgumentValue::Boolean(true)));
+                        if let (true, Some(guild_id)) = (read, command.guild_id) {
+                            if let Err(e) = self
+                                .read_wolfram_alpha(&ctx, &command, guild_id, input)
+                                .await
+                            {
+                                info!("{}", e);
+                            }
+                        }
                     }
                 }
                 "help" => {
                     util::help(&ctx.http, &command).await.unwrap();
                 }
+                "ping" => {
+                    if let Err(e) = util::ping(&ctx, &command, self).await {
+                        info!("{}", e);
+                    }
+                }
                 _ => (),
             };
+        } else if let Interaction::Autocomplete(interaction) = interaction {
+            // 候補を出す引数は、どれも話者の名前
+            if let Err(e) = self.autocomplete_voice(&ctx, &interaction).await {
+                info!("{}", e);
+            }
         } else if let Interaction::MessageComponent(msg) = interaction {
-            if let ComponentType::SelectMenu = msg.data.component_type {
-                info!("{:?}", msg.data.values);
-                let id: i64 = msg.data.values[0].parse().unwrap();
-                let q = self.database.get_speaker(id as usize).await.unwrap();
-                let generator_type = q.generator_type;
-                let style_id = q.style_id;
-                let user_id = msg.user.id.0;
-                let mut user_config = self
-                    .database
-                    .get_user_config_or_default(user_id as i64)
-                    .await
-                    .unwrap();
-                user_config.generator_type =
-                    Generators::try_from(generator_type.as_str()).unwrap() as i64;
-                user_config.voice_type = style_id;
-                self.database
-                    .update_user_config(&user_config)
-                    .await
-                    .unwrap();
+            // ほかの人のメッセージのボタンでも、そのコマンドを使えるロールがなければ断る
+            if let Some(name) = component_command(&msg.data.custom_id) {
+                let is_manager = msg
+                    .member
+                    .as_ref()
+                    .and_then(|member| member.permissions)
+                    .is_some_and(|permissions| permissions.manage_guild());
+                let checked = if name == "set_guild_default_voice" && !is_manager {
+                    Err(anyhow!("サーバーの管理権限が必要だよ"))
+                } else {
+                    check_permission(&self.database, msg.guild_id, msg.member.as_ref(), name).await
+                };
+                if let Err(e) = checked {
+                    let res = msg
+                        .create_interaction_response(&ctx.http, |res| {
+                            res.interaction_response_data(|message| {
+                                message.content(e.to_string()).flags(
+                                    InteractionApplicationCommandCallbackDataFlags::EPHEMERAL,
+                                )
+                            })
+                        })
+                        .await;
+                    info!("{:?}", res);
+                    return;
+                }
+            }
+            if let Some(channel_id) = msg.data.custom_id.strip_prefix(JOIN_MOVE_PREFIX) {
+                let content = async {
+                    let guild_id = msg.guild_id.ok_or_else(|| anyhow!("guild id not found"))?;
+                    let channel_id = ChannelId(channel_id.parse()?);
+                    let text_channel_id =
+                        meta::read_channel_for(self, guild_id, channel_id, msg.channel_id).await?;
+                    meta::connect(&ctx, self, guild_id, channel_id, text_channel_id).await?;
+                    Ok::<_, anyhow::Error>(
+                        guild_bundle(&self.database, Some(guild_id.0 as i64))
+                            .await
+                            .join,
+                    )
+                }
+                .await;
+                let res = msg
+                    .create_interaction_response(&ctx.http, |res| {
+                        res.kind(InteractionResponseType::UpdateMessage)
+                            .interaction_response_data(|message| {
+                                message
+                                    .content(match content {
+                                        Ok(content) => content.to_string(),
+                                        Err(error) => error.to_string(),
+                                    })
+                                    .components(|c| c)
+                            })
+                    })
+                    .await;
+                info!("{:?}", res);
+            } else if let Some(choice) = msg.data.custom_id.strip_prefix(LEAVE_PREFIX) {
+                let content = async {
+                    let guild_id = msg.guild_id.ok_or_else(|| anyhow!("guild id not found"))?;
+                    match choice {
+                        "confirm" => {
+                            meta::flush(&ctx, self, guild_id).await?;
+                            meta::leave(&ctx, guild_id).await?;
+                            Ok::<_, anyhow::Error>(
+                                guild_bundle(&self.database, Some(guild_id.0 as i64))
+                                    .await
+                                    .leave
+                                    .to_string(),
+                            )
+                        }
+                        "finish" => {
+                            meta::leave_after_drain(&ctx, self, guild_id).await?;
+                            Ok("読み終わったら抜けるね".to_string())
+                        }
+                        _ => Ok("抜けるのをやめたよ".to_string()),
+                    }
+                }
+                .await;
                 let res = msg
                     .create_interaction_response(&ctx.http, |res| {
                         res.kind(InteractionResponseType::UpdateMessage)
+                            .interaction_response_data(|message| {
+                                message
+                                    .content(content.unwrap_or_else(|e| e.to_string()))
+                                    .components(|c| c)
+                            })
+                    })
+                    .await;
+                info!("{:?}", res);
+            } else if let Some(action) = msg.data.custom_id.strip_prefix(PANEL_PREFIX) {
+                // パネルは残しておきたいので、結果は押した人にだけ見える返信にする
+                let content = match check_permission(
+                    &self.database,
+                    msg.guild_id,
+                    msg.member.as_ref(),
+                    action,
+                )
+                .await
+                {
+                    Ok(()) => self.panel_action(&ctx, &msg, action).await,
+                    Err(e) => Err(e),
+                };
+                let res = msg
+                    .create_interaction_response(&ctx.http, |res| {
+                        res.interaction_response_data(|message| {
+                            match &content {
+                                Ok(content) => {
+                                    content.add_buttons(message);
+                                    message.content(&content.msg)
+                                }
+                                Err(e) => message.content(e.to_string()),
+                            }
+                            .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
                     })
                     .await;
                 info!("{:?}", res);
+            } else if let Some(choice) = msg.data.custom_id.strip_prefix(LONG_READ_PREFIX) {
+                // 確認のメッセージは答えが決まったら消すので、ここでは返事だけする
+                let res = self
+                    .long_read
+                    .resolve(msg.message.id, msg.user.id, choice == "read")
+                    .await;
+                let res = match res {
+                    Ok(()) => {
+                        msg.create_interaction_response(&ctx.http, |res| {
+                            res.kind(InteractionResponseType::DeferredUpdateMessage)
+                        })
+                        .await
+                    }
+                    Err(e) => {
+                        msg.create_interaction_response(&ctx.http, |res| {
+                            res.interaction_response_data(|message| {
+                                message.content(e.to_string()).flags(
+                                    InteractionApplicationCommandCallbackDataFlags::EPHEMERAL,
+                                )
+                            })
+                        })
+                        .await
+                    }
+                };
+                info!("{:?}", res);
+            } else if let Some(choice) = msg.data.custom_id.strip_prefix(DICT_ADD_PREFIX) {
+                let content = async {
+                    let (choice, id) = choice
+                        .split_once(':')
+                        .ok_or_else(|| anyhow!("invalid custom_id"))?;
+                    let entry = self
+                        .dict_adds
+                        .take(InteractionId(id.parse()?), msg.user.id)
+                        .await?;
+                    if choice != "confirm" {
+                        return Ok("登録をやめたよ".to_string());
+                    }
+                    self.database.update_dict(&entry).await?;
+                    Ok::<_, anyhow::Error>(fill(
+                        guild_bundle(&self.database, msg.guild_id.map(|id| id.0 as i64))
+                            .await
+                            .add,
+                        &[&entry.word, &entry.read_word],
+                    ))
+                }
+                .await;
+                let res = match content {
+                    Ok(content) => {
+                        msg.create_interaction_response(&ctx.http, |res| {
+                            res.kind(InteractionResponseType::UpdateMessage)
+                                .interaction_response_data(|message| {
+                                    message.content(content).components(|c| c)
+                                })
+                        })
+                        .await
+                    }
+                    // ほかの人が押したときは、確認のメッセージを残して押した人にだけ返す
+                    Err(e) => {
+                        msg.create_interaction_response(&ctx.http, |res| {
+                            res.interaction_response_data(|message| {
+                                message.content(e.to_string()).flags(
+                                    InteractionApplicationCommandCallbackDataFlags::EPHEMERAL,
+                                )
+                            })
+                        })
+                        .await
+                    }
+                };
+                info!("{:?}", res);
+            } else if [DICT_PAGE_PREFIX, DICT_DELETE_PREFIX, DICT_REMOVE_PREFIX]
+                .iter()
+                .any(|prefix| msg.data.custom_id.starts_with(prefix))
+            {
+                if let Err(e) = self.dict_component(&ctx, &msg).await {
+                    info!("{}", e);
+                }
+            } else if let Some(selection) = msg.data.custom_id.strip_prefix(VOICE_SEARCH_PREFIX) {
+                if let Err(e) = self.preview_search_result(&ctx, &msg, selection).await {
+                    info!("{}", e);
+                }
+            } else if let Some(selection) = msg.data.custom_id.strip_prefix(VOICE_PAGE_PREFIX) {
+                if let Err(e) = self.turn_voice_page(&ctx, &msg, selection).await {
+                    info!("{}", e);
+                }
+            } else if let Some(selection) = msg.data.custom_id.strip_prefix(VOICE_CONFIRM_PREFIX) {
+                if let Err(e) = self.confirm_voice(&ctx, &msg, selection).await {
+                    info!("{}", e);
+                }
+            } else if let Some(action) = msg.data.custom_id.strip_prefix(SETUP_PREFIX) {
+                if let Err(e) = self.setup_component(&ctx, &msg, action).await {
+                    info!("{}", e);
+                }
+            } else if let ComponentType::SelectMenu = msg.data.component_type {
+                if let Err(e) = self.preview_voice(&ctx, &msg).await {
+                    info!("{}", e);
+                }
             }
         }
     }
 }
+
+#[test]
+fn chunk_speakers_test() {
+    let voice = |id, generator_type: &str| VoiceType {
+        id,
+        name: "name".to_string(),
+        style_id: id,
+        style_name: "style".to_string(),
+        generator_type: generator_type.to_string(),
+    };
+    let mut speakers = (0..30).map(|id| voice(id, "VOICEVOX")).collect::<Vec<_>>();
+    speakers.extend((30..33).map(|id| voice(id, "COEIROINK")));
+    let chunks = chunk_speakers(speakers.clone(), &["COEIROINK", "VOICEVOX"]);
+    assert_eq!(
+        chunks.iter().map(|chunk| chunk.len()).collect::<Vec<_>>(),
+        vec![3, 25, 5]
+    );
+    assert_eq!(chunks[0][0].generator_type, "COEIROINK");
+    assert_eq!(chunks[2][4].id, 29);
+
+    // 落ちているエンジンの話者は出さない
+    let chunks = chunk_speakers(speakers, &["COEIROINK"]);
+    assert_eq!(chunks.len(), 1);
+}
+
+#[test]
+fn dict_page_test() {
+    let dict = |n: usize| {
+        (0..n)
+            .map(|i| Dict {
+                word: format!("w{}", i),
+                read_word: format!("r{}", i),
+                priority: 0,
+            })
+            .collect::<Vec<_>>()
+    };
+    let page = DictPage::new(dict(25), 1);
+    assert_eq!((page.page, page.pages, page.total), (1, 3, 25));
+    assert_eq!(page.entries[0].word, "w10");
+    assert!(page.description().starts_with("11. w10 → r10 (優先度 0)"));
+
+    // 最後のページより先は最後のページにする
+    let page = DictPage::new(dict(25), 9);
+    assert_eq!((page.page, page.entries.len()), (2, 5));
+
+    let page = DictPage::new(dict(0), 0);
+    assert_eq!((page.page, page.pages), (0, 1));
+    assert_eq!(page.description(), "辞書にはまだなにも登録されてないよ");
+}
+
+#[test]
+fn component_command_test() {
+    assert_eq!(component_command("leave:confirm"), Some("leave"));
+    assert_eq!(component_command("join_move:1"), Some("join"));
+    assert_eq!(component_command("dict_remove:0"), Some("dict"));
+    assert_eq!(component_command("dict_add:confirm:1"), Some("add"));
+    assert_eq!(component_command("panel:skip"), None);
+    assert_eq!(component_command("0:1"), Some("set_voice_type"));
+    assert_eq!(component_command("voice_page:1"), Some("set_voice_type"));
+    assert_eq!(component_command("voice_search:3:ずんだ"), Some("voice"));
+    assert_eq!(
+        component_command("voice_confirm:guild_default:3"),
+        Some("set_guild_default_voice")
+    );
+    assert_eq!(
+        component_command("guild_default:0:1"),
+        Some("set_guild_default_voice")
+    );
+}
+
+#[test]
+fn read_filtered_test() {
+    // webhook の投稿は bot 扱いで member を持たない
+    let webhook: Message = serde_json::from_value(serde_json::json!({
+        "id": "1",
+        "channel_id": "1",
+        "guild_id": "1",
+        "webhook_id": "3",
+        "author": {"id": "3", "username": "GitHub", "discriminator": "0000", "avatar": null, "bot": true},
+        "attachments": [],
+        "content": "push しました",
+        "edited_timestamp": null,
+        "embeds": [],
+        "mention_everyone": false,
+        "mention_roles": [],
+        "mentions": [],
+        "pinned": false,
+        "timestamp": "2022-01-01T00:00:00+00:00",
+        "tts": false,
+        "type": 0,
+    }))
+    .unwrap();
+    let filter = |kind: &str| ReadFilter {
+        guild_id: 1,
+        kind: kind.to_string(),
+        target_id: 0,
+    };
+    assert!(!read_filtered(&[], &webhook));
+    assert!(!read_filtered(&[filter("bots")], &webhook));
+    assert!(read_filtered(&[filter("webhooks")], &webhook));
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::{
+    http::Http,
+    model::id::{GuildId, UserId},
+};
+use songbird::Songbird;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use super::{db::GuildConfigDB, personality::guild_bundle, read_channel::ReadChannels};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// 同じ人のメッセージでも、これより間があいたら名前を読み直す
+const NAME_REPEAT_AFTER: Duration = Duration::from_secs(120);
+
+// サーバーごとに最後に読み上げた時刻を覚えておく
+#[derive(Clone, Default)]
+pub struct ActivityTracker {
+    last: Arc<Mutex<HashMap<GuildId, Instant>>>,
+    // サーバーごとに最後にメッセージを読んだ人とその時刻
+    last_author: Arc<Mutex<HashMap<GuildId, (UserId, Instant)>>>,
+}
+
+impl ActivityTracker {
+    pub async fn touch(&self, guild_id: GuildId) {
+        self.last.lock().await.insert(guild_id, Instant::now());
+    }
+
+    pub async fn forget(&self, guild_id: GuildId) {
+        self.last.lock().await.remove(&guild_id);
+        self.last_author.lock().await.remove(&guild_id);
+    }
+
+    // メッセージを読んだ人を記録し、直前のメッセージも同じ人だったかを返す
+    pub async fn is_consecutive(&self, guild_id: GuildId, author: UserId) -> bool {
+        self.is_consecutive_at(guild_id, author, Instant::now())
+            .await
+    }
+
+    async fn is_consecutive_at(&self, guild_id: GuildId, author: UserId, now: Instant) -> bool {
+        let prev = self
+            .last_author
+            .lock()
+            .await
+            .insert(guild_id, (author, now));
+        prev.is_some_and(|(prev_author, at)| {
+            prev_author == author && now.saturating_duration_since(at) < NAME_REPEAT_AFTER
+        })
+    }
+
+    // 各サーバーで最後に読み上げてから経った時間
+    pub async fn idle_durations(&self, now: Instant) -> Vec<(GuildId, Duration)> {
+        self.last
+            .lock()
+            .await
+            .iter()
+            .map(|(guild_id, last)| (*guild_id, now.saturating_duration_since(*last)))
+            .collect()
+    }
+}
+
+// 設定した時間読み上げがなかったサーバーのボイスチャンネルから抜ける。Supervisor から動かす
+pub async fn run_idle_leave(
+    database: sqlx::SqlitePool,
+    activity: ActivityTracker,
+    manager: Arc<Songbird>,
+    http: Arc<Http>,
+    read_channels: ReadChannels,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        for (guild_id, idle) in activity.idle_durations(Instant::now()).await {
+            if manager.get(guild_id).is_none() {
+                activity.forget(guild_id).await;
+                continue;
+            }
+            let timeout = match database
+                .get_guild_config_or_default(guild_id.0 as i64)
+                .await
+                .ok()
+                .and_then(|guild_config| guild_config.idle_timeout_min)
+            {
+                Some(timeout) => timeout,
+                None => continue,
+            };
+            if idle < Duration::from_secs(timeout as u64 * 60) {
+                continue;
+            }
+            info!("leaving {} after {:?} of inactivity", guild_id, idle);
+            let bundle = guild_bundle(&database, Some(guild_id.0 as i64)).await;
+            if let Some(channel_id) = read_channels.remove(guild_id).await {
+                channel_id
+                    .say(
+                        &http,
+                        format!(
+                            "{} 分読み上げがなかったから抜けるね。{}",
+                            timeout, bundle.leave
+                        ),
+                    )
+                    .await
+                    .ok();
+            }
+            manager.remove(guild_id).await.ok();
+            activity.forget(guild_id).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn idle_durations_test() {
+    let activity = ActivityTracker::default();
+    activity.touch(GuildId(1)).await;
+    let later = Instant::now() + Duration::from_secs(90);
+    let idle = activity.idle_durations(later).await;
+    assert_eq!(idle.len(), 1);
+    assert_eq!(idle[0].0, GuildId(1));
+    assert!(idle[0].1 >= Duration::from_secs(90));
+
+    activity.forget(GuildId(1)).await;
+    assert!(activity.idle_durations(later).await.is_empty());
+}
+
+#[tokio::test]
+async fn is_consecutive_test() {
+    let activity = ActivityTracker::default();
+    let guild_id = GuildId(1);
+    let now = Instant::now();
+    assert!(!activity.is_consecutive_at(guild_id, UserId(1), now).await);
+    assert!(activity.is_consecutive_at(guild_id, UserId(1), now).await);
+    assert!(!activity.is_consecutive_at(guild_id, UserId(2), now).await);
+    // 別のサーバーは別に数える
+    assert!(!activity.is_consecutive_at(GuildId(2), UserId(2), now).await);
+    // 間があいたら名前を読み直す
+    let later = now + NAME_REPEAT_AFTER;
+    assert!(!activity.is_consecutive_at(guild_id, UserId(2), later).await);
+}
@@ -0,0 +1,478 @@
+use std::{net::SocketAddr, path::Path, str::FromStr, sync::OnceLock};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::{commands::definition::Registration, handler::Generators};
+
+// 設定ファイルの場所。CONFIG_PATH で変えられる
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+// bot の設定。config.toml から読み、同じ名前を大文字にした環境変数 (.env も可) があればそちらを使う
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub discord_token: String,
+    pub app_id: u64,
+    pub database_path: String,
+    // nakochan 時代の辞書ファイル
+    pub legacy_dict_path: String,
+    // Open JTalk や効果音の変換で使う一時ファイルを置くフォルダ
+    pub temp_dir: String,
+    // 文中のコマンド (`!se` など) の接頭辞
+    pub prefix: String,
+    pub log_level: String,
+    pub base_url_coeiro: Option<String>,
+    pub base_url_voicevox: Option<String>,
+    // VOICEVOX 互換のエンジンを複数使うときの、カンマ区切りの URL。`URL*2` のように重みをつけられる
+    pub voicevox_pool: Option<String>,
+    // .htsvoice ファイルかそれを入れたフォルダ
+    pub open_jtalk_voice: Option<String>,
+    pub open_jtalk_dic: Option<String>,
+    pub open_jtalk_bin: String,
+    pub wolfram_alpha_app_id: Option<String>,
+    // 文字起こしのサーバー (whisper.cpp の server など) の URL。None なら声を聞かない
+    pub stt_url: Option<String>,
+    pub max_voice_connections: Option<usize>,
+    // カンマ区切りのサーバー ID
+    pub guild_allowlist: Option<String>,
+    pub guild_denylist: Option<String>,
+    pub waitlist_message: Option<String>,
+    // サーバーごとに 1 日に読み上げる文字数の上限
+    pub daily_char_quota: Option<u64>,
+    pub command_registration: String,
+    pub status_addr: Option<SocketAddr>,
+    pub metrics_log_interval: Option<u64>,
+    // 起動時にエンジンとデータベースの準備ができるまで待つ秒数。0 なら待たない
+    pub startup_timeout: u64,
+    // 読み上げの記録と入室の回数を残す日数。0 なら消さない
+    // /stats の「これまで」は残っている記録の合計なので、読み上げの記録は初期値では消さない
+    pub usage_stats_retention_days: u64,
+    pub join_count_retention_days: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            discord_token: String::new(),
+            app_id: 0,
+            database_path: "database.sqlite".to_string(),
+            legacy_dict_path: "read_dict.json".to_string(),
+            temp_dir: "temp".to_string(),
+            prefix: "!".to_string(),
+            log_level: "info".to_string(),
+            base_url_coeiro: None,
+            base_url_voicevox: None,
+            voicevox_pool: None,
+            open_jtalk_voice: None,
+            open_jtalk_dic: None,
+            open_jtalk_bin: "open_jtalk".to_string(),
+            wolfram_alpha_app_id: None,
+            stt_url: None,
+            max_voice_connections: None,
+            guild_allowlist: None,
+            guild_denylist: None,
+            waitlist_message: None,
+            daily_char_quota: None,
+            command_registration: "global".to_string(),
+            status_addr: None,
+            metrics_log_interval: None,
+            startup_timeout: 60,
+            usage_stats_retention_days: 0,
+            join_count_retention_days: 7,
+        }
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// 起動時に set_config するまでは初期値
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+pub fn set_config(config: Config) {
+    if CONFIG.set(config).is_err() {
+        tracing::warn!("config is already loaded");
+    }
+}
+
+// 空の値は設定していないものとして扱う
+fn set<T: FromStr>(errors: &mut Vec<String>, field: &mut T, key: &str, value: Option<String>)
+where
+    T::Err: std::fmt::Display,
+{
+    if let Some(value) = value.filter(|value| !value.trim().is_empty()) {
+        match value.trim().parse() {
+            Ok(value) => *field = value,
+            Err(e) => errors.push(format!("{}: {}", key, e)),
+        }
+    }
+}
+
+fn set_option<T: FromStr>(
+    errors: &mut Vec<String>,
+    field: &mut Option<T>,
+    key: &str,
+    value: Option<String>,
+) where
+    T::Err: std::fmt::Display,
+{
+    if let Some(value) = value.filter(|value| !value.trim().is_empty()) {
+        match value.trim().parse() {
+            Ok(value) => *field = Some(value),
+            Err(e) => errors.push(format!("{}: {}", key, e)),
+        }
+    }
+}
+
+impl Config {
+    // CONFIG_PATH (なければ config.toml) を読み、環境変数で上書きして確かめる。ファイルがなければ環境変数だけで作る
+    pub fn load() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let path = std::env::var("CONFIG_PATH").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+        let mut config = if Path::new(&path).exists() {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("cannot read {}: {}", path, e))?;
+            Self::from_toml(&text).map_err(|e| anyhow!("invalid {}: {}", path, e))?
+        } else {
+            Config::default()
+        };
+        config.apply_env(|key| std::env::var(key).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_toml(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let mut errors = Vec::new();
+        let e = &mut errors;
+        set(
+            e,
+            &mut self.discord_token,
+            "DISCORD_TOKEN",
+            var("DISCORD_TOKEN"),
+        );
+        set(e, &mut self.app_id, "APP_ID", var("APP_ID"));
+        set(
+            e,
+            &mut self.database_path,
+            "DATABASE_PATH",
+            var("DATABASE_PATH"),
+        );
+        set(
+            e,
+            &mut self.legacy_dict_path,
+            "LEGACY_DICT_PATH",
+            var("LEGACY_DICT_PATH"),
+        );
+        set(e, &mut self.temp_dir, "TEMP_DIR", var("TEMP_DIR"));
+        set(e, &mut self.prefix, "PREFIX", var("PREFIX"));
+        set(e, &mut self.log_level, "LOG_LEVEL", var("LOG_LEVEL"));
+        set_option(
+            e,
+            &mut self.base_url_coeiro,
+            "BASE_URL_COEIRO",
+            var("BASE_URL_COEIRO"),
+        );
+        set_option(
+            e,
+            &mut self.base_url_voicevox,
+            "BASE_URL_VOICEVOX",
+            var("BASE_URL_VOICEVOX"),
+        );
+        set_option(
+            e,
+            &mut self.voicevox_pool,
+            "VOICEVOX_POOL",
+            var("VOICEVOX_POOL"),
+        );
+        set_option(
+            e,
+            &mut self.open_jtalk_voice,
+            "OPEN_JTALK_VOICE",
+            var("OPEN_JTALK_VOICE"),
+        );
+        set_option(
+            e,
+            &mut self.open_jtalk_dic,
+            "OPEN_JTALK_DIC",
+            var("OPEN_JTALK_DIC"),
+        );
+        set(
+            e,
+            &mut self.open_jtalk_bin,
+            "OPEN_JTALK_BIN",
+            var("OPEN_JTALK_BIN"),
+        );
+        set_option(
+            e,
+            &mut self.wolfram_alpha_app_id,
+            "WOLFRAM_ALPHA_APP_ID",
+            var("WOLFRAM_ALPHA_APP_ID"),
+        );
+        set_option(e, &mut self.stt_url, "STT_URL", var("STT_URL"));
+        set_option(
+            e,
+            &mut self.max_voice_connections,
+            "MAX_VOICE_CONNECTIONS",
+            var("MAX_VOICE_CONNECTIONS"),
+        );
+        set_option(
+            e,
+            &mut self.guild_allowlist,
+            "GUILD_ALLOWLIST",
+            var("GUILD_ALLOWLIST"),
+        );
+        set_option(
+            e,
+            &mut self.guild_denylist,
+            "GUILD_DENYLIST",
+            var("GUILD_DENYLIST"),
+        );
+        set_option(
+            e,
+            &mut self.waitlist_message,
+            "WAITLIST_MESSAGE",
+            var("WAITLIST_MESSAGE"),
+        );
+        set_option(
+            e,
+            &mut self.daily_char_quota,
+            "DAILY_CHAR_QUOTA",
+            var("DAILY_CHAR_QUOTA"),
+        );
+        set(
+            e,
+            &mut self.command_registration,
+            "COMMAND_REGISTRATION",
+            var("COMMAND_REGISTRATION"),
+        );
+        set_option(e, &mut self.status_addr, "STATUS_ADDR", var("STATUS_ADDR"));
+        set_option(
+            e,
+            &mut self.metrics_log_interval,
+            "METRICS_LOG_INTERVAL",
+            var("METRICS_LOG_INTERVAL"),
+        );
+        set(
+            e,
+            &mut self.startup_timeout,
+            "STARTUP_TIMEOUT",
+            var("STARTUP_TIMEOUT"),
+        );
+        set(
+            e,
+            &mut self.usage_stats_retention_days,
+            "USAGE_STATS_RETENTION_DAYS",
+            var("USAGE_STATS_RETENTION_DAYS"),
+        );
+        set(
+            e,
+            &mut self.join_count_retention_days,
+            "JOIN_COUNT_RETENTION_DAYS",
+            var("JOIN_COUNT_RETENTION_DAYS"),
+        );
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "invalid environment variables:\n  {}",
+                errors.join("\n  ")
+            ))
+        }
+    }
+
+    // discord につながなくても要る設定を確かめる
+    fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        for (key, url) in [
+            ("base_url_coeiro", &self.base_url_coeiro),
+            ("base_url_voicevox", &self.base_url_voicevox),
+            ("stt_url", &self.stt_url),
+        ] {
+            if let Some(url) = url {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    errors.push(format!(
+                        "{} must start with http:// or https:// (got {})",
+                        key, url
+                    ));
+                }
+            }
+        }
+        if let Some(pool) = &self.voicevox_pool {
+            match parse_pool(pool) {
+                Ok(instances) => {
+                    for (url, _) in instances {
+                        if !url.starts_with("http://") && !url.starts_with("https://") {
+                            errors.push(format!(
+                                "voicevox_pool must start with http:// or https:// (got {})",
+                                url
+                            ));
+                        }
+                    }
+                }
+                Err(e) => errors.push(format!("voicevox_pool: {}", e)),
+            }
+        }
+        if self.open_jtalk_voice.is_some() != self.open_jtalk_dic.is_some() {
+            errors.push("open_jtalk_voice and open_jtalk_dic must be set together".to_string());
+        }
+        if tracing::Level::from_str(&self.log_level).is_err() {
+            errors.push(format!(
+                "log_level must be one of error, warn, info, debug, trace (got {})",
+                self.log_level
+            ));
+        }
+        if Registration::try_from(self.command_registration.as_str()).is_err() {
+            errors.push(format!(
+                "command_registration must be global or guild (got {})",
+                self.command_registration
+            ));
+        }
+        if self.prefix.trim().is_empty() {
+            errors.push("prefix must not be empty".to_string());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid config:\n  {}", errors.join("\n  ")))
+        }
+    }
+
+    // discord につなぐときだけ要る設定
+    pub fn require_discord(&self) -> Result<()> {
+        let mut missing = Vec::new();
+        if self.discord_token.trim().is_empty() {
+            missing.push("discord_token (DISCORD_TOKEN)");
+        }
+        if self.app_id == 0 {
+            missing.push("app_id (APP_ID)");
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("missing config: {}", missing.join(", ")))
+        }
+    }
+
+    // エンジンの場所。OpenJTalk は声のファイルかフォルダ。設定していなければ None
+    // VOICEVOX は voicevox_pool だけ設定していればその最初の URL
+    pub fn engine_location(&self, generator: Generators) -> Option<&str> {
+        match generator {
+            Generators::COEIROINK => self.base_url_coeiro.as_deref(),
+            Generators::VOICEVOX => self.base_url_voicevox.as_deref().or_else(|| {
+                self.voicevox_pool
+                    .as_deref()
+                    .and_then(|pool| pool.split(',').next())
+                    .map(|instance| instance.split('*').next().unwrap_or(instance).trim())
+            }),
+            Generators::OPENJTALK => self.open_jtalk_voice.as_deref(),
+        }
+    }
+
+    // VOICEVOX 互換のエンジンの (URL, 重み)。voicevox_pool がなければ base_url_voicevox だけ
+    pub fn voicevox_instances(&self) -> Vec<(String, u32)> {
+        match self.voicevox_pool.as_deref().map(parse_pool) {
+            Some(Ok(instances)) if !instances.is_empty() => instances,
+            _ => self
+                .base_url_voicevox
+                .iter()
+                .map(|url| (url.clone(), 1))
+                .collect(),
+        }
+    }
+}
+
+// `URL*重み,URL` を (URL, 重み) に分ける。重みを省くと 1
+fn parse_pool(pool: &str) -> Result<Vec<(String, u32)>> {
+    pool.split(',')
+        .map(str::trim)
+        .filter(|instance| !instance.is_empty())
+        .map(|instance| match instance.split_once('*') {
+            Some((url, weight)) => Ok((url.trim().to_string(), weight.trim().parse()?)),
+            None => Ok((instance.to_string(), 1)),
+        })
+        .collect()
+}
+
+#[test]
+fn config_test() {
+    let mut config = Config::from_toml(
+        r#"
+discord_token = "token"
+app_id = 42
+base_url_voicevox = "http://127.0.0.1:50021"
+status_addr = "127.0.0.1:9000"
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.app_id, 42);
+    assert_eq!(config.database_path, "database.sqlite");
+    assert_eq!(config.usage_stats_retention_days, 0);
+    assert_eq!(
+        config.engine_location(Generators::VOICEVOX),
+        Some("http://127.0.0.1:50021")
+    );
+    assert_eq!(config.engine_location(Generators::COEIROINK), None);
+    assert!(config.validate().is_ok());
+    assert!(config.require_discord().is_ok());
+    // 知らない項目は書き間違いとして弾く
+    assert!(Config::from_toml("discord_tokn = \"token\"").is_err());
+
+    // 環境変数が優先。空なら設定ファイルのまま
+    let env = |key: &str| match key {
+        "APP_ID" => Some("7".to_string()),
+        "DISCORD_TOKEN" => Some("".to_string()),
+        "MAX_VOICE_CONNECTIONS" => Some(" 3 ".to_string()),
+        _ => None,
+    };
+    config.apply_env(env).unwrap();
+    assert_eq!(config.app_id, 7);
+    assert_eq!(config.discord_token, "token");
+    assert_eq!(config.max_voice_connections, Some(3));
+    let error = config
+        .apply_env(|key| (key == "STATUS_ADDR").then(|| "nowhere".to_string()))
+        .unwrap_err();
+    assert!(error.to_string().contains("STATUS_ADDR"));
+
+    config.base_url_coeiro = Some("127.0.0.1:50031".to_string());
+    config.log_level = "loud".to_string();
+    let error = config.validate().unwrap_err().to_string();
+    assert!(error.contains("base_url_coeiro"));
+    assert!(error.contains("log_level"));
+    assert!(Config::default().require_discord().is_err());
+}
+
+#[test]
+fn voicevox_pool_test() {
+    let mut config = Config {
+        base_url_voicevox: Some("http://127.0.0.1:50021".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        config.voicevox_instances(),
+        vec![("http://127.0.0.1:50021".to_string(), 1)]
+    );
+
+    config.base_url_voicevox = None;
+    config.voicevox_pool = Some("http://127.0.0.1:50021*2, http://127.0.0.1:50121".to_string());
+    assert_eq!(
+        config.voicevox_instances(),
+        vec![
+            ("http://127.0.0.1:50021".to_string(), 2),
+            ("http://127.0.0.1:50121".to_string(), 1)
+        ]
+    );
+    assert_eq!(
+        config.engine_location(Generators::VOICEVOX),
+        Some("http://127.0.0.1:50021")
+    );
+    assert!(config.validate().is_ok());
+
+    config.voicevox_pool = Some("http://127.0.0.1:50021*heavy".to_string());
+    assert!(config.validate().is_err());
+}
@@ -0,0 +1,138 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serenity::model::id::{GuildId, MessageId};
+use tokio::sync::Mutex;
+
+use super::text::VoiceOverrides;
+
+// この文字数以下のメッセージを、続けて来た同じ声のメッセージとまとめて合成する
+pub const BATCH_MAX_CHARS: usize = 10;
+// /config batch_window で設定できる上限
+pub const MAX_BATCH_WINDOW_MS: i64 = 3000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchItem {
+    // 名前をつけた読み上げる文章
+    pub text: String,
+    // 名前を除いた本文が BATCH_MAX_CHARS 以下か
+    pub short: bool,
+    pub generator_type: u8,
+    pub voice_type: u32,
+    pub overrides: VoiceOverrides,
+    // 読み上げるメッセージ。まとめたら全部のメッセージ
+    pub message_ids: Vec<MessageId>,
+}
+
+impl BatchItem {
+    fn joins(&self, other: &BatchItem) -> bool {
+        self.short
+            && other.short
+            && self.generator_type == other.generator_type
+            && self.voice_type == other.voice_type
+            && self.overrides == other.overrides
+    }
+}
+
+// 短いメッセージが続けて来たら少し待ってまとめ、エンジンへのリクエストと間の無音を減らす
+#[derive(Clone, Default)]
+pub struct SynthesisBatcher {
+    pending: Arc<Mutex<HashMap<GuildId, Vec<BatchItem>>>>,
+}
+
+impl SynthesisBatcher {
+    // 待っている間は、順番が変わらないよう長いメッセージも積んで Some(false) を返す
+    // 待っていなければ短いメッセージだけ積んで Some(true) を返すので、呼んだ側が待ってから take する
+    // 長いメッセージなら None を返すので、そのまま読む
+    pub async fn push(&self, guild_id: GuildId, item: BatchItem) -> Option<bool> {
+        let mut pending = self.pending.lock().await;
+        match pending.get_mut(&guild_id) {
+            Some(items) => {
+                items.push(item);
+                Some(false)
+            }
+            None if item.short => {
+                pending.insert(guild_id, vec![item]);
+                Some(true)
+            }
+            None => None,
+        }
+    }
+
+    // 待っていた分を、続いている同じ声の短いメッセージをつなげて返す
+    pub async fn take(&self, guild_id: GuildId) -> Vec<BatchItem> {
+        let items = self
+            .pending
+            .lock()
+            .await
+            .remove(&guild_id)
+            .unwrap_or_default();
+        merge(items)
+    }
+}
+
+fn merge(items: Vec<BatchItem>) -> Vec<BatchItem> {
+    let mut merged: Vec<BatchItem> = Vec::new();
+    for item in items {
+        match merged.last_mut() {
+            Some(last) if last.joins(&item) => {
+                last.text = format!("{}、{}", last.text, item.text);
+                last.message_ids.extend(item.message_ids);
+            }
+            _ => merged.push(item),
+        }
+    }
+    merged
+}
+
+#[tokio::test]
+async fn synthesis_batcher_test() {
+    let item = |text: &str, short, voice_type| BatchItem {
+        text: text.to_string(),
+        short,
+        generator_type: 1,
+        voice_type,
+        overrides: VoiceOverrides::default(),
+        message_ids: vec![MessageId(text.chars().next().unwrap() as u64)],
+    };
+    let batcher = SynthesisBatcher::default();
+    let guild_id = GuildId(1);
+
+    // 待っていなければ長いメッセージはそのまま読む
+    assert_eq!(batcher.push(guild_id, item("長い", false, 1)).await, None);
+    assert_eq!(
+        batcher.push(guild_id, item("たろう w", true, 1)).await,
+        Some(true)
+    );
+    assert_eq!(
+        batcher.push(guild_id, item("はなこ 草", true, 1)).await,
+        Some(false)
+    );
+    assert_eq!(
+        batcher.push(guild_id, item("じろう おk", true, 3)).await,
+        Some(false)
+    );
+    assert_eq!(
+        batcher.push(guild_id, item("長文", false, 3)).await,
+        Some(false)
+    );
+    assert_eq!(
+        batcher.push(guild_id, item("うん", true, 3)).await,
+        Some(false)
+    );
+    assert_eq!(
+        batcher
+            .take(guild_id)
+            .await
+            .into_iter()
+            .map(|item| item.text)
+            .collect::<Vec<_>>(),
+        vec!["たろう w、はなこ 草", "じろう おk", "長文", "うん"]
+    );
+    assert!(batcher.push(guild_id, item("a", true, 1)).await.is_some());
+    assert!(batcher.push(guild_id, item("b", true, 1)).await.is_some());
+    assert_eq!(
+        batcher.take(guild_id).await[0].message_ids,
+        vec![MessageId('a' as u64), MessageId('b' as u64)]
+    );
+    assert!(batcher.take(guild_id).await.is_empty());
+}
@@ -0,0 +1,78 @@
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, GuildId},
+};
+use tracing::info;
+
+use super::db::{ChangelogDB, GuildConfigDB};
+
+// 版ごとに `## 版` の見出しで書いた変更点
+const CHANGELOG: &str = include_str!("../../CHANGELOG.md");
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+// discord のメッセージは 2000 文字まで
+const MESSAGE_LIMIT: usize = 1900;
+
+// changelog の version の見出しから次の見出しまで
+fn notes(changelog: &str, version: &str) -> Option<String> {
+    let heading = format!("## {}", version);
+    let notes = changelog
+        .lines()
+        .skip_while(|line| line.trim() != heading)
+        .skip(1)
+        .take_while(|line| !line.starts_with("## "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let notes = notes.trim();
+    (!notes.is_empty()).then(|| notes.to_string())
+}
+
+// いまの版の変更点のメッセージ。書いていなければ None
+pub fn message() -> Option<String> {
+    let notes = notes(CHANGELOG, VERSION)?;
+    let message = format!("nap-chan {} の変更点\n{}", VERSION, notes);
+    if message.chars().count() > MESSAGE_LIMIT {
+        Some(message.chars().take(MESSAGE_LIMIT).collect::<String>() + "…")
+    } else {
+        Some(message)
+    }
+}
+
+// お知らせのチャンネルを決めたサーバーに、いまの版の変更点を一度だけ送る
+pub async fn announce(http: &Http, database: &sqlx::SqlitePool, guilds: &[GuildId]) {
+    let message = match message() {
+        Some(message) => message,
+        None => return,
+    };
+    for guild_id in guilds {
+        let guild_config = match database.get_guild_config(guild_id.0 as i64).await {
+            Ok(guild_config) => guild_config,
+            Err(_) => continue,
+        };
+        let channel_id = match guild_config.announce_channel_id {
+            Some(channel_id) => ChannelId(channel_id as u64),
+            None => continue,
+        };
+        match database.claim_changelog(guild_id.0 as i64, VERSION).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                info!("{}", e);
+                continue;
+            }
+        }
+        if let Err(e) = channel_id.say(http, &message).await {
+            info!("could not announce changelog to {}: {}", guild_id, e);
+        }
+    }
+}
+
+#[test]
+fn notes_test() {
+    let changelog = "# 変更点\n\n## 0.2.0\n\n- b\n- c\n\n## 0.1.0\n- a\n";
+    assert_eq!(notes(changelog, "0.2.0").unwrap(), "- b\n- c");
+    assert_eq!(notes(changelog, "0.1.0").unwrap(), "- a");
+    assert_eq!(notes(changelog, "0.3.0"), None);
+    assert_eq!(notes("## 0.1.0\n## 0.0.1\n- a", "0.1.0"), None);
+    // 同梱の変更点にいまの版が書いてある
+    assert!(message().is_some());
+}
@@ -0,0 +1,96 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use serenity::model::{
+    channel::Message,
+    id::{GuildId, MessageId},
+};
+use tokio::sync::Mutex;
+
+// サーバーごとに覚えておく、読み上げたメッセージの数。これより前のメッセージの編集は読まない
+pub const RECENT_MESSAGES: usize = 30;
+
+// 編集されたら訂正を読めるよう、最近読み上げたメッセージを覚えておく
+#[derive(Clone, Default)]
+pub struct RecentMessages {
+    messages: Arc<Mutex<HashMap<GuildId, VecDeque<Message>>>>,
+}
+
+impl RecentMessages {
+    pub async fn remember(&self, guild_id: GuildId, msg: &Message) {
+        let mut messages = self.messages.lock().await;
+        let messages = messages.entry(guild_id).or_default();
+        if messages.len() == RECENT_MESSAGES {
+            messages.pop_front();
+        }
+        messages.push_back(msg.clone());
+    }
+
+    // 覚えているメッセージの本文が変わっていれば、新しい本文にして返す
+    // リンクの埋め込みがついただけの更新では本文が変わらないので None
+    pub async fn edit(
+        &self,
+        guild_id: GuildId,
+        message_id: MessageId,
+        content: &str,
+    ) -> Option<Message> {
+        let mut messages = self.messages.lock().await;
+        let msg = messages
+            .get_mut(&guild_id)?
+            .iter_mut()
+            .find(|msg| msg.id == message_id)?;
+        if msg.content == content {
+            return None;
+        }
+        msg.content = content.to_string();
+        Some(msg.clone())
+    }
+}
+
+#[tokio::test]
+async fn recent_messages_test() {
+    let message = |id: u64, content: &str| -> Message {
+        serde_json::from_value(serde_json::json!({
+            "id": id.to_string(),
+            "channel_id": "1",
+            "author": {"id": "2", "username": "nap", "discriminator": "0001", "avatar": null},
+            "attachments": [],
+            "content": content,
+            "edited_timestamp": null,
+            "embeds": [],
+            "mention_everyone": false,
+            "mention_roles": [],
+            "mentions": [],
+            "pinned": false,
+            "timestamp": "2022-01-01T00:00:00+00:00",
+            "tts": false,
+            "type": 0,
+        }))
+        .unwrap()
+    };
+    let recent = RecentMessages::default();
+    let guild_id = GuildId(1);
+    for id in 0..=RECENT_MESSAGES as u64 {
+        recent.remember(guild_id, &message(id, "こんにちわ")).await;
+    }
+    assert_eq!(
+        recent
+            .edit(guild_id, MessageId(1), "こんにちは")
+            .await
+            .map(|msg| msg.content),
+        Some("こんにちは".to_string())
+    );
+    // 同じ本文なら読まない
+    assert!(recent
+        .edit(guild_id, MessageId(1), "こんにちは")
+        .await
+        .is_none());
+    // 古いメッセージは忘れている
+    assert!(recent.edit(guild_id, MessageId(0), "やあ").await.is_none());
+    assert!(recent
+        .edit(GuildId(2), MessageId(1), "やあ")
+        .await
+        .is_none());
+}
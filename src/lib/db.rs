@@ -1,18 +1,31 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serenity::async_trait;
 use sqlx::{query, query_as};
 
+use super::engine::{engine, SynthesisParams, PITCH_RANGE, SPEED_RANGE};
+use super::ng_word::{NgAction, NgWord};
+use super::quiet_hours::{QuietHours, DEFAULT_UTC_OFFSET_MIN};
+use super::setup::SetupDraft;
 use crate::{handler::Generators, Dict};
 use anyhow::{anyhow, Result};
 
 #[async_trait]
 pub trait UserConfigDB {
-    async fn get_user_config_or_default(&self, user_id: i64) -> Result<UserConfig>;
-    async fn get_user_config(&self, user_id: i64) -> Result<UserConfig>;
+    async fn get_user_config_or_default(
+        &self,
+        user_id: i64,
+        guild_id: Option<i64>,
+    ) -> Result<UserConfig>;
+    // 行がなければ None
+    async fn get_user_config(&self, user_id: i64) -> Result<Option<UserConfig>>;
     async fn update_user_config(&self, user_config: &UserConfig) -> Result<u64>;
 }
 
-#[derive(Debug)]
+// user_config の hello, bye の初期値。この値のままならサーバーの口調のあいさつを使う
+pub const DEFAULT_HELLO: &str = "こんにちは";
+pub const DEFAULT_BYE: &str = "ばいばい";
+
+#[derive(Debug, Clone)]
 pub struct UserConfig {
     pub user_id: i64,
     pub hello: String,
@@ -20,70 +33,1288 @@ pub struct UserConfig {
     pub voice_type: i64,
     pub generator_type: i64,
     pub read_nickname: Option<String>,
+    pub ignored: bool,
+    // 読み上げの話速と声の高さ。VOICEVOX の speedScale, pitchScale と同じ
+    pub speed_scale: f64,
+    pub pitch_scale: f64,
+    // あいさつと名前を読まない時間帯 (quiet_hours::QuietHours)。どちらかが None なら決めていない
+    pub quiet_start_min: Option<i64>,
+    pub quiet_end_min: Option<i64>,
+    // 時間帯を見るときの UTC からの時差 (分)
+    pub utc_offset_min: i64,
+    // 自分で声を選んだか。選んでいなければ voice_type, generator_type の代わりにサーバーのデフォルトボイスを使う
+    pub voice_chosen: bool,
 }
 impl UserConfig {
     pub fn from_user_id(user_id: i64) -> Self {
         UserConfig {
             user_id,
-            hello: "こんにちは".to_string(),
-            bye: "ばいばい".to_string(),
+            hello: DEFAULT_HELLO.to_string(),
+            bye: DEFAULT_BYE.to_string(),
             voice_type: 1,
             generator_type: 0,
             read_nickname: None,
+            ignored: false,
+            speed_scale: 1.0,
+            pitch_scale: 0.0,
+            quiet_start_min: None,
+            quiet_end_min: None,
+            utc_offset_min: DEFAULT_UTC_OFFSET_MIN,
+            voice_chosen: false,
+        }
+    }
+
+    pub fn set_voice(&mut self, generator_type: i64, voice_type: i64) {
+        self.generator_type = generator_type;
+        self.voice_type = voice_type;
+        self.voice_chosen = true;
+    }
+
+    // 声を選んでいなければ、読み上げるサーバーのデフォルトボイスにする
+    pub fn resolve_voice(&mut self, guild_config: &GuildConfig) {
+        if !self.voice_chosen {
+            self.generator_type = guild_config.generator_type;
+            self.voice_type = guild_config.voice_type;
+        }
+    }
+
+    pub fn synthesis_params(&self) -> SynthesisParams {
+        SynthesisParams {
+            speed_scale: self.speed_scale,
+            pitch_scale: self.pitch_scale,
+        }
+    }
+}
+// /my_config で書き出し・読み込みするユーザー設定
+// 別の bot では話者の ID が違うことがあるので、声の名前も一緒に持つ
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PortableUserConfig {
+    pub hello: String,
+    pub bye: String,
+    pub generator_type: i64,
+    pub voice_type: i64,
+    pub voice_name: Option<String>,
+    pub read_nickname: Option<String>,
+    #[serde(default)]
+    pub ignored: bool,
+    #[serde(default)]
+    pub synthesis_params: SynthesisParams,
+    #[serde(default)]
+    pub quiet_hours: Option<(i64, i64)>,
+    #[serde(default = "default_utc_offset_min")]
+    pub utc_offset_min: i64,
+}
+
+fn default_utc_offset_min() -> i64 {
+    DEFAULT_UTC_OFFSET_MIN
+}
+
+impl PortableUserConfig {
+    pub fn new(user_config: &UserConfig, voice_name: Option<String>) -> Self {
+        PortableUserConfig {
+            hello: user_config.hello.clone(),
+            bye: user_config.bye.clone(),
+            generator_type: user_config.generator_type,
+            voice_type: user_config.voice_type,
+            voice_name,
+            read_nickname: user_config.read_nickname.clone(),
+            ignored: user_config.ignored,
+            synthesis_params: user_config.synthesis_params(),
+            quiet_hours: QuietHours::of(user_config)
+                .map(|quiet_hours| (quiet_hours.start_min, quiet_hours.end_min)),
+            utc_offset_min: user_config.utc_offset_min,
+        }
+    }
+
+    // 声は確かめてから渡すので、ここではそれ以外を写す
+    pub fn apply(self, user_config: &mut UserConfig, generator_type: i64, voice_type: i64) {
+        user_config.hello = self.hello;
+        user_config.bye = self.bye;
+        user_config.set_voice(generator_type, voice_type);
+        user_config.read_nickname = self.read_nickname;
+        user_config.ignored = self.ignored;
+        (user_config.quiet_start_min, user_config.quiet_end_min) = self.quiet_hours.unzip();
+        user_config.utc_offset_min = self.utc_offset_min;
+        user_config.speed_scale = self
+            .synthesis_params
+            .speed_scale
+            .clamp(SPEED_RANGE.0, SPEED_RANGE.1);
+        user_config.pitch_scale = self
+            .synthesis_params
+            .pitch_scale
+            .clamp(PITCH_RANGE.0, PITCH_RANGE.1);
+    }
+}
+
+// 漢字を読み違える話者には、VOICEVOX で読みをカタカナに直してから渡す。/config kana_speaker で選ぶ
+#[async_trait]
+pub trait KanaSpeakerDB {
+    async fn set_kana_speaker(
+        &self,
+        guild_id: i64,
+        generator_type: &str,
+        style_id: i64,
+        enabled: bool,
+    ) -> Result<()>;
+    async fn is_kana_speaker(
+        &self,
+        guild_id: i64,
+        generator_type: &str,
+        style_id: i64,
+    ) -> Result<bool>;
+}
+
+#[async_trait]
+impl KanaSpeakerDB for sqlx::SqlitePool {
+    async fn set_kana_speaker(
+        &self,
+        guild_id: i64,
+        generator_type: &str,
+        style_id: i64,
+        enabled: bool,
+    ) -> Result<()> {
+        let mut tx = self.begin().await?;
+        if enabled {
+            query!(
+                "INSERT OR IGNORE INTO kana_speakers (guild_id,generator_type,style_id) VALUES (?,?,?)",
+                guild_id,
+                generator_type,
+                style_id
+            )
+            .execute(&mut tx)
+            .await?;
+        } else {
+            query!(
+                "DELETE FROM kana_speakers WHERE guild_id = ? AND generator_type = ? AND style_id = ?",
+                guild_id,
+                generator_type,
+                style_id
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+    async fn is_kana_speaker(
+        &self,
+        guild_id: i64,
+        generator_type: &str,
+        style_id: i64,
+    ) -> Result<bool> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "SELECT style_id FROM kana_speakers WHERE guild_id = ? AND generator_type = ? AND style_id = ?",
+            guild_id,
+            generator_type,
+            style_id
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.is_some())
+    }
+}
+
+// /stats で出す、サーバーでの使われ方。日 (サーバーの時刻) とエンジンごとに足していく
+#[async_trait]
+pub trait UsageDB {
+    async fn record_usage(&self, guild_id: i64, usage: &Usage) -> Result<()>;
+    // (今日, これまで) のエンジンごとの合計
+    async fn get_usage(&self, guild_id: i64) -> Result<(Vec<Usage>, Vec<Usage>)>;
+    // 今日合成した文字数
+    async fn get_today_characters(&self, guild_id: i64) -> Result<i64>;
+    // 次の日の記録になるまでの秒数
+    async fn seconds_until_next_day(&self) -> Result<i64>;
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub generator_type: String,
+    // 読み上げたメッセージ。お知らせや /say は数えない
+    pub messages: i64,
+    pub characters: i64,
+    pub audio_ms: i64,
+}
+
+#[async_trait]
+impl UsageDB for sqlx::SqlitePool {
+    async fn record_usage(&self, guild_id: i64, usage: &Usage) -> Result<()> {
+        let mut tx = self.begin().await?;
+        query!(
+            "INSERT INTO usage_stats (guild_id,day,generator_type,messages,characters,audio_ms) VALUES (?,date('now','localtime'),?,?,?,?)
+            ON CONFLICT (guild_id,day,generator_type) DO UPDATE SET messages = messages + excluded.messages,characters = characters + excluded.characters,audio_ms = audio_ms + excluded.audio_ms",
+            guild_id,
+            usage.generator_type,
+            usage.messages,
+            usage.characters,
+            usage.audio_ms
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    async fn get_usage(&self, guild_id: i64) -> Result<(Vec<Usage>, Vec<Usage>)> {
+        let mut tx = self.begin().await?;
+        let today = query_as!(
+            Usage,
+            r#"SELECT generator_type,messages,characters,audio_ms FROM usage_stats WHERE guild_id = ? AND day = date('now','localtime') ORDER BY generator_type"#,
+            guild_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        let all_time = query_as!(
+            Usage,
+            r#"SELECT generator_type,SUM(messages) AS "messages!: i64",SUM(characters) AS "characters!: i64",SUM(audio_ms) AS "audio_ms!: i64" FROM usage_stats WHERE guild_id = ? GROUP BY generator_type ORDER BY generator_type"#,
+            guild_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok((today, all_time))
+    }
+    async fn get_today_characters(&self, guild_id: i64) -> Result<i64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            r#"SELECT COALESCE(SUM(characters),0) AS "characters!: i64" FROM usage_stats WHERE guild_id = ? AND day = date('now','localtime')"#,
+            guild_id
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.characters)
+    }
+    async fn seconds_until_next_day(&self) -> Result<i64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            r#"SELECT CAST(strftime('%s',date('now','localtime','+1 day'),'utc') AS INTEGER) - CAST(strftime('%s','now') AS INTEGER) AS "seconds!: i64""#
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.seconds)
+    }
+}
+
+// エンジンの更新でなくなった声を使っている人を、起動時に戻す (voice_remap)
+#[async_trait]
+pub trait VoiceRemapDB {
+    // 自分で声を選んだ人の (user_id, generator_type, voice_type)
+    async fn get_user_voices(&self) -> Result<Vec<(i64, i64, i64)>>;
+    // 声を戻し、次のコマンドで知らせるよう覚えておく
+    async fn remap_user_voice(
+        &self,
+        user_id: i64,
+        generator_type: i64,
+        voice_type: i64,
+    ) -> Result<()>;
+    // 知らせていなければ true を返し、知らせたことにする
+    async fn take_voice_remap_notice(&self, user_id: i64) -> Result<bool>;
+}
+
+#[async_trait]
+impl VoiceRemapDB for sqlx::SqlitePool {
+    async fn get_user_voices(&self) -> Result<Vec<(i64, i64, i64)>> {
+        let mut tx = self.begin().await?;
+        let q =
+            query!("SELECT user_id,generator_type,voice_type FROM user_config WHERE voice_chosen")
+                .fetch_all(&mut tx)
+                .await?;
+        tx.commit().await?;
+        Ok(q.into_iter()
+            .map(|r| (r.user_id, r.generator_type, r.voice_type))
+            .collect())
+    }
+    async fn remap_user_voice(
+        &self,
+        user_id: i64,
+        generator_type: i64,
+        voice_type: i64,
+    ) -> Result<()> {
+        let mut tx = self.begin().await?;
+        query!(
+            "UPDATE user_config SET generator_type = ?,voice_type = ? WHERE user_id = ?",
+            generator_type,
+            voice_type,
+            user_id
+        )
+        .execute(&mut tx)
+        .await?;
+        query!(
+            "INSERT OR IGNORE INTO voice_remap_notice (user_id) VALUES (?)",
+            user_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    async fn take_voice_remap_notice(&self, user_id: i64) -> Result<bool> {
+        let mut tx = self.begin().await?;
+        let q = query!("DELETE FROM voice_remap_notice WHERE user_id = ?", user_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected() > 0)
+    }
+}
+
+// /voice_favorite で名前をつけて保存した声と話速・高さ
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoicePreset {
+    pub user_id: i64,
+    pub name: String,
+    pub generator_type: i64,
+    pub voice_type: i64,
+    pub speed_scale: f64,
+    pub pitch_scale: f64,
+}
+
+impl VoicePreset {
+    pub fn new(user_config: &UserConfig, name: &str) -> Self {
+        VoicePreset {
+            user_id: user_config.user_id,
+            name: name.to_string(),
+            generator_type: user_config.generator_type,
+            voice_type: user_config.voice_type,
+            speed_scale: user_config.speed_scale,
+            pitch_scale: user_config.pitch_scale,
+        }
+    }
+
+    pub fn apply(&self, user_config: &mut UserConfig) {
+        user_config.set_voice(self.generator_type, self.voice_type);
+        user_config.speed_scale = self.speed_scale;
+        user_config.pitch_scale = self.pitch_scale;
+    }
+}
+
+#[async_trait]
+pub trait VoicePresetDB {
+    // 同じ名前があれば上書きする
+    async fn save_voice_preset(&self, voice_preset: &VoicePreset) -> Result<u64>;
+    async fn remove_voice_preset(&self, user_id: i64, name: &str) -> Result<u64>;
+    async fn get_voice_preset(&self, user_id: i64, name: &str) -> Result<Option<VoicePreset>>;
+    async fn get_voice_presets(&self, user_id: i64) -> Result<Vec<VoicePreset>>;
+}
+
+#[async_trait]
+impl VoicePresetDB for sqlx::SqlitePool {
+    async fn save_voice_preset(&self, voice_preset: &VoicePreset) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "INSERT OR REPLACE INTO voice_presets (user_id,name,generator_type,voice_type,speed_scale,pitch_scale) VALUES (?,?,?,?,?,?)",
+            voice_preset.user_id,
+            voice_preset.name,
+            voice_preset.generator_type,
+            voice_preset.voice_type,
+            voice_preset.speed_scale,
+            voice_preset.pitch_scale
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn remove_voice_preset(&self, user_id: i64, name: &str) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "DELETE FROM voice_presets WHERE user_id = ? AND name = ?",
+            user_id,
+            name
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn get_voice_preset(&self, user_id: i64, name: &str) -> Result<Option<VoicePreset>> {
+        let mut tx = self.begin().await?;
+        let q = query_as!(
+            VoicePreset,
+            "SELECT * FROM voice_presets WHERE user_id = ? AND name = ?",
+            user_id,
+            name
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q)
+    }
+    async fn get_voice_presets(&self, user_id: i64) -> Result<Vec<VoicePreset>> {
+        let mut tx = self.begin().await?;
+        let q = query_as!(
+            VoicePreset,
+            "SELECT * FROM voice_presets WHERE user_id = ? ORDER BY name",
+            user_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoiceType {
+    pub id: i64,
+    pub name: String,
+    pub style_id: i64,
+    pub style_name: String,
+    pub generator_type: String,
+}
+
+#[async_trait]
+impl UserConfigDB for sqlx::SqlitePool {
+    async fn get_user_config(&self, user_id: i64) -> Result<Option<UserConfig>> {
+        let mut tx = self.begin().await?;
+        let q = query_as!(
+            UserConfig,
+            "SELECT * FROM user_config WHERE user_id = ?",
+            user_id
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q)
+    }
+    async fn get_user_config_or_default(
+        &self,
+        user_id: i64,
+        guild_id: Option<i64>,
+    ) -> Result<UserConfig> {
+        // 初めてのユーザーの行は、設定を変えたときに update_user_config で作る
+        let mut user_config = self
+            .get_user_config(user_id)
+            .await?
+            .unwrap_or_else(|| UserConfig::from_user_id(user_id));
+        if let Some(guild_id) = guild_id.filter(|_| !user_config.voice_chosen) {
+            let guild_config = self.get_guild_config_or_default(guild_id).await?;
+            user_config.resolve_voice(&guild_config);
+        }
+        Ok(user_config)
+    }
+    async fn update_user_config(&self, user_config: &UserConfig) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!("INSERT INTO user_config (user_id,hello,bye,voice_type,generator_type,read_nickname,ignored,speed_scale,pitch_scale,quiet_start_min,quiet_end_min,utc_offset_min,voice_chosen) VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?) ON CONFLICT(user_id) DO UPDATE SET hello = excluded.hello,bye = excluded.bye,voice_type = excluded.voice_type,generator_type = excluded.generator_type,read_nickname = excluded.read_nickname,ignored = excluded.ignored,speed_scale = excluded.speed_scale,pitch_scale = excluded.pitch_scale,quiet_start_min = excluded.quiet_start_min,quiet_end_min = excluded.quiet_end_min,utc_offset_min = excluded.utc_offset_min,voice_chosen = excluded.voice_chosen",
+        user_config.user_id,user_config.hello,user_config.bye,user_config.voice_type,user_config.generator_type,user_config.read_nickname,user_config.ignored,user_config.speed_scale,user_config.pitch_scale,user_config.quiet_start_min,user_config.quiet_end_min,user_config.utc_offset_min,user_config.voice_chosen)
+        .execute(&mut tx).await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+}
+
+#[async_trait]
+pub trait GuildConfigDB {
+    async fn get_guild_config_or_default(&self, guild_id: i64) -> Result<GuildConfig>;
+    async fn get_guild_config(&self, guild_id: i64) -> Result<GuildConfig>;
+    async fn update_guild_config(&self, guild_config: &GuildConfig) -> Result<u64>;
+}
+
+#[derive(Debug, Clone)]
+pub struct GuildConfig {
+    pub guild_id: i64,
+    pub generator_type: i64,
+    pub voice_type: i64,
+    pub voice_region: Option<String>,
+    pub reaction_threshold: Option<i64>,
+    pub reaction_window: i64,
+    // メッセージに同じリアクションがこの数ついたら、だれのメッセージかと一緒に読み上げる。None なら読まない
+    pub reaction_milestone: Option<i64>,
+    // 新しい版の変更点を知らせるチャンネル。None なら知らせない
+    pub announce_channel_id: Option<i64>,
+    // ボイスチャンネルで話した声を文字起こしして書き込むチャンネル。None なら聞かない
+    pub transcribe_channel_id: Option<i64>,
+    // 貼られた曲の URL を /play の予約に入れるチャンネル。None なら使わない
+    pub song_request_channel_id: Option<i64>,
+    pub read_embed_title: bool,
+    pub read_embed_description: bool,
+    pub personality: String,
+    pub join_busy: String,
+    pub message_gap_ms: i64,
+    // 自動で入ったときに読み上げるテキストチャンネル。None なら自動では入らない
+    pub autojoin_channel_id: Option<i64>,
+    // この分数読み上げがなければ抜ける。None なら抜けない
+    pub idle_timeout_min: Option<i64>,
+    // メッセージの前に送った人の名前を読むか
+    pub read_name: bool,
+    // 返信のとき、誰への返信かを読むか
+    pub read_reply: bool,
+    // 読み方のわからない英単語をつづりからカタカナにして読むか
+    pub english_kana: bool,
+    // 読み上げ、/play の音楽、読み上げ中の音楽の音量 (%)
+    pub tts_volume: i64,
+    pub music_volume: i64,
+    pub ducked_volume: i64,
+    // 読み上げる言語 (ja, en)
+    pub language: String,
+    // 英語のサーバーで使う話者。None なら各自の声で読む
+    pub english_generator_type: Option<i64>,
+    pub english_voice_type: Option<i64>,
+    // 入退出のあいさつの形。None なら口調の文言を使う
+    pub greeting_template: Option<String>,
+    // あいさつのあと、この秒数は次のあいさつをしない
+    pub greeting_cooldown_sec: i64,
+    // /join したときに読み上げるチャンネル。voice ならボイスチャンネルのチャット、command ならコマンドを送ったチャンネル
+    pub read_channel: String,
+    // あいさつやコマンドの返事をどこに積むか。queue, next, interrupt (playback::Priority)
+    pub announce_priority: String,
+    // 短いメッセージをまとめて合成するまで待つミリ秒。0 ならまとめない
+    pub batch_window_ms: i64,
+    // 読み上げるチャンネルの下のスレッドも読むか
+    pub read_threads: bool,
+    // 入退出のあいさつの読み方。greeting, announce, off (greeting::GreetingMode)
+    pub greeting_mode: String,
+    // 最近読み上げたメッセージが編集されたら訂正を読むか
+    pub read_edits: bool,
+    // メッセージの前に読む名前。configured, nickname, username, none (text::NameReading)
+    pub name_reading: String,
+    // スタンプや GIF のリンクを「スタンプ、名前」「GIF」と読むか
+    pub read_stickers: bool,
+    // COEIROINK の話者にも、VOICEVOX の形態素解析で読みを決めてから渡すか
+    pub kana_reading: bool,
+    // 読み上げる前に待つミリ秒。待っている間に消されたり編集されたりしたメッセージは読まない。0 なら待たない
+    pub read_delay_ms: i64,
+}
+impl GuildConfig {
+    pub fn from_guild_id(guild_id: i64) -> Self {
+        GuildConfig {
+            guild_id,
+            generator_type: 0,
+            voice_type: 1,
+            voice_region: None,
+            reaction_threshold: None,
+            reaction_milestone: None,
+            announce_channel_id: None,
+            transcribe_channel_id: None,
+            song_request_channel_id: None,
+            reaction_window: 60,
+            read_embed_title: false,
+            read_embed_description: false,
+            personality: "casual".to_string(),
+            join_busy: "refuse".to_string(),
+            message_gap_ms: 0,
+            autojoin_channel_id: None,
+            idle_timeout_min: None,
+            read_name: true,
+            read_reply: true,
+            english_kana: false,
+            tts_volume: 100,
+            music_volume: 30,
+            ducked_volume: 5,
+            language: "ja".to_string(),
+            english_generator_type: None,
+            english_voice_type: None,
+            greeting_template: None,
+            greeting_cooldown_sec: 0,
+            read_channel: "voice".to_string(),
+            announce_priority: "next".to_string(),
+            batch_window_ms: 0,
+            read_threads: false,
+            greeting_mode: "greeting".to_string(),
+            read_edits: false,
+            name_reading: "configured".to_string(),
+            read_stickers: true,
+            kana_reading: false,
+            read_delay_ms: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl GuildConfigDB for sqlx::SqlitePool {
+    async fn get_guild_config(&self, guild_id: i64) -> Result<GuildConfig> {
+        let mut tx = self.begin().await?;
+        let q = query_as!(
+            GuildConfig,
+            "SELECT * FROM guild_config WHERE guild_id = ?",
+            guild_id
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or_else(|| anyhow!("key not found"))?;
+        tx.commit().await?;
+        Ok(q)
+    }
+    async fn get_guild_config_or_default(&self, guild_id: i64) -> Result<GuildConfig> {
+        match self.get_guild_config(guild_id).await {
+            Err(_) => {
+                query!("INSERT INTO guild_config (guild_id) VALUES (?)", guild_id)
+                    .execute(self)
+                    .await?;
+                Ok(GuildConfig::from_guild_id(guild_id))
+            }
+            Ok(q) => Ok(q),
+        }
+    }
+    async fn update_guild_config(&self, guild_config: &GuildConfig) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "UPDATE guild_config SET generator_type = ?,voice_type = ?,voice_region = ?,reaction_threshold = ?,reaction_window = ?,read_embed_title = ?,read_embed_description = ?,personality = ?,join_busy = ?,message_gap_ms = ?,autojoin_channel_id = ?,idle_timeout_min = ?,read_name = ?,read_reply = ?,english_kana = ?,tts_volume = ?,music_volume = ?,ducked_volume = ?,language = ?,english_generator_type = ?,english_voice_type = ?,greeting_template = ?,greeting_cooldown_sec = ?,read_channel = ?,announce_priority = ?,batch_window_ms = ?,read_threads = ?,greeting_mode = ?,read_edits = ?,name_reading = ?,read_stickers = ?,kana_reading = ?,read_delay_ms = ?,reaction_milestone = ?,announce_channel_id = ?,transcribe_channel_id = ?,song_request_channel_id = ? WHERE guild_id = ?",
+            guild_config.generator_type,
+            guild_config.voice_type,
+            guild_config.voice_region,
+            guild_config.reaction_threshold,
+            guild_config.reaction_window,
+            guild_config.read_embed_title,
+            guild_config.read_embed_description,
+            guild_config.personality,
+            guild_config.join_busy,
+            guild_config.message_gap_ms,
+            guild_config.autojoin_channel_id,
+            guild_config.idle_timeout_min,
+            guild_config.read_name,
+            guild_config.read_reply,
+            guild_config.english_kana,
+            guild_config.tts_volume,
+            guild_config.music_volume,
+            guild_config.ducked_volume,
+            guild_config.language,
+            guild_config.english_generator_type,
+            guild_config.english_voice_type,
+            guild_config.greeting_template,
+            guild_config.greeting_cooldown_sec,
+            guild_config.read_channel,
+            guild_config.announce_priority,
+            guild_config.batch_window_ms,
+            guild_config.read_threads,
+            guild_config.greeting_mode,
+            guild_config.read_edits,
+            guild_config.name_reading,
+            guild_config.read_stickers,
+            guild_config.kana_reading,
+            guild_config.read_delay_ms,
+            guild_config.reaction_milestone,
+            guild_config.announce_channel_id,
+            guild_config.transcribe_channel_id,
+            guild_config.song_request_channel_id,
+            guild_config.guild_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+}
+
+// 自動で入るきっかけにするユーザー。登録がなければ誰が入っても自動で入る
+#[async_trait]
+pub trait AutojoinDB {
+    async fn add_autojoin_user(&self, guild_id: i64, user_id: i64) -> Result<u64>;
+    async fn remove_autojoin_user(&self, guild_id: i64, user_id: i64) -> Result<u64>;
+    async fn clear_autojoin_users(&self, guild_id: i64) -> Result<u64>;
+    async fn get_autojoin_users(&self, guild_id: i64) -> Result<Vec<i64>>;
+}
+
+#[async_trait]
+impl AutojoinDB for sqlx::SqlitePool {
+    async fn add_autojoin_user(&self, guild_id: i64, user_id: i64) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "INSERT OR IGNORE INTO autojoin_user (guild_id,user_id) VALUES (?,?)",
+            guild_id,
+            user_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn remove_autojoin_user(&self, guild_id: i64, user_id: i64) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "DELETE FROM autojoin_user WHERE guild_id = ? AND user_id = ?",
+            guild_id,
+            user_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn clear_autojoin_users(&self, guild_id: i64) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!("DELETE FROM autojoin_user WHERE guild_id = ?", guild_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn get_autojoin_users(&self, guild_id: i64) -> Result<Vec<i64>> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "SELECT user_id FROM autojoin_user WHERE guild_id = ?",
+            guild_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.into_iter().map(|r| r.user_id).collect())
+    }
+}
+
+#[async_trait]
+pub trait FeatureFlagDB {
+    async fn set_feature_flag(&self, guild_id: i64, flag: &str, enabled: bool) -> Result<u64>;
+    async fn get_feature_flags(&self, guild_id: i64) -> Result<Vec<String>>;
+}
+
+#[async_trait]
+impl FeatureFlagDB for sqlx::SqlitePool {
+    async fn set_feature_flag(&self, guild_id: i64, flag: &str, enabled: bool) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = if enabled {
+            query!(
+                "INSERT OR IGNORE INTO feature_flag (guild_id,flag) VALUES (?,?)",
+                guild_id,
+                flag
+            )
+            .execute(&mut tx)
+            .await?
+        } else {
+            query!(
+                "DELETE FROM feature_flag WHERE guild_id = ? AND flag = ?",
+                guild_id,
+                flag
+            )
+            .execute(&mut tx)
+            .await?
+        };
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn get_feature_flags(&self, guild_id: i64) -> Result<Vec<String>> {
+        let mut tx = self.begin().await?;
+        let q = query!("SELECT flag FROM feature_flag WHERE guild_id = ?", guild_id)
+            .fetch_all(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(q.into_iter().map(|r| r.flag).collect())
+    }
+}
+
+// サーバーごとの呼び方。user_config の read_nickname より優先する
+#[async_trait]
+pub trait GuildNicknameDB {
+    // None なら消して、全体の呼び方に戻す
+    async fn set_guild_nickname(
+        &self,
+        guild_id: i64,
+        user_id: i64,
+        read_nickname: Option<&str>,
+    ) -> Result<u64>;
+    async fn get_guild_nickname(&self, guild_id: i64, user_id: i64) -> Result<Option<String>>;
+}
+
+#[async_trait]
+impl GuildNicknameDB for sqlx::SqlitePool {
+    async fn set_guild_nickname(
+        &self,
+        guild_id: i64,
+        user_id: i64,
+        read_nickname: Option<&str>,
+    ) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = match read_nickname {
+            Some(read_nickname) => {
+                query!(
+                    "INSERT OR REPLACE INTO guild_nickname (guild_id,user_id,read_nickname) VALUES (?,?,?)",
+                    guild_id,
+                    user_id,
+                    read_nickname
+                )
+                .execute(&mut tx)
+                .await?
+            }
+            None => {
+                query!(
+                    "DELETE FROM guild_nickname WHERE guild_id = ? AND user_id = ?",
+                    guild_id,
+                    user_id
+                )
+                .execute(&mut tx)
+                .await?
+            }
+        };
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn get_guild_nickname(&self, guild_id: i64, user_id: i64) -> Result<Option<String>> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "SELECT read_nickname FROM guild_nickname WHERE guild_id = ? AND user_id = ?",
+            guild_id,
+            user_id
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.map(|r| r.read_nickname))
+    }
+}
+
+#[async_trait]
+pub trait CommandPermissionDB {
+    async fn add_permission_role(&self, guild_id: i64, level: &str, role_id: i64) -> Result<u64>;
+    async fn remove_permission_role(&self, guild_id: i64, level: &str, role_id: i64)
+        -> Result<u64>;
+    // (段階, ロール) の一覧
+    async fn get_permission_roles(&self, guild_id: i64) -> Result<Vec<(String, i64)>>;
+}
+
+#[async_trait]
+impl CommandPermissionDB for sqlx::SqlitePool {
+    async fn add_permission_role(&self, guild_id: i64, level: &str, role_id: i64) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "INSERT OR IGNORE INTO command_permission (guild_id,level,role_id) VALUES (?,?,?)",
+            guild_id,
+            level,
+            role_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn remove_permission_role(
+        &self,
+        guild_id: i64,
+        level: &str,
+        role_id: i64,
+    ) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "DELETE FROM command_permission WHERE guild_id = ? AND level = ? AND role_id = ?",
+            guild_id,
+            level,
+            role_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn get_permission_roles(&self, guild_id: i64) -> Result<Vec<(String, i64)>> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "SELECT level,role_id FROM command_permission WHERE guild_id = ?",
+            guild_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.into_iter().map(|r| (r.level, r.role_id)).collect())
+    }
+}
+
+// /setup で選んだ設定をまとめて書き込む。途中で失敗したらどれも変えない
+#[async_trait]
+pub trait SetupDB {
+    async fn apply_setup(&self, guild_id: i64, draft: &SetupDraft) -> Result<()>;
+}
+
+#[async_trait]
+impl SetupDB for sqlx::SqlitePool {
+    async fn apply_setup(&self, guild_id: i64, draft: &SetupDraft) -> Result<()> {
+        let mut tx = self.begin().await?;
+        query!(
+            "INSERT OR IGNORE INTO guild_config (guild_id) VALUES (?)",
+            guild_id
+        )
+        .execute(&mut tx)
+        .await?;
+        let (generator_type, voice_type) = draft.voice.unzip();
+        query!(
+            "UPDATE guild_config SET read_channel = COALESCE(?, read_channel), generator_type = COALESCE(?, generator_type), voice_type = COALESCE(?, voice_type), greeting_mode = COALESCE(?, greeting_mode) WHERE guild_id = ?",
+            draft.read_channel,
+            generator_type,
+            voice_type,
+            draft.greeting_mode,
+            guild_id
+        )
+        .execute(&mut tx)
+        .await?;
+        // 管理のロールを選んだら、前に決めたロールと置き換える
+        if let Some(role) = draft.admin_role {
+            query!(
+                "DELETE FROM command_permission WHERE guild_id = ? AND level = 'admin'",
+                guild_id
+            )
+            .execute(&mut tx)
+            .await?;
+            if let Some(role) = role {
+                let role_id = role.0 as i64;
+                query!(
+                    "INSERT INTO command_permission (guild_id,level,role_id) VALUES (?,'admin',?)",
+                    guild_id,
+                    role_id
+                )
+                .execute(&mut tx)
+                .await?;
+            }
         }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+// サーバーごとに、変更点を知らせた最後の版
+#[async_trait]
+pub trait ChangelogDB {
+    // version をまだ知らせていなければ知らせたことにして true を返す
+    async fn claim_changelog(&self, guild_id: i64, version: &str) -> Result<bool>;
+}
+
+#[async_trait]
+impl ChangelogDB for sqlx::SqlitePool {
+    async fn claim_changelog(&self, guild_id: i64, version: &str) -> Result<bool> {
+        let mut tx = self.begin().await?;
+        let claimed = query!(
+            "INSERT INTO changelog_announced (guild_id,version) VALUES (?,?)
+            ON CONFLICT (guild_id) DO UPDATE SET version = excluded.version WHERE version != excluded.version",
+            guild_id,
+            version
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+        Ok(claimed > 0)
+    }
+}
+
+// 日ごとにたまっていく記録を、残す日数を過ぎたら消す
+#[async_trait]
+pub trait RetentionDB {
+    // days 日より前の日の記録を消し、消した行数を返す
+    async fn prune_usage_stats(&self, days: u64) -> Result<u64>;
+    async fn prune_join_count(&self, days: u64) -> Result<u64>;
+    // (usage_stats, join_count) の行数
+    async fn count_retained_rows(&self) -> Result<(i64, i64)>;
+}
+
+#[async_trait]
+impl RetentionDB for sqlx::SqlitePool {
+    async fn prune_usage_stats(&self, days: u64) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let modifier = format!("-{} days", days);
+        let q = query!(
+            "DELETE FROM usage_stats WHERE day < date('now','localtime',?)",
+            modifier
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn prune_join_count(&self, days: u64) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let modifier = format!("-{} days", days);
+        let q = query!(
+            "DELETE FROM join_count WHERE day < date('now','localtime',?)",
+            modifier
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn count_retained_rows(&self) -> Result<(i64, i64)> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            r#"SELECT (SELECT COUNT(*) FROM usage_stats) AS "usage_stats!: i64",(SELECT COUNT(*) FROM join_count) AS "join_count!: i64""#
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok((q.usage_stats, q.join_count))
     }
 }
-#[derive(Debug)]
-pub struct VoiceType {
-    pub id: i64,
-    pub name: String,
-    pub style_id: i64,
-    pub style_name: String,
-    pub generator_type: String,
+
+// あいさつの {count} に入れる、今日 (サーバーの時刻) 何回目の入室か
+#[async_trait]
+pub trait JoinCountDB {
+    // 入室を数える。前の日までの記録は消す
+    async fn record_join(&self, guild_id: i64, user_id: i64) -> Result<()>;
+    // (今日入った回数, いまの時 (0-23))
+    async fn get_join_count(&self, guild_id: i64, user_id: i64) -> Result<(i64, i64)>;
 }
 
 #[async_trait]
-impl UserConfigDB for sqlx::SqlitePool {
-    async fn get_user_config(&self, user_id: i64) -> Result<UserConfig> {
+impl JoinCountDB for sqlx::SqlitePool {
+    async fn record_join(&self, guild_id: i64, user_id: i64) -> Result<()> {
         let mut tx = self.begin().await?;
-        let q = query_as!(
-            UserConfig,
-            "SELECT * FROM user_config WHERE user_id = ?",
+        query!("DELETE FROM join_count WHERE day < date('now','localtime')")
+            .execute(&mut tx)
+            .await?;
+        query!(
+            "INSERT INTO join_count (guild_id,user_id,day,joins) VALUES (?,?,date('now','localtime'),1)
+            ON CONFLICT (guild_id,user_id,day) DO UPDATE SET joins = joins + 1",
+            guild_id,
+            user_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    async fn get_join_count(&self, guild_id: i64, user_id: i64) -> Result<(i64, i64)> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            r#"SELECT COALESCE((SELECT joins FROM join_count WHERE guild_id = ? AND user_id = ? AND day = date('now','localtime')),0) AS "joins!: i64",CAST(strftime('%H','now','localtime') AS INTEGER) AS "hour!: i64""#,
+            guild_id,
             user_id
         )
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok((q.joins, q.hour))
+    }
+}
+
+#[async_trait]
+pub trait NgWordDB {
+    async fn add_ng_word(&self, guild_id: i64, ng_word: &NgWord) -> Result<u64>;
+    async fn remove_ng_word(&self, guild_id: i64, word: &str) -> Result<u64>;
+    async fn get_ng_words(&self, guild_id: i64) -> Result<Vec<NgWord>>;
+}
+
+#[async_trait]
+impl NgWordDB for sqlx::SqlitePool {
+    async fn add_ng_word(&self, guild_id: i64, ng_word: &NgWord) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let action: &str = ng_word.action.into();
+        let q = query!(
+            "INSERT OR REPLACE INTO ng_word (guild_id,word,action) VALUES (?,?,?)",
+            guild_id,
+            ng_word.word,
+            action
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn remove_ng_word(&self, guild_id: i64, word: &str) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "DELETE FROM ng_word WHERE guild_id = ? AND word = ?",
+            guild_id,
+            word
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    // 知らない扱いの行は伏せるものとして扱う
+    async fn get_ng_words(&self, guild_id: i64) -> Result<Vec<NgWord>> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "SELECT word,action FROM ng_word WHERE guild_id = ? ORDER BY word",
+            guild_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.into_iter()
+            .map(|r| NgWord {
+                word: r.word,
+                action: NgAction::try_from(r.action.as_str()).unwrap_or(NgAction::Replace),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+pub trait DictPackDB {
+    async fn set_dict_pack(&self, guild_id: i64, name: &str, enabled: bool) -> Result<u64>;
+    async fn get_dict_packs(&self, guild_id: i64) -> Result<Vec<String>>;
+}
+
+#[async_trait]
+impl DictPackDB for sqlx::SqlitePool {
+    async fn set_dict_pack(&self, guild_id: i64, name: &str, enabled: bool) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = if enabled {
+            query!(
+                "INSERT OR IGNORE INTO dict_pack (guild_id,name) VALUES (?,?)",
+                guild_id,
+                name
+            )
+            .execute(&mut tx)
+            .await?
+        } else {
+            query!(
+                "DELETE FROM dict_pack WHERE guild_id = ? AND name = ?",
+                guild_id,
+                name
+            )
+            .execute(&mut tx)
+            .await?
+        };
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn get_dict_packs(&self, guild_id: i64) -> Result<Vec<String>> {
+        let mut tx = self.begin().await?;
+        let q = query!("SELECT name FROM dict_pack WHERE guild_id = ?", guild_id)
+            .fetch_all(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(q.into_iter().map(|r| r.name).collect())
+    }
+}
+
+#[async_trait]
+pub trait SoundEffectDB {
+    async fn add_sound_effect(&self, guild_id: i64, name: &str, data: &[u8]) -> Result<u64>;
+    async fn remove_sound_effect(&self, guild_id: i64, name: &str) -> Result<u64>;
+    async fn get_sound_effect(&self, guild_id: i64, name: &str) -> Result<Option<Vec<u8>>>;
+    // (名前, バイト数) の一覧
+    async fn get_sound_effect_sizes(&self, guild_id: i64) -> Result<Vec<(String, i64)>>;
+}
+
+#[async_trait]
+impl SoundEffectDB for sqlx::SqlitePool {
+    async fn add_sound_effect(&self, guild_id: i64, name: &str, data: &[u8]) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "INSERT OR REPLACE INTO sound_effect (guild_id,name,data) VALUES (?,?,?)",
+            guild_id,
+            name,
+            data
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn remove_sound_effect(&self, guild_id: i64, name: &str) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "DELETE FROM sound_effect WHERE guild_id = ? AND name = ?",
+            guild_id,
+            name
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn get_sound_effect(&self, guild_id: i64, name: &str) -> Result<Option<Vec<u8>>> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "SELECT data FROM sound_effect WHERE guild_id = ? AND name = ?",
+            guild_id,
+            name
+        )
         .fetch_optional(&mut tx)
-        .await?
-        .ok_or_else(|| anyhow!("key not found"))?;
+        .await?;
         tx.commit().await?;
-        Ok(q)
+        Ok(q.map(|r| r.data))
     }
-    async fn get_user_config_or_default(&self, user_id: i64) -> Result<UserConfig> {
-        match self.get_user_config(user_id).await {
-            Err(_) => {
-                query!("INSERT INTO user_config (user_id) VALUES (?)", user_id)
-                    .execute(self)
-                    .await?;
-                Ok(UserConfig::from_user_id(user_id))
-            }
-            Ok(q) => Ok(q),
+    async fn get_sound_effect_sizes(&self, guild_id: i64) -> Result<Vec<(String, i64)>> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            r#"SELECT name, LENGTH(data) AS "size!: i64" FROM sound_effect WHERE guild_id = ? ORDER BY name"#,
+            guild_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.into_iter().map(|r| (r.name, r.size)).collect())
+    }
+}
+
+#[async_trait]
+pub trait ReadFilterDB {
+    async fn add_read_filter(&self, read_filter: &ReadFilter) -> Result<u64>;
+    async fn remove_read_filter(&self, read_filter: &ReadFilter) -> Result<u64>;
+    async fn get_read_filters(&self, guild_id: i64) -> Result<Vec<ReadFilter>>;
+}
+
+// 読み上げないメッセージの条件。kind は bots, webhooks, user, role のどれか
+// user, role のときは target_id にその ID を入れる
+#[derive(Debug)]
+pub struct ReadFilter {
+    pub guild_id: i64,
+    pub kind: String,
+    pub target_id: i64,
+}
+
+impl ReadFilter {
+    pub fn matches(&self, author_id: u64, is_bot: bool, is_webhook: bool, roles: &[u64]) -> bool {
+        match self.kind.as_str() {
+            "bots" => is_bot && !is_webhook,
+            "webhooks" => is_webhook,
+            "user" => author_id as i64 == self.target_id,
+            "role" => roles.iter().any(|&role| role as i64 == self.target_id),
+            _ => false,
         }
     }
-    async fn update_user_config(&self, user_config: &UserConfig) -> Result<u64> {
+}
+
+#[async_trait]
+impl ReadFilterDB for sqlx::SqlitePool {
+    async fn add_read_filter(&self, read_filter: &ReadFilter) -> Result<u64> {
         let mut tx = self.begin().await?;
-        let q = query!("UPDATE user_config SET hello = ?,bye = ?,voice_type = ?,generator_type = ?,read_nickname = ? WHERE user_id = ?",
-        user_config.hello,user_config.bye,user_config.voice_type,user_config.generator_type,user_config.read_nickname,user_config.user_id)
-        .execute(&mut tx).await?;
+        let q = query!(
+            "INSERT OR IGNORE INTO read_filter (guild_id,kind,target_id) VALUES (?,?,?)",
+            read_filter.guild_id,
+            read_filter.kind,
+            read_filter.target_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q.rows_affected())
+    }
+    async fn remove_read_filter(&self, read_filter: &ReadFilter) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "DELETE FROM read_filter WHERE guild_id = ? AND kind = ? AND target_id = ?",
+            read_filter.guild_id,
+            read_filter.kind,
+            read_filter.target_id
+        )
+        .execute(&mut tx)
+        .await?;
         tx.commit().await?;
         Ok(q.rows_affected())
     }
+    async fn get_read_filters(&self, guild_id: i64) -> Result<Vec<ReadFilter>> {
+        let mut tx = self.begin().await?;
+        let q = query_as!(
+            ReadFilter,
+            "SELECT * FROM read_filter WHERE guild_id = ?",
+            guild_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(q)
+    }
 }
 
 #[async_trait]
 pub trait DictDB {
+    // user_id が 0 の単語はみんなのメッセージに当てる辞書
     async fn update_dict(&self, dict: &Dict) -> Result<u64>;
-    async fn get_dict(&self, word: &str) -> Result<String>;
+    // まとめて登録する。途中で失敗したら一つも登録しない
+    // read_dict.json から取り込んだ、そのサーバーだけで使う単語。取り込むたびに入れ替える
+    async fn import_guild_dict(&self, guild_id: i64, entries: &[Dict]) -> Result<usize>;
+    async fn get_guild_dict(&self, guild_id: i64) -> Result<Vec<Dict>>;
     async fn get_dict_all(&self) -> Result<Vec<Dict>>;
     async fn remove(&self, word: &str) -> Result<()>;
+    async fn edit_dict(&self, word: &str, read_word: &str, priority: Option<i64>)
+        -> Result<String>;
+    // /mydict で登録した、その人のメッセージにだけ当てる単語
+    async fn update_user_dict(&self, user_id: i64, word: &str, read_word: &str) -> Result<()>;
+    async fn remove_user_dict(&self, user_id: i64, word: &str) -> Result<u64>;
+    async fn get_user_dict(&self, user_id: i64) -> Result<Vec<Dict>>;
 }
 
 #[async_trait]
@@ -91,9 +1322,10 @@ impl DictDB for sqlx::SqlitePool {
     async fn update_dict(&self, dict: &Dict) -> Result<u64> {
         let mut tx = self.begin().await?;
         let q = query!(
-            "INSERT OR REPLACE INTO dict VALUES (?,?)",
+            "INSERT OR REPLACE INTO dict (word,read_word,priority,user_id) VALUES (?,?,?,0)",
             dict.word,
-            dict.read_word
+            dict.read_word,
+            dict.priority
         )
         .execute(&mut tx)
         .await
@@ -101,59 +1333,181 @@ impl DictDB for sqlx::SqlitePool {
         tx.commit().await?;
         Ok(q)
     }
-    async fn get_dict(&self, word: &str) -> Result<String> {
+    async fn import_guild_dict(&self, guild_id: i64, entries: &[Dict]) -> Result<usize> {
         let mut tx = self.begin().await?;
-        let dict = query!("SELECT read_word FROM dict WHERE word = ?", word)
-            .fetch_optional(&mut tx)
-            .await?
-            .ok_or_else(|| anyhow!("key not found"))?
-            .read_word;
+        query!("DELETE FROM guild_dict WHERE guild_id = ?", guild_id)
+            .execute(&mut tx)
+            .await?;
+        for dict in entries.iter() {
+            query!(
+                "INSERT OR REPLACE INTO guild_dict (guild_id,word,read_word,priority) VALUES (?,?,?,?)",
+                guild_id,
+                dict.word,
+                dict.read_word,
+                dict.priority
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(entries.len())
+    }
+    async fn get_guild_dict(&self, guild_id: i64) -> Result<Vec<Dict>> {
+        let mut tx = self.begin().await?;
+        let dict = sqlx::query_as!(
+            Dict,
+            "SELECT word,read_word,priority FROM guild_dict WHERE guild_id = ? ORDER BY priority DESC, length(word) DESC, word",
+            guild_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
         tx.commit().await?;
         Ok(dict)
     }
     async fn get_dict_all(&self) -> Result<Vec<Dict>> {
         let mut tx = self.begin().await?;
-        let dict = sqlx::query_as!(Dict, "SELECT word,read_word FROM dict")
-            .fetch_all(&mut tx)
-            .await?;
+        // 置き換えを試す順 (優先度が高い順、同じなら長い順)
+        let dict = sqlx::query_as!(
+            Dict,
+            "SELECT word,read_word,priority FROM dict WHERE user_id = 0 ORDER BY priority DESC, length(word) DESC, word"
+        )
+        .fetch_all(&mut tx)
+        .await?;
         tx.commit().await?;
         Ok(dict)
     }
     async fn remove(&self, word: &str) -> Result<()> {
         let mut tx = self.begin().await.unwrap();
-        sqlx::query!("DELETE FROM dict WHERE word = ?", word)
+        sqlx::query!("DELETE FROM dict WHERE word = ? AND user_id = 0", word)
             .execute(&mut tx)
             .await?;
         tx.commit().await?;
         Ok(())
     }
+    // 登録済みの単語の読みを書き換え、前の読みを返す。priority が None なら優先度はそのまま
+    async fn edit_dict(
+        &self,
+        word: &str,
+        read_word: &str,
+        priority: Option<i64>,
+    ) -> Result<String> {
+        let mut tx = self.begin().await?;
+        let before = query!(
+            "SELECT read_word FROM dict WHERE word = ? AND user_id = 0",
+            word
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or_else(|| anyhow!("{} は辞書に登録されてないよ", word))?
+        .read_word;
+        query!(
+            "UPDATE dict SET read_word = ?, priority = COALESCE(?, priority) WHERE word = ? AND user_id = 0",
+            read_word,
+            priority,
+            word
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(before)
+    }
+    async fn update_user_dict(&self, user_id: i64, word: &str, read_word: &str) -> Result<()> {
+        let mut tx = self.begin().await?;
+        query!(
+            "INSERT OR REPLACE INTO dict (word,read_word,priority,user_id) VALUES (?,?,0,?)",
+            word,
+            read_word,
+            user_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    async fn remove_user_dict(&self, user_id: i64, word: &str) -> Result<u64> {
+        let mut tx = self.begin().await?;
+        let q = query!(
+            "DELETE FROM dict WHERE word = ? AND user_id = ?",
+            word,
+            user_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+        Ok(q)
+    }
+    async fn get_user_dict(&self, user_id: i64) -> Result<Vec<Dict>> {
+        let mut tx = self.begin().await?;
+        let dict = sqlx::query_as!(
+            Dict,
+            "SELECT word,read_word,priority FROM dict WHERE user_id = ? ORDER BY length(word) DESC, word",
+            user_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(dict)
+    }
 }
 
 #[async_trait]
 pub trait SpeakerDB {
-    async fn speaker_name_to_id(&self, name: &str) -> Result<(Generators, u32)>;
     async fn speaker_id_to_name(&self, generator_type: Generators, id: u32) -> Result<String>;
     async fn insert_speaker_data(&self) -> Result<()>;
+    async fn reload_speakers(&self) -> Result<SpeakerDiff>;
     async fn get_speaker(&self, id: usize) -> Result<VoiceType>;
     async fn get_all_speakers(&self) -> Result<Vec<VoiceType>>;
 }
 
+// エンジンから取り直した話者と DB の話者の差分
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SpeakerDiff {
+    pub added: Vec<VoiceType>,
+    pub removed: Vec<VoiceType>,
+}
+
+// エンジンの話者一覧を DB の行の形で取得する。id は DB に入れるまで 0
+async fn fetch_speakers(generator: Generators) -> Result<Vec<VoiceType>> {
+    let generator_type: &str = generator.into();
+    Ok(engine(generator)
+        .await?
+        .speakers()
+        .await?
+        .into_iter()
+        .flat_map(|speaker| {
+            speaker.styles.into_iter().map(move |style| VoiceType {
+                id: 0,
+                name: speaker.name.clone(),
+                style_id: style.id as i64,
+                style_name: style.name,
+                generator_type: generator_type.to_string(),
+            })
+        })
+        .collect())
+}
+
+// (generator_type, style_id) が同じなら同じ声とみなす
+fn diff_speakers(existing: &[VoiceType], fetched: &[VoiceType]) -> SpeakerDiff {
+    let same = |a: &VoiceType, b: &VoiceType| {
+        a.generator_type == b.generator_type && a.style_id == b.style_id
+    };
+    SpeakerDiff {
+        added: fetched
+            .iter()
+            .filter(|f| !existing.iter().any(|e| same(e, f)))
+            .cloned()
+            .collect(),
+        removed: existing
+            .iter()
+            .filter(|e| !fetched.iter().any(|f| same(e, f)))
+            .cloned()
+            .collect(),
+    }
+}
+
 #[async_trait]
 impl SpeakerDB for sqlx::SqlitePool {
-    async fn speaker_name_to_id(&self, name: &str) -> Result<(Generators, u32)> {
-        let mut tx = self.begin().await.unwrap();
-        let q = query!(
-            "SELECT generator_type,style_id FROM speakers WHERE style_name = ?",
-            name
-        )
-        .fetch_one(&mut tx)
-        .await?;
-        tx.commit().await?;
-        Ok((
-            Generators::try_from(q.generator_type.as_str())?,
-            q.style_id as u32,
-        ))
-    }
     async fn speaker_id_to_name(&self, generator_type: Generators, id: u32) -> Result<String> {
         let mut tx = self.begin().await.unwrap();
         let str: &str = generator_type.into();
@@ -168,18 +1522,6 @@ impl SpeakerDB for sqlx::SqlitePool {
         Ok(format!("{} {}", q.name, q.style_name))
     }
     async fn insert_speaker_data(&self) -> Result<()> {
-        dotenv::dotenv().ok();
-        #[derive(Deserialize, Clone)]
-        struct Style {
-            pub name: String,
-            pub id: u32,
-        }
-        #[derive(Deserialize, Clone)]
-        struct Speaker {
-            pub name: String,
-            pub styles: Vec<Style>,
-        }
-
         let mut tx = self.begin().await.unwrap();
         query!("DELETE FROM speakers")
             .execute(&mut tx)
@@ -189,25 +1531,19 @@ impl SpeakerDB for sqlx::SqlitePool {
             .execute(&mut tx)
             .await
             .unwrap();
-        let voicevox_voice_types: Result<Vec<Speaker>> = async {
-            let base_url = std::env::var("BASE_URL_VOICEVOX")?;
-            let query_url = format!("{}/speakers", base_url);
-            let client = reqwest::Client::new();
-            let res = client.get(query_url).send().await?;
-
-            //info!("{:?}",res.text().await);
-            res.json().await.map_err(|e| e.into())
-        }
-        .await;
-        if let Ok(voicevox_voice_types) = voicevox_voice_types {
-            for speaker in voicevox_voice_types {
-                for style in speaker.styles {
+        for generator in [
+            Generators::VOICEVOX,
+            Generators::COEIROINK,
+            Generators::OPENJTALK,
+        ] {
+            if let Ok(speakers) = fetch_speakers(generator).await {
+                for speaker in speakers {
                     query!(
                         "INSERT INTO speakers (name,style_id,style_name,generator_type) VALUES (?,?,?,?)",
                         speaker.name,
-                        style.id,
-                        style.name,
-                        "VOICEVOX"
+                        speaker.style_id,
+                        speaker.style_name,
+                        speaker.generator_type
                     )
                     .execute(&mut tx)
                     .await
@@ -215,32 +1551,52 @@ impl SpeakerDB for sqlx::SqlitePool {
                 }
             }
         }
-        let coeiro_voice_types: Result<Vec<Speaker>> = async {
-            let base_url = std::env::var("BASE_URL_COEIRO")?;
-            let query_url = format!("{}/speakers", base_url);
-            let client = reqwest::Client::new();
-            let res = client.get(query_url).send().await?;
-            res.json().await.map_err(|e| e.into())
+        tx.commit().await?;
+        Ok(())
+    }
+    // 起動中に取り直す。select menu が id を使うので、残った声の id は変えない
+    async fn reload_speakers(&self) -> Result<SpeakerDiff> {
+        let existing = self.get_all_speakers().await?;
+        let mut diff = SpeakerDiff::default();
+        for generator in [
+            Generators::VOICEVOX,
+            Generators::COEIROINK,
+            Generators::OPENJTALK,
+        ] {
+            let generator_type: &str = generator.into();
+            // 応答しなかったエンジンの声は消さない
+            let fetched = match fetch_speakers(generator).await {
+                Ok(fetched) => fetched,
+                Err(_) => continue,
+            };
+            let existing = existing
+                .iter()
+                .filter(|speaker| speaker.generator_type == generator_type)
+                .cloned()
+                .collect::<Vec<_>>();
+            let SpeakerDiff { added, removed } = diff_speakers(&existing, &fetched);
+            diff.added.extend(added);
+            diff.removed.extend(removed);
         }
-        .await;
-        if let Ok(coeiro_voice_types) = coeiro_voice_types {
-            for speaker in coeiro_voice_types {
-                for style in speaker.styles {
-                    query!(
-                        "INSERT INTO speakers (name,style_id,style_name,generator_type) VALUES (?,?,?,?)",
-                        speaker.name,
-                        style.id,
-                        style.name,
-                        "COEIROINK"
-                    )
-                    .execute(&mut tx)
-                    .await
-                    .unwrap();
-                }
-            }
+        let mut tx = self.begin().await?;
+        for speaker in diff.added.iter() {
+            query!(
+                "INSERT INTO speakers (name,style_id,style_name,generator_type) VALUES (?,?,?,?)",
+                speaker.name,
+                speaker.style_id,
+                speaker.style_name,
+                speaker.generator_type
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        for speaker in diff.removed.iter() {
+            query!("DELETE FROM speakers WHERE id = ?", speaker.id)
+                .execute(&mut tx)
+                .await?;
         }
         tx.commit().await?;
-        Ok(())
+        Ok(diff)
     }
     async fn get_speaker(&self, id: usize) -> Result<VoiceType> {
         let id = id as i64;
@@ -261,3 +1617,120 @@ impl SpeakerDB for sqlx::SqlitePool {
         Ok(q)
     }
 }
+
+#[test]
+fn read_filter_test() {
+    let filter = |kind: &str, target_id| ReadFilter {
+        guild_id: 0,
+        kind: kind.to_string(),
+        target_id,
+    };
+    // webhook のメッセージも bot 扱いなので、bots では webhook を除く
+    assert!(filter("bots", 0).matches(1, true, false, &[]));
+    assert!(!filter("bots", 0).matches(1, true, true, &[]));
+    assert!(filter("webhooks", 0).matches(1, true, true, &[]));
+    assert!(!filter("webhooks", 0).matches(1, false, false, &[]));
+    assert!(filter("user", 1).matches(1, false, false, &[]));
+    assert!(!filter("user", 2).matches(1, false, false, &[]));
+    assert!(filter("role", 3).matches(1, false, false, &[2, 3]));
+    assert!(!filter("role", 4).matches(1, false, false, &[2, 3]));
+}
+
+#[test]
+fn diff_speakers_test() {
+    let voice = |id, style_id, generator_type: &str| VoiceType {
+        id,
+        name: "name".to_string(),
+        style_id,
+        style_name: "style".to_string(),
+        generator_type: generator_type.to_string(),
+    };
+    let existing = vec![
+        voice(1, 0, "VOICEVOX"),
+        voice(2, 1, "VOICEVOX"),
+        voice(3, 1, "COEIROINK"),
+    ];
+    let fetched = vec![
+        voice(0, 1, "VOICEVOX"),
+        voice(0, 2, "VOICEVOX"),
+        voice(0, 1, "COEIROINK"),
+    ];
+    assert_eq!(
+        diff_speakers(&existing, &fetched),
+        SpeakerDiff {
+            added: vec![voice(0, 2, "VOICEVOX")],
+            removed: vec![voice(1, 0, "VOICEVOX")],
+        }
+    );
+    assert_eq!(diff_speakers(&existing, &existing), SpeakerDiff::default());
+}
+
+#[test]
+fn portable_user_config_test() {
+    let mut user_config = UserConfig::from_user_id(1);
+    user_config.read_nickname = Some("なっぷ".to_string());
+    let portable = PortableUserConfig::new(&user_config, Some("ずんだもん ノーマル".to_string()));
+    let json = serde_json::to_string(&portable).unwrap();
+    assert_eq!(
+        serde_json::from_str::<PortableUserConfig>(&json).unwrap(),
+        portable
+    );
+
+    // ignored がなくても読める
+    let old: PortableUserConfig = serde_json::from_str(
+        r#"{"hello":"やあ","bye":"またね","generator_type":1,"voice_type":3,"voice_name":null,"read_nickname":null}"#,
+    )
+    .unwrap();
+    let mut user_config = UserConfig::from_user_id(2);
+    old.apply(&mut user_config, 1, 8);
+    assert_eq!(user_config.hello, "やあ");
+    assert_eq!(user_config.voice_type, 8);
+    assert!(!user_config.ignored);
+    assert_eq!(user_config.synthesis_params(), SynthesisParams::default());
+    assert_eq!(user_config.quiet_start_min, None);
+    assert_eq!(user_config.utc_offset_min, DEFAULT_UTC_OFFSET_MIN);
+
+    // 範囲の外の値は丸める
+    let fast: PortableUserConfig = serde_json::from_str(
+        r#"{"hello":"やあ","bye":"またね","generator_type":1,"voice_type":3,"voice_name":null,"read_nickname":null,"synthesis_params":{"speed_scale":5.0,"pitch_scale":0.1}}"#,
+    )
+    .unwrap();
+    fast.apply(&mut user_config, 1, 8);
+    assert_eq!(user_config.speed_scale, SPEED_RANGE.1);
+    assert_eq!(user_config.pitch_scale, 0.1);
+}
+
+#[test]
+fn voice_preset_test() {
+    let mut user_config = UserConfig::from_user_id(1);
+    user_config.voice_type = 3;
+    user_config.speed_scale = 1.5;
+    let preset = VoicePreset::new(&user_config, "早口");
+    assert_eq!(preset.name, "早口");
+
+    let mut user_config = UserConfig::from_user_id(1);
+    user_config.hello = "やあ".to_string();
+    preset.apply(&mut user_config);
+    assert_eq!(user_config.voice_type, 3);
+    assert_eq!(user_config.speed_scale, 1.5);
+    // 声と話速・高さのほかは変えない
+    assert_eq!(user_config.hello, "やあ");
+}
+
+#[test]
+fn resolve_voice_test() {
+    let mut guild_config = GuildConfig::from_guild_id(1);
+    guild_config.generator_type = 1;
+    guild_config.voice_type = 8;
+    // 声を選んでいなければサーバーのデフォルトボイスを使う
+    let mut user_config = UserConfig::from_user_id(2);
+    user_config.resolve_voice(&guild_config);
+    assert_eq!((user_config.generator_type, user_config.voice_type), (1, 8));
+    assert!(!user_config.voice_chosen);
+
+    // サーバーのデフォルトボイスと同じ声でも、選んだ声は変えない
+    user_config.set_voice(1, 8);
+    guild_config.voice_type = 3;
+    user_config.resolve_voice(&guild_config);
+    assert_eq!((user_config.generator_type, user_config.voice_type), (1, 8));
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::model::id::{InteractionId, UserId};
+use tokio::sync::Mutex;
+
+use crate::Dict;
+
+// /add の確認ボタンの custom_id の接頭辞。後ろに confirm:ID か cancel:ID をつける
+pub const DICT_ADD_PREFIX: &str = "dict_add:";
+// 確認ボタンがこの時間押されなければ登録しない
+const PENDING_TIMEOUT: Duration = Duration::from_secs(600);
+
+// /add で登録する前に知らせること
+#[derive(Debug, PartialEq, Eq)]
+pub enum DictConflict {
+    // もう登録されている単語の読みを変える。前の読みを持つ
+    Overwrite(String),
+    // 読みに単語そのものが入っている
+    SelfExpanding,
+    // 読みから置き換えをたどると単語に戻る。たどった単語を順に持つ
+    Cycle(Vec<String>),
+}
+
+impl DictConflict {
+    pub fn message(&self, entry: &Dict) -> String {
+        match self {
+            Self::Overwrite(before) => format!(
+                "{} はもう {} と読むように登録されているよ。{} に上書きするね",
+                entry.word, before, entry.read_word
+            ),
+            Self::SelfExpanding => format!(
+                "読みの {} に {} が入っているので、置き換えた後にまた置き換わることがあるよ",
+                entry.read_word, entry.word
+            ),
+            Self::Cycle(words) => format!("{} と読み方がぐるぐる回るよ", words.join(" → ")),
+        }
+    }
+}
+
+// dict に entry を足したときに知らせること。なければ空
+pub fn check_dict_add(dict: &[Dict], entry: &Dict) -> Vec<DictConflict> {
+    let mut conflicts = Vec::new();
+    if entry.word.is_empty() {
+        return conflicts;
+    }
+    if let Some(old) = dict.iter().find(|w| w.word == entry.word) {
+        if old.read_word != entry.read_word {
+            conflicts.push(DictConflict::Overwrite(old.read_word.clone()));
+        }
+    }
+    if entry.read_word.contains(&entry.word) {
+        conflicts.push(DictConflict::SelfExpanding);
+    }
+    if let Some(words) = find_cycle(dict, entry) {
+        conflicts.push(DictConflict::Cycle(words));
+    }
+    conflicts
+}
+
+// entry の読みに入っている単語の読み、その読みに入っている単語の読み…とたどって entry の単語に戻る一番短い道
+fn find_cycle(dict: &[Dict], entry: &Dict) -> Option<Vec<String>> {
+    let read_of = |word: &str| -> Option<&str> {
+        if word == entry.word {
+            Some(&entry.read_word)
+        } else {
+            dict.iter()
+                .find(|w| w.word == word)
+                .map(|w| w.read_word.as_str())
+        }
+    };
+    let words = dict
+        .iter()
+        .map(|w| w.word.as_str())
+        .filter(|word| !word.is_empty() && *word != entry.word)
+        .collect::<Vec<_>>();
+    // 単語からたどってきた前の単語
+    let mut previous: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([entry.word.as_str()]);
+    while let Some(word) = queue.pop_front() {
+        let read = read_of(word)?;
+        // 自分の読みに自分が入っているのは SelfExpanding で知らせる
+        if word != entry.word && read.contains(&entry.word) {
+            let mut path = vec![entry.word.clone(), word.to_string()];
+            let mut current = word;
+            while let Some(prev) = previous.get(current).filter(|prev| **prev != entry.word) {
+                path.insert(1, prev.to_string());
+                current = prev;
+            }
+            path.push(entry.word.clone());
+            return Some(path);
+        }
+        for next in words.iter().filter(|next| read.contains(*next)) {
+            if !previous.contains_key(next) {
+                previous.insert(next, word);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+// 確認待ちの /add。登録する人と、登録する単語
+type Pending = (UserId, Dict, Instant);
+
+#[derive(Clone, Default)]
+pub struct PendingDictAdds {
+    pending: Arc<Mutex<HashMap<InteractionId, Pending>>>,
+}
+
+impl PendingDictAdds {
+    pub async fn insert(&self, id: InteractionId, user: UserId, entry: Dict) {
+        let mut pending = self.pending.lock().await;
+        let now = Instant::now();
+        pending.retain(|_, (_, _, at)| now.saturating_duration_since(*at) < PENDING_TIMEOUT);
+        pending.insert(id, (user, entry, now));
+    }
+
+    // ボタンが押されたら確認待ちから外す。/add した人以外は選べない
+    pub async fn take(&self, id: InteractionId, user: UserId) -> anyhow::Result<Dict> {
+        let mut pending = self.pending.lock().await;
+        match pending.get(&id) {
+            Some((author, _, _)) if *author != user => {
+                Err(anyhow::anyhow!("/add した人だけが選べるよ"))
+            }
+            Some((_, _, at)) if at.elapsed() >= PENDING_TIMEOUT => {
+                pending.remove(&id);
+                Err(anyhow::anyhow!("時間がたったので登録をやめたよ"))
+            }
+            Some(_) => Ok(pending.remove(&id).unwrap().1),
+            None => Err(anyhow::anyhow!("もう登録するかどうか決まっているよ")),
+        }
+    }
+}
+
+#[test]
+fn check_dict_add_test() {
+    let dict = |word: &str, read_word: &str| Dict {
+        word: word.to_string(),
+        read_word: read_word.to_string(),
+        priority: 0,
+    };
+    let entries = vec![dict("a", "b"), dict("b", "c"), dict("c", "x")];
+
+    assert!(check_dict_add(&entries, &dict("d", "でぃー")).is_empty());
+    assert!(check_dict_add(&entries, &dict("a", "b")).is_empty());
+    assert_eq!(
+        check_dict_add(&entries, &dict("a", "z")),
+        vec![DictConflict::Overwrite("b".to_string())]
+    );
+    assert_eq!(
+        check_dict_add(&entries, &dict("w", "ww")),
+        vec![DictConflict::SelfExpanding]
+    );
+    // x を a と読むと、a → b → c → x と置き換えが戻ってくる
+    assert_eq!(
+        check_dict_add(&entries, &dict("x", "a")),
+        vec![DictConflict::Cycle(vec![
+            "x".to_string(),
+            "a".to_string(),
+            "b".to_string(),
+            "c".to_string(),
+            "x".to_string()
+        ])]
+    );
+    assert_eq!(
+        check_dict_add(&[dict("b", "a")], &dict("a", "b")),
+        vec![DictConflict::Cycle(vec![
+            "a".to_string(),
+            "b".to_string(),
+            "a".to_string()
+        ])]
+    );
+}
+
+#[tokio::test]
+async fn pending_dict_adds_test() {
+    let pending = PendingDictAdds::default();
+    let entry = Dict {
+        word: "a".to_string(),
+        read_word: "b".to_string(),
+        priority: 0,
+    };
+    pending.insert(InteractionId(1), UserId(2), entry).await;
+    assert!(pending.take(InteractionId(1), UserId(3)).await.is_err());
+    assert_eq!(
+        pending
+            .take(InteractionId(1), UserId(2))
+            .await
+            .unwrap()
+            .read_word,
+        "b"
+    );
+    assert!(pending.take(InteractionId(1), UserId(2)).await.is_err());
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use super::db::DictPackDB;
+use crate::Dict;
+
+// crate に同梱している辞書パック。/dict_pack enable で有効にしたサーバーだけで使う
+pub struct DictPack {
+    pub name: &'static str,
+    pub description: &'static str,
+    // {"単語": "読み", ...} の形式
+    json: &'static str,
+}
+
+pub const PACKS: [DictPack; 3] = [
+    DictPack {
+        name: "net",
+        description: "ネットスラング",
+        json: include_str!("../../dict_packs/net.json"),
+    },
+    DictPack {
+        name: "gaming",
+        description: "ゲーム用語",
+        json: include_str!("../../dict_packs/gaming.json"),
+    },
+    DictPack {
+        name: "programming",
+        description: "プログラミング用語",
+        json: include_str!("../../dict_packs/programming.json"),
+    },
+];
+
+impl DictPack {
+    pub fn find(name: &str) -> Result<&'static DictPack> {
+        PACKS
+            .iter()
+            .find(|pack| pack.name == name)
+            .ok_or_else(|| anyhow!("{} という辞書パックはないよ", name))
+    }
+
+    fn entries(&self) -> Vec<Dict> {
+        let entries: HashMap<String, String> = serde_json::from_str(self.json).unwrap_or_default();
+        entries
+            .into_iter()
+            .map(|(word, read_word)| Dict {
+                word,
+                read_word,
+                priority: 0,
+            })
+            .collect()
+    }
+}
+
+// サーバーで有効にしたパックの単語を、長い順に並べて返す
+// パックを消したり名前を変えたりしても、残った名前は無視する
+pub async fn guild_pack_entries(database: &sqlx::SqlitePool, guild_id: i64) -> Vec<Dict> {
+    let names = database.get_dict_packs(guild_id).await.unwrap_or_default();
+    pack_entries(&names)
+}
+
+fn pack_entries(names: &[String]) -> Vec<Dict> {
+    let mut entries = PACKS
+        .iter()
+        .filter(|pack| names.iter().any(|name| name == pack.name))
+        .flat_map(|pack| pack.entries())
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.word.len().cmp(&a.word.len()).then(a.word.cmp(&b.word)));
+    entries
+}
+
+#[test]
+fn dict_pack_test() {
+    for pack in PACKS.iter() {
+        assert!(!pack.entries().is_empty(), "{} is broken", pack.name);
+    }
+    assert!(DictPack::find("net").is_ok());
+    assert!(DictPack::find("removed").is_err());
+
+    let entries = pack_entries(&["programming".to_string(), "removed".to_string()]);
+    assert_eq!(
+        entries.len(),
+        DictPack::find("programming").unwrap().entries().len()
+    );
+    // 長い単語を先に当てる
+    assert!(entries
+        .windows(2)
+        .all(|pair| pair[0].word.len() >= pair[1].word.len()));
+    assert!(pack_entries(&[]).is_empty());
+}
@@ -0,0 +1,985 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use serenity::async_trait;
+use tokio::sync::Semaphore;
+use tracing::info;
+
+use super::{app_config::config, temp::temp_path};
+use crate::handler::Generators;
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Style {
+    pub name: String,
+    pub id: u32,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Speaker {
+    pub name: String,
+    pub styles: Vec<Style>,
+}
+
+// /set_speed, /set_pitch で設定できる範囲。VOICEVOX の audio_query の speedScale, pitchScale と同じ意味
+pub const SPEED_RANGE: (f64, f64) = (0.5, 2.0);
+pub const PITCH_RANGE: (f64, f64) = (-0.15, 0.15);
+
+// 話者ごとの初期値から変える合成のパラメーター
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SynthesisParams {
+    pub speed_scale: f64,
+    pub pitch_scale: f64,
+}
+
+impl Default for SynthesisParams {
+    fn default() -> Self {
+        SynthesisParams {
+            speed_scale: 1.0,
+            pitch_scale: 0.0,
+        }
+    }
+}
+
+// 音声合成エンジンとのやりとり
+#[async_trait]
+pub trait Engine: Send + Sync {
+    async fn speakers(&self) -> Result<Vec<Speaker>>;
+    async fn synthesize(
+        &self,
+        text: &str,
+        style_id: u32,
+        params: SynthesisParams,
+    ) -> Result<Vec<u8>>;
+    // 漢字を含む文章を、カタカナの読みにする。style_id は読みを調べるのに使う話者
+    // MeCab や Lindera は組み込まず、エンジンの audio_query が返す読みを使う
+    async fn to_kana(&self, text: &str, style_id: u32) -> Result<String> {
+        let _ = (text, style_id);
+        Err(anyhow!("this engine cannot read text as kana"))
+    }
+}
+
+// audio_query の結果を、エンジンの初期値の代わりに params で合成するよう書き換える
+fn patch_audio_query(query: &mut Value, params: SynthesisParams) {
+    query["speedScale"] = json!(params.speed_scale);
+    query["pitchScale"] = json!(params.pitch_scale);
+}
+
+// audio_query の kana (AquesTalk 風の記法) から、アクセントや区切りの記号を除く
+fn plain_kana(kana: &str) -> String {
+    kana.chars()
+        .filter(|c| !matches!(c, '\'' | '_' | '/'))
+        .collect()
+}
+
+// エンジンの API の種類
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Protocol {
+    // VOICEVOX と古い COEIROINK の /audio_query, /synthesis
+    Legacy,
+    // 新しい COEIROINK の /v1 以下
+    CoeiroinkV2,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 2] = [Self::Legacy, Self::CoeiroinkV2];
+
+    pub fn speakers_path(self) -> &'static str {
+        match self {
+            Self::Legacy => "/speakers",
+            Self::CoeiroinkV2 => "/v1/speakers",
+        }
+    }
+}
+
+// base_url ごとに判定した API の種類
+fn protocols() -> &'static Mutex<HashMap<String, Protocol>> {
+    static PROTOCOLS: OnceLock<Mutex<HashMap<String, Protocol>>> = OnceLock::new();
+    PROTOCOLS.get_or_init(Default::default)
+}
+
+// /v1/speakers に答えるなら新しい COEIROINK とみなす
+async fn detect_protocol(client: &Client, base_url: &str) -> Protocol {
+    if let Some(protocol) = protocols().lock().unwrap().get(base_url) {
+        return *protocol;
+    }
+    let protocol = match client
+        .get(format!(
+            "{}{}",
+            base_url,
+            Protocol::CoeiroinkV2.speakers_path()
+        ))
+        .send()
+        .await
+    {
+        Ok(res) if res.status().is_success() => Protocol::CoeiroinkV2,
+        Ok(_) => Protocol::Legacy,
+        // 接続できないときは判定を保留する
+        Err(_) => return Protocol::Legacy,
+    };
+    protocols()
+        .lock()
+        .unwrap()
+        .insert(base_url.to_string(), protocol);
+    protocol
+}
+
+// エンジンが落ちたら、入れ替わったときに備えて判定をやり直す
+pub fn forget_protocol(base_url: &str) {
+    protocols().lock().unwrap().remove(base_url);
+    v2_speaker_cache().lock().unwrap().remove(base_url);
+}
+
+// エンジンごとに同時に投げる合成の数。メッセージが続いてもエンジンが詰まらないようにする
+const MAX_CONCURRENT_SYNTHESES: usize = 2;
+const MAX_ATTEMPTS: u32 = 3;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+// COEIROINK は合成に時間がかかるので長めに待つ
+fn timeout(generator: Generators) -> Duration {
+    match generator {
+        Generators::COEIROINK => Duration::from_secs(60),
+        Generators::VOICEVOX => Duration::from_secs(30),
+        Generators::OPENJTALK => Duration::from_secs(10),
+    }
+}
+
+// base_url ごとの合成の同時実行数の制限
+fn semaphore(base_url: &str) -> Arc<Semaphore> {
+    static SEMAPHORES: OnceLock<Mutex<HashMap<String, Arc<Semaphore>>>> = OnceLock::new();
+    SEMAPHORES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .entry(base_url.to_string())
+        .or_insert_with(|| Arc::new(Semaphore::new(MAX_CONCURRENT_SYNTHESES)))
+        .clone()
+}
+
+fn retry_delay(attempt: u32) -> Duration {
+    INITIAL_RETRY_DELAY * 2_u32.pow(attempt)
+}
+
+// 混んでいるときや一時的に落ちているときだけやり直す
+fn is_retryable(status: Option<StatusCode>, timeout_or_connect: bool) -> bool {
+    timeout_or_connect
+        || status.is_some_and(|status| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        })
+}
+
+// 失敗したら間隔を倍にしながら MAX_ATTEMPTS 回まで送り直す
+async fn send_with_retry(request: RequestBuilder) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+        let res = request
+            .try_clone()
+            .ok_or_else(|| anyhow!("request cannot be retried"))?
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+        match res {
+            Ok(res) => return Ok(res),
+            Err(e)
+                if attempt + 1 < MAX_ATTEMPTS
+                    && is_retryable(e.status(), e.is_timeout() || e.is_connect()) =>
+            {
+                info!("engine request failed, retrying: {}", e);
+                tokio::time::sleep(retry_delay(attempt)).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+pub async fn engine(generator: Generators) -> Result<Arc<dyn Engine>> {
+    if generator == Generators::OPENJTALK {
+        return Ok(Arc::new(OpenJTalkEngine::from_config()?));
+    }
+    if generator == Generators::VOICEVOX {
+        let instances = config().voicevox_instances();
+        if instances.len() > 1 {
+            // 設定は起動中に変わらないので、プールは 1 つだけ作って使い回す
+            static POOL: OnceLock<Arc<EnginePool>> = OnceLock::new();
+            return Ok(POOL
+                .get_or_init(|| Arc::new(EnginePool::new(generator, instances)))
+                .clone());
+        }
+    }
+    let base_url = config()
+        .engine_location(generator)
+        .ok_or_else(|| anyhow!("{} is not configured", <&str>::from(generator)))?
+        .to_string();
+    http_engine(generator, base_url).await
+}
+
+// エンジンの種類ごとの HTTP クライアント。接続を使い回すよう、合成のたびには作らない
+fn client(generator: Generators) -> Result<Client> {
+    static CLIENTS: OnceLock<Mutex<HashMap<Generators, Client>>> = OnceLock::new();
+    let mut clients = CLIENTS.get_or_init(Default::default).lock().unwrap();
+    if let Some(client) = clients.get(&generator) {
+        return Ok(client.clone());
+    }
+    let client = Client::builder().timeout(timeout(generator)).build()?;
+    clients.insert(generator, client.clone());
+    Ok(client)
+}
+
+// エンジンの種類と base_url ごとのエンジン。プロトコルがわかったものだけ覚えておく
+type EngineKey = (Generators, String);
+
+fn http_engines() -> &'static Mutex<HashMap<EngineKey, Arc<dyn Engine>>> {
+    static HTTP_ENGINES: OnceLock<Mutex<HashMap<EngineKey, Arc<dyn Engine>>>> = OnceLock::new();
+    HTTP_ENGINES.get_or_init(Default::default)
+}
+
+async fn http_engine(generator: Generators, base_url: String) -> Result<Arc<dyn Engine>> {
+    let key = (generator, base_url);
+    if let Some(engine) = http_engines().lock().unwrap().get(&key) {
+        return Ok(engine.clone());
+    }
+    let (generator, base_url) = key;
+    let client = client(generator)?;
+    let engine: Arc<dyn Engine> = match detect_protocol(&client, &base_url).await {
+        Protocol::Legacy => Arc::new(LegacyEngine {
+            base_url: base_url.clone(),
+            client,
+        }),
+        Protocol::CoeiroinkV2 => Arc::new(CoeiroinkV2Engine {
+            base_url: base_url.clone(),
+            client,
+        }),
+    };
+    // 繋がらずに判定できなかったときは、次に繋がったときにもう一度調べる
+    if protocols().lock().unwrap().contains_key(&base_url) {
+        http_engines()
+            .lock()
+            .unwrap()
+            .insert((generator, base_url), engine.clone());
+    }
+    Ok(engine)
+}
+
+// 落ちたインスタンスに、この間は合成を振り分けない
+const INSTANCE_DOWN_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct PoolState {
+    // 重みつきラウンドロビンで次に使う番号
+    counter: u64,
+    // 落ちたインスタンスと、また使ってみる時刻
+    down_until: HashMap<String, Instant>,
+    // インスタンスごとの話者のスタイル ID。VOICEVOX と Nemo のように話者が違うことがある
+    styles: HashMap<String, Vec<u32>>,
+}
+
+// base_url ごとの、エンジンのプールでの状態
+fn pool_state() -> &'static Mutex<PoolState> {
+    static POOL_STATE: OnceLock<Mutex<PoolState>> = OnceLock::new();
+    POOL_STATE.get_or_init(Default::default)
+}
+
+// プールのインスタンスが使えるかどうか。/status で表示する
+pub fn pool_statuses() -> Vec<(String, bool)> {
+    let state = pool_state().lock().unwrap();
+    let now = Instant::now();
+    config()
+        .voicevox_instances()
+        .into_iter()
+        .map(|(base_url, _)| {
+            let alive = state
+                .down_until
+                .get(&base_url)
+                .is_none_or(|until| *until <= now);
+            (base_url, alive)
+        })
+        .collect()
+}
+
+// 重みの合計で counter を割った余りが入る区間のインスタンスを選ぶ
+fn weighted_pick(weights: &[u32], counter: u64) -> Option<usize> {
+    let total = weights.iter().map(|weight| *weight as u64).sum::<u64>();
+    if total == 0 {
+        return None;
+    }
+    let mut rest = counter % total;
+    weights.iter().position(|weight| {
+        if rest < *weight as u64 {
+            true
+        } else {
+            rest -= *weight as u64;
+            false
+        }
+    })
+}
+
+// VOICEVOX 互換のエンジン (VOICEVOX Nemo や別のマシンのエンジンなど) を複数立て、合成を重みつきで振り分ける
+// 失敗したインスタンスはしばらく外し、ほかのインスタンスで合成し直す
+pub struct EnginePool {
+    generator: Generators,
+    // (base_url, 重み)
+    instances: Vec<(String, u32)>,
+}
+
+impl EnginePool {
+    pub fn new(generator: Generators, instances: Vec<(String, u32)>) -> Self {
+        EnginePool {
+            generator,
+            instances,
+        }
+    }
+
+    // style_id を持っているインスタンスを、振り分ける順に並べる。落ちているものは最後に回す
+    fn order(&self, style_id: u32) -> Vec<usize> {
+        let mut state = pool_state().lock().unwrap();
+        let now = Instant::now();
+        let (up, down): (Vec<usize>, Vec<usize>) = (0..self.instances.len())
+            .filter(|i| {
+                // 話者をまだ取っていなければどのインスタンスでも試す
+                state
+                    .styles
+                    .get(&self.instances[*i].0)
+                    .is_none_or(|styles| styles.contains(&style_id))
+            })
+            .partition(|i| {
+                state
+                    .down_until
+                    .get(&self.instances[*i].0)
+                    .is_none_or(|until| *until <= now)
+            });
+        let weights = up.iter().map(|i| self.instances[*i].1).collect::<Vec<_>>();
+        let mut order = Vec::new();
+        if let Some(first) = weighted_pick(&weights, state.counter) {
+            state.counter += 1;
+            order.push(up[first]);
+        }
+        for i in up {
+            if !order.contains(&i) {
+                order.push(i);
+            }
+        }
+        order.extend(down);
+        order
+    }
+
+    fn mark(&self, base_url: &str, alive: bool) {
+        let mut state = pool_state().lock().unwrap();
+        if alive {
+            state.down_until.remove(base_url);
+        } else {
+            info!("engine instance {} is down", base_url);
+            state.down_until.insert(
+                base_url.to_string(),
+                Instant::now() + INSTANCE_DOWN_DURATION,
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl Engine for EnginePool {
+    // 全インスタンスの話者をまとめる。同じスタイル ID は最初のインスタンスのものを使う
+    async fn speakers(&self) -> Result<Vec<Speaker>> {
+        let mut speakers: Vec<Speaker> = Vec::new();
+        let mut error = None;
+        for (base_url, _) in self.instances.iter() {
+            match http_engine(self.generator, base_url.clone())
+                .await?
+                .speakers()
+                .await
+            {
+                Ok(instance_speakers) => {
+                    self.mark(base_url, true);
+                    pool_state().lock().unwrap().styles.insert(
+                        base_url.clone(),
+                        instance_speakers
+                            .iter()
+                            .flat_map(|speaker| speaker.styles.iter().map(|style| style.id))
+                            .collect(),
+                    );
+                    for speaker in instance_speakers {
+                        let known = speakers.iter().any(|known| {
+                            known.styles.iter().any(|style| {
+                                speaker.styles.iter().any(|other| other.id == style.id)
+                            })
+                        });
+                        if !known {
+                            speakers.push(speaker);
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.mark(base_url, false);
+                    error = Some(e);
+                }
+            }
+        }
+        match error {
+            Some(e) if speakers.is_empty() => Err(e),
+            _ => Ok(speakers),
+        }
+    }
+    async fn synthesize(
+        &self,
+        text: &str,
+        style_id: u32,
+        params: SynthesisParams,
+    ) -> Result<Vec<u8>> {
+        let mut error = anyhow!("no engine instance has style {}", style_id);
+        for i in self.order(style_id) {
+            let (base_url, _) = &self.instances[i];
+            let engine = http_engine(self.generator, base_url.clone()).await?;
+            match engine.synthesize(text, style_id, params).await {
+                Ok(wav) => {
+                    self.mark(base_url, true);
+                    return Ok(wav);
+                }
+                Err(e) => {
+                    self.mark(base_url, false);
+                    error = e;
+                }
+            }
+        }
+        Err(error)
+    }
+    async fn to_kana(&self, text: &str, style_id: u32) -> Result<String> {
+        let mut error = anyhow!("no engine instance has style {}", style_id);
+        for i in self.order(style_id) {
+            let (base_url, _) = &self.instances[i];
+            let engine = http_engine(self.generator, base_url.clone()).await?;
+            match engine.to_kana(text, style_id).await {
+                Ok(kana) => {
+                    self.mark(base_url, true);
+                    return Ok(kana);
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+}
+
+pub struct LegacyEngine {
+    base_url: String,
+    client: Client,
+}
+
+#[async_trait]
+impl Engine for LegacyEngine {
+    async fn speakers(&self) -> Result<Vec<Speaker>> {
+        let res = self
+            .client
+            .get(format!(
+                "{}{}",
+                self.base_url,
+                Protocol::Legacy.speakers_path()
+            ))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(res.json().await?)
+    }
+    async fn synthesize(
+        &self,
+        text: &str,
+        style_id: u32,
+        params: SynthesisParams,
+    ) -> Result<Vec<u8>> {
+        let _permit = semaphore(&self.base_url).acquire_owned().await?;
+        let query = [("text", text), ("speaker", &style_id.to_string())];
+        let voice_query_url = format!("{}/audio_query", self.base_url);
+        let res = send_with_retry(self.client.post(voice_query_url).query(&query)).await?;
+        let mut synthesis_body: Value = res.json().await?;
+        patch_audio_query(&mut synthesis_body, params);
+        let synthesis_arg = [("speaker", style_id)];
+        let synthesis_url = format!("{}/synthesis", self.base_url);
+        let synthesis_res = send_with_retry(
+            self.client
+                .post(synthesis_url)
+                .json(&synthesis_body)
+                .query(&synthesis_arg),
+        )
+        .await?;
+        Ok(synthesis_res.bytes().await?.to_vec())
+    }
+    // VOICEVOX の audio_query は読みを kana で返す
+    async fn to_kana(&self, text: &str, style_id: u32) -> Result<String> {
+        let _permit = semaphore(&self.base_url).acquire_owned().await?;
+        let query = [("text", text), ("speaker", &style_id.to_string())];
+        let voice_query_url = format!("{}/audio_query", self.base_url);
+        let res = send_with_retry(self.client.post(voice_query_url).query(&query)).await?;
+        let body: Value = res.json().await?;
+        let kana = body["kana"]
+            .as_str()
+            .ok_or_else(|| anyhow!("audio_query has no kana"))?;
+        Ok(plain_kana(kana))
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct V2Style {
+    style_name: String,
+    style_id: u32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct V2Speaker {
+    speaker_name: String,
+    speaker_uuid: String,
+    styles: Vec<V2Style>,
+}
+
+impl From<V2Speaker> for Speaker {
+    fn from(speaker: V2Speaker) -> Self {
+        Speaker {
+            name: speaker.speaker_name,
+            styles: speaker
+                .styles
+                .into_iter()
+                .map(|style| Style {
+                    name: style.style_name,
+                    id: style.style_id,
+                })
+                .collect(),
+        }
+    }
+}
+
+// v2 の合成は話者の UUID とスタイル ID の組で指定する
+fn v2_synthesis_body(
+    speakers: &[V2Speaker],
+    style_id: u32,
+    text: &str,
+    prosody: &Value,
+    params: SynthesisParams,
+) -> Result<Value> {
+    let speaker = speakers
+        .iter()
+        .find(|speaker| {
+            speaker
+                .styles
+                .iter()
+                .any(|style| style.style_id == style_id)
+        })
+        .ok_or_else(|| anyhow!("style {} not found", style_id))?;
+    Ok(json!({
+        "speakerUuid": speaker.speaker_uuid,
+        "styleId": style_id,
+        "text": text,
+        "prosodyDetail": prosody.get("detail").cloned().unwrap_or_else(|| json!([])),
+        "speedScale": params.speed_scale,
+        "volumeScale": 1.0,
+        "pitchScale": params.pitch_scale,
+        "intonationScale": 1.0,
+        "prePhonemeLength": 0.1,
+        "postPhonemeLength": 0.1,
+        "outputSamplingRate": 24000,
+    }))
+}
+
+pub struct CoeiroinkV2Engine {
+    base_url: String,
+    client: Client,
+}
+
+// base_url ごとの v2 の話者。合成のたびに /v1/speakers を聞かないよう覚えておく
+fn v2_speaker_cache() -> &'static Mutex<HashMap<String, Vec<V2Speaker>>> {
+    static V2_SPEAKERS: OnceLock<Mutex<HashMap<String, Vec<V2Speaker>>>> = OnceLock::new();
+    V2_SPEAKERS.get_or_init(Default::default)
+}
+
+impl CoeiroinkV2Engine {
+    // エンジンに聞いて覚え直す。ヘルスチェックや /reload_speakers で speakers から呼ばれる
+    async fn v2_speakers(&self) -> Result<Vec<V2Speaker>> {
+        let res = self
+            .client
+            .get(format!(
+                "{}{}",
+                self.base_url,
+                Protocol::CoeiroinkV2.speakers_path()
+            ))
+            .send()
+            .await?
+            .error_for_status()?;
+        let speakers: Vec<V2Speaker> = res.json().await?;
+        v2_speaker_cache()
+            .lock()
+            .unwrap()
+            .insert(self.base_url.clone(), speakers.clone());
+        Ok(speakers)
+    }
+
+    // 覚えている話者に style_id がなければ、話者が増えたかもしれないので聞き直す
+    async fn v2_speakers_with(&self, style_id: u32) -> Result<Vec<V2Speaker>> {
+        let cached = v2_speaker_cache()
+            .lock()
+            .unwrap()
+            .get(&self.base_url)
+            .cloned();
+        match cached {
+            Some(speakers) if has_v2_style(&speakers, style_id) => Ok(speakers),
+            _ => self.v2_speakers().await,
+        }
+    }
+}
+
+fn has_v2_style(speakers: &[V2Speaker], style_id: u32) -> bool {
+    speakers.iter().any(|speaker| {
+        speaker
+            .styles
+            .iter()
+            .any(|style| style.style_id == style_id)
+    })
+}
+
+#[async_trait]
+impl Engine for CoeiroinkV2Engine {
+    async fn speakers(&self) -> Result<Vec<Speaker>> {
+        Ok(self
+            .v2_speakers()
+            .await?
+            .into_iter()
+            .map(Speaker::from)
+            .collect())
+    }
+    async fn synthesize(
+        &self,
+        text: &str,
+        style_id: u32,
+        params: SynthesisParams,
+    ) -> Result<Vec<u8>> {
+        let _permit = semaphore(&self.base_url).acquire_owned().await?;
+        let prosody: Value = send_with_retry(
+            self.client
+                .post(format!("{}/v1/estimate_prosody", self.base_url))
+                .json(&json!({ "text": text })),
+        )
+        .await?
+        .json()
+        .await?;
+        let body = v2_synthesis_body(
+            &self.v2_speakers_with(style_id).await?,
+            style_id,
+            text,
+            &prosody,
+            params,
+        )?;
+        let synthesis_res = send_with_retry(
+            self.client
+                .post(format!("{}/v1/synthesis", self.base_url))
+                .json(&body),
+        )
+        .await?;
+        Ok(synthesis_res.bytes().await?.to_vec())
+    }
+}
+
+// open_jtalk を起動して合成する。open_jtalk_dic に辞書、open_jtalk_bin に実行ファイル (省略時は PATH から探す) を設定する
+pub struct OpenJTalkEngine {
+    bin: String,
+    dic: String,
+    voices: Vec<PathBuf>,
+}
+
+impl OpenJTalkEngine {
+    fn from_config() -> Result<Self> {
+        let config = config();
+        let (voice, dic) = match (&config.open_jtalk_voice, &config.open_jtalk_dic) {
+            (Some(voice), Some(dic)) => (voice, dic),
+            _ => return Err(anyhow!("OpenJTalk is not configured")),
+        };
+        Ok(OpenJTalkEngine {
+            bin: config.open_jtalk_bin.clone(),
+            dic: dic.clone(),
+            voices: htsvoices(Path::new(voice))?,
+        })
+    }
+}
+
+// ファイルならその声だけ、フォルダなら中の .htsvoice を名前順に並べ、その順番をスタイル ID にする
+fn htsvoices(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut voices = std::fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "htsvoice"))
+        .collect::<Vec<_>>();
+    voices.sort();
+    Ok(voices)
+}
+
+fn open_jtalk_speakers(voices: &[PathBuf]) -> Vec<Speaker> {
+    vec![Speaker {
+        name: "OpenJTalk".to_string(),
+        styles: voices
+            .iter()
+            .enumerate()
+            .map(|(id, voice)| Style {
+                name: voice
+                    .file_stem()
+                    .map_or_else(String::new, |stem| stem.to_string_lossy().to_string()),
+                id: id as u32,
+            })
+            .collect(),
+    }]
+}
+
+// 設定されていて、声のファイルと open_jtalk が見つかれば使える
+pub fn open_jtalk_available() -> bool {
+    match OpenJTalkEngine::from_config() {
+        Ok(engine) => {
+            !engine.voices.is_empty()
+                && engine.voices.iter().all(|voice| voice.is_file())
+                && Path::new(&engine.dic).is_dir()
+                && std::process::Command::new(&engine.bin)
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .status()
+                    .is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+fn run_open_jtalk(
+    bin: &str,
+    dic: &str,
+    voice: &Path,
+    text: &str,
+    params: SynthesisParams,
+) -> Result<Vec<u8>> {
+    let (input, output) = (
+        temp_path(None, "ojt", "txt")?,
+        temp_path(None, "ojt", "wav")?,
+    );
+    std::fs::write(&input, text)?;
+    let status = std::process::Command::new(bin)
+        .arg("-x")
+        .arg(dic)
+        .arg("-m")
+        .arg(voice)
+        .args(["-r", &params.speed_scale.to_string()])
+        // pitchScale は 1 で 1 オクターブなので半音に直す
+        .args(["-fm", &(params.pitch_scale * 12.0).to_string()])
+        .arg("-ow")
+        .arg(&output)
+        .arg(&input)
+        .status();
+    let wav = match status {
+        Ok(status) if status.success() => std::fs::read(&output).map_err(anyhow::Error::from),
+        Ok(status) => Err(anyhow!("open_jtalk failed: {}", status)),
+        Err(e) => Err(anyhow!("failed to start open_jtalk: {}", e)),
+    };
+    std::fs::remove_file(&input).ok();
+    std::fs::remove_file(&output).ok();
+    wav
+}
+
+#[async_trait]
+impl Engine for OpenJTalkEngine {
+    async fn speakers(&self) -> Result<Vec<Speaker>> {
+        Ok(open_jtalk_speakers(&self.voices))
+    }
+    async fn synthesize(
+        &self,
+        text: &str,
+        style_id: u32,
+        params: SynthesisParams,
+    ) -> Result<Vec<u8>> {
+        let voice = self
+            .voices
+            .get(style_id as usize)
+            .ok_or_else(|| anyhow!("style {} not found", style_id))?
+            .clone();
+        let _permit = semaphore(&self.bin).acquire_owned().await?;
+        let (bin, dic, text) = (self.bin.clone(), self.dic.clone(), text.to_string());
+        let task =
+            tokio::task::spawn_blocking(move || run_open_jtalk(&bin, &dic, &voice, &text, params));
+        tokio::time::timeout(timeout(Generators::OPENJTALK), task).await??
+    }
+}
+
+#[test]
+fn weighted_pick_test() {
+    let picks = (0..6)
+        .map(|counter| weighted_pick(&[2, 1], counter).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(picks, vec![0, 0, 1, 0, 0, 1]);
+    // 重みが 0 のインスタンスには振り分けない
+    assert_eq!(weighted_pick(&[0, 3], 1), Some(1));
+    assert_eq!(weighted_pick(&[0], 0), None);
+    assert_eq!(weighted_pick(&[], 0), None);
+}
+
+#[test]
+fn legacy_speakers_fixture_test() {
+    let speakers: Vec<Speaker> =
+        serde_json::from_str(include_str!("../../fixtures/voicevox_speakers.json")).unwrap();
+    assert_eq!(speakers.len(), 2);
+    assert_eq!(speakers[0].name, "四国めたん");
+    assert_eq!(
+        speakers[0].styles[0],
+        Style {
+            name: "ノーマル".to_string(),
+            id: 2
+        }
+    );
+}
+
+#[test]
+fn v2_speakers_fixture_test() {
+    let speakers: Vec<V2Speaker> =
+        serde_json::from_str(include_str!("../../fixtures/coeiroink_v2_speakers.json")).unwrap();
+    // 覚えている話者にないスタイルなら聞き直す
+    assert!(has_v2_style(&speakers, 5));
+    assert!(!has_v2_style(&speakers, 9999));
+    let converted: Vec<Speaker> = speakers.into_iter().map(Speaker::from).collect();
+    assert_eq!(
+        converted[0],
+        Speaker {
+            name: "つくよみちゃん".to_string(),
+            styles: vec![
+                Style {
+                    name: "れいせい".to_string(),
+                    id: 0
+                },
+                Style {
+                    name: "おしとやか".to_string(),
+                    id: 5
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn v2_synthesis_body_test() {
+    let speakers: Vec<V2Speaker> =
+        serde_json::from_str(include_str!("../../fixtures/coeiroink_v2_speakers.json")).unwrap();
+    let prosody: Value =
+        serde_json::from_str(include_str!("../../fixtures/coeiroink_v2_prosody.json")).unwrap();
+    let params = SynthesisParams {
+        speed_scale: 1.5,
+        pitch_scale: -0.05,
+    };
+    let body = v2_synthesis_body(&speakers, 5, "こんにちは", &prosody, params).unwrap();
+    assert_eq!(body["speakerUuid"], "3c37646f-3881-5374-2a83-149267990abc");
+    assert_eq!(body["styleId"], 5);
+    assert_eq!(body["text"], "こんにちは");
+    assert_eq!(body["prosodyDetail"], prosody["detail"]);
+    assert_eq!(body["speedScale"], 1.5);
+    assert_eq!(body["pitchScale"], -0.05);
+
+    let params = SynthesisParams::default();
+    let body = v2_synthesis_body(&speakers, 1, "こんにちは", &prosody, params).unwrap();
+    assert_eq!(body["speakerUuid"], "292ea286-3d5f-f1cc-157c-66462a6a9d08");
+
+    assert!(v2_synthesis_body(&speakers, 42, "こんにちは", &prosody, params).is_err());
+}
+
+#[test]
+fn patch_audio_query_test() {
+    let mut query =
+        json!({"accent_phrases": [], "speedScale": 1.0, "pitchScale": 0.0, "volumeScale": 1.0});
+    patch_audio_query(
+        &mut query,
+        SynthesisParams {
+            speed_scale: 1.2,
+            pitch_scale: 0.1,
+        },
+    );
+    assert_eq!(query["speedScale"], 1.2);
+    assert_eq!(query["pitchScale"], 0.1);
+    // ほかの値はエンジンが返したまま
+    assert_eq!(query["volumeScale"], 1.0);
+}
+
+#[test]
+fn retry_test() {
+    assert_eq!(retry_delay(0), Duration::from_millis(200));
+    assert_eq!(retry_delay(2), Duration::from_millis(800));
+    assert!(is_retryable(Some(StatusCode::SERVICE_UNAVAILABLE), false));
+    assert!(is_retryable(Some(StatusCode::TOO_MANY_REQUESTS), false));
+    assert!(is_retryable(None, true));
+    // 文章や話者がおかしいときはやり直しても同じ
+    assert!(!is_retryable(Some(StatusCode::UNPROCESSABLE_ENTITY), false));
+    assert!(!is_retryable(None, false));
+}
+
+#[test]
+fn plain_kana_test() {
+    assert_eq!(plain_kana("コンニチワ'/セ'カイ"), "コンニチワセカイ");
+    assert_eq!(plain_kana("キョ'オワ、テ_ンキガ'"), "キョオワ、テンキガ");
+}
+
+#[tokio::test]
+async fn semaphore_test() {
+    let first = semaphore("http://semaphore-test");
+    let _permits = first
+        .clone()
+        .acquire_many_owned(MAX_CONCURRENT_SYNTHESES as u32)
+        .await
+        .unwrap();
+    // 同じエンジンなら同じ制限を共有する
+    assert!(semaphore("http://semaphore-test").try_acquire().is_err());
+    assert!(semaphore("http://other").try_acquire().is_ok());
+}
+
+#[tokio::test]
+async fn http_engine_cache_test() {
+    let base_url = "http://engine-cache-test".to_string();
+    protocols()
+        .lock()
+        .unwrap()
+        .insert(base_url.clone(), Protocol::Legacy);
+    // プロトコルがわかっていれば、同じエンジンとクライアントを使い回す
+    let first = http_engine(Generators::VOICEVOX, base_url.clone())
+        .await
+        .unwrap();
+    let second = http_engine(Generators::VOICEVOX, base_url.clone())
+        .await
+        .unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    let other = http_engine(Generators::COEIROINK, base_url).await.unwrap();
+    assert!(!Arc::ptr_eq(&first, &other));
+}
+
+#[test]
+fn open_jtalk_speakers_test() {
+    let voices = vec![
+        PathBuf::from("voices/mei_normal.htsvoice"),
+        PathBuf::from("voices/nitech_jp_atr503_m001.htsvoice"),
+    ];
+    assert_eq!(
+        open_jtalk_speakers(&voices),
+        vec![Speaker {
+            name: "OpenJTalk".to_string(),
+            styles: vec![
+                Style {
+                    name: "mei_normal".to_string(),
+                    id: 0
+                },
+                Style {
+                    name: "nitech_jp_atr503_m001".to_string(),
+                    id: 1
+                },
+            ],
+        }]
+    );
+    // フォルダでなければそのファイルだけ
+    assert_eq!(
+        htsvoices(Path::new("voices/mei_normal.htsvoice")).unwrap(),
+        vec![PathBuf::from("voices/mei_normal.htsvoice")]
+    );
+}
@@ -0,0 +1,86 @@
+use anyhow::anyhow;
+
+use super::db::FeatureFlagDB;
+
+// サーバーごとに試せる実験的な機能。/config beta で有効にする
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Feature {
+    // メッセージの先頭の [speed:1.5] や [voice:ずんだもん] で読み方を変える
+    InlineTags,
+    // 「> 名前: セリフ」の引用をその人の声で読む
+    Dialogue,
+    // 声を決めていない人に、ボイスチャンネルにいる間だけ別々の声を順番に配る
+    VoiceRotation,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Self::InlineTags, Self::Dialogue, Self::VoiceRotation];
+}
+
+impl TryFrom<&str> for Feature {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "inline_tags" => Ok(Self::InlineTags),
+            "dialogue" => Ok(Self::Dialogue),
+            "voice_rotation" => Ok(Self::VoiceRotation),
+            _ => Err(anyhow!("no such feature")),
+        }
+    }
+}
+
+impl From<Feature> for &str {
+    fn from(feature: Feature) -> Self {
+        match feature {
+            Feature::InlineTags => "inline_tags",
+            Feature::Dialogue => "dialogue",
+            Feature::VoiceRotation => "voice_rotation",
+        }
+    }
+}
+
+// サーバーで有効になっている機能
+#[derive(Debug, Default)]
+pub struct FeatureFlags {
+    enabled: Vec<Feature>,
+}
+
+impl FeatureFlags {
+    // サーバー外や読めないときはどれも無効
+    pub async fn load(database: &sqlx::SqlitePool, guild_id: Option<i64>) -> Self {
+        let flags = match guild_id {
+            Some(guild_id) => database
+                .get_feature_flags(guild_id)
+                .await
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        Self::from_names(&flags)
+    }
+
+    // 消えた機能の名前は無視する
+    fn from_names(names: &[String]) -> Self {
+        FeatureFlags {
+            enabled: names
+                .iter()
+                .filter_map(|name| Feature::try_from(name.as_str()).ok())
+                .collect(),
+        }
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.enabled.contains(&feature)
+    }
+}
+
+#[test]
+fn feature_flags_test() {
+    for feature in Feature::ALL {
+        let name: &str = feature.into();
+        assert_eq!(Feature::try_from(name).unwrap(), feature);
+    }
+    let flags = FeatureFlags::from_names(&["inline_tags".to_string(), "removed".to_string()]);
+    assert!(flags.is_enabled(Feature::InlineTags));
+    assert!(!FeatureFlags::default().is_enabled(Feature::InlineTags));
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use tokio::sync::Mutex;
+
+// この間に bot のチャンネルから出た人を、一緒に移動したとみなす
+pub const FOLLOW_WINDOW: Duration = Duration::from_secs(30);
+
+struct Departure {
+    user_id: UserId,
+    // 抜けただけなら None
+    destination: Option<ChannelId>,
+    at: Instant,
+}
+
+// bot のチャンネルから出た人の行き先を覚えておき、みんなで移動したときについていく
+#[derive(Clone, Default)]
+pub struct MoveTracker {
+    departures: Arc<Mutex<HashMap<GuildId, Vec<Departure>>>>,
+}
+
+impl MoveTracker {
+    pub async fn record(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        destination: Option<ChannelId>,
+        now: Instant,
+    ) {
+        let mut departures = self.departures.lock().await;
+        let departures = departures.entry(guild_id).or_default();
+        departures.retain(|departure| {
+            departure.user_id != user_id
+                && now.saturating_duration_since(departure.at) < FOLLOW_WINDOW
+        });
+        departures.push(Departure {
+            user_id,
+            destination,
+            at: now,
+        });
+    }
+
+    // チャンネルが空になったときに呼ぶ。FOLLOW_WINDOW 以内に出た人の過半数が同じチャンネルへ移っていればそこを返す
+    pub async fn take_destination(&self, guild_id: GuildId, now: Instant) -> Option<ChannelId> {
+        let departures = self.departures.lock().await.remove(&guild_id)?;
+        let recent = departures
+            .iter()
+            .filter(|departure| now.saturating_duration_since(departure.at) < FOLLOW_WINDOW)
+            .collect::<Vec<_>>();
+        let mut counts = HashMap::<ChannelId, usize>::new();
+        for departure in recent.iter() {
+            if let Some(destination) = departure.destination {
+                *counts.entry(destination).or_default() += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .filter(|(_, count)| count * 2 > recent.len())
+            .map(|(destination, _)| destination)
+    }
+}
+
+#[tokio::test]
+async fn move_tracker_test() {
+    let tracker = MoveTracker::default();
+    let guild_id = GuildId(1);
+    let now = Instant::now();
+
+    tracker
+        .record(guild_id, UserId(1), Some(ChannelId(10)), now)
+        .await;
+    tracker
+        .record(guild_id, UserId(2), Some(ChannelId(10)), now)
+        .await;
+    tracker.record(guild_id, UserId(3), None, now).await;
+    assert_eq!(
+        tracker.take_destination(guild_id, now).await,
+        Some(ChannelId(10))
+    );
+    // 一度ついていったら忘れる
+    assert_eq!(tracker.take_destination(guild_id, now).await, None);
+
+    // ばらばらに移ったらついていかない
+    tracker
+        .record(guild_id, UserId(1), Some(ChannelId(10)), now)
+        .await;
+    tracker
+        .record(guild_id, UserId(2), Some(ChannelId(20)), now)
+        .await;
+    assert_eq!(tracker.take_destination(guild_id, now).await, None);
+
+    // 前に出た人は数えない
+    tracker.record(guild_id, UserId(1), None, now).await;
+    tracker
+        .record(
+            guild_id,
+            UserId(2),
+            Some(ChannelId(20)),
+            now + FOLLOW_WINDOW,
+        )
+        .await;
+    assert_eq!(
+        tracker
+            .take_destination(guild_id, now + FOLLOW_WINDOW)
+            .await,
+        Some(ChannelId(20))
+    );
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::model::id::{GuildId, UserId};
+use tokio::sync::Mutex;
+
+use super::{db::GuildConfig, language::Language};
+
+// この間に同じ人がまた出入りしたら、あいさつしない
+pub const REJOIN_WINDOW: Duration = Duration::from_secs(60);
+// 入室はこの間待ち、続けて入ってきた人をまとめて「〇人が参加しました」にする
+pub const BATCH_WINDOW: Duration = Duration::from_secs(2);
+// /config greeting_cooldown で設定できる上限
+pub const MAX_GREETING_COOLDOWN_SEC: i64 = 600;
+
+// 入退出のあいさつの読み方。/config greeting で設定する
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GreetingMode {
+    // 各自のあいさつを読む。決めていない人には口調のあいさつ
+    Greeting,
+    // あいさつを決めていない人は「〇〇さんが参加しました」と知らせる
+    Announce,
+    // 人の多いサーバー向けに、あいさつしない
+    Off,
+}
+
+impl GreetingMode {
+    pub fn of(guild_config: &GuildConfig) -> Self {
+        match guild_config.greeting_mode.as_str() {
+            "announce" => Self::Announce,
+            "off" => Self::Off,
+            _ => Self::Greeting,
+        }
+    }
+}
+
+// 各自のあいさつに書ける、あいさつするときに埋める変数
+const TIME_VARIABLE: &str = "{time}";
+const COUNT_VARIABLE: &str = "{count}";
+
+pub fn has_variables(text: &str) -> bool {
+    text.contains(TIME_VARIABLE) || text.contains(COUNT_VARIABLE)
+}
+
+// hour 時 (サーバーの時刻) のあいさつ
+pub fn time_greeting(hour: i64, language: Language) -> &'static str {
+    match (language, hour) {
+        (Language::Japanese, 5..=10) => "おはよう",
+        (Language::Japanese, 11..=17) => "こんにちは",
+        (Language::Japanese, _) => "こんばんは",
+        (Language::English, 5..=10) => "good morning",
+        (Language::English, 11..=17) => "hello",
+        (Language::English, _) => "good evening",
+    }
+}
+
+// {time} を時間帯のあいさつに、{count} を今日何回目の入室かに置き換える
+pub fn fill_variables(text: &str, hour: i64, count: i64, language: Language) -> String {
+    text.replace(TIME_VARIABLE, time_greeting(hour, language))
+        .replace(COUNT_VARIABLE, &count.to_string())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Greeting {
+    pub text: String,
+    pub generator_type: u8,
+    pub voice_type: u32,
+}
+
+#[derive(Default)]
+struct GreetingState {
+    // サーバーと人ごとの最後の出入り
+    last_moves: HashMap<(GuildId, UserId), Instant>,
+    // まとめて読むのを待っている入室のあいさつ
+    batches: HashMap<GuildId, Vec<Greeting>>,
+    last_spoken: HashMap<GuildId, Instant>,
+}
+
+// 出入りが続いたときにあいさつで埋まらないようにする
+#[derive(Clone, Default)]
+pub struct GreetingLimiter {
+    state: Arc<Mutex<GreetingState>>,
+}
+
+impl GreetingLimiter {
+    // 出入りを記録し、REJOIN_WINDOW 以内にも出入りしていたら false を返す
+    pub async fn admit_move(&self, guild_id: GuildId, user_id: UserId, now: Instant) -> bool {
+        let mut state = self.state.lock().await;
+        state
+            .last_moves
+            .retain(|_, last| now.saturating_duration_since(*last) < REJOIN_WINDOW);
+        state.last_moves.insert((guild_id, user_id), now).is_none()
+    }
+
+    // 入室のあいさつを積む。最初に積んだときだけ true を返すので、呼んだ側が BATCH_WINDOW 待って take_batch する
+    pub async fn push_join(&self, guild_id: GuildId, greeting: Greeting) -> bool {
+        let mut state = self.state.lock().await;
+        let batch = state.batches.entry(guild_id).or_default();
+        batch.push(greeting);
+        batch.len() == 1
+    }
+
+    pub async fn take_batch(&self, guild_id: GuildId) -> Vec<Greeting> {
+        self.state
+            .lock()
+            .await
+            .batches
+            .remove(&guild_id)
+            .unwrap_or_default()
+    }
+
+    // 前のあいさつから cooldown たっていなければ false。読むなら時刻を記録する
+    pub async fn try_speak(&self, guild_id: GuildId, cooldown: Duration, now: Instant) -> bool {
+        let mut state = self.state.lock().await;
+        if state
+            .last_spoken
+            .get(&guild_id)
+            .is_some_and(|last| now.saturating_duration_since(*last) < cooldown)
+        {
+            return false;
+        }
+        state.last_spoken.insert(guild_id, now);
+        true
+    }
+}
+
+#[test]
+fn greeting_mode_test() {
+    let mut guild_config = GuildConfig::from_guild_id(1);
+    assert_eq!(GreetingMode::of(&guild_config), GreetingMode::Greeting);
+    guild_config.greeting_mode = "announce".to_string();
+    assert_eq!(GreetingMode::of(&guild_config), GreetingMode::Announce);
+    guild_config.greeting_mode = "off".to_string();
+    assert_eq!(GreetingMode::of(&guild_config), GreetingMode::Off);
+}
+
+#[test]
+fn fill_variables_test() {
+    assert_eq!(
+        fill_variables("{time}！今日{count}回目", 7, 2, Language::Japanese),
+        "おはよう！今日2回目"
+    );
+    assert_eq!(
+        fill_variables("{time}", 12, 1, Language::Japanese),
+        "こんにちは"
+    );
+    assert_eq!(
+        fill_variables("{time}", 23, 1, Language::Japanese),
+        "こんばんは"
+    );
+    assert_eq!(
+        fill_variables("{time}", 3, 1, Language::English),
+        "good evening"
+    );
+    assert!(has_variables("{count}回目"));
+    assert!(!has_variables("やあ"));
+}
+
+#[tokio::test]
+async fn greeting_limiter_test() {
+    let limiter = GreetingLimiter::default();
+    let (guild_id, user_id) = (GuildId(1), UserId(2));
+    let now = Instant::now();
+
+    assert!(limiter.admit_move(guild_id, user_id, now).await);
+    // すぐ抜けて入り直してもあいさつしない
+    assert!(
+        !limiter
+            .admit_move(guild_id, user_id, now + Duration::from_secs(5))
+            .await
+    );
+    assert!(limiter.admit_move(guild_id, UserId(3), now).await);
+    assert!(
+        limiter
+            .admit_move(guild_id, user_id, now + REJOIN_WINDOW * 2)
+            .await
+    );
+
+    let greeting = |text: &str| Greeting {
+        text: text.to_string(),
+        generator_type: 1,
+        voice_type: 1,
+    };
+    assert!(limiter.push_join(guild_id, greeting("a")).await);
+    assert!(!limiter.push_join(guild_id, greeting("b")).await);
+    assert_eq!(limiter.take_batch(guild_id).await.len(), 2);
+    assert!(limiter.take_batch(guild_id).await.is_empty());
+    assert!(limiter.push_join(guild_id, greeting("c")).await);
+
+    let cooldown = Duration::from_secs(10);
+    assert!(limiter.try_speak(guild_id, cooldown, now).await);
+    assert!(
+        !limiter
+            .try_speak(guild_id, cooldown, now + Duration::from_secs(3))
+            .await
+    );
+    assert!(limiter.try_speak(guild_id, Duration::ZERO, now).await);
+    assert!(limiter.try_speak(guild_id, cooldown, now + cooldown).await);
+}
@@ -0,0 +1,148 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use serenity::http::Http;
+use tokio::sync::RwLock;
+use tracing::info;
+
+use super::{
+    app_config::config,
+    db::SpeakerDB,
+    engine::{forget_protocol, open_jtalk_available, Protocol},
+    read_channel::ReadChannels,
+};
+use crate::handler::Generators;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 各エンジンが応答するかどうかを保持する。まだ確認していないエンジンは使えるものとして扱う
+#[derive(Clone, Default)]
+pub struct EngineHealth {
+    status: Arc<RwLock<HashMap<Generators, bool>>>,
+}
+
+impl EngineHealth {
+    pub async fn is_available(&self, generator: Generators) -> bool {
+        *self.status.read().await.get(&generator).unwrap_or(&true)
+    }
+
+    // 確認したことのあるエンジンの状態
+    pub async fn statuses(&self) -> Vec<(Generators, bool)> {
+        let mut statuses = self
+            .status
+            .read()
+            .await
+            .iter()
+            .map(|(generator, alive)| (*generator, *alive))
+            .collect::<Vec<_>>();
+        statuses.sort_by_key(|(generator, _)| *generator as u8);
+        statuses
+    }
+
+    // 全エンジンに問い合わせ、状態が変わったエンジンを返す
+    pub async fn check(&self) -> Vec<(Generators, bool)> {
+        let mut changed = Vec::new();
+        for generator in Generators::ALL {
+            // URL が設定されていないエンジンは監視しない
+            let base_url = match config().engine_location(generator) {
+                Some(base_url) => base_url,
+                None => continue,
+            };
+            let alive = is_alive(generator, base_url).await;
+            let prev = self.status.write().await.insert(generator, alive);
+            if prev.is_some() && prev != Some(alive) {
+                changed.push((generator, alive));
+            }
+        }
+        changed
+    }
+
+    // 指定したエンジンが落ちていたら、動いている別のエンジンの話者に置き換える
+    pub async fn fallback(
+        &self,
+        database: &sqlx::SqlitePool,
+        generator_type: u8,
+        voice_type: u32,
+    ) -> Result<(u8, u32)> {
+        let generator = Generators::try_from(generator_type)?;
+        if self.is_available(generator).await {
+            return Ok((generator_type, voice_type));
+        }
+        let speakers = database.get_all_speakers().await?;
+        for other in Generators::ALL {
+            if other == generator || !self.is_available(other).await {
+                continue;
+            }
+            let name: &str = other.into();
+            if let Some(speaker) = speakers.iter().find(|s| s.generator_type == name) {
+                info!(
+                    "fallback from {} to {}",
+                    Into::<&str>::into(generator),
+                    name
+                );
+                return Ok((other as u8, speaker.style_id as u32));
+            }
+        }
+        Ok((generator_type, voice_type))
+    }
+}
+
+// base_url は config().engine_location(generator) の URL
+pub async fn is_alive(generator: Generators, base_url: &str) -> bool {
+    match generator {
+        Generators::OPENJTALK => open_jtalk_available(),
+        // 複数立てていれば、どれかが動いていれば使える
+        Generators::VOICEVOX => {
+            let mut alive = false;
+            for (base_url, _) in config().voicevox_instances() {
+                alive |= ping(&base_url).await;
+            }
+            alive
+        }
+        _ => ping(base_url).await,
+    }
+}
+
+// 古い API と新しい COEIROINK の API のどちらかに答えれば動いているとみなす
+async fn ping(base_url: &str) -> bool {
+    let client = match reqwest::Client::builder().timeout(PING_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(_) => return false,
+    };
+    for protocol in Protocol::ALL {
+        if client
+            .get(format!("{}{}", base_url, protocol.speakers_path()))
+            .send()
+            .await
+            .is_ok_and(|res| res.status().is_success())
+        {
+            return true;
+        }
+    }
+    forget_protocol(base_url);
+    false
+}
+
+// 定期的にエンジンを確認し、状態が変わったら読み上げチャンネルに知らせる。Supervisor から動かす
+pub async fn run_health_check(health: EngineHealth, http: Arc<Http>, read_channels: ReadChannels) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        for (generator, alive) in health.check().await {
+            let name: &str = generator.into();
+            let text = if alive {
+                format!("{} が復活したよ", name)
+            } else {
+                format!(
+                    "{} に接続できなくなったよ。しばらく別のエンジンで読み上げるね",
+                    name
+                )
+            };
+            info!("{}", text);
+            for (_, channel_id) in read_channels.all().await {
+                channel_id.say(&http, &text).await.ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use serenity::model::id::GuildId;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use super::{app_config::Config, db::UsageDB};
+
+const DEFAULT_WAITLIST_MESSAGE: &str = "いまは満員だから入れないよ。少し待ってからもう一度呼んでね";
+
+// 公開で動かすときの制限。max_voice_connections, guild_allowlist, guild_denylist, waitlist_message で設定する
+#[derive(Clone, Debug, Default)]
+pub struct HostingLimits {
+    // 同時に入れるボイスチャンネルの数。None なら制限しない
+    pub max_connections: Option<usize>,
+    // None ならどのサーバーでも使える
+    allowlist: Option<HashSet<u64>>,
+    denylist: HashSet<u64>,
+    waitlist_message: Option<String>,
+    // 満員で断った回数
+    refused: Arc<AtomicU64>,
+    // サーバーごとに 1 日に読み上げる文字数。None なら制限しない
+    daily_char_quota: Option<u64>,
+    // 今日の文字数を使い切ったサーバー。日が変わると run_quota_reset が空にする
+    exhausted: Arc<Mutex<HashSet<GuildId>>>,
+}
+
+// 1 日の文字数の残り
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quota {
+    Available,
+    // いま使い切った。知らせてから読み上げを止める
+    Reached,
+    // もう知らせた
+    Exhausted,
+}
+
+// カンマ区切りのサーバー ID
+fn parse_guild_ids(s: &str) -> HashSet<u64> {
+    s.split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
+}
+
+impl HostingLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            config.max_voice_connections,
+            config.guild_allowlist.as_deref(),
+            config.guild_denylist.as_deref(),
+            config.waitlist_message.clone(),
+            config.daily_char_quota,
+        )
+    }
+
+    fn new(
+        max_connections: Option<usize>,
+        allowlist: Option<&str>,
+        denylist: Option<&str>,
+        waitlist_message: Option<String>,
+        daily_char_quota: Option<u64>,
+    ) -> Self {
+        HostingLimits {
+            max_connections,
+            allowlist: allowlist.map(parse_guild_ids),
+            denylist: denylist.map(parse_guild_ids).unwrap_or_default(),
+            waitlist_message,
+            refused: Default::default(),
+            daily_char_quota,
+            exhausted: Default::default(),
+        }
+    }
+
+    pub fn is_allowed(&self, guild_id: GuildId) -> bool {
+        !self.denylist.contains(&guild_id.0)
+            && self
+                .allowlist
+                .as_ref()
+                .is_none_or(|allowlist| allowlist.contains(&guild_id.0))
+    }
+
+    // connected はいま入っているボイスチャンネルの数。already ならそのサーバーではもう入っている
+    pub fn check(&self, guild_id: GuildId, connected: usize, already: bool) -> Result<()> {
+        if !self.is_allowed(guild_id) {
+            return Err(anyhow!("このサーバーでは使えないよ"));
+        }
+        if already {
+            return Ok(());
+        }
+        if self.max_connections.is_some_and(|max| connected >= max) {
+            self.refused.fetch_add(1, Ordering::Relaxed);
+            return Err(anyhow!(
+                "{}",
+                self.waitlist_message
+                    .as_deref()
+                    .unwrap_or(DEFAULT_WAITLIST_MESSAGE)
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn refused(&self) -> u64 {
+        self.refused.load(Ordering::Relaxed)
+    }
+
+    // 今日合成した文字数が上限に届いていたら、日が変わるまで読まない
+    // 読み始めたメッセージは途中で止めないので、上限を少し超えることがある
+    pub async fn check_quota(&self, database: &sqlx::SqlitePool, guild_id: GuildId) -> Quota {
+        let quota = match self.daily_char_quota {
+            Some(quota) => quota,
+            None => return Quota::Available,
+        };
+        if self.exhausted.lock().await.contains(&guild_id) {
+            return Quota::Exhausted;
+        }
+        let used = database
+            .get_today_characters(guild_id.0 as i64)
+            .await
+            .unwrap_or(0);
+        self.quota_after(guild_id, used.max(0) as u64, quota).await
+    }
+
+    async fn quota_after(&self, guild_id: GuildId, used: u64, quota: u64) -> Quota {
+        if used < quota {
+            return Quota::Available;
+        }
+        if self.exhausted.lock().await.insert(guild_id) {
+            info!(
+                "{} used up the daily quota of {} characters",
+                guild_id, quota
+            );
+            Quota::Reached
+        } else {
+            Quota::Exhausted
+        }
+    }
+
+    async fn reset_quota(&self) {
+        self.exhausted.lock().await.clear();
+    }
+}
+
+// 日が変わったら、文字数を使い切ったサーバーの読み上げを再開する。Supervisor から動かす
+pub async fn run_quota_reset(limits: HostingLimits, database: sqlx::SqlitePool) {
+    loop {
+        let secs = database.seconds_until_next_day().await.unwrap_or(60);
+        // 日付が変わった直後に数え直すよう、少し遅らせる
+        tokio::time::sleep(std::time::Duration::from_secs(secs.max(1) as u64 + 1)).await;
+        limits.reset_quota().await;
+    }
+}
+
+#[test]
+fn hosting_limits_test() {
+    let limits = HostingLimits::new(Some(2), None, Some("3, 4"), None, None);
+    assert!(limits.check(GuildId(1), 1, false).is_ok());
+    assert!(limits.check(GuildId(1), 2, false).is_err());
+    // もう入っているサーバーなら移動できる
+    assert!(limits.check(GuildId(1), 2, true).is_ok());
+    assert!(limits.check(GuildId(3), 0, false).is_err());
+    assert_eq!(limits.refused(), 1);
+
+    let limits = HostingLimits::new(None, Some("1,2"), None, Some("満員".to_string()), None);
+    assert!(limits.check(GuildId(2), 100, false).is_ok());
+    assert!(limits.check(GuildId(5), 0, false).is_err());
+    assert_eq!(limits.refused(), 0);
+
+    let limits = HostingLimits::new(Some(0), None, None, Some("満員".to_string()), None);
+    assert_eq!(
+        limits.check(GuildId(1), 0, false).unwrap_err().to_string(),
+        "満員"
+    );
+}
+
+#[tokio::test]
+async fn quota_test() {
+    let limits = HostingLimits::new(None, None, None, None, Some(100));
+    let guild_id = GuildId(1);
+    assert_eq!(
+        limits.quota_after(guild_id, 99, 100).await,
+        Quota::Available
+    );
+    assert_eq!(limits.quota_after(guild_id, 100, 100).await, Quota::Reached);
+    // 知らせるのは一度だけ
+    assert_eq!(
+        limits.quota_after(guild_id, 120, 100).await,
+        Quota::Exhausted
+    );
+    assert_eq!(
+        limits.quota_after(GuildId(2), 0, 100).await,
+        Quota::Available
+    );
+    limits.reset_quota().await;
+    assert_eq!(limits.quota_after(guild_id, 0, 100).await, Quota::Available);
+}
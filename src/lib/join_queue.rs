@@ -0,0 +1,70 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use serenity::model::id::{ChannelId, GuildId};
+use tokio::sync::Mutex;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JoinRequest {
+    pub voice_channel_id: ChannelId,
+    pub text_channel_id: ChannelId,
+}
+
+// bot が別のチャンネルで使われている間に来た /join を、空くまで待たせておく
+#[derive(Clone, Default)]
+pub struct JoinQueue {
+    queue: Arc<Mutex<HashMap<GuildId, VecDeque<JoinRequest>>>>,
+}
+
+impl JoinQueue {
+    // 予約して待ち順を返す。同じボイスチャンネルの予約は読み上げチャンネルだけ差し替える
+    pub async fn push(&self, guild_id: GuildId, request: JoinRequest) -> usize {
+        let mut queue = self.queue.lock().await;
+        let requests = queue.entry(guild_id).or_default();
+        match requests
+            .iter()
+            .position(|r| r.voice_channel_id == request.voice_channel_id)
+        {
+            Some(i) => {
+                requests[i] = request;
+                i + 1
+            }
+            None => {
+                requests.push_back(request);
+                requests.len()
+            }
+        }
+    }
+
+    pub async fn pop(&self, guild_id: GuildId) -> Option<JoinRequest> {
+        let mut queue = self.queue.lock().await;
+        let requests = queue.get_mut(&guild_id)?;
+        let request = requests.pop_front();
+        if requests.is_empty() {
+            queue.remove(&guild_id);
+        }
+        request
+    }
+}
+
+#[tokio::test]
+async fn join_queue_test() {
+    let queue = JoinQueue::default();
+    let guild_id = GuildId(1);
+    let request = |voice, text| JoinRequest {
+        voice_channel_id: ChannelId(voice),
+        text_channel_id: ChannelId(text),
+    };
+    assert_eq!(queue.push(guild_id, request(10, 20)).await, 1);
+    assert_eq!(queue.push(guild_id, request(11, 20)).await, 2);
+    // 同じボイスチャンネルは一つにまとめる
+    assert_eq!(queue.push(guild_id, request(10, 21)).await, 1);
+    assert_eq!(queue.push(GuildId(2), request(12, 22)).await, 1);
+
+    assert_eq!(queue.pop(guild_id).await, Some(request(10, 21)));
+    assert_eq!(queue.pop(guild_id).await, Some(request(11, 20)));
+    assert_eq!(queue.pop(guild_id).await, None);
+    assert_eq!(queue.pop(GuildId(2)).await, Some(request(12, 22)));
+}
@@ -0,0 +1,121 @@
+use anyhow::anyhow;
+
+use super::db::GuildConfig;
+
+// サーバーで読み上げる言語。読みの整形と bot の文言を切り替える
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Language {
+    Japanese,
+    English,
+}
+
+impl TryFrom<&str> for Language {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "ja" => Ok(Self::Japanese),
+            "en" => Ok(Self::English),
+            _ => Err(anyhow!("no such language")),
+        }
+    }
+}
+
+impl From<Language> for &str {
+    fn from(language: Language) -> Self {
+        match language {
+            Language::Japanese => "ja",
+            Language::English => "en",
+        }
+    }
+}
+
+impl Language {
+    // 読めない設定なら日本語
+    pub fn of(guild_config: &GuildConfig) -> Self {
+        Language::try_from(guild_config.language.as_str()).unwrap_or(Language::Japanese)
+    }
+
+    // discord の表示言語 (interaction の locale)。日本語でなければ英語
+    pub fn of_locale(locale: &str) -> Self {
+        if locale.starts_with("ja") {
+            Language::Japanese
+        } else {
+            Language::English
+        }
+    }
+
+    // 返信のときに読む「〇〇さんへの返信」
+    pub fn reply_to(self, name: &str) -> String {
+        match self {
+            Language::Japanese => format!("{}さんへの返信", name),
+            Language::English => format!("reply to {}", name),
+        }
+    }
+
+    // スタンプの代わりに読む文
+    pub fn sticker(self, name: &str) -> String {
+        match self {
+            Language::Japanese => format!("スタンプ、{}", name),
+            Language::English => format!("sticker, {}", name),
+        }
+    }
+
+    // 曲のリクエストを流し始めるときに読む文。名前を読まない設定なら None
+    pub fn song_request(self, name: Option<&str>, title: &str) -> String {
+        match (self, name) {
+            (Language::Japanese, Some(name)) => {
+                format!("{}さんのリクエストで、{}を流します", name, title)
+            }
+            (Language::Japanese, None) => format!("リクエストの曲、{}を流します", title),
+            (Language::English, Some(name)) => {
+                format!("now playing {}, requested by {}", title, name)
+            }
+            (Language::English, None) => format!("now playing {}, a song request", title),
+        }
+    }
+
+    // 英語のサーバーで使う話者。設定されていなければ None
+    pub fn voice(self, guild_config: &GuildConfig) -> Option<(u8, u32)> {
+        match (
+            self,
+            guild_config.english_generator_type,
+            guild_config.english_voice_type,
+        ) {
+            (Language::English, Some(generator_type), Some(voice_type)) => {
+                Some((generator_type as u8, voice_type as u32))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn language_test() {
+    let mut guild_config = GuildConfig::from_guild_id(1);
+    assert_eq!(Language::of(&guild_config), Language::Japanese);
+    guild_config.english_generator_type = Some(1);
+    guild_config.english_voice_type = Some(3);
+    assert_eq!(Language::of(&guild_config).voice(&guild_config), None);
+
+    guild_config.language = "en".to_string();
+    let language = Language::of(&guild_config);
+    assert_eq!(language, Language::English);
+    assert_eq!(language.voice(&guild_config), Some((1, 3)));
+    assert_eq!(language.reply_to("taro"), "reply to taro");
+    assert_eq!(
+        language.song_request(Some("taro"), "song"),
+        "now playing song, requested by taro"
+    );
+    assert_eq!(
+        Language::Japanese.song_request(None, "曲"),
+        "リクエストの曲、曲を流します"
+    );
+    assert_eq!(language.sticker("wave"), "sticker, wave");
+
+    guild_config.language = "xx".to_string();
+    assert_eq!(Language::of(&guild_config), Language::Japanese);
+
+    assert_eq!(Language::of_locale("ja"), Language::Japanese);
+    assert_eq!(Language::of_locale("en-US"), Language::English);
+}
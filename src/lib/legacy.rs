@@ -0,0 +1,77 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Result};
+
+use super::{app_config::config, db::DictDB};
+use crate::Dict;
+
+// nakochan 時代の辞書ファイル ({"単語": "読み", ...} の形式) 。場所は legacy_dict_path で変えられる
+pub fn legacy_dict_path() -> &'static str {
+    &config().legacy_dict_path
+}
+
+pub fn legacy_dict_exists() -> bool {
+    Path::new(legacy_dict_path()).exists()
+}
+
+fn parse_legacy_dict(json: &str) -> Result<Vec<Dict>> {
+    let entries: HashMap<String, String> = serde_json::from_str(json)?;
+    Ok(entries
+        .into_iter()
+        .map(|(word, read_word)| Dict {
+            word,
+            read_word,
+            priority: 0,
+        })
+        .collect())
+}
+
+// /migrate_legacy_dict と /dict_reload に渡されたサーバーの ID
+pub fn parse_guild_id(value: &str) -> Result<u64> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("サーバーの ID を数字で指定してね"))
+}
+
+// read_dict.json の中身を指定したサーバーの辞書に取り込み、ファイルを退避する。取り込んだ件数を返す
+pub async fn migrate_legacy_dict(database: &sqlx::SqlitePool, guild_id: i64) -> Result<usize> {
+    let count = reload_legacy_dict(database, guild_id).await?;
+    std::fs::rename(
+        legacy_dict_path(),
+        format!("{}.migrated", legacy_dict_path()),
+    )?;
+    Ok(count)
+}
+
+// 手で書き換えた read_dict.json を、再起動せずに辞書へ反映する。ファイルは残す
+// そのサーバーに前に取り込んだ単語は入れ替わるので、ファイルから消した単語は辞書からも消える
+pub async fn reload_legacy_dict(database: &sqlx::SqlitePool, guild_id: i64) -> Result<usize> {
+    if !legacy_dict_exists() {
+        return Err(anyhow!("{} が見つからないよ", legacy_dict_path()));
+    }
+    let entries = parse_legacy_dict(&std::fs::read_to_string(legacy_dict_path())?)?;
+    database.import_guild_dict(guild_id, &entries).await
+}
+
+#[test]
+fn parse_legacy_dict_test() {
+    let mut entries = parse_legacy_dict(r#"{"nap": "なっぷ", "rust": "らすと"}"#).unwrap();
+    entries.sort_by(|a, b| a.word.cmp(&b.word));
+    assert_eq!(
+        entries
+            .iter()
+            .map(|w| (w.word.as_str(), w.read_word.as_str()))
+            .collect::<Vec<_>>(),
+        vec![("nap", "なっぷ"), ("rust", "らすと")]
+    );
+
+    assert!(parse_legacy_dict("[]").is_err());
+}
+
+#[test]
+fn parse_guild_id_test() {
+    assert_eq!(parse_guild_id(" 123456789 ").unwrap(), 123456789);
+    assert!(parse_guild_id("nap").is_err());
+    assert!(parse_guild_id("-1").is_err());
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serenity::{
+    client::Context,
+    model::{
+        channel::Message,
+        id::{GuildId, MessageId, UserId},
+        interactions::message_component::ButtonStyle,
+    },
+};
+use tokio::sync::{oneshot, Mutex, OwnedMutexGuard};
+
+// 読み上げる文章がこの文字数を超えたら、読む前に送った人に確認する
+pub const LONG_MESSAGE_CHARS: usize = 200;
+// 確認に答えがなければ、この時間のあとに読む
+const AUTO_READ_AFTER: Duration = Duration::from_secs(15);
+// 確認のメッセージに出す文章の長さ
+const PREVIEW_CHARS: usize = 300;
+
+// 長文の確認ボタンの custom_id の接頭辞。後ろに read か skip をつける
+pub const LONG_READ_PREFIX: &str = "long_read:";
+
+// 送った人と、読むかどうかの答えを返す先
+type Pending = (UserId, oneshot::Sender<bool>);
+
+// 確認待ちの長文。確認のメッセージ ID ごとに持つ
+// 確認している間は同じサーバーのあとのメッセージを待たせ、読む順番が入れ替わらないようにする
+#[derive(Clone, Default)]
+pub struct LongReadConfirm {
+    pending: Arc<Mutex<HashMap<MessageId, Pending>>>,
+    turns: Arc<Mutex<HashMap<GuildId, Arc<Mutex<()>>>>>,
+}
+
+impl LongReadConfirm {
+    // 確認が終わるまでサーバーの順番を押さえておく
+    async fn hold(&self, guild_id: GuildId) -> OwnedMutexGuard<()> {
+        let turn = self.turns.lock().await.entry(guild_id).or_default().clone();
+        turn.lock_owned().await
+    }
+
+    // 前のメッセージの確認が終わるまで待つ
+    pub async fn wait_turn(&self, guild_id: GuildId) {
+        drop(self.hold(guild_id).await);
+    }
+
+    async fn register(&self, preview_id: MessageId, author: UserId) -> oneshot::Receiver<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(preview_id, (author, tx));
+        rx
+    }
+
+    // ボタンが押されたら答えを伝える。送った人以外は選べない
+    pub async fn resolve(&self, preview_id: MessageId, user: UserId, read: bool) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+        match pending.get(&preview_id) {
+            Some((author, _)) if *author != user => {
+                return Err(anyhow!("送った人だけが選べるよ"));
+            }
+            Some(_) => {}
+            None => return Err(anyhow!("もう読むかどうか決まっているよ")),
+        }
+        if let Some((_, tx)) = pending.remove(&preview_id) {
+            tx.send(read).ok();
+        }
+        Ok(())
+    }
+
+    // 答えを待つ。時間切れなら読む
+    async fn wait(
+        &self,
+        preview_id: MessageId,
+        rx: oneshot::Receiver<bool>,
+        timeout: Duration,
+    ) -> bool {
+        let read = tokio::time::timeout(timeout, rx)
+            .await
+            .map_or(true, |read| read.unwrap_or(true));
+        self.pending.lock().await.remove(&preview_id);
+        read
+    }
+
+    // 読み上げる文章を見せて、読むかどうかを送った人に聞く
+    pub async fn ask(&self, ctx: &Context, msg: &Message, text: &str) -> Result<bool> {
+        let _turn = match msg.guild_id {
+            Some(guild_id) => Some(self.hold(guild_id).await),
+            None => None,
+        };
+        let preview = if text.chars().count() > PREVIEW_CHARS {
+            text.chars().take(PREVIEW_CHARS).collect::<String>() + "…"
+        } else {
+            text.to_string()
+        };
+        let preview_msg = msg
+            .channel_id
+            .send_message(&ctx.http, |m| {
+                m.reference_message(msg)
+                    .content(format!(
+                        "長いメッセージだけど読む？ {} 秒たったら読むね\n> {}",
+                        AUTO_READ_AFTER.as_secs(),
+                        preview.replace('\n', "\n> ")
+                    ))
+                    .components(|c| {
+                        c.create_action_row(|row| {
+                            row.create_button(|button| {
+                                button
+                                    .style(ButtonStyle::Primary)
+                                    .custom_id(format!("{}read", LONG_READ_PREFIX))
+                                    .label("読む")
+                            })
+                            .create_button(|button| {
+                                button
+                                    .style(ButtonStyle::Secondary)
+                                    .custom_id(format!("{}skip", LONG_READ_PREFIX))
+                                    .label("読まない")
+                            })
+                        })
+                    })
+            })
+            .await?;
+        let rx = self.register(preview_msg.id, msg.author.id).await;
+        let read = self.wait(preview_msg.id, rx, AUTO_READ_AFTER).await;
+        preview_msg.delete(&ctx.http).await.ok();
+        Ok(read)
+    }
+}
+
+#[tokio::test]
+async fn long_read_confirm_test() {
+    let confirm = LongReadConfirm::default();
+    let (preview_id, author) = (MessageId(1), UserId(1));
+
+    let rx = confirm.register(preview_id, author).await;
+    assert!(confirm.resolve(preview_id, UserId(2), false).await.is_err());
+    confirm.resolve(preview_id, author, false).await.unwrap();
+    assert!(!confirm.wait(preview_id, rx, Duration::from_secs(1)).await);
+    assert!(confirm.resolve(preview_id, author, true).await.is_err());
+
+    // 答えがなければ読む
+    let rx = confirm.register(preview_id, author).await;
+    assert!(
+        confirm
+            .wait(preview_id, rx, Duration::from_millis(10))
+            .await
+    );
+    assert!(confirm.resolve(preview_id, author, false).await.is_err());
+}
+
+#[tokio::test]
+async fn long_read_turn_test() {
+    let confirm = LongReadConfirm::default();
+    let turn = confirm.hold(GuildId(1)).await;
+    // 確認している間は同じサーバーのメッセージを待たせる
+    assert!(
+        tokio::time::timeout(Duration::from_millis(10), confirm.wait_turn(GuildId(1)))
+            .await
+            .is_err()
+    );
+    confirm.wait_turn(GuildId(2)).await;
+    drop(turn);
+    confirm.wait_turn(GuildId(1)).await;
+}
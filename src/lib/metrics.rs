@@ -0,0 +1,177 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tracing::info;
+
+// 合成にかかった時間のヒストグラムの区切り (秒)
+const SYNTHESIS_BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+struct Histogram {
+    // SYNTHESIS_BUCKETS の各区切り以下だった数 (累積しない)
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; SYNTHESIS_BUCKETS.len()];
+        }
+        if let Some(i) = SYNTHESIS_BUCKETS.iter().position(|le| value <= *le) {
+            self.buckets[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+struct MetricsState {
+    messages_read: u64,
+    // エンジンごと
+    synthesis_seconds: BTreeMap<String, Histogram>,
+    engine_errors: BTreeMap<String, u64>,
+}
+
+// 読み上げた数や合成にかかった時間を数える。/metrics か METRICS_LOG_INTERVAL ごとのログで出す
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Default::default)
+}
+
+impl Metrics {
+    // 読み上げのキューに積んだメッセージ
+    pub fn message_read(&self) {
+        self.state.lock().unwrap().messages_read += 1;
+    }
+
+    // 失敗した合成は時間を数えず、エラーとして数える
+    pub fn synthesis_finished(&self, engine: &str, elapsed: Duration, ok: bool) {
+        let mut state = self.state.lock().unwrap();
+        if ok {
+            state
+                .synthesis_seconds
+                .entry(engine.to_string())
+                .or_default()
+                .observe(elapsed.as_secs_f64());
+        } else {
+            *state.engine_errors.entry(engine.to_string()).or_default() += 1;
+        }
+    }
+
+    // Prometheus のテキスト形式
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        writeln!(
+            out,
+            "# HELP nap_chan_messages_read_total Messages queued for reading."
+        )
+        .ok();
+        writeln!(out, "# TYPE nap_chan_messages_read_total counter").ok();
+        writeln!(out, "nap_chan_messages_read_total {}", state.messages_read).ok();
+        writeln!(
+            out,
+            "# HELP nap_chan_synthesis_seconds Time taken to synthesize a message."
+        )
+        .ok();
+        writeln!(out, "# TYPE nap_chan_synthesis_seconds histogram").ok();
+        for (engine, histogram) in state.synthesis_seconds.iter() {
+            let mut cumulative = 0;
+            for (le, count) in SYNTHESIS_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "nap_chan_synthesis_seconds_bucket{{engine=\"{}\",le=\"{}\"}} {}",
+                    engine, le, cumulative
+                )
+                .ok();
+            }
+            writeln!(
+                out,
+                "nap_chan_synthesis_seconds_bucket{{engine=\"{}\",le=\"+Inf\"}} {}",
+                engine, histogram.count
+            )
+            .ok();
+            writeln!(
+                out,
+                "nap_chan_synthesis_seconds_sum{{engine=\"{}\"}} {}",
+                engine, histogram.sum
+            )
+            .ok();
+            writeln!(
+                out,
+                "nap_chan_synthesis_seconds_count{{engine=\"{}\"}} {}",
+                engine, histogram.count
+            )
+            .ok();
+        }
+        writeln!(
+            out,
+            "# HELP nap_chan_engine_errors_total Failed synthesis requests."
+        )
+        .ok();
+        writeln!(out, "# TYPE nap_chan_engine_errors_total counter").ok();
+        for (engine, errors) in state.engine_errors.iter() {
+            writeln!(
+                out,
+                "nap_chan_engine_errors_total{{engine=\"{}\"}} {}",
+                engine, errors
+            )
+            .ok();
+        }
+        out
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(&*self.state.lock().unwrap()).unwrap_or_default()
+    }
+}
+
+// STATUS_ADDR を使わない人向けに、interval ごとに JSON 一行でログに出す。Supervisor から動かす
+pub async fn run_metrics_log(interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // 起動直後の空の値は出さない
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        info!(target: "metrics", "{}", metrics().to_json());
+    }
+}
+
+#[test]
+fn metrics_test() {
+    let metrics = Metrics::default();
+    metrics.message_read();
+    metrics.message_read();
+    metrics.synthesis_finished("VOICEVOX", Duration::from_millis(200), true);
+    metrics.synthesis_finished("VOICEVOX", Duration::from_secs(3), true);
+    metrics.synthesis_finished("VOICEVOX", Duration::from_secs(60), true);
+    metrics.synthesis_finished("COEIROINK", Duration::from_secs(1), false);
+
+    let text = metrics.render();
+    assert!(text.contains("nap_chan_messages_read_total 2\n"));
+    // 区切りごとに累積する
+    assert!(text.contains("nap_chan_synthesis_seconds_bucket{engine=\"VOICEVOX\",le=\"0.25\"} 1\n"));
+    assert!(text.contains("nap_chan_synthesis_seconds_bucket{engine=\"VOICEVOX\",le=\"5\"} 2\n"));
+    assert!(text.contains("nap_chan_synthesis_seconds_bucket{engine=\"VOICEVOX\",le=\"+Inf\"} 3\n"));
+    assert!(text.contains("nap_chan_synthesis_seconds_count{engine=\"VOICEVOX\"} 3\n"));
+    assert!(text.contains("nap_chan_engine_errors_total{engine=\"COEIROINK\"} 1\n"));
+    assert!(!text.contains("nap_chan_synthesis_seconds_count{engine=\"COEIROINK\"}"));
+
+    let json: serde_json::Value = serde_json::from_str(&metrics.to_json()).unwrap();
+    assert_eq!(json["messages_read"], 2);
+    assert_eq!(json["synthesis_seconds"]["VOICEVOX"]["count"], 3);
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use super::db::GuildConfig;
+
+// 読み上げが終わってから音楽の音量を戻すまで待つ時間。続けて読むメッセージの間で音量が上下しないようにする
+pub const RELEASE_DELAY: Duration = Duration::from_millis(800);
+// /config volume で設定できる上限 (%)
+pub const MAX_VOLUME: i64 = 200;
+
+// 読み上げと /play の音楽の音量
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MixPolicy {
+    pub tts: f32,
+    pub music: f32,
+    // 読み上げている間の音楽の音量
+    pub ducked: f32,
+}
+
+impl MixPolicy {
+    pub fn from_guild_config(guild_config: &GuildConfig) -> Self {
+        MixPolicy {
+            tts: guild_config.tts_volume as f32 / 100.0,
+            music: guild_config.music_volume as f32 / 100.0,
+            ducked: guild_config.ducked_volume as f32 / 100.0,
+        }
+    }
+
+    pub fn music_volume(&self, ducking: Ducking) -> f32 {
+        match ducking {
+            Ducking::Full => self.music,
+            // 下げた音量のほうが大きくならないようにする
+            Ducking::Ducked | Ducking::Releasing(_) => self.ducked.min(self.music),
+        }
+    }
+}
+
+impl Default for MixPolicy {
+    fn default() -> Self {
+        MixPolicy::from_guild_config(&GuildConfig::from_guild_id(0))
+    }
+}
+
+// 読み上げのトラックのイベント
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpeechEvent {
+    // 読み上げのトラックが流れ始めた
+    Started,
+    // 積んである読み上げがなくなった。値はこの待ちの番号
+    Drained(u64),
+    // RELEASE_DELAY 待った
+    Released(u64),
+}
+
+// 読み上げに合わせて音楽の音量を下げる状態
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Ducking {
+    #[default]
+    Full,
+    Ducked,
+    // 読み上げが終わり、次のメッセージが来ないか待っている
+    Releasing(u64),
+}
+
+impl Ducking {
+    pub fn next(self, event: SpeechEvent) -> Ducking {
+        match (self, event) {
+            (_, SpeechEvent::Started) => Ducking::Ducked,
+            (Ducking::Full, SpeechEvent::Drained(_)) => Ducking::Full,
+            (_, SpeechEvent::Drained(id)) => Ducking::Releasing(id),
+            // 待っている間にまた読み始めていたら戻さない
+            (Ducking::Releasing(waiting), SpeechEvent::Released(id)) if waiting == id => {
+                Ducking::Full
+            }
+            (ducking, SpeechEvent::Released(_)) => ducking,
+        }
+    }
+}
+
+#[test]
+fn ducking_test() {
+    let policy = MixPolicy::default();
+    let ducking = Ducking::Full.next(SpeechEvent::Started);
+    assert_eq!(ducking, Ducking::Ducked);
+    assert_eq!(policy.music_volume(ducking), 0.05);
+
+    let ducking = ducking.next(SpeechEvent::Drained(1));
+    assert_eq!(ducking, Ducking::Releasing(1));
+    // 次の読み上げが来たら下げたまま
+    let again = ducking.next(SpeechEvent::Started);
+    assert_eq!(again.next(SpeechEvent::Released(1)), Ducking::Ducked);
+    // 古い待ちでは戻さない
+    let ducking = again.next(SpeechEvent::Drained(2));
+    assert_eq!(
+        ducking.next(SpeechEvent::Released(1)),
+        Ducking::Releasing(2)
+    );
+    assert_eq!(ducking.next(SpeechEvent::Released(2)), Ducking::Full);
+
+    assert_eq!(Ducking::Full.next(SpeechEvent::Drained(3)), Ducking::Full);
+    assert_eq!(policy.music_volume(Ducking::Full), 0.3);
+}
@@ -1,3 +1,49 @@
+pub mod activity;
+pub mod app_config;
+pub mod batch;
+pub mod changelog;
+pub mod correction;
 pub mod db;
+pub mod dict_check;
+pub mod dict_pack;
+pub mod engine;
+pub mod feature;
+pub mod follow;
+pub mod greeting;
+pub mod health;
+pub mod hosting;
+pub mod join_queue;
+pub mod language;
+pub mod legacy;
+pub mod long_read;
+pub mod metrics;
+pub mod mixer;
+pub mod music;
+pub mod ng_word;
+pub mod opus_cache;
+pub mod permission;
+pub mod personality;
+pub mod playback;
+pub mod quiet_hours;
+pub mod reaction;
+pub mod read_channel;
+pub mod read_delay;
+pub mod retention;
+pub mod rotation;
+pub mod search;
+pub mod settings;
+pub mod setup;
+pub mod shutdown;
+pub mod song_request;
+pub mod sound_effect;
+pub mod speak;
+pub mod startup;
+pub mod status_server;
+pub mod supervisor;
+pub mod temp;
 pub mod text;
+pub mod thread;
+pub mod transcribe;
+pub mod usage;
 pub mod voice;
+pub mod voice_remap;
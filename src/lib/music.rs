@@ -0,0 +1,367 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serenity::{async_trait, model::id::GuildId};
+use songbird::{tracks::TrackHandle, Event, EventContext, Songbird, TrackEvent};
+use tokio::sync::{oneshot, Mutex};
+use tracing::info;
+
+use super::mixer::{Ducking, MixPolicy, SpeechEvent, RELEASE_DELAY};
+
+// サーバーごとに予約できる曲の数
+const MAX_QUEUE: usize = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Song {
+    pub url: String,
+    // 読み込むまでは url
+    pub title: String,
+}
+
+// 予約した曲と、流れ始めたことを知らせる先
+type Queued = (Song, Option<oneshot::Sender<Song>>);
+
+#[derive(Default)]
+struct MusicState {
+    queue: VecDeque<Queued>,
+    current: Option<(Song, TrackHandle)>,
+    // 次の曲を読み込んでいる間は次を始めない
+    loading: bool,
+    policy: MixPolicy,
+    ducking: Ducking,
+    // SpeechEvent::Drained の番号
+    releases: u64,
+}
+
+impl MusicState {
+    fn volume(&self) -> f32 {
+        self.policy.music_volume(self.ducking)
+    }
+
+    fn apply_volume(&self) {
+        if let Some((_, handle)) = &self.current {
+            handle.set_volume(self.volume()).ok();
+        }
+    }
+}
+
+// /play の曲。読み上げのキューとは別のトラックで流し、読み上げの間は音量を下げる (mixer.rs)
+#[derive(Clone, Default)]
+pub struct Music {
+    state: Arc<Mutex<HashMap<GuildId, MusicState>>>,
+}
+
+struct SongEndNotifier {
+    music: Music,
+    manager: Arc<Songbird>,
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl songbird::EventHandler for SongEndNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(tracks) = ctx {
+            for (_, handle) in tracks.iter() {
+                self.music.finish(self.guild_id, handle).await;
+            }
+        }
+        if let Err(e) = self.music.play_next(&self.manager, self.guild_id).await {
+            info!("{}", e);
+        }
+        None
+    }
+}
+
+impl Music {
+    // 予約に加え、何番目かを返す。0 ならすぐ流れる
+    async fn add(
+        &self,
+        guild_id: GuildId,
+        url: &str,
+        started: Option<oneshot::Sender<Song>>,
+    ) -> Result<usize> {
+        let mut state = self.state.lock().await;
+        let state = state.entry(guild_id).or_default();
+        if state.queue.len() >= MAX_QUEUE {
+            return Err(anyhow!("予約は {} 曲までだよ", MAX_QUEUE));
+        }
+        state.queue.push_back((
+            Song {
+                url: url.to_string(),
+                title: url.to_string(),
+            },
+            started,
+        ));
+        let playing = state.current.is_some() || state.loading;
+        Ok(if playing { state.queue.len() } else { 0 })
+    }
+
+    // url の曲を予約し、何も流れていなければ流す
+    pub async fn push(
+        &self,
+        manager: &Arc<Songbird>,
+        guild_id: GuildId,
+        url: &str,
+        policy: MixPolicy,
+    ) -> Result<usize> {
+        self.enqueue(manager, guild_id, url, policy, None).await
+    }
+
+    // push と同じく予約し、流れ始めたら曲を受け取れるようにする
+    // 読み込めなかったり、流れる前に /stop されたりしたら受け取れない
+    pub async fn request(
+        &self,
+        manager: &Arc<Songbird>,
+        guild_id: GuildId,
+        url: &str,
+        policy: MixPolicy,
+    ) -> Result<(usize, oneshot::Receiver<Song>)> {
+        let (tx, rx) = oneshot::channel();
+        let position = self
+            .enqueue(manager, guild_id, url, policy, Some(tx))
+            .await?;
+        Ok((position, rx))
+    }
+
+    async fn enqueue(
+        &self,
+        manager: &Arc<Songbird>,
+        guild_id: GuildId,
+        url: &str,
+        policy: MixPolicy,
+        started: Option<oneshot::Sender<Song>>,
+    ) -> Result<usize> {
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            return Err(anyhow!("曲の url を指定してね"));
+        }
+        if manager.get(guild_id).is_none() {
+            return Err(anyhow!("ボイスチャンネルに入ってないよ"));
+        }
+        let position = self.add(guild_id, url, started).await?;
+        self.set_policy(guild_id, policy).await;
+        if position == 0 {
+            self.play_next(manager, guild_id).await?;
+        }
+        Ok(position)
+    }
+
+    async fn finish(&self, guild_id: GuildId, handle: &TrackHandle) {
+        if let Some(state) = self.state.lock().await.get_mut(&guild_id) {
+            if state
+                .current
+                .as_ref()
+                .is_some_and(|(_, current)| current.uuid() == handle.uuid())
+            {
+                state.current = None;
+            }
+        }
+    }
+
+    // 何も流れていなければ次の曲を流す。読み込めない曲は飛ばす
+    async fn play_next(&self, manager: &Arc<Songbird>, guild_id: GuildId) -> Result<()> {
+        let handler_lock = match manager.get(guild_id) {
+            Some(handler_lock) => handler_lock,
+            // 抜けたら予約も消す
+            None => {
+                self.state.lock().await.remove(&guild_id);
+                return Ok(());
+            }
+        };
+        loop {
+            let (mut song, started) = {
+                let mut state = self.state.lock().await;
+                let state = state.entry(guild_id).or_default();
+                if state.current.is_some() || state.loading {
+                    return Ok(());
+                }
+                match state.queue.pop_front() {
+                    Some(queued) => {
+                        state.loading = true;
+                        queued
+                    }
+                    None => return Ok(()),
+                }
+            };
+            let input = songbird::ytdl(&song.url).await;
+            let volume = {
+                let mut state = self.state.lock().await;
+                let state = state.entry(guild_id).or_default();
+                if input.is_err() {
+                    state.loading = false;
+                }
+                state.volume()
+            };
+            let input = match input {
+                Ok(input) => input,
+                Err(e) => {
+                    info!("failed to load {}: {:?}", song.url, e);
+                    continue;
+                }
+            };
+            if let Some(title) = &input.metadata.title {
+                song.title = title.clone();
+            }
+            let (mut track, handle) = songbird::tracks::create_player(input);
+            track.set_volume(volume);
+            handle
+                .add_event(
+                    Event::Track(TrackEvent::End),
+                    SongEndNotifier {
+                        music: self.clone(),
+                        manager: manager.clone(),
+                        guild_id,
+                    },
+                )
+                .ok();
+            // 読み上げは通話、音楽の順にロックをとるので、音楽のロックを持たずに通話のロックをとる
+            handler_lock.lock().await.play(track);
+            let mut state = self.state.lock().await;
+            let state = state.entry(guild_id).or_default();
+            // 読み込んでいる間に /stop された
+            if !state.loading {
+                handle.stop().ok();
+                return Ok(());
+            }
+            state.loading = false;
+            if state.volume() != volume {
+                handle.set_volume(state.volume()).ok();
+            }
+            state.current = Some((song.clone(), handle));
+            if let Some(started) = started {
+                started.send(song).ok();
+            }
+            return Ok(());
+        }
+    }
+
+    // いま流れている曲と、その再生位置
+    pub async fn now_playing(&self, guild_id: GuildId) -> Option<(Song, Option<Duration>)> {
+        let (song, handle) = self.state.lock().await.get(&guild_id)?.current.clone()?;
+        let position = handle.get_info().await.ok().map(|info| info.position);
+        Some((song, position))
+    }
+
+    pub async fn queue(&self, guild_id: GuildId) -> Vec<Song> {
+        self.state
+            .lock()
+            .await
+            .get(&guild_id)
+            .map_or_else(Vec::new, |state| {
+                state.queue.iter().map(|(song, _)| song.clone()).collect()
+            })
+    }
+
+    // いまの曲を止める。終わったら次の曲が流れる
+    pub async fn skip(&self, guild_id: GuildId) -> Result<Song> {
+        let (song, handle) = self
+            .state
+            .lock()
+            .await
+            .get(&guild_id)
+            .and_then(|state| state.current.clone())
+            .ok_or_else(|| anyhow!("曲は流れていないよ"))?;
+        handle.stop()?;
+        Ok(song)
+    }
+
+    // 予約を全部消して止める。消した曲の数を返す
+    pub async fn stop(&self, guild_id: GuildId) -> usize {
+        let state = self.state.lock().await.remove(&guild_id);
+        match state {
+            Some(state) => {
+                let current = state.current.map(|(_, handle)| handle.stop().ok());
+                state.queue.len() + current.map_or(0, |_| 1)
+            }
+            None => 0,
+        }
+    }
+
+    // /config volume で変えた音量を、いま流れている曲にも反映する
+    pub async fn set_policy(&self, guild_id: GuildId, policy: MixPolicy) {
+        let mut state = self.state.lock().await;
+        let state = state.entry(guild_id).or_default();
+        if state.policy != policy {
+            state.policy = policy;
+            state.apply_volume();
+        }
+    }
+
+    // 読み上げが始まったら音量を下げる
+    pub async fn speech_started(&self, guild_id: GuildId) {
+        self.transition(guild_id, SpeechEvent::Started).await;
+    }
+
+    // 読み上げがなくなったら、RELEASE_DELAY 待ってから音量を戻す
+    pub async fn speech_drained(&self, guild_id: GuildId) {
+        let id = {
+            let mut state = self.state.lock().await;
+            let state = state.entry(guild_id).or_default();
+            state.releases += 1;
+            state.releases
+        };
+        if let Ducking::Releasing(_) = self.transition(guild_id, SpeechEvent::Drained(id)).await {
+            let music = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(RELEASE_DELAY).await;
+                music.transition(guild_id, SpeechEvent::Released(id)).await;
+            });
+        }
+    }
+
+    async fn transition(&self, guild_id: GuildId, event: SpeechEvent) -> Ducking {
+        let mut state = self.state.lock().await;
+        let state = state.entry(guild_id).or_default();
+        let volume = state.volume();
+        state.ducking = state.ducking.next(event);
+        if state.volume() != volume {
+            state.apply_volume();
+        }
+        state.ducking
+    }
+}
+
+#[tokio::test]
+async fn music_queue_test() {
+    let music = Music::default();
+    let guild_id = GuildId(1);
+    assert_eq!(
+        music
+            .add(guild_id, "https://example.com/1", None)
+            .await
+            .unwrap(),
+        0
+    );
+    // 読み込み中なら後ろに並ぶ
+    music.state.lock().await.get_mut(&guild_id).unwrap().loading = true;
+    assert_eq!(
+        music
+            .add(guild_id, "https://example.com/2", None)
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(music.queue(guild_id).await.len(), 2);
+    assert!(music.skip(guild_id).await.is_err());
+
+    music.speech_started(guild_id).await;
+    assert_eq!(music.state.lock().await[&guild_id].ducking, Ducking::Ducked);
+    music.speech_drained(guild_id).await;
+    assert_eq!(
+        music.state.lock().await[&guild_id].ducking,
+        Ducking::Releasing(1)
+    );
+
+    // 流れる前に止めたら知らせない
+    let (tx, mut rx) = oneshot::channel();
+    music
+        .add(guild_id, "https://example.com/3", Some(tx))
+        .await
+        .unwrap();
+    assert_eq!(music.stop(guild_id).await, 3);
+    assert!(rx.try_recv().is_err());
+    assert!(music.queue(guild_id).await.is_empty());
+}
@@ -0,0 +1,91 @@
+use anyhow::anyhow;
+
+// NG ワードを伏せるときに代わりに読む音
+pub const BLEEP: &str = "ピー";
+
+// NG ワードを含むメッセージの扱い。/ngword add で単語ごとに決める
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NgAction {
+    // 単語をピーに置き換えて読む
+    Replace,
+    // メッセージごと読まない
+    Skip,
+}
+
+impl TryFrom<&str> for NgAction {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "replace" => Ok(Self::Replace),
+            "skip" => Ok(Self::Skip),
+            _ => Err(anyhow!("no such ng word action")),
+        }
+    }
+}
+
+impl From<NgAction> for &str {
+    fn from(action: NgAction) -> Self {
+        match action {
+            NgAction::Replace => "replace",
+            NgAction::Skip => "skip",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NgWord {
+    pub word: String,
+    pub action: NgAction,
+}
+
+// NG ワードを伏せた文章を返す。読まない単語が入っていれば None
+// 英字は大文字と小文字を区別しない
+pub fn filter_ng_words(text: &str, ng_words: &[NgWord]) -> Option<String> {
+    let mut text = text.to_string();
+    for ng_word in ng_words.iter().filter(|ng_word| !ng_word.word.is_empty()) {
+        let re = regex::RegexBuilder::new(&regex::escape(&ng_word.word))
+            .case_insensitive(true)
+            .build()
+            .ok()?;
+        if !re.is_match(&text) {
+            continue;
+        }
+        match ng_word.action {
+            NgAction::Skip => return None,
+            NgAction::Replace => text = re.replace_all(&text, BLEEP).to_string(),
+        }
+    }
+    Some(text)
+}
+
+#[test]
+fn filter_ng_words_test() {
+    let ng_word = |word: &str, action| NgWord {
+        word: word.to_string(),
+        action,
+    };
+    let ng_words = vec![
+        ng_word("ばか", NgAction::Replace),
+        ng_word("Spoiler", NgAction::Replace),
+        ng_word("犯人は", NgAction::Skip),
+    ];
+    assert_eq!(
+        filter_ng_words("ばかばか spoiler!", &ng_words).unwrap(),
+        "ピーピー ピー!"
+    );
+    assert_eq!(filter_ng_words("犯人はヤス", &ng_words), None);
+    assert_eq!(
+        filter_ng_words("こんにちは (a+b)", &ng_words).unwrap(),
+        "こんにちは (a+b)"
+    );
+    // 記号も文字どおりに探す
+    assert_eq!(
+        filter_ng_words("a+b", &[ng_word("a+b", NgAction::Replace)]).unwrap(),
+        "ピー"
+    );
+    for action in [NgAction::Replace, NgAction::Skip] {
+        let name: &str = action.into();
+        assert_eq!(NgAction::try_from(name).unwrap(), action);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use songbird::{
+    driver::Bitrate,
+    input::{cached::Compressed, Input},
+};
+use tokio::sync::Mutex;
+
+// 覚えておく音声の数。古いものから忘れる
+const MAX_ENTRIES: usize = 128;
+// これより長いお知らせは同じ文が続くことが少ないので覚えない
+pub const MAX_PHRASE_CHARS: usize = 60;
+// 読み上げの声なので音楽ほどのビットレートはいらない
+const BITRATE: Bitrate = Bitrate::BitsPerSecond(64_000);
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum CacheKey {
+    // あいさつやコマンドの返事など、同じ声で何度も読む文
+    Phrase {
+        text: String,
+        generator_type: u8,
+        voice_type: u32,
+    },
+    SoundEffect {
+        guild_id: i64,
+        name: String,
+    },
+}
+
+#[derive(Default)]
+struct OpusCacheState {
+    entries: HashMap<CacheKey, Compressed>,
+    // 入れた順。いっぱいになったら先頭から消す
+    order: VecDeque<CacheKey>,
+}
+
+// よく鳴らす音声を一度だけ Opus にしておき、鳴らすたびに PCM から変換しなくていいようにする
+#[derive(Clone, Default)]
+pub struct OpusCache {
+    state: Arc<Mutex<OpusCacheState>>,
+}
+
+impl OpusCache {
+    pub async fn get(&self, key: &CacheKey) -> Option<Input> {
+        let state = self.state.lock().await;
+        state
+            .entries
+            .get(key)
+            .map(|compressed| compressed.new_handle().into())
+    }
+
+    // input を最後まで Opus にして覚え、鳴らす Input を返す
+    pub async fn insert(&self, key: CacheKey, input: Input) -> Result<Input> {
+        let compressed = tokio::task::spawn_blocking(move || {
+            let mut compressed = Compressed::new(input, BITRATE)?;
+            compressed.raw.load_all();
+            Ok::<_, anyhow::Error>(compressed)
+        })
+        .await??;
+        let input = compressed.new_handle().into();
+        let mut state = self.state.lock().await;
+        if state.entries.insert(key.clone(), compressed).is_none() {
+            state.order.push_back(key);
+        }
+        while state.order.len() > MAX_ENTRIES {
+            if let Some(oldest) = state.order.pop_front() {
+                state.entries.remove(&oldest);
+            }
+        }
+        Ok(input)
+    }
+
+    // 効果音を登録し直したり消したりしたら、前の音を忘れる
+    pub async fn remove(&self, key: &CacheKey) {
+        let mut state = self.state.lock().await;
+        if state.entries.remove(key).is_some() {
+            state.order.retain(|k| k != key);
+        }
+    }
+}
+
+#[tokio::test]
+async fn opus_cache_test() {
+    use super::voice::silence;
+    use songbird::input::Codec;
+    use std::time::Duration;
+
+    let cache = OpusCache::default();
+    let key = |name: &str| CacheKey::SoundEffect {
+        guild_id: 1,
+        name: name.to_string(),
+    };
+    assert!(cache.get(&key("a")).await.is_none());
+    let input = cache
+        .insert(key("a"), silence(Duration::from_millis(200)))
+        .await
+        .unwrap();
+    assert!(matches!(input.kind, Codec::Opus(_)));
+    assert!(cache.get(&key("a")).await.is_some());
+
+    cache.remove(&key("a")).await;
+    assert!(cache.get(&key("a")).await.is_none());
+
+    for i in 0..=MAX_ENTRIES {
+        cache
+            .insert(key(&i.to_string()), silence(Duration::from_millis(20)))
+            .await
+            .unwrap();
+    }
+    // いっぱいになったら古いものから忘れる
+    assert!(cache.get(&key("0")).await.is_none());
+    assert!(cache.get(&key(&MAX_ENTRIES.to_string())).await.is_some());
+}
@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+use serenity::model::{
+    guild::Member,
+    id::{GuildId, RoleId},
+};
+
+use tracing::warn;
+
+use super::db::CommandPermissionDB;
+
+// コマンドを使えるロールの段階。/bot_perm でサーバーごとにロールを決める
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PermissionLevel {
+    // 辞書の編集や bot を抜けさせるなど、ほかの人の読み上げに関わるコマンド
+    Admin,
+    // 参加や自分の設定など、ふだん使うコマンド
+    User,
+}
+
+// Admin のロールが要るコマンド
+const ADMIN_COMMANDS: &[&str] = &[
+    "add",
+    "rem",
+    "dict_edit",
+    "dict",
+    "leave",
+    "flush",
+    "mute",
+    "unmute",
+    "stop",
+];
+// だれでも使えるコマンド。管理用のコマンドはそれぞれサーバーの管理権限を見る
+const OPEN_COMMANDS: &[&str] = &[
+    "help",
+    "changelog",
+    "ping",
+    "status",
+    "shard_info",
+    "config",
+    "setup",
+    "read_filter",
+    "autojoin",
+    "dict_pack",
+    "ngword",
+    "bot_perm",
+    "set_greeting_template",
+    "reload_speakers",
+    "migrate_legacy_dict",
+    "dict_reload",
+    "prune",
+];
+
+impl PermissionLevel {
+    pub const ALL: [PermissionLevel; 2] = [Self::Admin, Self::User];
+
+    // コマンドに要る段階。None ならだれでも使える。パネルのボタンも同じ名前で調べる
+    pub fn of_command(name: &str) -> Option<Self> {
+        if ADMIN_COMMANDS.contains(&name) {
+            Some(Self::Admin)
+        } else if OPEN_COMMANDS.contains(&name) {
+            None
+        } else {
+            Some(Self::User)
+        }
+    }
+}
+
+impl TryFrom<&str> for PermissionLevel {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "admin" => Ok(Self::Admin),
+            "user" => Ok(Self::User),
+            _ => Err(anyhow!("no such permission level")),
+        }
+    }
+}
+
+impl From<PermissionLevel> for &str {
+    fn from(level: PermissionLevel) -> Self {
+        match level {
+            PermissionLevel::Admin => "admin",
+            PermissionLevel::User => "user",
+        }
+    }
+}
+
+// allowed はサーバーで決めた (段階, ロール)。その段階のロールを決めていなければだれでも使える
+// Admin のロールを持っている人は User のコマンドも使える
+pub fn is_allowed(
+    level: PermissionLevel,
+    allowed: &[(PermissionLevel, RoleId)],
+    roles: &[RoleId],
+) -> bool {
+    if !allowed
+        .iter()
+        .any(|(allowed_level, _)| *allowed_level == level)
+    {
+        return true;
+    }
+    allowed.iter().any(|(allowed_level, role)| {
+        (*allowed_level == level || *allowed_level == PermissionLevel::Admin)
+            && roles.contains(role)
+    })
+}
+
+// コマンドを使ってよいか調べる。サーバー外とサーバーの管理権限を持っている人はいつでも使える
+pub async fn check_permission(
+    database: &sqlx::SqlitePool,
+    guild_id: Option<GuildId>,
+    member: Option<&Member>,
+    command_name: &str,
+) -> Result<()> {
+    let (guild_id, member, level) =
+        match (guild_id, member, PermissionLevel::of_command(command_name)) {
+            (Some(guild_id), Some(member), Some(level)) => (guild_id, member, level),
+            _ => return Ok(()),
+        };
+    if member
+        .permissions
+        .is_some_and(|permissions| permissions.manage_guild())
+    {
+        return Ok(());
+    }
+    // DB が読めないときは、だれも使えなくなるより今までどおり使えるほうにする
+    let roles = match database.get_permission_roles(guild_id.0 as i64).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            warn!("could not read permission roles of {}: {}", guild_id, e);
+            return Ok(());
+        }
+    };
+    let allowed = roles
+        .into_iter()
+        .filter_map(|(level, role_id)| {
+            Some((
+                PermissionLevel::try_from(level.as_str()).ok()?,
+                RoleId(role_id as u64),
+            ))
+        })
+        .collect::<Vec<_>>();
+    if is_allowed(level, &allowed, &member.roles) {
+        Ok(())
+    } else {
+        Err(anyhow!("このコマンドを使えるロールを持っていないよ"))
+    }
+}
+
+#[test]
+fn permission_test() {
+    for level in PermissionLevel::ALL {
+        let name: &str = level.into();
+        assert_eq!(PermissionLevel::try_from(name).unwrap(), level);
+    }
+    assert_eq!(
+        PermissionLevel::of_command("rem"),
+        Some(PermissionLevel::Admin)
+    );
+    assert_eq!(
+        PermissionLevel::of_command("join"),
+        Some(PermissionLevel::User)
+    );
+    assert_eq!(PermissionLevel::of_command("bot_perm"), None);
+
+    let (admin, member) = (RoleId(1), RoleId(2));
+    // 何も決めていなければ今までどおりだれでも使える
+    assert!(is_allowed(PermissionLevel::Admin, &[], &[]));
+
+    let allowed = [(PermissionLevel::Admin, admin)];
+    assert!(is_allowed(PermissionLevel::Admin, &allowed, &[admin]));
+    assert!(!is_allowed(PermissionLevel::Admin, &allowed, &[member]));
+    assert!(is_allowed(PermissionLevel::User, &allowed, &[]));
+
+    let allowed = [
+        (PermissionLevel::Admin, admin),
+        (PermissionLevel::User, member),
+    ];
+    assert!(is_allowed(PermissionLevel::User, &allowed, &[member]));
+    assert!(is_allowed(PermissionLevel::User, &allowed, &[admin]));
+    assert!(!is_allowed(PermissionLevel::User, &allowed, &[RoleId(3)]));
+}
@@ -0,0 +1,249 @@
+use anyhow::anyhow;
+
+use super::{db::GuildConfigDB, language::Language};
+
+// bot の口調。サーバーごとに選べる
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Personality {
+    Casual,
+    Polite,
+    Character,
+}
+
+impl TryFrom<&str> for Personality {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "casual" => Ok(Self::Casual),
+            "polite" => Ok(Self::Polite),
+            "character" => Ok(Self::Character),
+            _ => Err(anyhow!("no such personality")),
+        }
+    }
+}
+
+impl From<Personality> for &str {
+    fn from(personality: Personality) -> Self {
+        match personality {
+            Personality::Casual => "casual",
+            Personality::Polite => "polite",
+            Personality::Character => "character",
+        }
+    }
+}
+
+// bot が話す文言一式。{0}, {1}, ... は fill で埋める
+pub struct Bundle {
+    pub join: &'static str,
+    pub leave: &'static str,
+    pub add: &'static str,
+    pub rem: &'static str,
+    pub rem_not_found: &'static str,
+    pub mute: &'static str,
+    pub unmute: &'static str,
+    pub greet_changed: &'static str,
+    pub nickname_changed: &'static str,
+    pub rand_member: &'static str,
+    pub greeting: &'static str,
+    pub default_hello: &'static str,
+    pub default_bye: &'static str,
+    pub read_channel_changed: &'static str,
+    pub many_joined: &'static str,
+    // /config greeting announce で、あいさつを決めていない人の入退出に読む
+    pub joined: &'static str,
+    pub left: &'static str,
+    // 読み上げたメッセージが編集されたときに読む
+    pub corrected: &'static str,
+    // 終了するときにボイスチャンネルで読む
+    pub restart: &'static str,
+    // 読むときの整形に使う言語
+    pub language: Language,
+}
+
+static CASUAL: Bundle = Bundle {
+    join: "おはよ！",
+    leave: "ばいばい",
+    add: "これからは、{0} を {1} って読むね",
+    rem: "これからは {0} って読むね",
+    rem_not_found: "その単語は登録されてないよ！",
+    mute: "ミュートしたよ",
+    unmute: "ミュート解除したよ",
+    greet_changed: "{0}さん、これから{1}ってあいさつするね",
+    nickname_changed: "{0}さん、これからは{1}って呼ぶね",
+    rand_member: "でけでけでけでけ・・・でん！{0}",
+    greeting: "{0}さん、{1}",
+    default_hello: "こんにちは",
+    default_bye: "ばいばい",
+    read_channel_changed: "これからは {0} を読み上げるね",
+    many_joined: "{0}人が参加したよ",
+    joined: "{0}さんが参加したよ",
+    left: "{0}さんが抜けたよ",
+    corrected: "訂正、{0}",
+    restart: "再起動するね",
+    language: Language::Japanese,
+};
+
+static POLITE: Bundle = Bundle {
+    join: "読み上げを開始します",
+    leave: "読み上げを終了します",
+    add: "今後は {0} を {1} と読み上げます",
+    rem: "今後は {0} とそのまま読み上げます",
+    rem_not_found: "その単語は登録されていません",
+    mute: "ミュートしました",
+    unmute: "ミュートを解除しました",
+    greet_changed: "{0}様、今後は{1}とごあいさついたします",
+    nickname_changed: "{0}様、今後は{1}とお呼びいたします",
+    rand_member: "厳正な抽選の結果、{0}様が選ばれました",
+    greeting: "{0}様、{1}",
+    default_hello: "いらっしゃいませ",
+    default_bye: "お疲れさまでした",
+    read_channel_changed: "今後は {0} を読み上げます",
+    many_joined: "{0}名様が参加されました",
+    joined: "{0}様が参加されました",
+    left: "{0}様が退出されました",
+    corrected: "訂正いたします、{0}",
+    restart: "再起動します",
+    language: Language::Japanese,
+};
+
+static CHARACTER: Bundle = Bundle {
+    join: "ボクが来たのだ！",
+    leave: "またなのだ！",
+    add: "これからは {0} を {1} って読むのだ",
+    rem: "これからは {0} って読むのだ",
+    rem_not_found: "その単語は登録されてないのだ！",
+    mute: "ミュートしたのだ",
+    unmute: "ミュート解除したのだ",
+    greet_changed: "{0}さん、これから{1}ってあいさつするのだ",
+    nickname_changed: "{0}さん、これからは{1}って呼ぶのだ",
+    rand_member: "でけでけでけでけ・・・でん！{0}なのだ！",
+    greeting: "{0}さん、{1}",
+    default_hello: "こんにちはなのだ",
+    default_bye: "またねなのだ",
+    read_channel_changed: "これからは {0} を読み上げるのだ",
+    many_joined: "{0}人が来たのだ！",
+    joined: "{0}さんが来たのだ",
+    left: "{0}さんが帰ったのだ",
+    corrected: "訂正なのだ、{0}",
+    restart: "再起動するのだ",
+    language: Language::Japanese,
+};
+
+// 英語のサーバーでは口調によらずこれを使う
+static ENGLISH: Bundle = Bundle {
+    join: "Hello!",
+    leave: "Bye!",
+    add: "From now on, I'll read {0} as {1}",
+    rem: "From now on, I'll read {0} as it is",
+    rem_not_found: "That word isn't registered!",
+    mute: "Muted",
+    unmute: "Unmuted",
+    greet_changed: "{0}, I'll greet you with {1} from now on",
+    nickname_changed: "{0}, I'll call you {1} from now on",
+    rand_member: "Drum roll... {0}!",
+    greeting: "{0}, {1}",
+    default_hello: "hello",
+    default_bye: "bye",
+    read_channel_changed: "From now on, I'll read {0}",
+    many_joined: "{0} people joined",
+    joined: "{0} joined",
+    left: "{0} left",
+    corrected: "Correction: {0}",
+    restart: "Restarting now",
+    language: Language::English,
+};
+
+impl Personality {
+    pub fn bundle(self) -> &'static Bundle {
+        match self {
+            Self::Casual => &CASUAL,
+            Self::Polite => &POLITE,
+            Self::Character => &CHARACTER,
+        }
+    }
+}
+
+// サーバーの口調と言語の文言を取得する。サーバー外や設定が読めないときは Casual
+pub async fn guild_bundle(database: &sqlx::SqlitePool, guild_id: Option<i64>) -> &'static Bundle {
+    let guild_config = match guild_id {
+        Some(guild_id) => database.get_guild_config_or_default(guild_id).await.ok(),
+        None => None,
+    };
+    match &guild_config {
+        Some(guild_config) if Language::of(guild_config) == Language::English => &ENGLISH,
+        _ => guild_config
+            .and_then(|guild_config| Personality::try_from(guild_config.personality.as_str()).ok())
+            .unwrap_or(Personality::Casual)
+            .bundle(),
+    }
+}
+
+// /set_greeting_template で設定できる長さ
+pub const MAX_GREETING_TEMPLATE_CHARS: usize = 60;
+
+// サーバーで設定するあいさつの形。{name} に名前、{greeting} に各自のあいさつが入る
+pub fn check_greeting_template(template: &str) -> anyhow::Result<()> {
+    if !template.contains("{greeting}") {
+        return Err(anyhow!("あいさつを入れる {{greeting}} を含めてね"));
+    }
+    if template.chars().count() > MAX_GREETING_TEMPLATE_CHARS {
+        return Err(anyhow!(
+            "あいさつの形は {} 文字までにしてね",
+            MAX_GREETING_TEMPLATE_CHARS
+        ));
+    }
+    Ok(())
+}
+
+pub fn fill_greeting(template: &str, name: &str, greeting: &str) -> String {
+    fill(
+        &template
+            .replace("{name}", "{0}")
+            .replace("{greeting}", "{1}"),
+        &[name, greeting],
+    )
+}
+
+pub fn fill(template: &str, args: &[&str]) -> String {
+    let re = regex::Regex::new(r"\{(\d+)\}").unwrap();
+    re.replace_all(template, |caps: &regex::Captures| {
+        caps[1]
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| args.get(i))
+            .map_or_else(|| caps[0].to_string(), |arg| arg.to_string())
+    })
+    .to_string()
+}
+
+#[test]
+fn fill_test() {
+    assert_eq!(
+        fill(CASUAL.add, &["nap", "なっぷ"]),
+        "これからは、nap を なっぷ って読むね"
+    );
+    assert_eq!(
+        fill(CASUAL.greeting, &["たろう", "こんにちは"]),
+        "たろうさん、こんにちは"
+    );
+    // 引数の中の {1} はそのまま
+    assert_eq!(fill("{0}と{1}", &["{1}", "b"]), "{1}とb");
+    assert_eq!(fill("{0}", &[]), "{0}");
+}
+
+#[test]
+fn greeting_template_test() {
+    assert_eq!(
+        fill_greeting("{greeting}、{name}さん！", "たろう", "おはよう"),
+        "おはよう、たろうさん！"
+    );
+    // 名前は入れなくてもよい
+    assert_eq!(fill_greeting("{greeting}", "たろう", "やあ"), "やあ");
+    assert!(check_greeting_template("{name}さん、{greeting}").is_ok());
+    assert!(check_greeting_template("{name}さん").is_err());
+    assert!(
+        check_greeting_template(&("あ".repeat(MAX_GREETING_TEMPLATE_CHARS) + "{greeting}"))
+            .is_err()
+    );
+}
@@ -0,0 +1,491 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use serenity::{
+    async_trait,
+    model::id::{GuildId, MessageId},
+};
+use songbird::{
+    tracks::{TrackHandle, TrackQueue},
+    Event, EventContext, TrackEvent,
+};
+use tokio::sync::Mutex;
+
+use super::{db::GuildConfig, music::Music};
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+// 消されたメッセージを覚えておく数。合成中に消されたメッセージは積まない
+const DELETED_MESSAGES: usize = 100;
+
+// あいさつやコマンドの返事など、bot からのお知らせの読み方。/config announce_priority で設定する
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    // メッセージの読み上げと同じく後ろに積む
+    Queue,
+    // 読んでいるメッセージの次に割り込む
+    Next,
+    // 読んでいるメッセージを一時停止して先に読み、終わったら続きを読む
+    Interrupt,
+}
+
+impl Priority {
+    pub fn of(guild_config: &GuildConfig) -> Self {
+        match guild_config.announce_priority.as_str() {
+            "queue" => Self::Queue,
+            "interrupt" => Self::Interrupt,
+            _ => Self::Next,
+        }
+    }
+
+    // お知らせを入れるキューの位置。読んでいるトラックと、先に割り込んだお知らせの後ろにする
+    // queue はキューのトラックがお知らせかどうか
+    pub fn position(self, queue: &[bool]) -> usize {
+        let start = match self {
+            Self::Queue => return queue.len(),
+            Self::Next => 1,
+            Self::Interrupt => 0,
+        }
+        .min(queue.len());
+        start
+            + queue[start..]
+                .iter()
+                .take_while(|announcement| **announcement)
+                .count()
+    }
+}
+
+#[derive(Default)]
+struct PlaybackState {
+    // キューに積んだ読み上げのトラック。間にはさむ無音は含まない
+    tracks: HashMap<GuildId, Vec<TrackHandle>>,
+    // tracks のうち、割り込んで積んだお知らせ
+    announcements: HashMap<GuildId, Vec<TrackHandle>>,
+    // /flush するたびに増やす。合成中に flush されたメッセージは積まない
+    generations: HashMap<GuildId, u64>,
+    // ボイスチャンネルに入るたびに増やす。読み終わってから抜ける前に入り直されていたら抜けない
+    sessions: HashMap<GuildId, u64>,
+    // tracks のトラックと、そのもとになったメッセージ。まとめて読んだトラックは複数のメッセージに結びつく
+    message_tracks: HashMap<GuildId, Vec<(MessageId, TrackHandle)>>,
+    // 最近消されたメッセージ
+    deleted: HashMap<GuildId, VecDeque<MessageId>>,
+}
+
+// サーバーごとの読み上げのトラックを覚えておき、/skip や /flush で止められるようにする
+// 読み上げのトラックのイベントで /play の音楽の音量を下げる
+#[derive(Clone, Default)]
+pub struct Playback {
+    state: Arc<Mutex<PlaybackState>>,
+    music: Music,
+}
+
+struct TrackEndNotifier {
+    playback: Playback,
+    guild_id: GuildId,
+}
+
+struct TrackStartNotifier {
+    music: Music,
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl songbird::EventHandler for TrackStartNotifier {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        self.music.speech_started(self.guild_id).await;
+        None
+    }
+}
+
+#[async_trait]
+impl songbird::EventHandler for TrackEndNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(tracks) = ctx {
+            for (_, handle) in tracks.iter() {
+                self.playback.remove(self.guild_id, handle).await;
+            }
+        }
+        None
+    }
+}
+
+impl Playback {
+    pub fn new(music: Music) -> Self {
+        Playback {
+            music,
+            ..Default::default()
+        }
+    }
+
+    pub async fn generation(&self, guild_id: GuildId) -> u64 {
+        *self
+            .state
+            .lock()
+            .await
+            .generations
+            .get(&guild_id)
+            .unwrap_or(&0)
+    }
+
+    pub async fn session(&self, guild_id: GuildId) -> u64 {
+        *self
+            .state
+            .lock()
+            .await
+            .sessions
+            .get(&guild_id)
+            .unwrap_or(&0)
+    }
+
+    // ボイスチャンネルに入ったり移ったりしたときに呼ぶ
+    pub async fn begin_session(&self, guild_id: GuildId) {
+        *self
+            .state
+            .lock()
+            .await
+            .sessions
+            .entry(guild_id)
+            .or_default() += 1;
+    }
+
+    // 合成を始めたときの generation のままなら登録して true を返す
+    pub async fn register(&self, guild_id: GuildId, handle: &TrackHandle, generation: u64) -> bool {
+        let mut state = self.state.lock().await;
+        if *state.generations.get(&guild_id).unwrap_or(&0) != generation {
+            return false;
+        }
+        let tracks = state.tracks.entry(guild_id).or_default();
+        // キューが空なら一時停止を経ずにすぐ流れるので、Play イベントが来ない
+        let starts_now = tracks.is_empty();
+        tracks.push(handle.clone());
+        drop(state);
+        if starts_now {
+            self.music.speech_started(guild_id).await;
+        }
+        handle
+            .add_event(
+                Event::Track(TrackEvent::Play),
+                TrackStartNotifier {
+                    music: self.music.clone(),
+                    guild_id,
+                },
+            )
+            .ok();
+        handle
+            .add_event(
+                Event::Track(TrackEvent::End),
+                TrackEndNotifier {
+                    playback: self.clone(),
+                    guild_id,
+                },
+            )
+            .ok();
+        true
+    }
+
+    // 合成している間にメッセージが消されていたら true
+    pub async fn is_deleted(&self, guild_id: GuildId, message_ids: &[MessageId]) -> bool {
+        self.state
+            .lock()
+            .await
+            .deleted
+            .get(&guild_id)
+            .is_some_and(|deleted| message_ids.iter().any(|id| deleted.contains(id)))
+    }
+
+    // 積んだトラックをもとのメッセージと結びつけ、消されたら止められるようにする
+    pub async fn attach(&self, guild_id: GuildId, message_ids: &[MessageId], handle: &TrackHandle) {
+        self.state
+            .lock()
+            .await
+            .message_tracks
+            .entry(guild_id)
+            .or_default()
+            .extend(message_ids.iter().map(|id| (*id, handle.clone())));
+    }
+
+    // 消されたメッセージの読み上げを取り消す。読んでいれば止め、積んであれば取り除く
+    // 取り消したトラックの数を返す。合成中なら is_deleted で積まないようにする
+    pub async fn cancel_message(
+        &self,
+        guild_id: GuildId,
+        message_id: MessageId,
+        queue: &TrackQueue,
+    ) -> usize {
+        let mut state = self.state.lock().await;
+        let deleted = state.deleted.entry(guild_id).or_default();
+        if deleted.len() == DELETED_MESSAGES {
+            deleted.pop_front();
+        }
+        deleted.push_back(message_id);
+        let handles = match state.message_tracks.get_mut(&guild_id) {
+            Some(message_tracks) => {
+                let (cancelled, rest) = message_tracks
+                    .drain(..)
+                    .partition::<Vec<_>, _>(|(id, _)| *id == message_id);
+                *message_tracks = rest;
+                cancelled
+                    .into_iter()
+                    .map(|(_, handle)| handle)
+                    .collect::<Vec<_>>()
+            }
+            None => Vec::new(),
+        };
+        for handle in handles.iter() {
+            if let Some(tracks) = state.tracks.get_mut(&guild_id) {
+                tracks.retain(|t| t.uuid() != handle.uuid());
+            }
+            if let Some(announcements) = state.announcements.get_mut(&guild_id) {
+                announcements.retain(|t| t.uuid() != handle.uuid());
+            }
+        }
+        let drained = state
+            .tracks
+            .get(&guild_id)
+            .is_none_or(|tracks| tracks.is_empty());
+        drop(state);
+        for handle in handles.iter() {
+            match queue
+                .current_queue()
+                .iter()
+                .position(|t| t.uuid() == handle.uuid())
+            {
+                Some(0) => {
+                    queue.skip().ok();
+                }
+                Some(index) => {
+                    if let Some(queued) = queue.dequeue(index) {
+                        queued.stop().ok();
+                    }
+                }
+                None => {}
+            }
+        }
+        if !handles.is_empty() && drained {
+            self.music.speech_drained(guild_id).await;
+        }
+        handles.len()
+    }
+
+    // 割り込んで積んだお知らせとして覚えておく
+    pub async fn mark_announcement(&self, guild_id: GuildId, handle: &TrackHandle) {
+        self.state
+            .lock()
+            .await
+            .announcements
+            .entry(guild_id)
+            .or_default()
+            .push(handle.clone());
+    }
+
+    // キューの各トラックがお知らせかどうか
+    pub async fn announcement_flags(&self, guild_id: GuildId, queue: &[TrackHandle]) -> Vec<bool> {
+        let state = self.state.lock().await;
+        let announcements = state.announcements.get(&guild_id);
+        queue
+            .iter()
+            .map(|track| {
+                announcements.is_some_and(|announcements| {
+                    announcements.iter().any(|a| a.uuid() == track.uuid())
+                })
+            })
+            .collect()
+    }
+
+    async fn remove(&self, guild_id: GuildId, handle: &TrackHandle) {
+        let mut state = self.state.lock().await;
+        if let Some(announcements) = state.announcements.get_mut(&guild_id) {
+            announcements.retain(|t| t.uuid() != handle.uuid());
+        }
+        if let Some(message_tracks) = state.message_tracks.get_mut(&guild_id) {
+            message_tracks.retain(|(_, t)| t.uuid() != handle.uuid());
+        }
+        if let Some(tracks) = state.tracks.get_mut(&guild_id) {
+            tracks.retain(|t| t.uuid() != handle.uuid());
+            if tracks.is_empty() {
+                drop(state);
+                self.music.speech_drained(guild_id).await;
+            }
+        }
+    }
+
+    // いま読んでいる (なければ次に読む) メッセージを飛ばす
+    pub async fn skip(&self, guild_id: GuildId, queue: &TrackQueue) -> Result<()> {
+        let queued = queue.current_queue();
+        let queue_position =
+            |handle: &TrackHandle| queued.iter().position(|t| t.uuid() == handle.uuid());
+        // 割り込んだお知らせは積んだ順と読む順が違うので、キューでいちばん前のものを飛ばす
+        let handle = self
+            .state
+            .lock()
+            .await
+            .tracks
+            .get_mut(&guild_id)
+            .filter(|tracks| !tracks.is_empty())
+            .map(|tracks| {
+                let index = (0..tracks.len())
+                    .min_by_key(|i| queue_position(&tracks[*i]).unwrap_or(usize::MAX))
+                    .unwrap_or(0);
+                tracks.remove(index)
+            })
+            .ok_or_else(|| anyhow!("読み上げ中のメッセージはないよ"))?;
+        match queue_position(&handle) {
+            Some(0) => queue.skip()?,
+            // 間の無音を流している間は、まだ始まっていない次のメッセージを取り除く
+            Some(index) => {
+                if let Some(queued) = queue.dequeue(index) {
+                    queued.stop().ok();
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    // 積んである読み上げと、合成中のメッセージを全部取り消す。取り消したトラックの数を返す
+    pub async fn flush(&self, guild_id: GuildId, queue: &TrackQueue) -> usize {
+        let mut state = self.state.lock().await;
+        *state.generations.entry(guild_id).or_default() += 1;
+        let count = state
+            .tracks
+            .remove(&guild_id)
+            .map_or(0, |tracks| tracks.len());
+        state.announcements.remove(&guild_id);
+        state.message_tracks.remove(&guild_id);
+        queue.stop();
+        drop(state);
+        self.music.speech_drained(guild_id).await;
+        count
+    }
+
+    // 読み上げが全部終わるまで待つ。timeout までに終わらなければ false を返す
+    pub async fn wait_drained(&self, guild_id: GuildId, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.len(guild_id).await > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+        true
+    }
+
+    // まだ読み終わっていない読み上げの数
+    pub async fn len(&self, guild_id: GuildId) -> usize {
+        self.state
+            .lock()
+            .await
+            .tracks
+            .get(&guild_id)
+            .map_or(0, |tracks| tracks.len())
+    }
+}
+
+#[tokio::test]
+async fn playback_test() {
+    use super::voice::silence;
+
+    let playback = Playback::default();
+    let queue = TrackQueue::new();
+    let guild_id = GuildId(1);
+    let (_, first) = songbird::tracks::create_player(silence(Duration::from_millis(10)));
+    let (_, second) = songbird::tracks::create_player(silence(Duration::from_millis(10)));
+
+    let generation = playback.generation(guild_id).await;
+    assert!(playback.register(guild_id, &first, generation).await);
+    assert!(playback.register(guild_id, &second, generation).await);
+    assert_eq!(playback.len(guild_id).await, 2);
+
+    playback.skip(guild_id, &queue).await.unwrap();
+    assert_eq!(playback.len(guild_id).await, 1);
+
+    assert_eq!(playback.flush(guild_id, &queue).await, 1);
+    assert_eq!(playback.len(guild_id).await, 0);
+    assert!(playback.skip(guild_id, &queue).await.is_err());
+
+    // flush の前に合成を始めたメッセージは積まない
+    let (_, late) = songbird::tracks::create_player(silence(Duration::from_millis(10)));
+    assert!(!playback.register(guild_id, &late, generation).await);
+    let generation = playback.generation(guild_id).await;
+    assert!(playback.register(guild_id, &late, generation).await);
+
+    // flush では入り直したことにならない
+    let session = playback.session(guild_id).await;
+    playback.flush(guild_id, &queue).await;
+    assert_eq!(playback.session(guild_id).await, session);
+    playback.begin_session(guild_id).await;
+    assert_ne!(playback.session(guild_id).await, session);
+}
+
+#[tokio::test]
+async fn cancel_message_test() {
+    use super::voice::silence;
+
+    let playback = Playback::default();
+    let queue = TrackQueue::new();
+    let guild_id = GuildId(1);
+    let (_, first) = songbird::tracks::create_player(silence(Duration::from_millis(10)));
+    let (_, second) = songbird::tracks::create_player(silence(Duration::from_millis(10)));
+    for (message_id, handle) in [(MessageId(1), &first), (MessageId(2), &second)] {
+        assert!(playback.register(guild_id, handle, 0).await);
+        playback.attach(guild_id, &[message_id], handle).await;
+    }
+
+    assert_eq!(
+        playback
+            .cancel_message(guild_id, MessageId(1), &queue)
+            .await,
+        1
+    );
+    assert_eq!(playback.len(guild_id).await, 1);
+    // 読んでいないメッセージが消されても何もしない
+    assert_eq!(
+        playback
+            .cancel_message(guild_id, MessageId(3), &queue)
+            .await,
+        0
+    );
+    assert_eq!(playback.len(guild_id).await, 1);
+
+    // 合成中に消されたメッセージは積まない
+    assert!(
+        playback
+            .is_deleted(guild_id, &[MessageId(4), MessageId(3)])
+            .await
+    );
+    assert!(!playback.is_deleted(guild_id, &[MessageId(4)]).await);
+    assert!(!playback.is_deleted(GuildId(2), &[MessageId(3)]).await);
+}
+
+#[tokio::test]
+async fn wait_drained_test() {
+    use super::voice::silence;
+
+    let playback = Playback::default();
+    let guild_id = GuildId(1);
+    assert!(playback.wait_drained(guild_id, Duration::ZERO).await);
+
+    let (_, track) = songbird::tracks::create_player(silence(Duration::from_millis(10)));
+    assert!(playback.register(guild_id, &track, 0).await);
+    assert!(!playback.wait_drained(guild_id, Duration::ZERO).await);
+}
+
+#[test]
+fn priority_position_test() {
+    let queue = [false, true, true, false, false];
+    assert_eq!(Priority::Queue.position(&queue), 5);
+    // 先に割り込んだお知らせの後ろに入れる
+    assert_eq!(Priority::Next.position(&queue), 3);
+    assert_eq!(Priority::Interrupt.position(&queue), 0);
+    assert_eq!(Priority::Interrupt.position(&[true, false]), 1);
+    assert_eq!(Priority::Next.position(&[]), 0);
+    assert_eq!(Priority::Interrupt.position(&[]), 0);
+
+    let mut guild_config = GuildConfig::from_guild_id(0);
+    assert_eq!(Priority::of(&guild_config), Priority::Next);
+    guild_config.announce_priority = "interrupt".to_string();
+    assert_eq!(Priority::of(&guild_config), Priority::Interrupt);
+}
@@ -0,0 +1,157 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use super::db::UserConfig;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+// 時差を決めていない人は日本の時刻で見る
+pub const DEFAULT_UTC_OFFSET_MIN: i64 = 9 * 60;
+// UTC-12:00 から UTC+14:00 まで
+const UTC_OFFSET_RANGE: (i64, i64) = (-12 * 60, 14 * 60);
+
+// 入退出のあいさつとメッセージの前の名前を読まない時間帯。/quiet_hours で決める
+// 分は 0 時からで、start_min > end_min なら日をまたぐ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start_min: i64,
+    pub end_min: i64,
+}
+
+impl QuietHours {
+    pub fn of(user_config: &UserConfig) -> Option<Self> {
+        Some(Self {
+            start_min: user_config.quiet_start_min?,
+            end_min: user_config.quiet_end_min?,
+        })
+    }
+
+    // 23:00-07:00 の形
+    pub fn parse(value: &str) -> Result<Self> {
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| anyhow!("23:00-07:00 のように書いてね"))?;
+        let quiet_hours = Self {
+            start_min: parse_time(start)?,
+            end_min: parse_time(end)?,
+        };
+        if quiet_hours.start_min == quiet_hours.end_min {
+            return Err(anyhow!("始まりと終わりは違う時刻にしてね"));
+        }
+        Ok(quiet_hours)
+    }
+
+    // minute はその人の時刻で 0 時からの分
+    pub fn contains(self, minute: i64) -> bool {
+        if self.start_min < self.end_min {
+            (self.start_min..self.end_min).contains(&minute)
+        } else {
+            minute >= self.start_min || minute < self.end_min
+        }
+    }
+}
+
+impl std::fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            format_time(self.start_min),
+            format_time(self.end_min)
+        )
+    }
+}
+
+fn parse_time(value: &str) -> Result<i64> {
+    let invalid = || anyhow!("{} は時刻として読めないよ。07:00 のように書いてね", value);
+    let (hour, minute) = value.trim().split_once(':').ok_or_else(invalid)?;
+    let (hour, minute) = (
+        hour.parse::<i64>().map_err(|_| invalid())?,
+        minute.parse::<i64>().map_err(|_| invalid())?,
+    );
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) {
+        return Err(invalid());
+    }
+    Ok(hour * 60 + minute)
+}
+
+fn format_time(minute: i64) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+// +09:00 や -05:00、+9 の形
+pub fn parse_utc_offset(value: &str) -> Result<i64> {
+    let invalid = || anyhow!("{} は時差として読めないよ。+09:00 のように書いてね", value);
+    let value = value.trim();
+    let (sign, rest) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (hour, minute) = rest.split_once(':').unwrap_or((rest, "0"));
+    let (hour, minute) = (
+        hour.parse::<i64>().map_err(|_| invalid())?,
+        minute.parse::<i64>().map_err(|_| invalid())?,
+    );
+    if !(0..60).contains(&minute) {
+        return Err(invalid());
+    }
+    let offset = sign * (hour * 60 + minute);
+    if !(UTC_OFFSET_RANGE.0..=UTC_OFFSET_RANGE.1).contains(&offset) {
+        return Err(invalid());
+    }
+    Ok(offset)
+}
+
+pub fn format_utc_offset(offset: i64) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!("UTC{}{}", sign, format_time(offset.abs()))
+}
+
+// unix_secs の時刻が、その人の静かな時間帯に入っているか
+pub fn is_quiet_at(user_config: &UserConfig, unix_secs: i64) -> bool {
+    QuietHours::of(user_config).is_some_and(|quiet_hours| {
+        let minute =
+            (unix_secs.div_euclid(60) + user_config.utc_offset_min).rem_euclid(MINUTES_PER_DAY);
+        quiet_hours.contains(minute)
+    })
+}
+
+pub fn is_quiet(user_config: &UserConfig) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    is_quiet_at(user_config, now)
+}
+
+#[test]
+fn quiet_hours_test() {
+    let night = QuietHours::parse("23:00-07:00").unwrap();
+    assert_eq!(night.to_string(), "23:00-07:00");
+    assert!(night.contains(23 * 60));
+    assert!(night.contains(3 * 60));
+    assert!(!night.contains(7 * 60));
+    assert!(!night.contains(12 * 60));
+    let lunch = QuietHours::parse("12:00-13:30").unwrap();
+    assert!(lunch.contains(13 * 60));
+    assert!(!lunch.contains(13 * 60 + 30));
+
+    assert!(QuietHours::parse("23:00").is_err());
+    assert!(QuietHours::parse("25:00-07:00").is_err());
+    assert!(QuietHours::parse("07:00-07:00").is_err());
+
+    assert_eq!(parse_utc_offset("+09:00").unwrap(), 540);
+    assert_eq!(parse_utc_offset("9").unwrap(), 540);
+    assert_eq!(parse_utc_offset("-05:30").unwrap(), -330);
+    assert!(parse_utc_offset("+15:00").is_err());
+    assert_eq!(format_utc_offset(-330), "UTC-05:30");
+
+    let mut user_config = UserConfig::from_user_id(1);
+    // 2022-01-01 15:00 UTC は日本の 24:00
+    let unix_secs = 1_641_049_200;
+    assert!(!is_quiet_at(&user_config, unix_secs));
+    user_config.quiet_start_min = Some(night.start_min);
+    user_config.quiet_end_min = Some(night.end_min);
+    assert!(is_quiet_at(&user_config, unix_secs));
+    user_config.utc_offset_min = 0;
+    assert!(!is_quiet_at(&user_config, unix_secs));
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::model::{channel::ReactionType, id::MessageId};
+use tokio::sync::Mutex;
+
+#[derive(Default)]
+struct ReactionState {
+    // (メッセージ, 絵文字) ごとの、集計期間内にリアクションがついた時刻
+    history: HashMap<(MessageId, String), Vec<Instant>>,
+    // 一度読み上げた (メッセージ, 絵文字) は繰り返し読まない
+    announced: HashSet<(MessageId, String)>,
+    // announced に入れた順。MAX_TRACKED を超えたら古いものから忘れる
+    announced_order: VecDeque<(MessageId, String)>,
+    // (メッセージ, 絵文字) ごとの、これまでについたリアクションの数
+    totals: HashMap<(MessageId, String), usize>,
+    // totals に入れた順。MAX_TRACKED を超えたら古いものから忘れる
+    tracked: VecDeque<(MessageId, String)>,
+    // 数が reaction_milestone に届いて読み上げた (メッセージ, 絵文字)
+    milestones: HashSet<(MessageId, String)>,
+}
+
+// 読み上げたか、reaction_milestone のために数を覚えておく (メッセージ, 絵文字) の数
+const MAX_TRACKED: usize = 1000;
+
+// 短時間にたくさんついたリアクションを数える
+#[derive(Clone, Default)]
+pub struct ReactionCounter {
+    state: Arc<Mutex<ReactionState>>,
+}
+
+impl ReactionCounter {
+    // リアクションを記録し、集計期間内の件数が threshold に達したらその件数を返す
+    pub async fn add(
+        &self,
+        message_id: MessageId,
+        emoji: &str,
+        threshold: usize,
+        window: Duration,
+    ) -> Option<usize> {
+        self.add_at(message_id, emoji, threshold, window, Instant::now())
+            .await
+    }
+
+    async fn add_at(
+        &self,
+        message_id: MessageId,
+        emoji: &str,
+        threshold: usize,
+        window: Duration,
+        now: Instant,
+    ) -> Option<usize> {
+        let mut state = self.state.lock().await;
+        let key = (message_id, emoji.to_string());
+        let history = state.history.entry(key.clone()).or_default();
+        history.retain(|&t| now.duration_since(t) <= window);
+        history.push(now);
+        let count = history.len();
+        state
+            .history
+            .retain(|_, history| history.iter().any(|&t| now.duration_since(t) <= window));
+        if count < threshold || !state.announced.insert(key.clone()) {
+            return None;
+        }
+        state.announced_order.push_back(key);
+        if state.announced_order.len() > MAX_TRACKED {
+            if let Some(oldest) = state.announced_order.pop_front() {
+                state.announced.remove(&oldest);
+            }
+        }
+        Some(count)
+    }
+}
+
+impl ReactionCounter {
+    // リアクションの数を 1 つ増やし、初めて milestone 個に届いたら true を返す
+    pub async fn add_total(&self, message_id: MessageId, emoji: &str, milestone: usize) -> bool {
+        let mut state = self.state.lock().await;
+        let key = (message_id, emoji.to_string());
+        if !state.totals.contains_key(&key) {
+            if state.tracked.len() == MAX_TRACKED {
+                if let Some(oldest) = state.tracked.pop_front() {
+                    state.totals.remove(&oldest);
+                    state.milestones.remove(&oldest);
+                }
+            }
+            state.tracked.push_back(key.clone());
+        }
+        let total = state.totals.entry(key.clone()).or_default();
+        *total += 1;
+        *total >= milestone && state.milestones.insert(key)
+    }
+
+    // リアクションが外されたら数を減らす。一度読んだものはもう一度届いても読まない
+    pub async fn remove_total(&self, message_id: MessageId, emoji: &str) {
+        let mut state = self.state.lock().await;
+        if let Some(total) = state.totals.get_mut(&(message_id, emoji.to_string())) {
+            *total = total.saturating_sub(1);
+        }
+    }
+}
+
+pub fn emoji_name(emoji: &ReactionType) -> String {
+    match emoji {
+        ReactionType::Custom { name, .. } => name.clone().unwrap_or_default(),
+        ReactionType::Unicode(s) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+#[tokio::test]
+async fn reaction_counter_test() {
+    let counter = ReactionCounter::default();
+    let window = Duration::from_secs(60);
+    let now = Instant::now();
+    let message_id = MessageId(1);
+    assert_eq!(counter.add_at(message_id, "👍", 3, window, now).await, None);
+    assert_eq!(counter.add_at(message_id, "🎉", 3, window, now).await, None);
+    assert_eq!(counter.add_at(message_id, "👍", 3, window, now).await, None);
+    assert_eq!(
+        counter.add_at(message_id, "👍", 3, window, now).await,
+        Some(3)
+    );
+    // 一度読んだら同じ絵文字では読まない
+    assert_eq!(counter.add_at(message_id, "👍", 3, window, now).await, None);
+
+    // 集計期間を過ぎたリアクションは数えない
+    let later = now + Duration::from_secs(61);
+    assert_eq!(
+        counter.add_at(message_id, "🎉", 3, window, later).await,
+        None
+    );
+    assert_eq!(
+        counter.add_at(message_id, "🎉", 3, window, later).await,
+        None
+    );
+    assert_eq!(
+        counter.add_at(message_id, "🎉", 3, window, later).await,
+        Some(3)
+    );
+
+    // 読み上げたものを覚えておく数を超えたら古いものから忘れる
+    for id in 2..(2 + MAX_TRACKED as u64) {
+        counter.add_at(MessageId(id), "👍", 1, window, later).await;
+    }
+    assert_eq!(counter.state.lock().await.announced.len(), MAX_TRACKED);
+    assert_eq!(
+        counter.add_at(message_id, "👍", 1, window, later).await,
+        Some(1)
+    );
+}
+
+#[tokio::test]
+async fn reaction_milestone_test() {
+    let counter = ReactionCounter::default();
+    let message_id = MessageId(1);
+    assert!(!counter.add_total(message_id, "👍", 3).await);
+    assert!(!counter.add_total(message_id, "👍", 3).await);
+    counter.remove_total(message_id, "👍").await;
+    assert!(!counter.add_total(message_id, "👍", 3).await);
+    assert!(counter.add_total(message_id, "👍", 3).await);
+    // 一度読んだら、外してつけ直しても読まない
+    counter.remove_total(message_id, "👍").await;
+    assert!(!counter.add_total(message_id, "👍", 3).await);
+    assert!(!counter.add_total(MessageId(2), "👍", 3).await);
+
+    // 覚えておく数を超えたら古いメッセージから忘れる
+    for id in 3..(3 + MAX_TRACKED as u64) {
+        counter.add_total(MessageId(id), "👍", 3).await;
+    }
+    assert_eq!(counter.state.lock().await.totals.len(), MAX_TRACKED);
+    assert!(counter.add_total(message_id, "👍", 1).await);
+}
@@ -0,0 +1,120 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serenity::model::id::{ChannelId, GuildId};
+use tokio::sync::Mutex;
+
+// サーバーごとの読み上げるテキストチャンネル。/join したときに決め、抜けたら忘れる
+#[derive(Clone, Default)]
+pub struct ReadChannels {
+    channels: Arc<Mutex<HashMap<GuildId, ChannelId>>>,
+}
+
+impl ReadChannels {
+    pub async fn get(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.channels.lock().await.get(&guild_id).copied()
+    }
+
+    // 前に読んでいたチャンネルを返す
+    pub async fn set(&self, guild_id: GuildId, channel_id: ChannelId) -> Option<ChannelId> {
+        self.channels.lock().await.insert(guild_id, channel_id)
+    }
+
+    pub async fn remove(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.channels.lock().await.remove(&guild_id)
+    }
+
+    // エンジンの状態など、全部のサーバーに知らせるときに使う
+    pub async fn all(&self) -> Vec<(GuildId, ChannelId)> {
+        self.channels
+            .lock()
+            .await
+            .iter()
+            .map(|(guild_id, channel_id)| (*guild_id, *channel_id))
+            .collect()
+    }
+}
+
+// たくさんのサーバーで同時に入ったり読んだり抜けたりしても、ほかのサーバーの状態と混ざらない
+#[tokio::test]
+async fn concurrent_guilds_test() {
+    use std::time::Duration;
+
+    use serenity::model::id::MessageId;
+    use songbird::tracks::TrackQueue;
+
+    use super::{
+        batch::{BatchItem, SynthesisBatcher},
+        playback::Playback,
+        read_delay::ReadDelay,
+    };
+
+    const GUILDS: u64 = 50;
+    let channels = ReadChannels::default();
+    let playback = Playback::new(Default::default());
+    let (batches, delay) = (SynthesisBatcher::default(), ReadDelay::default());
+    let tasks = (1..=GUILDS)
+        .map(|id| {
+            let (channels, playback, batches, delay) = (
+                channels.clone(),
+                playback.clone(),
+                batches.clone(),
+                delay.clone(),
+            );
+            tokio::spawn(async move {
+                let guild_id = GuildId(id);
+                // /join
+                assert_eq!(channels.set(guild_id, ChannelId(id * 100)).await, None);
+                // メッセージ ID はサーバーをまたいで同じものを使い、サーバーごとに分かれているか見る
+                for n in 0..10 {
+                    let message_id = MessageId(n);
+                    let item = BatchItem {
+                        text: format!("{}-{}", id, n),
+                        short: true,
+                        generator_type: 0,
+                        voice_type: 1,
+                        overrides: Default::default(),
+                        message_ids: vec![message_id],
+                    };
+                    assert_eq!(batches.push(guild_id, item).await, Some(n == 0));
+                    assert!(delay.wait(message_id, Duration::from_millis(1)).await);
+                    assert_eq!(channels.get(guild_id).await, Some(ChannelId(id * 100)));
+                    tokio::task::yield_now().await;
+                }
+                let items = batches.take(guild_id).await;
+                assert_eq!(items.len(), 1);
+                assert!(items[0].text.starts_with(&format!("{}-0、", id)));
+                assert_eq!(items[0].message_ids.len(), 10);
+                // 奇数のサーバーはメッセージを消して抜け、偶数のサーバーは読み上げチャンネルを変えて入り直す
+                if id % 2 == 1 {
+                    playback
+                        .cancel_message(guild_id, MessageId(0), &TrackQueue::new())
+                        .await;
+                    assert_eq!(channels.remove(guild_id).await, Some(ChannelId(id * 100)));
+                } else {
+                    assert_eq!(
+                        channels.set(guild_id, ChannelId(id * 100 + 1)).await,
+                        Some(ChannelId(id * 100))
+                    );
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    for id in 1..=GUILDS {
+        assert_eq!(
+            playback.is_deleted(GuildId(id), &[MessageId(0)]).await,
+            id % 2 == 1
+        );
+    }
+    let mut all = channels.all().await;
+    all.sort();
+    assert_eq!(
+        all,
+        (1..=GUILDS)
+            .filter(|id| id % 2 == 0)
+            .map(|id| (GuildId(id), ChannelId(id * 100 + 1)))
+            .collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,55 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serenity::model::id::MessageId;
+use tokio::sync::Mutex;
+
+// /config read_delay で設定できる上限
+pub const MAX_READ_DELAY_MS: i64 = 10000;
+
+// read_delay_ms のあいだ読むのを待っているメッセージ。値が true なら待っている間に消されたか編集された
+#[derive(Clone, Default)]
+pub struct ReadDelay {
+    pending: Arc<Mutex<HashMap<MessageId, bool>>>,
+}
+
+impl ReadDelay {
+    // delay だけ待ち、その間に取り消されなければ true を返す
+    pub async fn wait(&self, message_id: MessageId, delay: Duration) -> bool {
+        self.pending.lock().await.insert(message_id, false);
+        tokio::time::sleep(delay).await;
+        !self
+            .pending
+            .lock()
+            .await
+            .remove(&message_id)
+            .unwrap_or(false)
+    }
+
+    // 待っているメッセージなら読むのを取り消して true を返す
+    pub async fn cancel(&self, message_id: MessageId) -> bool {
+        match self.pending.lock().await.get_mut(&message_id) {
+            Some(cancelled) => {
+                *cancelled = true;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[tokio::test]
+async fn read_delay_test() {
+    let delay = ReadDelay::default();
+    assert!(!delay.cancel(MessageId(1)).await);
+    assert!(delay.wait(MessageId(1), Duration::from_millis(10)).await);
+
+    let waiting = tokio::spawn({
+        let delay = delay.clone();
+        async move { delay.wait(MessageId(2), Duration::from_millis(100)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(delay.cancel(MessageId(2)).await);
+    assert!(!waiting.await.unwrap());
+    // 読み終わったメッセージは取り消せない
+    assert!(!delay.cancel(MessageId(2)).await);
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use tracing::info;
+
+use super::{
+    app_config::config,
+    db::{RetentionDB, UsageDB},
+};
+
+// 消した記録の数と、残っている記録の数
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pruned {
+    pub table: &'static str,
+    pub days: u64,
+    pub deleted: u64,
+    pub remaining: i64,
+}
+
+// 設定した日数より前の記録を消す。0 日の記録は消さない
+pub async fn prune(database: &sqlx::SqlitePool) -> Result<Vec<Pruned>> {
+    let (usage_days, join_days) = (
+        config().usage_stats_retention_days,
+        config().join_count_retention_days,
+    );
+    let usage_deleted = match usage_days {
+        0 => 0,
+        days => database.prune_usage_stats(days).await?,
+    };
+    let join_deleted = match join_days {
+        0 => 0,
+        days => database.prune_join_count(days).await?,
+    };
+    let (usage_remaining, join_remaining) = database.count_retained_rows().await?;
+    Ok(vec![
+        Pruned {
+            table: "usage_stats",
+            days: usage_days,
+            deleted: usage_deleted,
+            remaining: usage_remaining,
+        },
+        Pruned {
+            table: "join_count",
+            days: join_days,
+            deleted: join_deleted,
+            remaining: join_remaining,
+        },
+    ])
+}
+
+// /prune の返事。表ごとに消した数と残りの数
+pub fn prune_summary(pruned: &[Pruned]) -> String {
+    pruned
+        .iter()
+        .map(|pruned| {
+            let retention = match pruned.days {
+                0 => "消さない".to_string(),
+                days => format!("{} 日残す", days),
+            };
+            format!(
+                "{} ({}): {} 行消して、残りは {} 行",
+                pruned.table, retention, pruned.deleted, pruned.remaining
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// 1 日に 1 回、日付が変わったあとに古い記録を消す。Supervisor から動かす
+pub async fn run_pruner(database: sqlx::SqlitePool) {
+    loop {
+        let secs = database.seconds_until_next_day().await.unwrap_or(60);
+        tokio::time::sleep(std::time::Duration::from_secs(secs.max(1) as u64 + 1)).await;
+        match prune(&database).await {
+            Ok(pruned) => {
+                for pruned in pruned.iter().filter(|pruned| pruned.deleted > 0) {
+                    info!("pruned {} rows from {}", pruned.deleted, pruned.table);
+                }
+            }
+            Err(e) => info!("failed to prune old records: {}", e),
+        }
+    }
+}
+
+#[test]
+fn prune_summary_test() {
+    let pruned = [
+        Pruned {
+            table: "usage_stats",
+            days: 365,
+            deleted: 3,
+            remaining: 10,
+        },
+        Pruned {
+            table: "join_count",
+            days: 0,
+            deleted: 0,
+            remaining: 2,
+        },
+    ];
+    assert_eq!(
+        prune_summary(&pruned),
+        "usage_stats (365 日残す): 3 行消して、残りは 10 行\njoin_count (消さない): 0 行消して、残りは 2 行"
+    );
+}
@@ -0,0 +1,122 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serenity::model::id::{GuildId, UserId};
+use tokio::sync::Mutex;
+
+use super::db::VoiceType;
+use crate::handler::Generators;
+
+#[derive(Default)]
+struct RotationState {
+    assigned: HashMap<UserId, (u8, u32)>,
+    // 次に配る声の番号
+    next: usize,
+}
+
+// 会話モードで、声を決めていない人に順番に配った声。bot がボイスチャンネルに入り直すと配り直す
+#[derive(Clone, Default)]
+pub struct VoiceRotation {
+    state: Arc<Mutex<HashMap<GuildId, RotationState>>>,
+}
+
+impl VoiceRotation {
+    // 配ってあればその声、なければ candidates から次の声を配る。全部配り終えたら最初の声に戻る
+    pub async fn voice_for(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        candidates: &[(u8, u32)],
+    ) -> Option<(u8, u32)> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let mut state = self.state.lock().await;
+        let state = state.entry(guild_id).or_default();
+        if let Some(voice) = state.assigned.get(&user_id) {
+            return Some(*voice);
+        }
+        let voice = candidates[state.next % candidates.len()];
+        state.next += 1;
+        state.assigned.insert(user_id, voice);
+        Some(voice)
+    }
+
+    pub async fn reset(&self, guild_id: GuildId) {
+        self.state.lock().await.remove(&guild_id);
+    }
+}
+
+// 配る声の候補。聞き分けやすいよう話者ごとに最初のスタイルだけを使い、サーバーのデフォルトの声は除く
+// generators は動いているエンジンの名前
+pub fn rotation_candidates(
+    speakers: &[VoiceType],
+    generators: &[&str],
+    default: (u8, u32),
+) -> Vec<(u8, u32)> {
+    let mut seen = Vec::new();
+    let mut candidates = Vec::new();
+    for speaker in speakers {
+        if !generators.contains(&speaker.generator_type.as_str())
+            || seen.contains(&(&speaker.generator_type, &speaker.name))
+        {
+            continue;
+        }
+        seen.push((&speaker.generator_type, &speaker.name));
+        let generator_type = match Generators::try_from(speaker.generator_type.as_str()) {
+            Ok(generator) => generator as u8,
+            Err(_) => continue,
+        };
+        let voice = (generator_type, speaker.style_id as u32);
+        if voice != default {
+            candidates.push(voice);
+        }
+    }
+    candidates
+}
+
+#[tokio::test]
+async fn voice_rotation_test() {
+    let voice = |id, name: &str, generator_type: &str| VoiceType {
+        id,
+        name: name.to_string(),
+        style_id: id,
+        style_name: "style".to_string(),
+        generator_type: generator_type.to_string(),
+    };
+    let speakers = vec![
+        voice(1, "めたん", "VOICEVOX"),
+        voice(2, "めたん", "VOICEVOX"),
+        voice(3, "ずんだもん", "VOICEVOX"),
+        voice(4, "つくよみちゃん", "COEIROINK"),
+        voice(5, "春日部つむぎ", "VOICEVOX"),
+    ];
+    assert_eq!(
+        rotation_candidates(&speakers, &["VOICEVOX", "COEIROINK"], (1, 3)),
+        vec![(1, 1), (0, 4), (1, 5)]
+    );
+    // 落ちているエンジンの声は配らない
+    let candidates = rotation_candidates(&speakers, &["VOICEVOX"], (0, 1));
+    assert_eq!(candidates, vec![(1, 1), (1, 3), (1, 5)]);
+
+    let rotation = VoiceRotation::default();
+    let guild_id = GuildId(1);
+    assert_eq!(
+        rotation.voice_for(guild_id, UserId(10), &candidates).await,
+        Some((1, 1))
+    );
+    assert_eq!(
+        rotation.voice_for(guild_id, UserId(11), &candidates).await,
+        Some((1, 3))
+    );
+    // 同じ人には同じ声
+    assert_eq!(
+        rotation.voice_for(guild_id, UserId(10), &candidates).await,
+        Some((1, 1))
+    );
+    rotation.reset(guild_id).await;
+    assert_eq!(
+        rotation.voice_for(guild_id, UserId(11), &candidates).await,
+        Some((1, 1))
+    );
+    assert_eq!(rotation.voice_for(guild_id, UserId(12), &[]).await, None);
+}
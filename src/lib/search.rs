@@ -0,0 +1,107 @@
+use wana_kana::to_hiragana::to_hiragana;
+
+use super::db::VoiceType;
+
+// 検索結果に出す話者の数。ボタン 4 行分
+pub const SEARCH_LIMIT: usize = 20;
+// 入力中に出す候補の数。discord の上限
+pub const AUTOCOMPLETE_LIMIT: usize = 25;
+
+// カタカナ・ローマ字はひらがなに、英字は小文字にそろえ、空白を除く
+fn normalize(s: &str) -> Vec<char> {
+    to_hiragana(&s.to_lowercase())
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+// token が target にどれだけ近いか。小さいほど近く、含まれなければ None
+fn token_score(token: &[char], target: &[char]) -> Option<usize> {
+    if token.is_empty() {
+        return Some(0);
+    }
+    if let Some(pos) = target
+        .windows(token.len())
+        .position(|window| window == token)
+    {
+        return Some(pos);
+    }
+    // 飛び飛びに含まれていれば、飛ばした文字数だけ遠いとみなす
+    let mut gaps = 0;
+    let mut chars = target.iter();
+    let mut started = false;
+    for c in token {
+        loop {
+            match chars.next() {
+                Some(t) if t == c => break,
+                Some(_) => {
+                    if started {
+                        gaps += 1;
+                    }
+                }
+                None => return None,
+            }
+        }
+        started = true;
+    }
+    Some(100 + gaps)
+}
+
+// 空白区切りの全部の語を含む話者を、近い順に SEARCH_LIMIT 人まで返す
+pub fn search_speakers(speakers: &[VoiceType], query: &str) -> Vec<VoiceType> {
+    rank_speakers(speakers, query, SEARCH_LIMIT)
+}
+
+pub fn rank_speakers(speakers: &[VoiceType], query: &str, limit: usize) -> Vec<VoiceType> {
+    let tokens = query.split_whitespace().map(normalize).collect::<Vec<_>>();
+    let mut scored = speakers
+        .iter()
+        .filter_map(|speaker| {
+            let target = normalize(&format!("{}{}", speaker.name, speaker.style_name));
+            tokens
+                .iter()
+                .map(|token| token_score(token, &target))
+                .sum::<Option<usize>>()
+                .map(|score| (score, speaker))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|(score, speaker)| (*score, speaker.id));
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, speaker)| speaker.clone())
+        .collect()
+}
+
+#[test]
+fn search_speakers_test() {
+    let voice = |id, name: &str, style_name: &str| VoiceType {
+        id,
+        name: name.to_string(),
+        style_id: id,
+        style_name: style_name.to_string(),
+        generator_type: "VOICEVOX".to_string(),
+    };
+    let speakers = vec![
+        voice(1, "ずんだもん", "ノーマル"),
+        voice(2, "ずんだもん", "ささやき"),
+        voice(3, "四国めたん", "ささやき"),
+        voice(4, "MANA", "のーまる"),
+    ];
+    let ids = |query| {
+        search_speakers(&speakers, query)
+            .iter()
+            .map(|speaker| speaker.id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids("ずんだもん ささやき"), vec![2]);
+    assert_eq!(ids("ささやき"), vec![2, 3]);
+    // カタカナ・ローマ字でもひらがなでも引ける
+    assert_eq!(ids("ズンダモン"), vec![1, 2]);
+    assert_eq!(ids("zundamon sasayaki"), vec![2]);
+    assert_eq!(ids("mana"), vec![4]);
+    // 飛び飛びでも当たる
+    assert_eq!(ids("ずもさや"), vec![2]);
+    assert!(ids("ちがう").is_empty());
+    assert_eq!(rank_speakers(&speakers, "", 3).len(), 3);
+}
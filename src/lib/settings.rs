@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use super::db::{GuildConfig, GuildConfigDB, GuildNicknameDB, UserConfig, UserConfigDB};
+
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// ファイルのロックや接続切れなど、待てば直るかもしれないエラー
+fn is_transient(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Io(_)) | Some(sqlx::Error::PoolTimedOut) => true,
+        Some(sqlx::Error::Database(e)) => e
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(is_transient_code),
+        _ => false,
+    }
+}
+
+// sqlx は SQLITE_IOERR_READ (266) のような拡張コードを返すので、下の 8 ビットの基本コードで見る
+// SQLITE_BUSY, SQLITE_LOCKED, SQLITE_IOERR, SQLITE_CANTOPEN
+fn is_transient_code(code: i32) -> bool {
+    matches!(code & 0xff, 5 | 6 | 10 | 14)
+}
+
+// 一時的なエラーなら少し待ってやり直す
+pub async fn retry<T, F, Fut>(f: F) -> Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                tokio::time::sleep(RETRY_DELAY * attempt).await;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+// 声はサーバーのデフォルトボイスで変わるので、(user_id, guild_id) ごとに覚える
+type UserKey = (i64, Option<i64>);
+
+// DB が読めない間も読み上げを続けられるよう、最後に読めた設定を覚えておく
+#[derive(Clone, Default)]
+pub struct SettingsCache {
+    users: Arc<Mutex<HashMap<UserKey, UserConfig>>>,
+    guilds: Arc<Mutex<HashMap<i64, GuildConfig>>>,
+    degraded: Arc<AtomicBool>,
+}
+
+impl SettingsCache {
+    fn succeeded(&self) {
+        if self.degraded.swap(false, Ordering::Relaxed) {
+            info!("database connection recovered");
+        }
+    }
+
+    fn failed(&self, e: &anyhow::Error) {
+        if !self.degraded.swap(true, Ordering::Relaxed) {
+            warn!("database unavailable, using cached settings: {}", e);
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    // 読めなければ覚えている設定、それもなければ初期値を返す
+    // read_nickname は guild_id のサーバーで決めた呼び方があればそちらにする
+    pub async fn user_config(
+        &self,
+        database: &sqlx::SqlitePool,
+        user_id: i64,
+        guild_id: Option<i64>,
+    ) -> UserConfig {
+        let mut user_config = self.global_user_config(database, user_id, guild_id).await;
+        if let Some(guild_id) = guild_id {
+            if let Ok(Some(nickname)) = database.get_guild_nickname(guild_id, user_id).await {
+                user_config.read_nickname = Some(nickname);
+            }
+        }
+        user_config
+    }
+
+    async fn global_user_config(
+        &self,
+        database: &sqlx::SqlitePool,
+        user_id: i64,
+        guild_id: Option<i64>,
+    ) -> UserConfig {
+        match retry(|| database.get_user_config_or_default(user_id, guild_id)).await {
+            Ok(user_config) => {
+                self.succeeded();
+                self.users
+                    .lock()
+                    .await
+                    .insert((user_id, guild_id), user_config.clone());
+                user_config
+            }
+            Err(e) => {
+                self.failed(&e);
+                self.users
+                    .lock()
+                    .await
+                    .get(&(user_id, guild_id))
+                    .cloned()
+                    .unwrap_or_else(|| UserConfig::from_user_id(user_id))
+            }
+        }
+    }
+
+    pub async fn guild_config(&self, database: &sqlx::SqlitePool, guild_id: i64) -> GuildConfig {
+        match retry(|| database.get_guild_config_or_default(guild_id)).await {
+            Ok(guild_config) => {
+                self.succeeded();
+                self.guilds
+                    .lock()
+                    .await
+                    .insert(guild_id, guild_config.clone());
+                guild_config
+            }
+            Err(e) => {
+                self.failed(&e);
+                self.guilds
+                    .lock()
+                    .await
+                    .get(&guild_id)
+                    .cloned()
+                    .unwrap_or_else(|| GuildConfig::from_guild_id(guild_id))
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn retry_test() {
+    use std::sync::atomic::AtomicU32;
+
+    let calls = AtomicU32::new(0);
+    let res = retry(|| async {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            Err(sqlx::Error::PoolTimedOut.into())
+        } else {
+            Ok(42)
+        }
+    })
+    .await;
+    assert_eq!(res.unwrap(), 42);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // 直らないエラーはやり直さない
+    let calls = AtomicU32::new(0);
+    let res: Result<()> = retry(|| async {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(sqlx::Error::RowNotFound.into())
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // SQLITE_IOERR_READ, SQLITE_BUSY_SNAPSHOT, SQLITE_CANTOPEN_ISDIR
+    assert!(is_transient_code(266));
+    assert!(is_transient_code(517));
+    assert!(is_transient_code(526));
+    assert!(is_transient_code(5));
+    // SQLITE_CONSTRAINT_UNIQUE
+    assert!(!is_transient_code(2067));
+}
+
+#[tokio::test]
+async fn settings_cache_fallback_test() {
+    let database = sqlx::sqlite::SqlitePoolOptions::new()
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    let cache = SettingsCache::default();
+    // テーブルがないので読めず、初期値を返す
+    let user_config = cache.user_config(&database, 1, None).await;
+    assert_eq!(user_config.hello, UserConfig::from_user_id(1).hello);
+    assert!(cache.is_degraded());
+
+    let mut cached = UserConfig::from_user_id(1);
+    cached.hello = "やあ".to_string();
+    cache.users.lock().await.insert((1, None), cached);
+    assert_eq!(cache.user_config(&database, 1, None).await.hello, "やあ");
+    assert_eq!(cache.guild_config(&database, 1).await.guild_id, 1);
+}
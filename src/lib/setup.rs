@@ -0,0 +1,350 @@
+use anyhow::{anyhow, Result};
+use serenity::{
+    builder::CreateComponents,
+    model::{id::RoleId, interactions::message_component::ButtonStyle},
+};
+
+use super::db::VoiceType;
+use crate::handler::Generators;
+
+// /setup の select menu とボタンの custom_id の接頭辞。後ろに 手順:ここまでの選択 をつける
+pub const SETUP_PREFIX: &str = "setup:";
+// select menu に出せる選択肢の数。1 つは「今のまま」に使う
+const OPTIONS_LIMIT: usize = 25;
+// 選ばなかった項目
+const KEEP: &str = "keep";
+
+// /setup で順に選ぶもの
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetupStep {
+    ReadChannel,
+    Voice,
+    Greeting,
+    Permission,
+    Confirm,
+}
+
+impl SetupStep {
+    pub fn next(self) -> Self {
+        match self {
+            Self::ReadChannel => Self::Voice,
+            Self::Voice => Self::Greeting,
+            Self::Greeting => Self::Permission,
+            Self::Permission | Self::Confirm => Self::Confirm,
+        }
+    }
+
+    fn prompt(self) -> &'static str {
+        match self {
+            Self::ReadChannel => "1/4 `/join` したときに読み上げるチャンネルを選んでね",
+            Self::Voice => {
+                "2/4 初めて使う人の声を選んでね (ほかの声は `/set_guild_default_voice` で選べるよ)"
+            }
+            Self::Greeting => "3/4 入退出のあいさつの読み方を選んでね",
+            Self::Permission => "4/4 辞書の編集や退出などの管理のコマンドを使えるロールを選んでね",
+            Self::Confirm => "この設定で保存する？",
+        }
+    }
+}
+
+impl TryFrom<&str> for SetupStep {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "read_channel" => Ok(Self::ReadChannel),
+            "voice" => Ok(Self::Voice),
+            "greeting" => Ok(Self::Greeting),
+            "permission" => Ok(Self::Permission),
+            _ => Err(anyhow!("no such setup step")),
+        }
+    }
+}
+
+impl From<SetupStep> for &str {
+    fn from(step: SetupStep) -> Self {
+        match step {
+            SetupStep::ReadChannel => "read_channel",
+            SetupStep::Voice => "voice",
+            SetupStep::Greeting => "greeting",
+            SetupStep::Permission => "permission",
+            SetupStep::Confirm => "confirm",
+        }
+    }
+}
+
+// ここまでに選んだ設定。None は今のままにする
+// 途中で再起動しても続けられるよう、custom_id に入れて持ち回る
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SetupDraft {
+    // voice か command (GuildConfig::read_channel)
+    pub read_channel: Option<String>,
+    // (generator_type, voice_type)
+    pub voice: Option<(i64, i64)>,
+    // greeting, announce, off (greeting::GreetingMode)
+    pub greeting_mode: Option<String>,
+    // 管理のコマンドを使えるロール。Some(None) ならロールで制限しない
+    pub admin_role: Option<Option<RoleId>>,
+}
+
+// custom_id の後ろの部分で決まる操作
+#[derive(Debug, PartialEq, Eq)]
+pub enum SetupAction {
+    // step の select menu で選んだ
+    Select(SetupStep, SetupDraft),
+    Save(SetupDraft),
+    Cancel,
+}
+
+impl SetupAction {
+    pub fn parse(custom_id: &str) -> Result<Self> {
+        let (action, draft) = custom_id.split_once(':').unwrap_or((custom_id, ""));
+        match action {
+            "cancel" => Ok(Self::Cancel),
+            "save" => Ok(Self::Save(SetupDraft::decode(draft)?)),
+            step => Ok(Self::Select(
+                SetupStep::try_from(step)?,
+                SetupDraft::decode(draft)?,
+            )),
+        }
+    }
+}
+
+impl SetupDraft {
+    // read_channel,voice,greeting_mode,admin_role の順にカンマでつなぐ
+    fn encode(&self) -> String {
+        [
+            self.read_channel.clone(),
+            self.voice
+                .map(|(generator_type, voice_type)| format!("{}/{}", generator_type, voice_type)),
+            self.greeting_mode.clone(),
+            self.admin_role.map(|role| match role {
+                Some(role) => role.0.to_string(),
+                None => "none".to_string(),
+            }),
+        ]
+        .into_iter()
+        .map(|value| value.unwrap_or_else(|| KEEP.to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+
+    fn decode(encoded: &str) -> Result<Self> {
+        let mut draft = Self::default();
+        if encoded.is_empty() {
+            return Ok(draft);
+        }
+        let values = encoded.split(',').collect::<Vec<_>>();
+        let steps = [
+            SetupStep::ReadChannel,
+            SetupStep::Voice,
+            SetupStep::Greeting,
+            SetupStep::Permission,
+        ];
+        if values.len() != steps.len() {
+            return Err(anyhow!("invalid setup draft: {}", encoded));
+        }
+        for (step, value) in steps.into_iter().zip(values) {
+            draft = draft.select(step, value)?;
+        }
+        Ok(draft)
+    }
+
+    // step の選択肢で value を選んだ後の設定
+    pub fn select(mut self, step: SetupStep, value: &str) -> Result<Self> {
+        if value == KEEP {
+            return Ok(self);
+        }
+        match step {
+            SetupStep::ReadChannel => match value {
+                "voice" | "command" => self.read_channel = Some(value.to_string()),
+                _ => return Err(anyhow!("no such read channel mode: {}", value)),
+            },
+            SetupStep::Voice => {
+                let (generator_type, voice_type) = value
+                    .split_once('/')
+                    .ok_or_else(|| anyhow!("invalid voice: {}", value))?;
+                self.voice = Some((generator_type.parse()?, voice_type.parse()?));
+            }
+            SetupStep::Greeting => match value {
+                "greeting" | "announce" | "off" => self.greeting_mode = Some(value.to_string()),
+                _ => return Err(anyhow!("no such greeting mode: {}", value)),
+            },
+            SetupStep::Permission => {
+                self.admin_role = Some(match value {
+                    "none" => None,
+                    role => Some(RoleId(role.parse()?)),
+                })
+            }
+            SetupStep::Confirm => return Err(anyhow!("nothing to select")),
+        }
+        Ok(self)
+    }
+
+    // 保存する前に見せる設定の一覧。voice_name は選んだ声の名前
+    pub fn summary(&self, voice_name: Option<&str>) -> String {
+        let keep = "今のまま".to_string();
+        let read_channel = self
+            .read_channel
+            .as_deref()
+            .map_or(keep.clone(), |mode| read_channel_label(mode).to_string());
+        let greeting = self
+            .greeting_mode
+            .as_deref()
+            .map_or(keep.clone(), |mode| greeting_label(mode).to_string());
+        let admin_role = match self.admin_role {
+            Some(Some(role)) => format!("<@&{}>", role.0),
+            Some(None) => "だれでも使える".to_string(),
+            None => keep.clone(),
+        };
+        format!(
+            "読み上げるチャンネル: {}\nデフォルトの声: {}\nあいさつ: {}\n管理のコマンド: {}",
+            read_channel,
+            voice_name.map_or(keep, str::to_string),
+            greeting,
+            admin_role
+        )
+    }
+}
+
+fn read_channel_label(mode: &str) -> &'static str {
+    match mode {
+        "command" => "/join を送ったチャンネル",
+        _ => "ボイスチャンネルのチャット",
+    }
+}
+
+fn greeting_label(mode: &str) -> &'static str {
+    match mode {
+        "announce" => "参加・退出を知らせる",
+        "off" => "あいさつしない",
+        _ => "各自のあいさつ",
+    }
+}
+
+// step で見せる文。Confirm なら設定の一覧もつける
+pub fn content(step: SetupStep, draft: &SetupDraft, voice_name: Option<&str>) -> String {
+    match step {
+        SetupStep::Confirm => format!("{}\n{}", step.prompt(), draft.summary(voice_name)),
+        _ => step.prompt().to_string(),
+    }
+}
+
+// step の select menu か、保存・やめるのボタンを並べる
+// speakers は声の、roles は (ロール, 名前) のロールの選択肢
+pub fn add_components(
+    c: &mut CreateComponents,
+    step: SetupStep,
+    draft: &SetupDraft,
+    speakers: &[VoiceType],
+    roles: &[(RoleId, String)],
+) {
+    let encoded = draft.encode();
+    if step == SetupStep::Confirm {
+        c.create_action_row(|row| {
+            row.create_button(|button| {
+                button
+                    .style(ButtonStyle::Primary)
+                    .custom_id(format!("{}save:{}", SETUP_PREFIX, encoded))
+                    .label("保存")
+            })
+            .create_button(|button| {
+                button
+                    .style(ButtonStyle::Secondary)
+                    .custom_id(format!("{}cancel", SETUP_PREFIX))
+                    .label("やめる")
+            })
+        });
+        return;
+    }
+    let options: Vec<(String, String)> = match step {
+        SetupStep::ReadChannel => ["voice", "command"]
+            .iter()
+            .map(|mode| (read_channel_label(mode).to_string(), mode.to_string()))
+            .collect(),
+        SetupStep::Voice => speakers
+            .iter()
+            .filter_map(|speaker| {
+                let generator_type = Generators::try_from(speaker.generator_type.as_str()).ok()?;
+                Some((
+                    format!("{} {}", speaker.name, speaker.style_name),
+                    format!("{}/{}", generator_type as u8, speaker.style_id),
+                ))
+            })
+            .collect(),
+        SetupStep::Greeting => ["greeting", "announce", "off"]
+            .iter()
+            .map(|mode| (greeting_label(mode).to_string(), mode.to_string()))
+            .collect(),
+        SetupStep::Permission => {
+            std::iter::once(("だれでも使える".to_string(), "none".to_string()))
+                .chain(
+                    roles
+                        .iter()
+                        .map(|(role, name)| (name.clone(), role.0.to_string())),
+                )
+                .collect()
+        }
+        SetupStep::Confirm => unreachable!(),
+    };
+    let step_name: &str = step.into();
+    c.create_action_row(|row| {
+        row.create_select_menu(|menu| {
+            menu.custom_id(format!("{}{}:{}", SETUP_PREFIX, step_name, encoded))
+                .options(|os| {
+                    os.create_option(|o| o.label("今のまま").value(KEEP));
+                    for (label, value) in options.into_iter().take(OPTIONS_LIMIT - 1) {
+                        os.create_option(|o| o.label(label).value(value));
+                    }
+                    os
+                })
+        })
+    });
+    c.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .style(ButtonStyle::Secondary)
+                .custom_id(format!("{}cancel", SETUP_PREFIX))
+                .label("やめる")
+        })
+    });
+}
+
+#[test]
+fn setup_draft_test() {
+    let draft = SetupDraft::default();
+    assert_eq!(draft.encode(), "keep,keep,keep,keep");
+    assert_eq!(SetupDraft::decode(&draft.encode()).unwrap(), draft);
+
+    let draft = draft
+        .select(SetupStep::ReadChannel, "command")
+        .unwrap()
+        .select(SetupStep::Voice, "1/3")
+        .unwrap()
+        .select(SetupStep::Greeting, KEEP)
+        .unwrap()
+        .select(SetupStep::Permission, "123456789012345678")
+        .unwrap();
+    assert_eq!(draft.encode(), "command,1/3,keep,123456789012345678");
+    assert_eq!(SetupDraft::decode(&draft.encode()).unwrap(), draft);
+    assert_eq!(
+        SetupAction::parse(&format!("save:{}", draft.encode())).unwrap(),
+        SetupAction::Save(draft.clone())
+    );
+    assert_eq!(
+        SetupAction::parse("greeting:keep,keep,keep,none").unwrap(),
+        SetupAction::Select(
+            SetupStep::Greeting,
+            SetupDraft {
+                admin_role: Some(None),
+                ..Default::default()
+            }
+        )
+    );
+    assert_eq!(SetupAction::parse("cancel").unwrap(), SetupAction::Cancel);
+
+    assert!(draft.clone().select(SetupStep::ReadChannel, "all").is_err());
+    assert!(SetupDraft::decode("keep,keep").is_err());
+    // custom_id の長さの上限 (100 文字) に収まる
+    assert!(format!("{}permission:{}", SETUP_PREFIX, draft.encode()).len() <= 100);
+}
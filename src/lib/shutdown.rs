@@ -0,0 +1,130 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serenity::{cache::Cache, client::bridge::gateway::ShardManager, model::id::GuildId};
+use songbird::Songbird;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use super::{
+    db::GuildConfigDB, language::Language, mixer::MixPolicy, personality::guild_bundle,
+    text::VoiceOverrides, voice::create_track,
+};
+
+// 終了のお知らせを読み終わるのを待つ長さ。合成が遅くても、これより長くは終了を待たせない
+const FAREWELL_TIMEOUT: Duration = Duration::from_secs(10);
+const FAREWELL_POLL: Duration = Duration::from_millis(200);
+
+// Ctrl-C か、docker などが送る SIGTERM を待つ
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => info!("Ctrl-C received"),
+                    _ = terminate.recv() => info!("SIGTERM received"),
+                }
+                return;
+            }
+            Err(e) => info!("cannot listen for SIGTERM: {}", e),
+        }
+    }
+    tokio::signal::ctrl_c().await.ok();
+    info!("Ctrl-C received");
+}
+
+// ボイスチャンネルで終了を知らせてから抜け、discord とデータベースの接続を閉じる
+pub struct Shutdown {
+    pub cache: Arc<Cache>,
+    pub manager: Arc<Songbird>,
+    pub shard_manager: Arc<Mutex<ShardManager>>,
+    pub database: sqlx::SqlitePool,
+}
+
+impl Shutdown {
+    pub async fn run(self) {
+        info!("shutting down...");
+        let mut guild_ids = Vec::new();
+        for guild_id in self.cache.guilds().await {
+            if let Some(call) = self.manager.get(guild_id) {
+                if call.lock().await.current_channel().is_some() {
+                    guild_ids.push(guild_id);
+                }
+            }
+        }
+        // どのサーバーでも同時に読む
+        let farewells = guild_ids
+            .iter()
+            .map(|guild_id| {
+                let (manager, database, guild_id) =
+                    (self.manager.clone(), self.database.clone(), *guild_id);
+                tokio::spawn(async move {
+                    if let Err(e) = farewell(&manager, &database, guild_id).await {
+                        info!("farewell failed in {}: {}", guild_id, e);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let all = async {
+            for farewell in farewells {
+                farewell.await.ok();
+            }
+        };
+        if tokio::time::timeout(FAREWELL_TIMEOUT, all).await.is_err() {
+            info!("gave up waiting for farewells");
+        }
+        for guild_id in guild_ids {
+            if let Some(call) = self.manager.get(guild_id) {
+                call.lock().await.remove_all_global_events();
+            }
+            self.manager.remove(guild_id).await.ok();
+        }
+        self.shard_manager.lock().await.shutdown_all().await;
+        // 書きかけのものを書き終えてからデータベースを閉じる
+        self.database.close().await;
+        info!("shut down");
+    }
+}
+
+// 読み上げを止めて、終了のお知らせだけを読み終わるまで流す
+async fn farewell(
+    manager: &Songbird,
+    database: &sqlx::SqlitePool,
+    guild_id: GuildId,
+) -> Result<()> {
+    let guild_config = database
+        .get_guild_config_or_default(guild_id.0 as i64)
+        .await?;
+    let (generator_type, voice_type) =
+        Language::of(&guild_config).voice(&guild_config).unwrap_or((
+            guild_config.generator_type as u8,
+            guild_config.voice_type as u32,
+        ));
+    let bundle = guild_bundle(database, Some(guild_id.0 as i64)).await;
+    let (mut track, track_handle) = create_track(
+        bundle.restart,
+        voice_type,
+        generator_type,
+        &VoiceOverrides::default(),
+    )
+    .await?;
+    let volume = track.volume() * MixPolicy::from_guild_config(&guild_config).tts;
+    track.set_volume(volume);
+    {
+        let call = manager
+            .get(guild_id)
+            .ok_or_else(|| anyhow!("not connected"))?;
+        let mut call = call.lock().await;
+        call.queue().stop();
+        call.play(track);
+    }
+    while let Ok(state) = track_handle.get_info().await {
+        if state.playing.is_done() {
+            break;
+        }
+        tokio::time::sleep(FAREWELL_POLL).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use serenity::{
+    client::Context,
+    model::{channel::Message, guild::Guild},
+};
+use tracing::info;
+
+use super::{
+    language::Language,
+    mixer::MixPolicy,
+    text::{NameReading, TextMessage},
+    voice::play_announcement,
+};
+use crate::handler::Handler;
+
+// メッセージから曲の URL を取り出す。埋め込みを消す <URL> の形も受け付ける
+pub fn request_urls(content: &str) -> Vec<&str> {
+    content
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('<').trim_end_matches('>'))
+        .filter(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .collect()
+}
+
+// 曲のリクエストのチャンネルに貼られた URL を予約し、流れ始めたらだれのリクエストか読み上げる
+// 流れ始めるまでこのメッセージのタスクで待つ
+pub async fn request_songs(
+    ctx: &Context,
+    handler: &Handler,
+    guild: &Guild,
+    msg: &Message,
+) -> Result<()> {
+    let urls = request_urls(&msg.content);
+    if urls.is_empty() {
+        return Ok(());
+    }
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let guild_config = handler
+        .settings
+        .guild_config(&handler.database, guild.id.0 as i64)
+        .await;
+    let mut requests = Vec::new();
+    for url in urls {
+        match handler
+            .music
+            .request(
+                &manager,
+                guild.id,
+                url,
+                MixPolicy::from_guild_config(&guild_config),
+            )
+            .await
+        {
+            Ok((_, started)) => requests.push(started),
+            Err(e) => {
+                msg.reply(&ctx.http, format!("{}: {}", url, e)).await.ok();
+            }
+        }
+    }
+    if requests.is_empty() {
+        return Ok(());
+    }
+    msg.react(&ctx.http, '🎵').await.ok();
+    let user_config = handler
+        .settings
+        .user_config(
+            &handler.database,
+            msg.author.id.0 as i64,
+            Some(guild.id.0 as i64),
+        )
+        .await;
+    let nick = guild
+        .members
+        .get(&msg.author.id)
+        .and_then(|member| member.nick.clone());
+    // 予約した順に流れるので、順に待つ。流れる前に /stop されたら読まない
+    for started in requests {
+        let song = match started.await {
+            Ok(song) => song,
+            Err(_) => continue,
+        };
+        let guild_config = handler
+            .settings
+            .guild_config(&handler.database, guild.id.0 as i64)
+            .await;
+        let language = Language::of(&guild_config);
+        let name = NameReading::of(&guild_config).author_name(
+            user_config.read_nickname.clone(),
+            nick.as_deref(),
+            &msg.author.name,
+        );
+        let text = language
+            .song_request(name.as_deref(), &song.title)
+            .make_read_text(&handler.database, language)
+            .await;
+        if let Err(e) = play_announcement(
+            ctx,
+            handler,
+            &text,
+            guild_config.voice_type as u32,
+            guild_config.generator_type as u8,
+            guild.id,
+        )
+        .await
+        {
+            info!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn request_urls_test() {
+    assert_eq!(
+        request_urls("これ流して https://example.com/a <https://example.com/b>"),
+        vec!["https://example.com/a", "https://example.com/b"]
+    );
+    assert!(request_urls("あとで貼るね").is_empty());
+}
@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use serenity::{client::Context, model::id::GuildId};
+
+use super::{
+    app_config::config,
+    db::SoundEffectDB,
+    opus_cache::CacheKey,
+    temp::temp_path,
+    voice::{play_input, wav_to_input},
+};
+use crate::handler::Handler;
+
+// 取ってくるファイルの大きさの上限
+const MAX_DOWNLOAD_BYTES: usize = 2 * 1024 * 1024;
+// 効果音の長さの上限。これより長いところは切る
+const MAX_CLIP_SECONDS: u32 = 10;
+// 24kHz モノラル 16bit の WAV にして保存するので、ひとつ 480KB ほどまで
+pub const MAX_CLIP_BYTES: usize = 24000 * 2 * MAX_CLIP_SECONDS as usize + 1024;
+// サーバーごとに保存できる効果音の合計
+pub const GUILD_QUOTA_BYTES: usize = 5 * 1024 * 1024;
+const MAX_NAME_CHARS: usize = 32;
+
+// 文中のテキストで効果音を鳴らすときの接頭辞。`!se 名前` と送る (`!` は prefix で変えられる)
+pub fn se_trigger() -> String {
+    format!("{}se ", config().prefix)
+}
+
+pub fn check_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.chars().count() > MAX_NAME_CHARS
+        || name.chars().any(char::is_whitespace)
+    {
+        return Err(anyhow!(
+            "名前は空白なしの {} 文字までにしてね",
+            MAX_NAME_CHARS
+        ));
+    }
+    Ok(())
+}
+
+// sizes は登録済みの (名前, バイト数)。同じ名前なら置き換える
+pub fn check_quota(sizes: &[(String, i64)], name: &str, size: usize) -> Result<()> {
+    if size > MAX_CLIP_BYTES {
+        return Err(anyhow!("効果音は {} KB までだよ", MAX_CLIP_BYTES / 1024));
+    }
+    let used: usize = sizes
+        .iter()
+        .filter(|(registered, _)| registered != name)
+        .map(|(_, size)| *size as usize)
+        .sum();
+    if used + size > GUILD_QUOTA_BYTES {
+        return Err(anyhow!(
+            "このサーバーの効果音の容量 ({} MB) を超えるよ。使わないものを消してね",
+            GUILD_QUOTA_BYTES / 1024 / 1024
+        ));
+    }
+    Ok(())
+}
+
+// ffmpeg で 24kHz モノラルの WAV にし、長すぎるところは切る
+// 一時ファイルは同時に登録するほかのサーバーと混ざらないよう temp_dir/サーバー ID/ に置く
+fn convert_to_wav(guild_id: i64, data: &[u8]) -> Result<Vec<u8>> {
+    let (input, output) = (
+        temp_path(Some(guild_id), "se", "")?,
+        temp_path(Some(guild_id), "se", "wav")?,
+    );
+    std::fs::write(&input, data)?;
+    let status = std::process::Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y", "-i"])
+        .arg(&input)
+        .args(["-t", &MAX_CLIP_SECONDS.to_string()])
+        .args(["-ac", "1", "-ar", "24000", "-acodec", "pcm_s16le"])
+        .arg(&output)
+        .status();
+    let wav = match status {
+        Ok(status) if status.success() => std::fs::read(&output).map_err(anyhow::Error::from),
+        Ok(_) => Err(anyhow!("音声ファイルとして読めなかったよ")),
+        Err(e) => Err(anyhow!("ffmpeg を起動できなかったよ: {}", e)),
+    };
+    std::fs::remove_file(&input).ok();
+    std::fs::remove_file(&output).ok();
+    wav
+}
+
+// url の音声を取ってきて name で登録する
+pub async fn add(database: &sqlx::SqlitePool, guild_id: i64, name: &str, url: &str) -> Result<()> {
+    check_name(name)?;
+    let res = reqwest::get(url).await?.error_for_status()?;
+    let too_large = || {
+        anyhow!(
+            "ファイルは {} MB までだよ",
+            MAX_DOWNLOAD_BYTES / 1024 / 1024
+        )
+    };
+    // 全部読む前に大きすぎるものは断る
+    if res
+        .content_length()
+        .is_some_and(|len| len as usize > MAX_DOWNLOAD_BYTES)
+    {
+        return Err(too_large());
+    }
+    let data = res.bytes().await?;
+    if data.len() > MAX_DOWNLOAD_BYTES {
+        return Err(too_large());
+    }
+    let wav = tokio::task::spawn_blocking(move || convert_to_wav(guild_id, &data)).await??;
+    // 鳴らせない音声は登録しない
+    wav_to_input(&wav)?;
+    check_quota(
+        &database.get_sound_effect_sizes(guild_id).await?,
+        name,
+        wav.len(),
+    )?;
+    database.add_sound_effect(guild_id, name, &wav).await?;
+    Ok(())
+}
+
+pub fn cache_key(guild_id: GuildId, name: &str) -> CacheKey {
+    CacheKey::SoundEffect {
+        guild_id: guild_id.0 as i64,
+        name: name.to_string(),
+    }
+}
+
+// 一度鳴らした効果音は Opus にして覚えておき、次からは WAV を読み直さない
+pub async fn play(ctx: &Context, handler: &Handler, guild_id: GuildId, name: &str) -> Result<()> {
+    let key = cache_key(guild_id, name);
+    let input = match handler.opus_cache.get(&key).await {
+        Some(input) => input,
+        None => {
+            let wav = handler
+                .database
+                .get_sound_effect(guild_id.0 as i64, name)
+                .await?
+                .ok_or_else(|| anyhow!("{} という効果音はないよ", name))?;
+            handler.opus_cache.insert(key, wav_to_input(&wav)?).await?
+        }
+    };
+    play_input(ctx, handler, guild_id, input).await
+}
+
+#[test]
+fn check_quota_test() {
+    let sizes = vec![
+        ("a".to_string(), (GUILD_QUOTA_BYTES - MAX_CLIP_BYTES) as i64),
+        ("b".to_string(), MAX_CLIP_BYTES as i64),
+    ];
+    assert!(check_quota(&[], "a", MAX_CLIP_BYTES + 1).is_err());
+    assert!(check_quota(&sizes, "c", 1).is_err());
+    // 置き換えるなら前の分は数えない
+    assert!(check_quota(&sizes, "b", MAX_CLIP_BYTES).is_ok());
+
+    assert!(check_name("ok").is_ok());
+    assert!(check_name("").is_err());
+    assert!(check_name("two words").is_err());
+}
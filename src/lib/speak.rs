@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+
+use super::{
+    db::GuildConfig,
+    engine::SynthesisParams,
+    language::Language,
+    text::TextMessage,
+    voice::{create_voice, wav_to_input},
+};
+
+// --out を省いたときに書き出すファイル
+const DEFAULT_OUT: &str = "speak.wav";
+
+// `nap-chan --speak "text"` で、discord につながずに読みの整形と合成だけを試す
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeakArgs {
+    pub text: String,
+    pub generator_type: u8,
+    pub voice_type: u32,
+    pub params: SynthesisParams,
+    pub language: Language,
+    pub out: String,
+}
+
+impl SpeakArgs {
+    // --speak がなければ None。ほかのオプションの値はサーバーの初期設定と同じ
+    pub fn parse(args: &[String]) -> Result<Option<Self>> {
+        if !args.iter().any(|arg| arg == "--speak") {
+            return Ok(None);
+        }
+        let defaults = GuildConfig::from_guild_id(0);
+        let mut speak = SpeakArgs {
+            text: String::new(),
+            generator_type: defaults.generator_type as u8,
+            voice_type: defaults.voice_type as u32,
+            params: SynthesisParams::default(),
+            language: Language::Japanese,
+            out: DEFAULT_OUT.to_string(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+            match arg.as_str() {
+                "--speak" => speak.text = value()?.clone(),
+                "--generator" => speak.generator_type = value()?.parse()?,
+                "--voice" => speak.voice_type = value()?.parse()?,
+                "--speed" => speak.params.speed_scale = value()?.parse()?,
+                "--pitch" => speak.params.pitch_scale = value()?.parse()?,
+                "--language" => speak.language = Language::try_from(value()?.as_str())?,
+                "--out" => speak.out = value()?.clone(),
+                _ => return Err(anyhow!("unknown argument: {}", arg)),
+            }
+        }
+        Ok(Some(speak))
+    }
+}
+
+// 辞書を当てた読みを表示して、合成した WAV を書き出す
+pub async fn run_speak(database: &sqlx::SqlitePool, args: &SpeakArgs) -> Result<()> {
+    let text = args.text.make_read_text(database, args.language).await;
+    println!("{}", text);
+    let wav = create_voice(&text, args.voice_type, args.generator_type, args.params).await?;
+    // 読み上げで使えない音声なら書き出さない
+    wav_to_input(&wav)?;
+    std::fs::write(&args.out, wav)?;
+    println!("wrote {}", args.out);
+    Ok(())
+}
+
+#[test]
+fn speak_args_test() {
+    let args = |args: &[&str]| {
+        SpeakArgs::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    };
+    assert_eq!(args(&[]).unwrap(), None);
+
+    let speak = args(&["--speak", "こんにちは"]).unwrap().unwrap();
+    assert_eq!(speak.text, "こんにちは");
+    assert_eq!((speak.generator_type, speak.voice_type), (0, 1));
+    assert_eq!(speak.out, DEFAULT_OUT);
+
+    let speak = args(&[
+        "--voice",
+        "3",
+        "--speak",
+        "hello",
+        "--language",
+        "en",
+        "--out",
+        "a.wav",
+    ])
+    .unwrap()
+    .unwrap();
+    assert_eq!(speak.voice_type, 3);
+    assert_eq!(speak.language, Language::English);
+    assert_eq!(speak.out, "a.wav");
+
+    assert!(args(&["--speak"]).is_err());
+    assert!(args(&["--speak", "a", "--voice", "x"]).is_err());
+    assert!(args(&["--speak", "a", "--verbose"]).is_err());
+}
@@ -0,0 +1,133 @@
+use std::{
+    future::Future,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use tracing::{info, warn};
+
+use super::{app_config::config, health::is_alive};
+use crate::handler::Generators;
+
+// 起動時に準備を待つときの、問い合わせの間隔の初めと上限
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+// attempt 回目に失敗したあとに待つ時間。倍々に延ばす
+pub fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2_u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF)
+}
+
+// f が成功するまで待ってやり直す。deadline までに成功しなければ最後のエラーを返す
+async fn wait_for<T, F, Fut>(what: &str, deadline: Instant, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Ok(value) => {
+                if attempt > 0 {
+                    info!("{} is ready", what);
+                }
+                return Ok(value);
+            }
+            Err(e) => {
+                let delay = backoff(attempt);
+                if Instant::now() + delay > deadline {
+                    return Err(e);
+                }
+                info!("waiting for {} ({}), retrying in {:?}", what, e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+fn deadline() -> Instant {
+    Instant::now() + Duration::from_secs(config().startup_timeout)
+}
+
+// docker compose でボリュームのマウントが遅れても、データベースのフォルダができるまで待つ
+pub async fn connect_database() -> Result<sqlx::SqlitePool> {
+    let path = &config().database_path;
+    wait_for("database", deadline(), || async {
+        if let Some(dir) = Path::new(path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            if !dir.is_dir() {
+                return Err(anyhow!("{} does not exist", dir.display()));
+            }
+        }
+        Ok(sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(10)
+            .connect_with(
+                sqlx::sqlite::SqliteConnectOptions::new()
+                    .filename(path)
+                    .create_if_missing(true)
+                    // バックアップなどでロックされていても少し待つ
+                    .busy_timeout(Duration::from_secs(10)),
+            )
+            .await?)
+    })
+    .await
+}
+
+// 設定したエンジンが応答するまで待つ。間に合わなければ警告だけして、落ちているものとして起動する
+pub async fn wait_for_engines() {
+    let deadline = deadline();
+    for generator in Generators::ALL {
+        let base_url = match config().engine_location(generator) {
+            Some(base_url) => base_url,
+            None => continue,
+        };
+        let name: &str = generator.into();
+        let res = wait_for(name, deadline, || async {
+            if is_alive(generator, base_url).await {
+                Ok(())
+            } else {
+                Err(anyhow!("no response from {}", base_url))
+            }
+        })
+        .await;
+        if let Err(e) = res {
+            warn!("{} is not ready: {}", name, e);
+        }
+    }
+}
+
+#[test]
+fn backoff_test() {
+    assert_eq!(backoff(0), INITIAL_BACKOFF);
+    assert_eq!(backoff(1), INITIAL_BACKOFF * 2);
+    assert_eq!(backoff(10), MAX_BACKOFF);
+    assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+}
+
+#[tokio::test]
+async fn wait_for_test() {
+    let mut calls = 0;
+    let res = wait_for("test", Instant::now() + Duration::from_secs(5), || {
+        calls += 1;
+        let ready = calls > 1;
+        async move {
+            if ready {
+                Ok(calls)
+            } else {
+                Err(anyhow!("not yet"))
+            }
+        }
+    })
+    .await;
+    assert_eq!(res.unwrap(), 2);
+
+    // 待つ時間がなければすぐ諦める
+    let res: Result<()> = wait_for("test", Instant::now(), || async { Err(anyhow!("down")) }).await;
+    assert!(res.is_err());
+}
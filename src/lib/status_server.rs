@@ -0,0 +1,244 @@
+use std::{convert::Infallible, fmt::Write, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Serialize;
+use serenity::cache::Cache;
+use songbird::Songbird;
+use tracing::{info, warn};
+
+use super::{
+    health::EngineHealth,
+    metrics::metrics,
+    music::Music,
+    playback::Playback,
+    settings::SettingsCache,
+    supervisor::{Supervisor, TaskStatus},
+};
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct GuildStatus {
+    // JSON で桁が落ちないよう文字列にする
+    pub guild_id: String,
+    pub channel_id: Option<String>,
+    pub speech_queue: usize,
+    pub music_queue: usize,
+    pub now_playing: Option<String>,
+}
+
+// /healthz, /metrics, /guilds で bot の状態を返す
+#[derive(Clone)]
+pub struct StatusServer {
+    pub cache: Arc<Cache>,
+    pub manager: Arc<Songbird>,
+    pub playback: Playback,
+    pub music: Music,
+    pub engine_health: EngineHealth,
+    pub settings: SettingsCache,
+    pub supervisor: Supervisor,
+}
+
+impl StatusServer {
+    // ボイスチャンネルに入っているか、読み上げや曲が残っているサーバー
+    async fn guilds(&self) -> Vec<GuildStatus> {
+        let mut guilds = Vec::new();
+        for guild_id in self.cache.guilds().await {
+            let channel_id = match self.manager.get(guild_id) {
+                Some(call) => call.lock().await.current_channel(),
+                None => None,
+            };
+            let speech_queue = self.playback.len(guild_id).await;
+            let music_queue = self.music.queue(guild_id).await.len();
+            let now_playing = self
+                .music
+                .now_playing(guild_id)
+                .await
+                .map(|(song, _)| song.title);
+            if channel_id.is_none() && speech_queue == 0 && music_queue == 0 {
+                continue;
+            }
+            guilds.push(GuildStatus {
+                guild_id: guild_id.0.to_string(),
+                channel_id: channel_id.map(|channel_id| channel_id.0.to_string()),
+                speech_queue,
+                music_queue,
+                now_playing,
+            });
+        }
+        guilds
+    }
+
+    async fn respond(&self, req: Request<Body>) -> Response<Body> {
+        if req.method() != Method::GET {
+            return text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed");
+        }
+        match req.uri().path() {
+            "/healthz" => {
+                if self.settings.is_degraded() {
+                    text(StatusCode::SERVICE_UNAVAILABLE, "database unavailable")
+                } else {
+                    text(StatusCode::OK, "ok")
+                }
+            }
+            "/metrics" => {
+                let engines = self
+                    .engine_health
+                    .statuses()
+                    .await
+                    .into_iter()
+                    .map(|(generator, alive)| (generator.into(), alive))
+                    .collect::<Vec<_>>();
+                let status = render_metrics(
+                    &self.guilds().await,
+                    &engines,
+                    self.settings.is_degraded(),
+                    &self.supervisor.statuses().await,
+                );
+                text(StatusCode::OK, &(status + &metrics().render()))
+            }
+            "/guilds" => match serde_json::to_string(&self.guilds().await) {
+                Ok(json) => Response::builder()
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(json))
+                    .unwrap_or_default(),
+                Err(e) => text(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+            },
+            _ => text(StatusCode::NOT_FOUND, "not found"),
+        }
+    }
+}
+
+fn text(status: StatusCode, body: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_default()
+}
+
+// Prometheus のテキスト形式
+fn render_metrics(
+    guilds: &[GuildStatus],
+    engines: &[(&str, bool)],
+    degraded: bool,
+    tasks: &[(&str, TaskStatus)],
+) -> String {
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, samples: Vec<(String, usize)>| {
+        writeln!(out, "# HELP nap_chan_{} {}", name, help).ok();
+        writeln!(out, "# TYPE nap_chan_{} gauge", name).ok();
+        for (labels, value) in samples {
+            writeln!(out, "nap_chan_{}{} {}", name, labels, value).ok();
+        }
+    };
+    gauge(
+        "voice_connections",
+        "Number of connected voice channels.",
+        vec![(
+            String::new(),
+            guilds.iter().filter(|g| g.channel_id.is_some()).count(),
+        )],
+    );
+    gauge(
+        "speech_queue_depth",
+        "Queued speech tracks per guild.",
+        guilds
+            .iter()
+            .map(|g| (format!("{{guild_id=\"{}\"}}", g.guild_id), g.speech_queue))
+            .collect(),
+    );
+    gauge(
+        "music_queue_depth",
+        "Queued songs per guild.",
+        guilds
+            .iter()
+            .map(|g| (format!("{{guild_id=\"{}\"}}", g.guild_id), g.music_queue))
+            .collect(),
+    );
+    gauge(
+        "engine_up",
+        "Whether the synthesis engine responds.",
+        engines
+            .iter()
+            .map(|(name, alive)| (format!("{{engine=\"{}\"}}", name), *alive as usize))
+            .collect(),
+    );
+    gauge(
+        "database_degraded",
+        "Whether cached settings are used because the database is unavailable.",
+        vec![(String::new(), degraded as usize)],
+    );
+    gauge(
+        "task_restarts",
+        "Restarts of each background task.",
+        tasks
+            .iter()
+            .map(|(name, status)| (format!("{{task=\"{}\"}}", name), status.restarts as usize))
+            .collect(),
+    );
+    out
+}
+
+// Supervisor から動かす。待ち受けられなければ終わり、間をあけて起動し直される
+pub async fn run_status_server(addr: SocketAddr, server: StatusServer) {
+    let make_service = make_service_fn(move |_| {
+        let server = server.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let server = server.clone();
+                async move { Ok::<_, Infallible>(server.respond(req).await) }
+            }))
+        }
+    });
+    let bound = match Server::try_bind(&addr) {
+        Ok(bound) => bound,
+        Err(e) => {
+            warn!("cannot listen on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("status server listening on {}", addr);
+    if let Err(e) = bound.serve(make_service).await {
+        warn!("status server stopped: {}", e);
+    }
+}
+
+#[test]
+fn render_metrics_test() {
+    let guilds = vec![
+        GuildStatus {
+            guild_id: "1".to_string(),
+            channel_id: Some("10".to_string()),
+            speech_queue: 2,
+            music_queue: 0,
+            now_playing: None,
+        },
+        GuildStatus {
+            guild_id: "2".to_string(),
+            channel_id: None,
+            speech_queue: 0,
+            music_queue: 3,
+            now_playing: Some("song".to_string()),
+        },
+    ];
+    let tasks = vec![(
+        "health_check",
+        TaskStatus {
+            running: true,
+            restarts: 4,
+            last_error: None,
+        },
+    )];
+    let metrics = render_metrics(&guilds, &[("VOICEVOX", true)], false, &tasks);
+    assert!(
+        metrics.contains("# TYPE nap_chan_voice_connections gauge\nnap_chan_voice_connections 1\n")
+    );
+    assert!(metrics.contains("nap_chan_speech_queue_depth{guild_id=\"1\"} 2\n"));
+    assert!(metrics.contains("nap_chan_music_queue_depth{guild_id=\"2\"} 3\n"));
+    assert!(metrics.contains("nap_chan_engine_up{engine=\"VOICEVOX\"} 1\n"));
+    assert!(metrics.contains("nap_chan_database_degraded 0\n"));
+    assert!(metrics.contains("nap_chan_task_restarts{task=\"health_check\"} 4\n"));
+}
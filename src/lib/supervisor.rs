@@ -0,0 +1,120 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex;
+use tracing::warn;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// これより長く動いていたら、次に落ちたときの待ち時間を最初からにする
+const STABLE_PERIOD: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug, Default)]
+pub struct TaskStatus {
+    pub running: bool,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+}
+
+// バックグラウンドのタスクを持ち、落ちたり終わったりしたら間をあけて起動し直す
+#[derive(Clone)]
+pub struct Supervisor {
+    tasks: Arc<Mutex<BTreeMap<&'static str, TaskStatus>>>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::with_backoff(INITIAL_BACKOFF, MAX_BACKOFF)
+    }
+}
+
+impl Supervisor {
+    pub fn with_backoff(initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Supervisor {
+            tasks: Default::default(),
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    // factory で作ったタスクを動かし続ける
+    pub fn spawn<F, Fut>(&self, name: &'static str, factory: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let tasks = self.tasks.clone();
+        let (initial_backoff, max_backoff) = (self.initial_backoff, self.max_backoff);
+        tokio::spawn(async move {
+            let mut backoff = initial_backoff;
+            loop {
+                tasks.lock().await.entry(name).or_default().running = true;
+                let started = Instant::now();
+                let error = match tokio::spawn(factory()).await {
+                    Ok(()) => "exited".to_string(),
+                    Err(e) => e.to_string(),
+                };
+                warn!("{} stopped: {}", name, error);
+                if started.elapsed() >= STABLE_PERIOD {
+                    backoff = initial_backoff;
+                }
+                {
+                    let mut tasks = tasks.lock().await;
+                    let status = tasks.entry(name).or_default();
+                    status.running = false;
+                    status.last_error = Some(error);
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(max_backoff);
+                tasks.lock().await.entry(name).or_default().restarts += 1;
+            }
+        });
+    }
+
+    pub async fn statuses(&self) -> Vec<(&'static str, TaskStatus)> {
+        self.tasks
+            .lock()
+            .await
+            .iter()
+            .map(|(name, status)| (*name, status.clone()))
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn supervisor_restart_test() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let supervisor = Supervisor::with_backoff(Duration::from_millis(1), Duration::from_millis(4));
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = runs.clone();
+    supervisor.spawn("flaky", move || {
+        let counter = counter.clone();
+        async move {
+            // 2 回落ちたあとは動き続ける
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                panic!("boom");
+            }
+            std::future::pending::<()>().await;
+        }
+    });
+    let mut status = None;
+    for _ in 0..100 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        if runs.load(Ordering::SeqCst) >= 3 {
+            status = supervisor.statuses().await.into_iter().next();
+            break;
+        }
+    }
+    let (name, status) = status.expect("task was not restarted");
+    assert_eq!(name, "flaky");
+    assert!(status.running);
+    assert_eq!(status.restarts, 2);
+    assert!(status.last_error.unwrap().contains("panic"));
+}
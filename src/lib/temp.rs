@@ -0,0 +1,137 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use tracing::info;
+
+use super::app_config::config;
+
+// この時間より前に作られた一時ファイルは、使い終わったのに消し損ねたものとして消す
+const STALE_AFTER: Duration = Duration::from_secs(10 * 60);
+// 消し損ねた一時ファイルを探す間隔
+const JANITOR_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// 一時ファイルを置くフォルダ。temp_dir で変えられる
+pub fn temp_dir() -> &'static Path {
+    Path::new(&config().temp_dir)
+}
+
+// dir の下に、ほかの一時ファイルと重ならない名前のパスを作る
+// 同じフォルダを使うほかのプロセスとも重ならないよう、プロセス ID もつける
+pub fn unique_path(dir: &Path, prefix: &str, extension: &str) -> PathBuf {
+    let name = format!(
+        "{}_{}_{}_{:x}",
+        prefix,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        rand::random::<u32>()
+    );
+    let path = dir.join(name);
+    if extension.is_empty() {
+        path
+    } else {
+        path.with_extension(extension)
+    }
+}
+
+// temp_dir (サーバーごとに分けるなら guild_id の下) に一時ファイルのパスを作る
+pub fn temp_path(guild_id: Option<i64>, prefix: &str, extension: &str) -> Result<PathBuf> {
+    let dir = match guild_id {
+        Some(guild_id) => temp_dir().join(guild_id.to_string()),
+        None => temp_dir().to_path_buf(),
+    };
+    std::fs::create_dir_all(&dir)?;
+    Ok(unique_path(&dir, prefix, extension))
+}
+
+// dir の下の、max_age より前に書き換えられたファイルを消して数を返す。空になったフォルダも消す
+pub fn clean_dir(dir: &Path, max_age: Duration) -> usize {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            removed += clean_dir(&path, max_age);
+            // 使っているフォルダは空でないので消えない
+            std::fs::remove_dir(&path).ok();
+            continue;
+        }
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| now.duration_since(modified).unwrap_or_default() >= max_age)
+            .unwrap_or(true);
+        if stale && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+// 起動したとき、前に落ちたときに残った一時ファイルを消す
+pub fn prepare_temp_dir() -> Result<()> {
+    let dir = temp_dir();
+    std::fs::create_dir_all(dir)?;
+    let removed = clean_dir(dir, STALE_AFTER);
+    if removed > 0 {
+        info!("removed {} stale files in {}", removed, dir.display());
+    }
+    Ok(())
+}
+
+// 終了するとき、一時ファイルを全部消す
+pub fn clear_temp_dir() {
+    clean_dir(temp_dir(), Duration::ZERO);
+}
+
+// 消し損ねた一時ファイルを定期的に消す。Supervisor から動かす
+pub async fn run_temp_janitor() {
+    let mut interval = tokio::time::interval(JANITOR_INTERVAL);
+    loop {
+        interval.tick().await;
+        let removed = tokio::task::spawn_blocking(|| clean_dir(temp_dir(), STALE_AFTER))
+            .await
+            .unwrap_or(0);
+        if removed > 0 {
+            info!(
+                "removed {} stale files in {}",
+                removed,
+                temp_dir().display()
+            );
+        }
+    }
+}
+
+#[test]
+fn temp_test() {
+    let dir = std::env::temp_dir().join(unique_path(Path::new(""), "nap_chan_temp_test", ""));
+    let sub = dir.join("1");
+    std::fs::create_dir_all(&sub).unwrap();
+
+    let (a, b) = (
+        unique_path(&dir, "ojt", "wav"),
+        unique_path(&sub, "ojt", "wav"),
+    );
+    assert_ne!(a.file_name(), b.file_name());
+    assert_eq!(a.extension().unwrap(), "wav");
+    std::fs::write(&a, b"a").unwrap();
+    std::fs::write(&b, b"b").unwrap();
+
+    // 新しいファイルは消さない
+    assert_eq!(clean_dir(&dir, STALE_AFTER), 0);
+    assert!(a.exists() && b.exists());
+
+    // 古いファイルは下のフォルダのものも消し、空になったフォルダも消す
+    assert_eq!(clean_dir(&dir, Duration::ZERO), 2);
+    assert!(!a.exists() && !sub.exists());
+    std::fs::remove_dir(&dir).unwrap();
+}
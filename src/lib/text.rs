@@ -1,8 +1,15 @@
 use regex;
-use serenity::async_trait;
+use serenity::{async_trait, model::channel::Embed};
 use tracing::info;
 
-use super::db::DictDB;
+use super::{
+    db::{DictDB, GuildConfig},
+    dict_pack::guild_pack_entries,
+    engine::{SynthesisParams, SPEED_RANGE},
+    language::Language,
+    settings::retry,
+};
+use crate::Dict;
 use alkana_rs::ALKANA;
 use wana_kana::{is_katakana::is_katakana, to_katakana::to_katakana};
 
@@ -10,11 +17,25 @@ use wana_kana::{is_katakana::is_katakana, to_katakana::to_katakana};
 pub trait TextMessage {
     fn replace_url(&self) -> Self;
     fn remove_spoiler(&self) -> Self;
-    async fn replace_by_dict(&self, database: &sqlx::SqlitePool) -> Self;
+    async fn replace_by_dict(
+        &self,
+        database: &sqlx::SqlitePool,
+        guild_id: Option<i64>,
+        user_id: Option<i64>,
+    ) -> Self;
     fn remove_custom_emoji(&self) -> Self;
-    async fn make_read_text(&self, database: &sqlx::SqlitePool) -> Self;
+    async fn make_read_text(&self, database: &sqlx::SqlitePool, language: Language) -> Self;
+    // guild_id のサーバーで有効にした辞書パックと、user_id の人の /mydict の単語も当てる
+    async fn make_guild_read_text(
+        &self,
+        database: &sqlx::SqlitePool,
+        language: Language,
+        guild_id: Option<i64>,
+        user_id: Option<i64>,
+    ) -> Self;
     fn hiraganize(&self) -> Self;
     fn remove_code_block(&self) -> Self;
+    fn replace_ruby(&self) -> Self;
 }
 #[async_trait]
 impl TextMessage for String {
@@ -26,14 +47,37 @@ impl TextMessage for String {
         let re = regex::Regex::new(r"\|\|[\s\S]*\|\|").unwrap();
         re.replace_all(self, "").to_string()
     }
-    async fn replace_by_dict(&self, database: &sqlx::SqlitePool) -> Self {
-        let mut text = self.clone();
-        for w in database.get_dict_all().await.unwrap() {
-            let before = &w.word;
-            let after = &w.read_word;
-            text = text.replace(before, after);
+    async fn replace_by_dict(
+        &self,
+        database: &sqlx::SqlitePool,
+        guild_id: Option<i64>,
+        user_id: Option<i64>,
+    ) -> Self {
+        // 辞書が読めなくても、置き換えずに読み上げは続ける
+        let mut dict = match retry(|| database.get_dict_all()).await {
+            Ok(dict) => dict,
+            Err(e) => {
+                info!("could not read dict: {}", e);
+                Vec::new()
+            }
+        };
+        // 旧辞書から取り込んだサーバーの単語を先に当てる
+        // 辞書パックの単語は、登録された単語が当たらなかったところにだけ当てる
+        if let Some(guild_id) = guild_id {
+            let mut guild_dict = database.get_guild_dict(guild_id).await.unwrap_or_default();
+            guild_dict.append(&mut dict);
+            dict = guild_dict;
+            dict.extend(guild_pack_entries(database, guild_id).await);
+        }
+        let text = apply_dict(self, &dict);
+        // 自分の辞書はみんなの辞書で置き換えた後の文に当てる
+        match user_id {
+            Some(user_id) => {
+                let user_dict = database.get_user_dict(user_id).await.unwrap_or_default();
+                apply_dict(&text, &user_dict)
+            }
+            None => text,
         }
-        text
     }
     fn hiraganize(&self) -> Self {
         let re_statement = regex::Regex::new(r"[a-zA-Z]+(\s+[a-zA-Z]+)*").unwrap();
@@ -85,19 +129,399 @@ impl TextMessage for String {
             self.to_string()
         }
     }
-    async fn make_read_text(&self, database: &sqlx::SqlitePool) -> Self {
-        self.replace_url()
+    // 英語のサーバーでは英単語をカタカナにしない
+    async fn make_read_text(&self, database: &sqlx::SqlitePool, language: Language) -> Self {
+        self.make_guild_read_text(database, language, None, None)
+            .await
+    }
+    async fn make_guild_read_text(
+        &self,
+        database: &sqlx::SqlitePool,
+        language: Language,
+        guild_id: Option<i64>,
+        user_id: Option<i64>,
+    ) -> Self {
+        let text = self
+            .replace_url()
             .remove_spoiler()
             .remove_code_block()
+            .replace_ruby()
             .remove_custom_emoji()
-            .replace_by_dict(database)
-            .await
-            .hiraganize()
+            .replace_by_dict(database, guild_id, user_id)
+            .await;
+        match language {
+            Language::Japanese => text.hiraganize(),
+            Language::English => text,
+        }
     }
     fn remove_code_block(&self) -> Self {
         let re = regex::Regex::new(r#"```[\s\S]*```"#).unwrap();
         re.replace_all(self, "").to_string()
     }
+    // ルビ付きの文字列を読みだけにする
+    // <ruby>漢字<rt>かんじ</rt></ruby>, [[rb:漢字 > かんじ]], ｜漢字《かんじ》, |漢字(かんじ), 漢字《かんじ》
+    fn replace_ruby(&self) -> Self {
+        let html = regex::Regex::new(r"(?s)<ruby>(.*?)</ruby>").unwrap();
+        let rt = regex::Regex::new(r"(?s)<rt>(.*?)</rt>").unwrap();
+        let rp = regex::Regex::new(r"(?s)<rp>.*?</rp>").unwrap();
+        let tag = regex::Regex::new(r"</?(rb|rt|rtc)>").unwrap();
+        let text = html.replace_all(self, |caps: &regex::Captures| {
+            let inner = rp.replace_all(&caps[1], "");
+            let readings = rt
+                .captures_iter(&inner)
+                .map(|c| c[1].to_string())
+                .collect::<String>();
+            if readings.is_empty() {
+                tag.replace_all(&inner, "").to_string()
+            } else {
+                readings
+            }
+        });
+        let pixiv = regex::Regex::new(r"\[\[rb:\s*([^>\]]+?)\s*>\s*([^\]]+?)\s*\]\]").unwrap();
+        let text = pixiv.replace_all(&text, "$2");
+        let bar = regex::Regex::new(r"[|｜][^|｜《》\n]+?《([^》\n]+)》").unwrap();
+        let text = bar.replace_all(&text, "$1");
+        // 括弧は普通の文章でも使うので、読みがかなのときだけ
+        let bar_paren =
+            regex::Regex::new(r"[|｜][^|｜()（）\n]+?[(（]([ぁ-んァ-ヶー]+)[)）]").unwrap();
+        let text = bar_paren.replace_all(&text, "$1");
+        let kanji = regex::Regex::new(r"[一-龠々〆ヵヶ]+《([^》\n]+)》").unwrap();
+        kanji.replace_all(&text, "$1").to_string()
+    }
+}
+
+// 先頭から順に見ていき、各位置で最初にマッチした単語 (dict の順) を置き換える
+// 置き換えた後の文字列はもう一度置き換えない
+pub fn apply_dict(text: &str, dict: &[Dict]) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match dict
+            .iter()
+            .find(|w| !w.word.is_empty() && rest.starts_with(&w.word))
+        {
+            Some(w) => {
+                result.push_str(&w.read_word);
+                rest = &rest[w.word.len()..];
+            }
+            None => {
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    result
+}
+
+// 埋め込みの説明文はこの文字数より後を読まない
+const EMBED_DESCRIPTION_LIMIT: usize = 50;
+
+// 名前の飾りによく使われる、文字として読まないもの
+const NAME_DECORATIONS: &[char] = &['彡', '卍', '乂'];
+
+// GIF のリンクの代わりに読む文
+pub const GIF: &str = "GIF";
+
+// Tenor や GIPHY のリンク、.gif で終わるリンクを GIF と読む。ほかの URL は make_read_text で URL になる
+pub fn replace_gif_url(text: &str) -> String {
+    let re = regex::Regex::new(
+        r"https?://(?:(?:www\.|media\d*\.)?(?:tenor\.com|giphy\.com)/[\w!?/+\-_~;.,*&@#$%()='\[\]]*|[\w!?/+\-_~;.,*&@#$%()='\[\]]+\.gif\b(?:\?[\w!?/+\-_~;.,*&@#$%()='\[\]]*)?)",
+    )
+    .unwrap();
+    re.replace_all(text, GIF).to_string()
+}
+
+// メッセージの前に読む名前の選び方。/set_name_reading で設定する
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NameReading {
+    // /set_nickname, /set_server_nickname で決めた読み。決めていなければサーバーのニックネーム
+    Configured,
+    // サーバーのニックネーム。なければユーザー名
+    Nickname,
+    // discord のユーザー名
+    Username,
+    // 名前を読まない
+    None,
+}
+
+impl NameReading {
+    // /config read_name で名前を読まないことにしていれば None
+    pub fn of(guild_config: &GuildConfig) -> Self {
+        if !guild_config.read_name {
+            return Self::None;
+        }
+        Self::try_from(guild_config.name_reading.as_str()).unwrap_or(Self::Configured)
+    }
+
+    // read_nickname は /set_nickname などで決めた読み、nick はサーバーのニックネーム
+    pub fn author_name(
+        self,
+        read_nickname: Option<String>,
+        nick: Option<&str>,
+        username: &str,
+    ) -> Option<String> {
+        match self {
+            Self::Configured => {
+                Some(read_nickname.unwrap_or_else(|| clean_display_name(nick.unwrap_or(username))))
+            }
+            Self::Nickname => Some(clean_display_name(nick.unwrap_or(username))),
+            Self::Username => Some(clean_display_name(username)),
+            Self::None => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for NameReading {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "configured" => Ok(Self::Configured),
+            "nickname" => Ok(Self::Nickname),
+            "username" => Ok(Self::Username),
+            "none" => Ok(Self::None),
+            _ => Err(anyhow::anyhow!("no such name reading")),
+        }
+    }
+}
+
+impl From<NameReading> for &str {
+    fn from(name_reading: NameReading) -> Self {
+        match name_reading {
+            NameReading::Configured => "configured",
+            NameReading::Nickname => "nickname",
+            NameReading::Username => "username",
+            NameReading::None => "none",
+        }
+    }
+}
+
+// 絵文字や記号で飾った表示名から、読める部分だけを取り出す。何も残らなければそのまま返す
+// ユーザーが /set_nickname で決めた名前には使わない
+pub fn clean_display_name(name: &str) -> String {
+    let cleaned = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() && !NAME_DECORATIONS.contains(&c) {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if cleaned.is_empty() {
+        name.to_string()
+    } else {
+        cleaned
+    }
+}
+
+// メッセージの埋め込みから読み上げる文章を作る
+pub fn embed_text(embeds: &[Embed], read_title: bool, read_description: bool) -> String {
+    embeds
+        .iter()
+        .flat_map(|embed| {
+            let title = embed.title.clone().filter(|_| read_title);
+            let description =
+                embed
+                    .description
+                    .as_ref()
+                    .filter(|_| read_description)
+                    .map(|description| {
+                        if description.chars().count() > EMBED_DESCRIPTION_LIMIT {
+                            description
+                                .chars()
+                                .take(EMBED_DESCRIPTION_LIMIT)
+                                .collect::<String>()
+                                + " 以下略"
+                        } else {
+                            description.clone()
+                        }
+                    });
+            title.into_iter().chain(description)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// メッセージの先頭に書ける [speed:1.5] や [voice:ずんだもん] で、そのメッセージだけ読み方を変える
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VoiceOverrides {
+    pub speed: Option<f64>,
+    pub voice: Option<String>,
+    // タグでは変えられず、/set_pitch の値が入る
+    pub pitch: Option<f64>,
+}
+
+impl VoiceOverrides {
+    // 指定していないものは送った人の /set_speed, /set_pitch の値で読む
+    pub fn with_user_params(mut self, user: SynthesisParams) -> Self {
+        self.speed.get_or_insert(user.speed_scale);
+        self.pitch.get_or_insert(user.pitch_scale);
+        self
+    }
+
+    pub fn synthesis_params(&self) -> SynthesisParams {
+        let default = SynthesisParams::default();
+        SynthesisParams {
+            speed_scale: self.speed.unwrap_or(default.speed_scale),
+            pitch_scale: self.pitch.unwrap_or(default.pitch_scale),
+        }
+    }
+}
+
+// 先頭のタグを読み取り、タグを除いた残りと一緒に返す。知らないタグからあとはそのまま読む
+pub fn split_inline_tags(text: &str) -> (VoiceOverrides, &str) {
+    let mut overrides = VoiceOverrides::default();
+    let mut rest = text.trim_start();
+    while let Some(tag) = rest.strip_prefix('[') {
+        let (body, after) = match tag.split_once(']') {
+            Some(split) => split,
+            None => break,
+        };
+        let (key, value) = match body.split_once([':', '：']) {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => break,
+        };
+        match key {
+            "speed" => match value.parse::<f64>() {
+                Ok(speed) if speed.is_finite() => {
+                    overrides.speed = Some(speed.clamp(SPEED_RANGE.0, SPEED_RANGE.1))
+                }
+                _ => break,
+            },
+            "voice" if !value.is_empty() => overrides.voice = Some(value.to_string()),
+            _ => break,
+        }
+        rest = after.trim_start();
+    }
+    (overrides, rest)
+}
+
+// 「> 名前: セリフ」の行をセリフごとに分ける。名前のない行は None
+// 二人以上の名前が引用されていなければ会話とはみなさない
+pub fn split_dialogue(text: &str) -> Option<Vec<(Option<&str>, String)>> {
+    let mut segments: Vec<(Option<&str>, String)> = Vec::new();
+    let mut speakers = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let quoted = line
+            .strip_prefix('>')
+            .and_then(|quote| quote.split_once([':', '：']))
+            .map(|(name, line)| (name.trim(), line.trim()))
+            .filter(|(name, line)| !name.is_empty() && !line.is_empty());
+        let (speaker, line) = match quoted {
+            Some((name, line)) => {
+                if !speakers.contains(&name) {
+                    speakers.push(name);
+                }
+                (Some(name), line)
+            }
+            None => (None, line),
+        };
+        match segments.last_mut() {
+            // 引用でない行が続いたらまとめて読む
+            Some((None, last_line)) if speaker.is_none() => {
+                last_line.push('\n');
+                last_line.push_str(line);
+            }
+            _ => segments.push((speaker, line.to_string())),
+        }
+    }
+    (speakers.len() >= 2).then_some(segments)
+}
+
+#[test]
+fn replace_ruby_test() {
+    let ruby = |s: &str| s.to_string().replace_ruby();
+    assert_eq!(
+        ruby("今日は<ruby>漢字<rp>(</rp><rt>かんじ</rt><rp>)</rp></ruby>の日"),
+        "今日はかんじの日"
+    );
+    assert_eq!(ruby("<ruby>明<rt>あ</rt>日<rt>した</rt></ruby>"), "あした");
+    assert_eq!(ruby("[[rb:魔法 > まほう]]少女"), "まほう少女");
+    assert_eq!(
+        ruby("これは｜二重螺旋《にじゅうらせん》です"),
+        "これはにじゅうらせんです"
+    );
+    assert_eq!(ruby("|勇者(ゆうしゃ)の剣"), "ゆうしゃの剣");
+    assert_eq!(ruby("吾輩は猫《ねこ》である"), "吾輩はねこである");
+    // ルビでない括弧やパイプはそのまま
+    assert_eq!(ruby("a|b (c) です"), "a|b (c) です");
+    assert_eq!(
+        ruby("<ruby>漢<rt>かん</rt></ruby>と[[rb:字 > じ]]と｜読《よ》み"),
+        "かんとじとよみ"
+    );
+}
+
+#[test]
+fn clean_display_name_test() {
+    assert_eq!(clean_display_name("★彡 たろう 彡★"), "たろう");
+    assert_eq!(clean_display_name("🍣すし🍣職人"), "すし 職人");
+    assert_eq!(clean_display_name("ゆー　ざー"), "ゆー ざー");
+    assert_eq!(clean_display_name("nap_chan"), "nap chan");
+    assert_eq!(clean_display_name("★☆★"), "★☆★");
+}
+
+#[test]
+fn replace_gif_url_test() {
+    assert_eq!(
+        replace_gif_url("見て https://tenor.com/view/cat-dance-12345"),
+        "見て GIF"
+    );
+    assert_eq!(
+        replace_gif_url(
+            "https://media.giphy.com/media/abc/giphy.gif と https://example.com/a.gif?x=1"
+        ),
+        "GIF と GIF"
+    );
+    // GIF でないリンクはそのまま
+    assert_eq!(
+        replace_gif_url("https://example.com/gifts"),
+        "https://example.com/gifts"
+    );
+}
+
+#[test]
+fn name_reading_test() {
+    let mut guild_config = GuildConfig::from_guild_id(1);
+    assert_eq!(NameReading::of(&guild_config), NameReading::Configured);
+    guild_config.name_reading = "username".to_string();
+    assert_eq!(NameReading::of(&guild_config), NameReading::Username);
+    guild_config.read_name = false;
+    assert_eq!(NameReading::of(&guild_config), NameReading::None);
+
+    let name = |reading: NameReading, read_nickname: Option<&str>, nick| {
+        reading.author_name(read_nickname.map(str::to_string), nick, "★nap★")
+    };
+    assert_eq!(
+        name(NameReading::Configured, Some("なっぷ"), Some("ナップ")).unwrap(),
+        "なっぷ"
+    );
+    assert_eq!(
+        name(NameReading::Configured, None, Some("ナップ")).unwrap(),
+        "ナップ"
+    );
+    assert_eq!(
+        name(NameReading::Nickname, Some("なっぷ"), None).unwrap(),
+        "nap"
+    );
+    assert_eq!(
+        name(NameReading::Username, Some("なっぷ"), Some("ナップ")).unwrap(),
+        "nap"
+    );
+    assert_eq!(name(NameReading::None, Some("なっぷ"), None), None);
+    for reading in [
+        NameReading::Configured,
+        NameReading::Nickname,
+        NameReading::Username,
+        NameReading::None,
+    ] {
+        let value: &str = reading.into();
+        assert_eq!(NameReading::try_from(value).unwrap(), reading);
+    }
 }
 
 #[test]
@@ -213,3 +637,414 @@ fn code_block_test() {
     let text = "aaa ``` test ``` bbb".to_string();
     assert_eq!("aaa  bbb", text.remove_code_block());
 }
+
+#[test]
+fn embed_text_test() {
+    let embed: Embed = serde_json::from_value(serde_json::json!({
+        "type": "rich",
+        "title": "タイトル",
+        "description": "あ".repeat(60),
+        "fields": [],
+    }))
+    .unwrap();
+    let embeds = vec![embed];
+    assert_eq!(embed_text(&embeds, false, false), "");
+    assert_eq!(embed_text(&embeds, true, false), "タイトル");
+    assert_eq!(
+        embed_text(&embeds, true, true),
+        format!("タイトル {} 以下略", "あ".repeat(50))
+    );
+}
+
+#[test]
+fn apply_dict_test() {
+    let dict = |word: &str, read_word: &str, priority| Dict {
+        word: word.to_string(),
+        read_word: read_word.to_string(),
+        priority,
+    };
+    // get_dict_all と同じく、優先度が高い順、同じなら長い順に並んでいる
+    let entries = vec![
+        dict("Rustacean", "ラスタシアン", 0),
+        dict("Rust", "ラスト", 0),
+    ];
+    assert_eq!(
+        apply_dict("Rust と Rustacean", &entries),
+        "ラスト と ラスタシアン"
+    );
+
+    let entries = vec![
+        dict("Rust", "さび", 1),
+        dict("Rustacean", "ラスタシアン", 0),
+    ];
+    assert_eq!(apply_dict("Rustacean", &entries), "さびacean");
+
+    // 置き換えた結果はもう一度置き換えない
+    let entries = vec![dict("a", "b", 0), dict("b", "a", 0)];
+    assert_eq!(apply_dict("ab", &entries), "ba");
+}
+
+#[test]
+fn split_dialogue_test() {
+    assert_eq!(
+        split_dialogue("見て\n> たろう: こんにちは\n> はなこ：やあ\nだって"),
+        Some(vec![
+            (None, "見て".to_string()),
+            (Some("たろう"), "こんにちは".to_string()),
+            (Some("はなこ"), "やあ".to_string()),
+            (None, "だって".to_string()),
+        ])
+    );
+    // 引用でない行が続いたらひとつにまとめる
+    assert_eq!(
+        split_dialogue("> a: 1\n> b: 2\nそう\nなんだ"),
+        Some(vec![
+            (Some("a"), "1".to_string()),
+            (Some("b"), "2".to_string()),
+            (None, "そう\nなんだ".to_string())
+        ])
+    );
+    // 一人だけの引用や名前のない引用は会話ではない
+    assert_eq!(
+        split_dialogue("> たろう: こんにちは\n> たろう: またね"),
+        None
+    );
+    assert_eq!(split_dialogue("> 引用です\nふつうの文"), None);
+}
+
+#[test]
+fn split_inline_tags_test() {
+    assert_eq!(
+        split_inline_tags("[speed:1.5][voice: ずんだもん ささやき] こんにちは"),
+        (
+            VoiceOverrides {
+                speed: Some(1.5),
+                voice: Some("ずんだもん ささやき".to_string()),
+                pitch: None,
+            },
+            "こんにちは"
+        )
+    );
+    assert_eq!(
+        split_inline_tags("[speed：10] はやい"),
+        (
+            VoiceOverrides {
+                speed: Some(2.0),
+                voice: None,
+                pitch: None,
+            },
+            "はやい"
+        )
+    );
+    // 先頭でないタグや知らないタグは読む
+    assert_eq!(
+        split_inline_tags("こんにちは [speed:1.5]"),
+        (VoiceOverrides::default(), "こんにちは [speed:1.5]")
+    );
+    assert_eq!(
+        split_inline_tags("[speed:1.2][nya:1] ねこ"),
+        (
+            VoiceOverrides {
+                speed: Some(1.2),
+                voice: None,
+                pitch: None,
+            },
+            "[nya:1] ねこ"
+        )
+    );
+    assert_eq!(
+        split_inline_tags("[speed:abc] ねこ"),
+        (VoiceOverrides::default(), "[speed:abc] ねこ")
+    );
+    assert_eq!(
+        split_inline_tags("[リンク](https://example.com)"),
+        (VoiceOverrides::default(), "[リンク](https://example.com)")
+    );
+}
+
+// 辞書にも alkana にもない英単語を、つづりから規則でカタカナにする
+// 5 文字までの大文字だけの単語は略語とみなしてそのまま (一文字ずつ) 読む
+pub fn katakanize_english(text: &str) -> String {
+    let re = regex::Regex::new(r"[A-Za-z]+").unwrap();
+    re.replace_all(text, |caps: &regex::Captures| {
+        let word = &caps[0];
+        let acronym = word.len() <= 5 && word.chars().all(|c| c.is_ascii_uppercase());
+        if word.len() < 2 || acronym {
+            word.to_string()
+        } else {
+            english_to_katakana(word)
+        }
+    })
+    .to_string()
+}
+
+// 母音の段。ア イ ウ エ オ の順
+const A: usize = 0;
+const I: usize = 1;
+const U: usize = 2;
+const E: usize = 3;
+const O: usize = 4;
+const VOWEL_KANA: [&str; 5] = ["ア", "イ", "ウ", "エ", "オ"];
+
+// 子音の行。最初に当てはまったものを使う
+const KANA_ROWS: [(&str, [&str; 5]); 22] = [
+    ("", ["ア", "イ", "ウ", "エ", "オ"]),
+    ("k", ["カ", "キ", "ク", "ケ", "コ"]),
+    ("g", ["ガ", "ギ", "グ", "ゲ", "ゴ"]),
+    ("s", ["サ", "シ", "ス", "セ", "ソ"]),
+    ("z", ["ザ", "ジ", "ズ", "ゼ", "ゾ"]),
+    ("t", ["タ", "ティ", "トゥ", "テ", "ト"]),
+    ("d", ["ダ", "ディ", "ドゥ", "デ", "ド"]),
+    ("n", ["ナ", "ニ", "ヌ", "ネ", "ノ"]),
+    ("h", ["ハ", "ヒ", "フ", "ヘ", "ホ"]),
+    ("b", ["バ", "ビ", "ブ", "ベ", "ボ"]),
+    ("p", ["パ", "ピ", "プ", "ペ", "ポ"]),
+    ("m", ["マ", "ミ", "ム", "メ", "モ"]),
+    ("y", ["ヤ", "イ", "ユ", "イエ", "ヨ"]),
+    ("r", ["ラ", "リ", "ル", "レ", "ロ"]),
+    ("w", ["ワ", "ウィ", "ウ", "ウェ", "ウォ"]),
+    ("f", ["ファ", "フィ", "フ", "フェ", "フォ"]),
+    ("v", ["ヴァ", "ヴィ", "ヴ", "ヴェ", "ヴォ"]),
+    ("j", ["ジャ", "ジ", "ジュ", "ジェ", "ジョ"]),
+    ("ch", ["チャ", "チ", "チュ", "チェ", "チョ"]),
+    ("sh", ["シャ", "シ", "シュ", "シェ", "ショ"]),
+    ("ts", ["ツァ", "ツィ", "ツ", "ツェ", "ツォ"]),
+    ("kw", ["クァ", "クィ", "ク", "クェ", "クォ"]),
+];
+
+// つづりの子音と、読みの子音の行。長いものから順に試す
+const ONSETS: [(&str, &str); 33] = [
+    ("tch", "ch"),
+    ("sch", "sk"),
+    ("ch", "ch"),
+    ("sh", "sh"),
+    ("th", "s"),
+    ("ph", "f"),
+    ("ts", "ts"),
+    ("ck", "k"),
+    ("qu", "kw"),
+    ("kn", "n"),
+    ("wr", "r"),
+    ("wh", "w"),
+    ("gh", ""),
+    ("ng", "ng"),
+    ("k", "k"),
+    ("g", "g"),
+    ("s", "s"),
+    ("z", "z"),
+    ("t", "t"),
+    ("d", "d"),
+    ("n", "n"),
+    ("h", "h"),
+    ("b", "b"),
+    ("p", "p"),
+    ("m", "m"),
+    ("r", "r"),
+    ("l", "r"),
+    ("f", "f"),
+    ("v", "v"),
+    ("j", "j"),
+    ("c", "k"),
+    ("q", "k"),
+    ("x", "ks"),
+];
+
+// 母音のつづりと読み。ー は伸ばす
+const VOWEL_SPELLINGS: [(&str, &[usize], bool); 21] = [
+    ("eigh", &[E, I], false),
+    ("igh", &[A, I], false),
+    ("ee", &[I], true),
+    ("ea", &[I], true),
+    ("oo", &[U], true),
+    ("ai", &[E, I], false),
+    ("ay", &[E, I], false),
+    ("ei", &[E, I], false),
+    ("ey", &[I], true),
+    ("oa", &[O], true),
+    ("ou", &[A, U], false),
+    ("ow", &[A, U], false),
+    ("au", &[O], true),
+    ("aw", &[O], true),
+    ("oi", &[O, I], false),
+    ("oy", &[O, I], false),
+    ("ie", &[I], true),
+    ("ue", &[U], true),
+    ("ew", &[U], true),
+    ("or", &[O], true),
+    ("ar", &[A], true),
+];
+
+// 子音だけで終わるときの読み
+fn coda(onset: &str) -> &'static str {
+    match onset {
+        "k" => "ク",
+        "g" => "グ",
+        "s" => "ス",
+        "z" => "ズ",
+        "t" => "ト",
+        "d" => "ド",
+        "n" => "ン",
+        "b" => "ブ",
+        "p" => "プ",
+        "m" => "ム",
+        "r" => "ル",
+        "w" => "ウ",
+        "f" => "フ",
+        "v" => "ヴ",
+        "j" => "ジ",
+        "ch" => "チ",
+        "sh" => "シュ",
+        "ts" => "ツ",
+        "kw" => "ク",
+        "sk" => "スク",
+        "ks" => "クス",
+        _ => "",
+    }
+}
+
+fn kana(onset: &str, vowel: usize) -> String {
+    // sk, ks は最初の子音を ス, ク にして残りの行で読む
+    let (prefix, onset) = match onset {
+        "sk" => ("ス", "k"),
+        "ks" => ("ク", "s"),
+        _ => ("", onset),
+    };
+    let row = KANA_ROWS
+        .iter()
+        .find(|(key, _)| *key == onset)
+        .map_or(&KANA_ROWS[0].1, |(_, row)| row);
+    format!("{}{}", prefix, row[vowel])
+}
+
+fn is_vowel(c: u8) -> bool {
+    b"aeiouy".contains(&c)
+}
+
+// 英単語をつづりからカタカナにする
+fn english_to_katakana(word: &str) -> String {
+    let w = word.to_ascii_lowercase().into_bytes();
+    let n = w.len();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < n {
+        let rest = &w[i..];
+        if rest.starts_with(b"tion") || rest.starts_with(b"sion") {
+            out.push_str(if rest[0] == b't' {
+                "ション"
+            } else {
+                "ジョン"
+            });
+            i += 4;
+            continue;
+        }
+        // 語末の e は読まない
+        if i + 1 == n && w[i] == b'e' && i > 0 && !is_vowel(w[i - 1]) {
+            break;
+        }
+        // y や w は次が母音のときだけ子音
+        let glide = matches!(w[i], b'y' | b'w') && rest.get(1).is_some_and(|&c| is_vowel(c));
+        let (mut onset, len) = if glide {
+            (if w[i] == b'y' { "y" } else { "w" }, 1)
+        } else {
+            ONSETS
+                .iter()
+                .find(|(spelling, _)| rest.starts_with(spelling.as_bytes()))
+                .map_or(("", 0), |(spelling, onset)| (*onset, spelling.len()))
+        };
+        if onset == "ng" {
+            out.push('ン');
+            onset = "g";
+        }
+        let mut j = i + len;
+        // 子音が重なって次が母音なら ッ にする
+        // 母音が続かなければひとつとして読む (cell, class)
+        if len == 1 && j < n && w[j] == w[i] {
+            if w.get(j + 1).is_some_and(|&c| is_vowel(c)) {
+                match onset {
+                    "n" | "m" => out.push('ン'),
+                    "r" => {}
+                    _ => out.push('ッ'),
+                }
+            }
+            j += 1;
+        } else if rest.starts_with(b"ck") {
+            out.push('ッ');
+        }
+        // 語末の e は読まない
+        let silent_e = i > 0 && j + 1 == n && w[j] == b'e';
+        if j >= n || !is_vowel(w[j]) || silent_e {
+            out.push_str(coda(onset));
+            i = j.max(i + 1);
+            continue;
+        }
+        // c の次が e, i, y なら s で読む
+        let onset = if onset == "k" && w[i] == b'c' && b"eiy".contains(&w[j]) {
+            "s"
+        } else {
+            onset
+        };
+        let rest = &w[j..];
+        let spelled = VOWEL_SPELLINGS.iter().find(|(spelling, _, _)| {
+            rest.starts_with(spelling.as_bytes())
+                // ar, or のあとに母音が続くなら r は次の音
+                && !(spelling.ends_with('r') && rest.get(2).is_some_and(|&c| is_vowel(c)))
+        });
+        let (vowels, long, vlen): (Vec<usize>, bool, usize) = match spelled {
+            Some((spelling, vowels, long)) => (vowels.to_vec(), *long, spelling.len()),
+            None => {
+                let next = rest.get(1).copied();
+                let r_colored = next == Some(b'r') && !rest.get(2).is_some_and(|&c| is_vowel(c));
+                // 母音 + 子音ひとつ + 語末の e や、tion の前の母音は長く読む (make, time, nation)
+                let magic_e = (next.is_some_and(|c| !is_vowel(c) && c != b'r')
+                    && j + 3 == n
+                    && w[n - 1] == b'e')
+                    || (rest[0] != b'i' && rest[1..].starts_with(b"tion"));
+                match (rest[0], r_colored, magic_e) {
+                    (b'e' | b'i' | b'u', true, _) => (vec![A], true, 2),
+                    (b'a', _, true) => (vec![E, I], false, 1),
+                    (b'i' | b'y', _, true) => (vec![A, I], false, 1),
+                    (b'o', _, true) => (vec![O], true, 1),
+                    (b'u', _, true) => (vec![U], true, 1),
+                    (b'e', _, true) => (vec![I], true, 1),
+                    (b'a', _, _) => (vec![A], false, 1),
+                    (b'e', _, _) => (vec![E], false, 1),
+                    (b'o', _, _) => (vec![O], false, 1),
+                    // 語末の y は伸ばす (happy)
+                    (b'y', _, _) => (vec![I], j + 1 == n, 1),
+                    (b'i', _, _) => (vec![I], false, 1),
+                    // 子音が続く u は ア (cut, summer)
+                    (_, _, _) => {
+                        let closed = next.is_some_and(|c| !is_vowel(c))
+                            && rest.get(2).is_none_or(|&c| !is_vowel(c) || c == b'e');
+                        (vec![if closed { A } else { U }], false, 1)
+                    }
+                }
+            }
+        };
+        out.push_str(&kana(onset, vowels[0]));
+        for &vowel in &vowels[1..] {
+            out.push_str(VOWEL_KANA[vowel]);
+        }
+        if long {
+            out.push('ー');
+        }
+        i = j + vlen;
+    }
+    out
+}
+
+#[test]
+fn english_to_katakana_test() {
+    assert_eq!(english_to_katakana("rust"), "ラスト");
+    assert_eq!(english_to_katakana("make"), "メイク");
+    assert_eq!(english_to_katakana("happy"), "ハッピー");
+    assert_eq!(english_to_katakana("light"), "ライト");
+    assert_eq!(english_to_katakana("back"), "バック");
+    assert_eq!(english_to_katakana("nation"), "ネイション");
+    assert_eq!(english_to_katakana("Sunday"), "サンデイ");
+    assert_eq!(english_to_katakana("cell"), "セル");
+    assert_eq!(
+        katakanize_english("API で Crate を見る"),
+        "API で クレイト を見る"
+    );
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serenity::{
+    client::Context,
+    model::{
+        channel::{Channel, ChannelType},
+        id::{ChannelId, GuildId},
+    },
+};
+use tokio::sync::Mutex;
+
+pub fn is_thread(kind: ChannelType) -> bool {
+    matches!(
+        kind,
+        ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+    )
+}
+
+// 読み上げるチャンネルの下のスレッド。/read_thread で選んだスレッドは bot がボイスチャンネルに入り直すまで読む
+#[derive(Clone, Default)]
+pub struct ThreadReader {
+    selected: Arc<Mutex<HashMap<GuildId, HashSet<ChannelId>>>>,
+    // チャンネルごとの親チャンネル。スレッドでなければ None
+    // スレッドの作成はキャッシュに入らないので、一度 API で調べたら覚えておく
+    parents: Arc<Mutex<HashMap<ChannelId, Option<ChannelId>>>>,
+}
+
+impl ThreadReader {
+    // 選んでいなければ選び、選んでいれば外す。選んだら true を返す
+    pub async fn toggle(&self, guild_id: GuildId, thread_id: ChannelId) -> bool {
+        let mut selected = self.selected.lock().await;
+        let threads = selected.entry(guild_id).or_default();
+        if threads.remove(&thread_id) {
+            false
+        } else {
+            threads.insert(thread_id);
+            true
+        }
+    }
+
+    pub async fn reset(&self, guild_id: GuildId) {
+        self.selected.lock().await.remove(&guild_id);
+    }
+
+    pub async fn remember_parent(&self, channel_id: ChannelId, parent_id: Option<ChannelId>) {
+        self.parents.lock().await.insert(channel_id, parent_id);
+    }
+
+    // スレッドなら親チャンネルを返す
+    pub async fn parent_of(&self, ctx: &Context, channel_id: ChannelId) -> Option<ChannelId> {
+        if let Some(parent_id) = self.parents.lock().await.get(&channel_id) {
+            return *parent_id;
+        }
+        let parent_id = match channel_id.to_channel(ctx).await.ok()? {
+            Channel::Guild(channel) if is_thread(channel.kind) => channel.category_id,
+            _ => None,
+        };
+        self.remember_parent(channel_id, parent_id).await;
+        parent_id
+    }
+
+    // 読み上げるチャンネルに届いたメッセージとして扱うか
+    // include_threads は /config read_threads の設定で、読み上げるチャンネルの下のスレッドを全部読む
+    pub async fn reads(
+        &self,
+        ctx: &Context,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        read_channel_id: ChannelId,
+        include_threads: bool,
+    ) -> bool {
+        if channel_id == read_channel_id
+            || self
+                .selected
+                .lock()
+                .await
+                .get(&guild_id)
+                .is_some_and(|threads| threads.contains(&channel_id))
+        {
+            return true;
+        }
+        include_threads && self.parent_of(ctx, channel_id).await == Some(read_channel_id)
+    }
+}
+
+#[tokio::test]
+async fn thread_reader_test() {
+    assert!(is_thread(ChannelType::PublicThread));
+    assert!(!is_thread(ChannelType::Text));
+
+    let threads = ThreadReader::default();
+    let guild_id = GuildId(1);
+    assert!(threads.toggle(guild_id, ChannelId(10)).await);
+    assert!(threads.toggle(guild_id, ChannelId(11)).await);
+    assert!(!threads.toggle(guild_id, ChannelId(10)).await);
+    assert_eq!(
+        threads.selected.lock().await.get(&guild_id),
+        Some(&HashSet::from([ChannelId(11)]))
+    );
+    threads.reset(guild_id).await;
+    assert!(threads.selected.lock().await.get(&guild_id).is_none());
+}
@@ -0,0 +1,228 @@
+use std::{collections::HashMap, io::Cursor, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serenity::{
+    async_trait,
+    cache::Cache,
+    http::Http,
+    model::id::{ChannelId, GuildId, UserId},
+};
+use songbird::{CoreEvent, Event, EventContext};
+use tokio::sync::Mutex;
+use tracing::info;
+
+// discord から届く音声は 48kHz のステレオ
+const RECEIVE_SAMPLE_RATE: usize = 48_000;
+const RECEIVE_CHANNELS: usize = 2;
+// whisper は 16kHz のモノラルで聞く
+const STT_SAMPLE_RATE: usize = 16_000;
+// 話し続けていても、この長さごとに区切って文字起こしする
+const MAX_SECONDS: usize = 30;
+// 咳や物音だけの短い音は送らない
+const MIN_MILLIS: usize = 500;
+const STT_TIMEOUT: Duration = Duration::from_secs(60);
+// discord のメッセージは 2000 文字まで
+const MESSAGE_LIMIT: usize = 1900;
+
+// 受け取る音声の通知。Transcriber をこれらに登録する
+pub const EVENTS: [CoreEvent; 3] = [
+    CoreEvent::SpeakingStateUpdate,
+    CoreEvent::SpeakingUpdate,
+    CoreEvent::VoicePacket,
+];
+
+#[derive(Default)]
+struct TranscriberState {
+    // ssrc と話している人
+    users: HashMap<u32, UserId>,
+    // ssrc ごとの、まだ送っていない音声
+    buffers: HashMap<u32, Vec<i16>>,
+}
+
+// ボイスチャンネルで話した声を stt_url に送って文字起こしし、channel_id に書き込む
+#[derive(Clone)]
+pub struct Transcriber {
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    stt_url: String,
+    http: Arc<Http>,
+    cache: Arc<Cache>,
+    state: Arc<Mutex<TranscriberState>>,
+}
+
+impl Transcriber {
+    pub fn new(
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        stt_url: String,
+        http: Arc<Http>,
+        cache: Arc<Cache>,
+    ) -> Self {
+        Self {
+            guild_id,
+            channel_id,
+            stt_url,
+            http,
+            cache,
+            state: Default::default(),
+        }
+    }
+
+    // ssrc の音声を送る。話した人がわからない、または bot なら捨てる
+    async fn flush(self, user_id: Option<UserId>, samples: Vec<i16>) {
+        if samples.len() < RECEIVE_SAMPLE_RATE * RECEIVE_CHANNELS * MIN_MILLIS / 1000 {
+            return;
+        }
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => return,
+        };
+        let member = self.cache.member(self.guild_id, user_id).await;
+        if member.as_ref().is_some_and(|member| member.user.bot) {
+            return;
+        }
+        let name = member.map_or_else(
+            || "だれか".to_string(),
+            |member| member.display_name().to_string(),
+        );
+        let text = match transcribe(&self.stt_url, samples).await {
+            Ok(text) => text,
+            Err(e) => {
+                info!("could not transcribe in {}: {}", self.guild_id, e);
+                return;
+            }
+        };
+        if text.is_empty() {
+            return;
+        }
+        let message = format!("{}: {}", name, text)
+            .chars()
+            .take(MESSAGE_LIMIT)
+            .collect::<String>();
+        if let Err(e) = self
+            .channel_id
+            .send_message(&self.http, |m| {
+                m.content(message).allowed_mentions(|a| a.empty_parse())
+            })
+            .await
+        {
+            info!("could not post transcription to {}: {}", self.channel_id, e);
+        }
+    }
+}
+
+#[async_trait]
+impl songbird::EventHandler for Transcriber {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        match ctx {
+            EventContext::SpeakingStateUpdate(speaking) => {
+                if let Some(user_id) = speaking.user_id {
+                    let mut state = self.state.lock().await;
+                    state.users.insert(speaking.ssrc, UserId(user_id.0));
+                }
+            }
+            EventContext::VoicePacket(data) => {
+                let audio = match data.audio {
+                    Some(audio) if !audio.is_empty() => audio,
+                    _ => return None,
+                };
+                let ssrc = data.packet.ssrc;
+                let mut state = self.state.lock().await;
+                let buffer = state.buffers.entry(ssrc).or_default();
+                buffer.extend_from_slice(audio);
+                if buffer.len() >= RECEIVE_SAMPLE_RATE * RECEIVE_CHANNELS * MAX_SECONDS {
+                    let samples = std::mem::take(buffer);
+                    let user_id = state.users.get(&ssrc).copied();
+                    tokio::spawn(self.clone().flush(user_id, samples));
+                }
+            }
+            // 話し終わったら、そこまでを送る
+            EventContext::SpeakingUpdate(data) if !data.speaking => {
+                let mut state = self.state.lock().await;
+                if let Some(samples) = state.buffers.remove(&data.ssrc) {
+                    let user_id = state.users.get(&data.ssrc).copied();
+                    tokio::spawn(self.clone().flush(user_id, samples));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+// 48kHz のステレオを 16kHz のモノラルにする。3 フレームの平均をとる
+fn downmix(samples: &[i16]) -> Vec<i16> {
+    let step = RECEIVE_CHANNELS * RECEIVE_SAMPLE_RATE / STT_SAMPLE_RATE;
+    samples
+        .chunks_exact(step)
+        .map(|chunk| (chunk.iter().map(|&s| s as i32).sum::<i32>() / step as i32) as i16)
+        .collect()
+}
+
+fn to_wav(samples: &[i16]) -> Result<Vec<u8>> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: STT_SAMPLE_RATE as u32,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut buf = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut buf, spec)?;
+    for &s in samples {
+        writer.write_sample(s)?;
+    }
+    writer.finalize()?;
+    Ok(buf.into_inner())
+}
+
+// whisper.cpp の server と同じく、file に wav を入れて送り {"text": ...} を受け取る
+async fn transcribe(stt_url: &str, samples: Vec<i16>) -> Result<String> {
+    let wav = tokio::task::spawn_blocking(move || to_wav(&downmix(&samples))).await??;
+    let form = reqwest::multipart::Form::new()
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(wav)
+                .file_name("voice.wav")
+                .mime_str("audio/wav")?,
+        )
+        .text("response_format", "json");
+    let res = reqwest::Client::builder()
+        .timeout(STT_TIMEOUT)
+        .build()?
+        .post(stt_url)
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?;
+    parse_text(&res.text().await?)
+}
+
+fn parse_text(body: &str) -> Result<String> {
+    let value: serde_json::Value = serde_json::from_str(body)?;
+    let text = value
+        .get("text")
+        .and_then(|text| text.as_str())
+        .ok_or_else(|| anyhow!("no text in response: {}", body))?;
+    Ok(text.trim().to_string())
+}
+
+#[test]
+fn transcribe_test() {
+    // 左右で 3 フレームずつ平均する
+    assert_eq!(
+        downmix(&[0, 6, 3, 9, 6, 12, 100, 100, 100, 100, 100, 100, 1]),
+        vec![6, 100]
+    );
+    let wav = to_wav(&[0, 1000, -1000]).unwrap();
+    let reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+    assert_eq!(reader.spec().sample_rate, 16_000);
+    assert_eq!(reader.spec().channels, 1);
+    assert_eq!(reader.len(), 3);
+
+    assert_eq!(
+        parse_text(r#"{"text": " こんにちは\n"}"#).unwrap(),
+        "こんにちは"
+    );
+    assert!(parse_text(r#"{"error": "bad"}"#).is_err());
+    assert!(parse_text("not json").is_err());
+}
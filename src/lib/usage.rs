@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use super::db::Usage;
+
+// 音声の長さを「3 分 5 秒」のように書く
+fn format_audio(audio_ms: i64) -> String {
+    let secs = Duration::from_millis(audio_ms.max(0) as u64).as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{} 秒", s),
+        (0, m, s) => format!("{} 分 {} 秒", m, s),
+        (h, m, _) => format!("{} 時間 {} 分", h, m),
+    }
+}
+
+fn format_usage(name: &str, usage: &Usage) -> String {
+    format!(
+        "{}: {} 件、{} 文字、{}",
+        name,
+        usage.messages,
+        usage.characters,
+        format_audio(usage.audio_ms)
+    )
+}
+
+// /stats の埋め込みの欄。合計とエンジンごとの内訳
+pub fn usage_summary(rows: &[Usage]) -> String {
+    if rows.is_empty() {
+        return "まだ読み上げていないよ".to_string();
+    }
+    let total = rows.iter().fold(Usage::default(), |total, usage| Usage {
+        generator_type: String::new(),
+        messages: total.messages + usage.messages,
+        characters: total.characters + usage.characters,
+        audio_ms: total.audio_ms + usage.audio_ms,
+    });
+    let mut lines = vec![format_usage("合計", &total)];
+    lines.extend(
+        rows.iter()
+            .map(|usage| format_usage(&usage.generator_type, usage)),
+    );
+    lines.join("\n")
+}
+
+#[test]
+fn usage_summary_test() {
+    assert_eq!(format_audio(5_400), "5 秒");
+    assert_eq!(format_audio(185_000), "3 分 5 秒");
+    assert_eq!(format_audio(3_900_000), "1 時間 5 分");
+
+    let usage = |generator_type: &str, messages, audio_ms| Usage {
+        generator_type: generator_type.to_string(),
+        messages,
+        characters: messages * 10,
+        audio_ms,
+    };
+    assert_eq!(
+        usage_summary(&[usage("COEIROINK", 1, 2_000), usage("VOICEVOX", 3, 60_000)]),
+        "合計: 4 件、40 文字、1 分 2 秒\nCOEIROINK: 1 件、10 文字、2 秒\nVOICEVOX: 3 件、30 文字、1 分 0 秒"
+    );
+    assert_eq!(usage_summary(&[]), "まだ読み上げていないよ");
+}
@@ -1,145 +1,999 @@
-use std::{convert::TryInto, fs::File, io::Write};
+use std::{
+    convert::TryInto,
+    io::Cursor,
+    time::{Duration, Instant},
+};
 
 use crate::handler::{Generators, Handler};
 use anyhow::{anyhow, Result};
-use reqwest;
 use serenity::{
     client::Context,
-    model::{channel::Message, id::GuildId},
+    model::{
+        channel::Message,
+        guild::Guild,
+        id::{GuildId, MessageId, UserId},
+    },
     utils::{content_safe, ContentSafeOptions},
 };
-use tempfile;
+use songbird::{
+    input::{Codec, Container, Input, Metadata, Reader},
+    tracks::{Track, TrackHandle},
+};
 use tracing::info;
 
-use super::{db::UserConfigDB, text::TextMessage};
+use super::{
+    batch::{BatchItem, BATCH_MAX_CHARS},
+    db::{GuildConfig, KanaSpeakerDB, NgWordDB, SpeakerDB, Usage, UsageDB},
+    engine::{engine, SynthesisParams},
+    feature::{Feature, FeatureFlags},
+    language::Language,
+    long_read::LONG_MESSAGE_CHARS,
+    metrics::metrics,
+    mixer::MixPolicy,
+    ng_word::filter_ng_words,
+    opus_cache::{CacheKey, MAX_PHRASE_CHARS},
+    playback::Priority,
+    quiet_hours::is_quiet,
+    rotation::rotation_candidates,
+    search::search_speakers,
+    text::{
+        clean_display_name, embed_text, katakanize_english, replace_gif_url, split_dialogue,
+        split_inline_tags, NameReading, TextMessage, VoiceOverrides,
+    },
+};
+
+// songbird は 48kHz の f32 PCM を受け取る
+const SAMPLE_RATE: u32 = 48000;
+// 最大振幅に張り付いたサンプルがこの割合を超えたら壊れた音声とみなす
+const CLIPPING_RATIO: f32 = 0.05;
 
-#[derive(Hash)]
-pub struct SpeakerId {
-    id: u32,
-    generator: Generators,
+// webhook や連携アプリの投稿には member がないので、ユーザー名で読む
+fn author_nick(msg: &Message) -> Option<&str> {
+    msg.member
+        .as_ref()
+        .and_then(|member| member.nick.as_deref())
 }
 
 pub async fn play_voice(ctx: &Context, msg: Message, handler: &Handler) -> Result<()> {
     info!("{}", &msg.content);
 
-    let mut temp_file = tempfile::Builder::new().tempfile_in("temp")?;
     let clean_option = ContentSafeOptions::new();
     let user_id = msg.author.id.0 as i64;
-    let nickname = handler
-        .database
-        .get_user_config_or_default(user_id)
-        .await?
-        .read_nickname
-        .unwrap_or(
-            msg.member
-                .as_ref()
-                .ok_or_else(|| anyhow!("member not found"))?
-                .nick
-                .as_ref()
-                .unwrap_or(&msg.author.name)
-                .to_string(),
-        );
-    let cleaned_content = content_safe(&ctx.cache, msg.content.clone(), &clean_option)
-        .await
-        .make_read_text(&handler.database)
+    let guild_id = msg.guild_id.map(|guild_id| guild_id.0 as i64);
+    let nick = author_nick(&msg);
+    let user_config = handler
+        .settings
+        .user_config(&handler.database, user_id, guild_id)
         .await;
+    // サーバー外では初期設定で読む
+    let guild_config = match guild_id {
+        Some(guild_id) => {
+            handler
+                .settings
+                .guild_config(&handler.database, guild_id)
+                .await
+        }
+        None => GuildConfig::from_guild_id(0),
+    };
+    let embed_text = embed_text(
+        &msg.embeds,
+        guild_config.read_embed_title,
+        guild_config.read_embed_description,
+    );
+    let language = Language::of(&guild_config);
+    let flags = FeatureFlags::load(&handler.database, guild_id).await;
+    let (mut overrides, content) = if flags.is_enabled(Feature::InlineTags) {
+        split_inline_tags(&msg.content)
+    } else {
+        (VoiceOverrides::default(), msg.content.as_str())
+    };
+    // スタンプや GIF は URL として読まず、名前を知らせる。スタンプの名前にも NG ワードを当てる
+    let (content, embed_text) = if guild_config.read_stickers {
+        let stickers = msg
+            .stickers
+            .iter()
+            .map(|sticker| language.sticker(&sticker.name))
+            .collect::<Vec<_>>();
+        (
+            replace_gif_url(content),
+            format!("{} {}", stickers.join(" "), embed_text),
+        )
+    } else {
+        (content.to_string(), embed_text)
+    };
+    // NG ワードは辞書より先に伏せる。読まない単語があればメッセージごと読まない
+    let ng_words = match guild_id {
+        Some(guild_id) => handler.database.get_ng_words(guild_id).await?,
+        None => Vec::new(),
+    };
+    let (content, embed_text) = match (
+        filter_ng_words(&content, &ng_words),
+        filter_ng_words(&embed_text, &ng_words),
+    ) {
+        (Some(content), Some(embed_text)) => (content, embed_text),
+        _ => return Ok(()),
+    };
+    let cleaned_content = content_safe(
+        &ctx.cache,
+        format!("{} {}", content, embed_text),
+        &clean_option,
+    )
+    .await
+    .make_guild_read_text(&handler.database, language, guild_id, Some(user_id))
+    .await;
+    let cleaned_content = english_kana(&guild_config, cleaned_content);
     info!("{}", &cleaned_content);
     if cleaned_content.chars().all(|c| !c.is_alphanumeric()) {
         return Ok(());
     }
-    let cleaned_text = format!(
-        "{} {}",
-        if msg.author.id != ctx.cache.as_ref().current_user_id().await {
-            nickname.make_read_text(&handler.database).await
-        } else {
-            String::new()
-        },
-        cleaned_content
-    );
-
-    let user_config = handler.database.get_user_config_or_default(user_id).await?;
+    // 貼り付けたログなどを間違えて長々と読まないよう、長文は送った人に確認する
+    // 確認している間に届いたメッセージは、答えが出るまで待ってから読む
+    if cleaned_content.chars().count() > LONG_MESSAGE_CHARS {
+        if !handler.long_read.ask(ctx, &msg, &cleaned_content).await? {
+            return Ok(());
+        }
+    } else if let Some(guild_id) = msg.guild_id {
+        handler.long_read.wait_turn(guild_id).await;
+    }
+    // 同じ人が続けて送ったメッセージでは名前を繰り返さない
+    let consecutive = match msg.guild_id {
+        Some(guild_id) => {
+            handler
+                .activity
+                .is_consecutive(guild_id, msg.author.id)
+                .await
+        }
+        None => false,
+    };
+    let reply = if guild_config.read_reply {
+        reply_announcement(ctx, handler, &msg, language)
+            .await
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+    // 静かな時間帯の人は名前を読まない
+    let name_reading = if is_quiet(&user_config) {
+        NameReading::None
+    } else {
+        NameReading::of(&guild_config)
+    };
+    let nickname =
+        name_reading.author_name(user_config.read_nickname.clone(), nick, &msg.author.name);
+    let name = match nickname {
+        Some(nickname)
+            if !consecutive && msg.author.id != ctx.cache.as_ref().current_user_id().await =>
+        {
+            nickname.make_read_text(&handler.database, language).await
+        }
+        _ => String::new(),
+    };
 
-    let voice_type = user_config.voice_type.try_into()?;
-    let generator_type = user_config.generator_type.try_into()?;
-    create_voice(
-        &cleaned_text,
-        voice_type,
-        generator_type,
-        temp_file.as_file_mut(),
-    )
-    .await?;
+    let (voice_type, generator_type) = (
+        user_config.voice_type.try_into()?,
+        user_config.generator_type.try_into()?,
+    );
+    // 声を決めていない人には、会話モードで配った声を使う
+    let default_voice = (
+        guild_config.generator_type as u8,
+        guild_config.voice_type as u32,
+    );
+    let (generator_type, voice_type) = match msg.guild_id {
+        Some(guild_id) if flags.is_enabled(Feature::VoiceRotation) && !user_config.voice_chosen => {
+            rotation_voice(handler, guild_id, msg.author.id, default_voice)
+                .await
+                .unwrap_or((generator_type, voice_type))
+        }
+        _ => (generator_type, voice_type),
+    };
+    overrides = overrides.with_user_params(user_config.synthesis_params());
 
     let guild = msg
         .guild(&ctx.cache)
         .await
         .ok_or_else(|| anyhow!("guild not found"))?;
-    let guild_id = guild.id;
-    let (_, path) = temp_file.keep()?;
-    let manager = songbird::get(ctx)
+    if flags.is_enabled(Feature::Dialogue) {
+        if let Some(dialogue) = split_dialogue(&content) {
+            let mut lines = vec![(format!("{} {}", name, reply), generator_type, voice_type)];
+            // 埋め込みは最後に送った人の声で読む
+            for (speaker, line) in dialogue.into_iter().chain([(None, embed_text)]) {
+                let (generator_type, voice_type) = match speaker {
+                    Some(speaker) => speaker_voice(handler, &guild, speaker)
+                        .await
+                        .unwrap_or((generator_type, voice_type)),
+                    None => (generator_type, voice_type),
+                };
+                let line = content_safe(&ctx.cache, line, &clean_option)
+                    .await
+                    .make_guild_read_text(&handler.database, language, guild_id, Some(user_id))
+                    .await;
+                let line = english_kana(&guild_config, line);
+                lines.push((line, generator_type, voice_type));
+            }
+            return play_dialogue(ctx, handler, guild.id, lines, &overrides, &[msg.id]).await;
+        }
+    }
+    let text = format!("{} {} {}", name, reply, cleaned_content);
+    if guild_config.batch_window_ms > 0 {
+        let item = BatchItem {
+            text,
+            short: cleaned_content.chars().count() <= BATCH_MAX_CHARS,
+            generator_type,
+            voice_type,
+            overrides,
+            message_ids: vec![msg.id],
+        };
+        return play_batched(ctx, handler, guild.id, item, guild_config.batch_window_ms).await;
+    }
+    play_text(
+        ctx,
+        handler,
+        &text,
+        voice_type,
+        generator_type,
+        guild.id,
+        &overrides,
+        false,
+        &[msg.id],
+    )
+    .await
+}
+
+// 短いメッセージは batch_window_ms 待ち、その間に来たメッセージとまとめて読む
+async fn play_batched(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    item: BatchItem,
+    batch_window_ms: i64,
+) -> Result<()> {
+    match handler.batches.push(guild_id, item.clone()).await {
+        None => {
+            return play_text(
+                ctx,
+                handler,
+                &item.text,
+                item.voice_type,
+                item.generator_type,
+                guild_id,
+                &item.overrides,
+                false,
+                &item.message_ids,
+            )
+            .await
+        }
+        Some(false) => return Ok(()),
+        Some(true) => {}
+    }
+    tokio::time::sleep(Duration::from_millis(batch_window_ms as u64)).await;
+    // ひとつ読めなくても残りは読む
+    for item in handler.batches.take(guild_id).await {
+        if let Err(e) = play_text(
+            ctx,
+            handler,
+            &item.text,
+            item.voice_type,
+            item.generator_type,
+            guild_id,
+            &item.overrides,
+            false,
+            &item.message_ids,
+        )
         .await
-        .ok_or_else(|| anyhow!("Songbird Voice client placed in at initialisation."))?
-        .clone();
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
-        let mut source = songbird::ffmpeg(&path).await?;
-        source.metadata.source_url = Some(path.to_string_lossy().to_string());
-        let (mut track, _) = songbird::tracks::create_player(source);
-        if generator_type == 0 {
-            track.set_volume(0.4);
+        {
+            info!("{}", e);
         }
-        handler.enqueue(track);
     }
     Ok(())
 }
 
+// 動いているエンジンの話者から、この人に配った声を返す
+async fn rotation_voice(
+    handler: &Handler,
+    guild_id: GuildId,
+    user_id: UserId,
+    default_voice: (u8, u32),
+) -> Option<(u8, u32)> {
+    let speakers = handler.database.get_all_speakers().await.ok()?;
+    let mut generators = Vec::new();
+    for generator in Generators::ALL {
+        if handler.engine_health.is_available(generator).await {
+            generators.push(generator.into());
+        }
+    }
+    let candidates = rotation_candidates(&speakers, &generators, default_voice);
+    handler
+        .rotation
+        .voice_for(guild_id, user_id, &candidates)
+        .await
+}
+
+// 辞書でも読めなかった英単語を、設定していればカタカナにする。英語のサーバーではそのまま
+fn english_kana(guild_config: &GuildConfig, text: String) -> String {
+    if guild_config.english_kana && Language::of(guild_config) == Language::Japanese {
+        katakanize_english(&text)
+    } else {
+        text
+    }
+}
+
+// 引用された名前のメンバーを探し、その人の声を返す
+async fn speaker_voice(handler: &Handler, guild: &Guild, name: &str) -> Option<(u8, u32)> {
+    let member = guild.members.values().find(|member| {
+        member.nick.as_deref() == Some(name) || member.user.name.eq_ignore_ascii_case(name)
+    })?;
+    let user_config = handler
+        .settings
+        .user_config(
+            &handler.database,
+            member.user.id.0 as i64,
+            Some(guild.id.0 as i64),
+        )
+        .await;
+    Some((
+        user_config.generator_type.try_into().ok()?,
+        user_config.voice_type.try_into().ok()?,
+    ))
+}
+
+// 返信なら「〇〇さんへの返信」を返す
+async fn reply_announcement(
+    ctx: &Context,
+    handler: &Handler,
+    msg: &Message,
+    language: Language,
+) -> Option<String> {
+    let referenced = match &msg.referenced_message {
+        Some(referenced) => (**referenced).clone(),
+        // 届いたメッセージに元のメッセージが含まれていなければ取りに行く
+        None => {
+            let reference = msg.message_reference.as_ref()?;
+            ctx.http
+                .get_message(reference.channel_id.0, reference.message_id?.0)
+                .await
+                .ok()?
+        }
+    };
+    let guild_id = msg.guild_id?;
+    let author = &referenced.author;
+    let nickname = match handler
+        .settings
+        .user_config(
+            &handler.database,
+            author.id.0 as i64,
+            Some(guild_id.0 as i64),
+        )
+        .await
+        .read_nickname
+    {
+        Some(nickname) => nickname,
+        None => clean_display_name(
+            &ctx.cache
+                .member(guild_id, author.id)
+                .await
+                .and_then(|member| member.nick)
+                .unwrap_or_else(|| author.name.clone()),
+        ),
+    };
+    Some(language.reply_to(&nickname.make_read_text(&handler.database, language).await))
+}
+
 pub async fn create_voice(
     text: &str,
     voice_type: u32,
     generator_type: u8,
-    temp_file: &mut File,
-) -> Result<()> {
-    dotenv::dotenv().ok();
-    let base_url = std::env::var(match generator_type {
-        0 => "BASE_URL_COEIRO",
-        1 => "BASE_URL_VOICEVOX",
-        _ => unreachable!(),
-    })?;
-    let params = [("text", text), ("speaker", &voice_type.to_string())];
-    let client = reqwest::Client::new();
-    let voice_query_url = format!("{}/audio_query", base_url);
-    let res = client.post(voice_query_url).query(&params).send().await?;
-    let synthesis_body = res.text().await?;
-    let synthesis_arg = [("speaker", voice_type)];
-    let synthesis_url = format!("{}/synthesis", base_url);
-    let synthesis_res = client
-        .post(synthesis_url)
-        .body(synthesis_body)
-        .query(&synthesis_arg)
-        .send()
-        .await?;
-    let _ = temp_file.write(&synthesis_res.bytes().await?)?;
+    params: SynthesisParams,
+) -> Result<Vec<u8>> {
+    let generator = Generators::try_from(generator_type)?;
+    let started = Instant::now();
+    let wav = match engine(generator).await {
+        Ok(engine) => engine.synthesize(text, voice_type, params).await,
+        Err(e) => Err(e),
+    };
+    metrics().synthesis_finished(generator.into(), started.elapsed(), wav.is_ok());
+    wav
+}
+
+// 合成した音声が壊れていたら一度だけ作り直す
+pub async fn synthesize(
+    text: &str,
+    voice_type: u32,
+    generator_type: u8,
+    params: SynthesisParams,
+) -> Result<Input> {
+    let mut last_error = None;
+    for _ in 0..2 {
+        let input = create_voice(text, voice_type, generator_type, params)
+            .await
+            .and_then(|wav| wav_to_input(&wav));
+        match input {
+            Ok(input) => return Ok(input),
+            Err(e) => {
+                info!("synthesis failed: {}", e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(anyhow!(
+        "音声の合成に失敗したよ: {}",
+        last_error.map_or_else(String::new, |e| e.to_string())
+    ))
+}
+
+fn validate_samples(samples: &[f32]) -> Result<()> {
+    if samples.is_empty() {
+        return Err(anyhow!("synthesized audio is empty"));
+    }
+    let clipped = samples.iter().filter(|s| s.abs() >= 0.999).count();
+    if clipped as f32 / samples.len() as f32 > CLIPPING_RATIO {
+        return Err(anyhow!("synthesized audio is clipped"));
+    }
     Ok(())
 }
 
+// エンジンが返す WAV をデコードし、48kHz の f32 PCM に変換してメモリ上の Input にする
+pub fn wav_to_input(wav: &[u8]) -> Result<Input> {
+    let mut reader = hound::WavReader::new(Cursor::new(wav))?;
+    let spec = reader.spec();
+    if spec.channels == 0 || spec.channels > 2 {
+        return Err(anyhow!("unsupported channel count: {}", spec.channels));
+    }
+    if spec.sample_rate == 0 || spec.bits_per_sample == 0 || spec.bits_per_sample > 32 {
+        return Err(anyhow!("invalid wav header"));
+    }
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+    };
+    validate_samples(&samples)?;
+    let channels = spec.channels as usize;
+    let resampled = resample(&samples, channels, spec.sample_rate, SAMPLE_RATE);
+    let frames = resampled.len() / channels;
+    let bytes = resampled
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    let metadata = Metadata {
+        channels: Some(spec.channels as u8),
+        sample_rate: Some(SAMPLE_RATE),
+        duration: Some(Duration::from_secs_f64(frames as f64 / SAMPLE_RATE as f64)),
+        ..Default::default()
+    };
+    Ok(Input::new(
+        channels == 2,
+        Reader::from_memory(bytes),
+        Codec::FloatPcm,
+        Container::Raw,
+        Some(metadata),
+    ))
+}
+
+// interleave されたサンプル列を線形補間でリサンプリングする
+fn resample(samples: &[f32], channels: usize, from: u32, to: u32) -> Vec<f32> {
+    if from == to {
+        return samples.to_vec();
+    }
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return Vec::new();
+    }
+    let out_frames = (frames as u64 * to as u64 / from as u64) as usize;
+    let mut out = Vec::with_capacity(out_frames * channels);
+    for i in 0..out_frames {
+        let pos = i as f64 * from as f64 / to as f64;
+        let idx = pos as usize;
+        let frac = (pos - idx as f64) as f32;
+        let next = (idx + 1).min(frames - 1);
+        for c in 0..channels {
+            let a = samples[idx * channels + c];
+            let b = samples[next * channels + c];
+            out.push(a + (b - a) * frac);
+        }
+    }
+    out
+}
+
 pub async fn play_raw_voice(
     ctx: &Context,
+    handler: &Handler,
+    str: &str,
+    voice_type: u32,
+    generator_type: u8,
+    guild_id: GuildId,
+    overrides: &VoiceOverrides,
+) -> Result<()> {
+    play_text(
+        ctx,
+        handler,
+        str,
+        voice_type,
+        generator_type,
+        guild_id,
+        overrides,
+        false,
+        &[],
+    )
+    .await
+}
+
+// あいさつやコマンドの返事など bot からのお知らせを、/config announce_priority に従ってメッセージより先に読む
+pub async fn play_announcement(
+    ctx: &Context,
+    handler: &Handler,
     str: &str,
     voice_type: u32,
     generator_type: u8,
     guild_id: GuildId,
 ) -> Result<()> {
-    let mut temp_file = tempfile::Builder::new().tempfile_in("temp")?;
-    create_voice(str, voice_type, generator_type, temp_file.as_file_mut()).await?;
-    let (_, path) = temp_file.keep()?;
+    play_text(
+        ctx,
+        handler,
+        str,
+        voice_type,
+        generator_type,
+        guild_id,
+        &VoiceOverrides::default(),
+        true,
+        &[],
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn play_text(
+    ctx: &Context,
+    handler: &Handler,
+    str: &str,
+    voice_type: u32,
+    generator_type: u8,
+    guild_id: GuildId,
+    overrides: &VoiceOverrides,
+    announcement: bool,
+    message_ids: &[MessageId],
+) -> Result<()> {
+    let (generator_type, voice_type) =
+        language_voice(handler, guild_id, (generator_type, voice_type)).await;
+    let (generator_type, voice_type) = match &overrides.voice {
+        Some(voice) => override_voice(handler, voice)
+            .await
+            .unwrap_or((generator_type, voice_type)),
+        None => (generator_type, voice_type),
+    };
+    let (generator_type, voice_type) = handler
+        .engine_health
+        .fallback(&handler.database, generator_type, voice_type)
+        .await?;
+    let generation = handler.playback.generation(guild_id).await;
+    let kana = kana_text(handler, guild_id, generator_type, voice_type, str).await;
+    // 短いお知らせは同じ文を何度も読むので、Opus にしたものを覚えておく
+    let track = if announcement && kana.chars().count() <= MAX_PHRASE_CHARS {
+        let key = CacheKey::Phrase {
+            text: kana.clone(),
+            generator_type,
+            voice_type,
+        };
+        let source = match handler.opus_cache.get(&key).await {
+            Some(source) => source,
+            None => {
+                let source = synthesize(
+                    &kana,
+                    voice_type,
+                    generator_type,
+                    overrides.synthesis_params(),
+                )
+                .await?;
+                handler.opus_cache.insert(key, source).await?
+            }
+        };
+        create_player(source, generator_type)
+    } else {
+        create_track(&kana, voice_type, generator_type, overrides).await?
+    };
+    record_usage(
+        handler,
+        guild_id,
+        generator_type,
+        str,
+        &track.1,
+        message_ids.len(),
+    )
+    .await;
+    enqueue(
+        ctx,
+        handler,
+        guild_id,
+        vec![track],
+        generation,
+        announcement,
+        message_ids,
+    )
+    .await
+}
+
+// 声の違うセリフをまとめて合成し、間に他のメッセージが入らないよう一度に積む
+// lines は (文章, generator_type, voice_type)
+pub async fn play_dialogue(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    lines: Vec<(String, u8, u32)>,
+    overrides: &VoiceOverrides,
+    message_ids: &[MessageId],
+) -> Result<()> {
+    let generation = handler.playback.generation(guild_id).await;
+    let mut tracks = Vec::new();
+    for (line, generator_type, voice_type) in lines {
+        if line.chars().all(|c| !c.is_alphanumeric()) {
+            continue;
+        }
+        let (generator_type, voice_type) =
+            language_voice(handler, guild_id, (generator_type, voice_type)).await;
+        let (generator_type, voice_type) = handler
+            .engine_health
+            .fallback(&handler.database, generator_type, voice_type)
+            .await?;
+        let kana = kana_text(handler, guild_id, generator_type, voice_type, &line).await;
+        let track = create_track(&kana, voice_type, generator_type, overrides).await?;
+        // メッセージは最初のセリフでだけ数える
+        let messages = if tracks.is_empty() {
+            message_ids.len()
+        } else {
+            0
+        };
+        record_usage(handler, guild_id, generator_type, &line, &track.1, messages).await;
+        tracks.push(track);
+    }
+    enqueue(
+        ctx,
+        handler,
+        guild_id,
+        tracks,
+        generation,
+        false,
+        message_ids,
+    )
+    .await
+}
+
+// /config kana_speaker で選んだ話者か、/config kana_reading でサーバー全体を直すことにしていれば
+// VOICEVOX の形態素解析 (OpenJTalk の辞書) で固有名詞や読み分けを決め、カタカナにした文章を返す
+// bot の中で Lindera などを動かす代わりに、すでに動いている VOICEVOX に解析させる
+// VOICEVOX が動いていないときや読めなかったときは、そのまま返す
+async fn kana_text(
+    handler: &Handler,
+    guild_id: GuildId,
+    generator_type: u8,
+    voice_type: u32,
+    text: &str,
+) -> String {
+    let converted = async {
+        let generator = Generators::try_from(generator_type)?;
+        // VOICEVOX と OpenJTalk の話者はもともと同じ解析で読んでいる
+        let whole_guild = generator == Generators::COEIROINK
+            && handler
+                .settings
+                .guild_config(&handler.database, guild_id.0 as i64)
+                .await
+                .kana_reading;
+        if !whole_guild
+            && !handler
+                .database
+                .is_kana_speaker(guild_id.0 as i64, generator.into(), voice_type as i64)
+                .await?
+        {
+            return Ok(None);
+        }
+        // 読みはどの話者でも同じなので、VOICEVOX の最初の話者で調べる
+        let speaker = handler
+            .database
+            .get_all_speakers()
+            .await?
+            .into_iter()
+            .find(|speaker| speaker.generator_type == <&str>::from(Generators::VOICEVOX))
+            .ok_or_else(|| anyhow!("no VOICEVOX speaker to read kana"))?;
+        let kana = engine(Generators::VOICEVOX)
+            .await?
+            .to_kana(text, speaker.style_id as u32)
+            .await?;
+        Ok::<_, anyhow::Error>(Some(kana))
+    }
+    .await;
+    match converted {
+        Ok(Some(kana)) => kana,
+        Ok(None) => text.to_string(),
+        Err(e) => {
+            info!("failed to read as kana: {}", e);
+            text.to_string()
+        }
+    }
+}
+
+// /stats のために、合成した文字数と音声の長さを数える。messages は読み上げたメッセージの数
+async fn record_usage(
+    handler: &Handler,
+    guild_id: GuildId,
+    generator_type: u8,
+    text: &str,
+    track_handle: &TrackHandle,
+    messages: usize,
+) {
+    let generator = match Generators::try_from(generator_type) {
+        Ok(generator) => generator,
+        Err(_) => return,
+    };
+    let usage = Usage {
+        generator_type: <&str>::from(generator).to_string(),
+        messages: messages as i64,
+        characters: text.chars().count() as i64,
+        audio_ms: track_handle
+            .metadata()
+            .duration
+            .map_or(0, |duration| duration.as_millis() as i64),
+    };
+    if let Err(e) = handler
+        .database
+        .record_usage(guild_id.0 as i64, &usage)
+        .await
+    {
+        info!("failed to record usage: {}", e);
+    }
+}
+
+// 英語のサーバーで話者が設定されていれば、その話者で読む
+async fn language_voice(handler: &Handler, guild_id: GuildId, voice: (u8, u32)) -> (u8, u32) {
+    let guild_config = handler
+        .settings
+        .guild_config(&handler.database, guild_id.0 as i64)
+        .await;
+    Language::of(&guild_config)
+        .voice(&guild_config)
+        .unwrap_or(voice)
+}
+
+pub async fn create_track(
+    str: &str,
+    voice_type: u32,
+    generator_type: u8,
+    overrides: &VoiceOverrides,
+) -> Result<(Track, TrackHandle)> {
+    let source = synthesize(
+        str,
+        voice_type,
+        generator_type,
+        overrides.synthesis_params(),
+    )
+    .await?;
+    Ok(create_player(source, generator_type))
+}
+
+fn create_player(source: Input, generator_type: u8) -> (Track, TrackHandle) {
+    let (mut track, track_handle) = songbird::tracks::create_player(source);
+    if generator_type == 0 {
+        track.set_volume(0.4);
+    }
+    (track, track_handle)
+}
+
+// 合成した音声以外 (効果音など) を読み上げと同じキューに積む
+pub async fn play_input(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    input: Input,
+) -> Result<()> {
+    let generation = handler.playback.generation(guild_id).await;
+    let track = songbird::tracks::create_player(input);
+    enqueue(ctx, handler, guild_id, vec![track], generation, false, &[]).await
+}
+
+// [voice:...] で指定された話者を検索し、いちばん近いものを返す
+async fn override_voice(handler: &Handler, voice: &str) -> Option<(u8, u32)> {
+    let speakers = handler.database.get_all_speakers().await.ok()?;
+    let speaker = search_speakers(&speakers, voice).into_iter().next()?;
+    let generator_type = Generators::try_from(speaker.generator_type.as_str()).ok()?;
+    Some((generator_type as u8, speaker.style_id as u32))
+}
+
+// 指定した長さの無音
+pub fn silence(duration: Duration) -> Input {
+    let frames = (duration.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+    let bytes = vec![0_u8; frames * std::mem::size_of::<f32>()];
+    let metadata = Metadata {
+        channels: Some(1),
+        sample_rate: Some(SAMPLE_RATE),
+        duration: Some(duration),
+        ..Default::default()
+    };
+    Input::new(
+        false,
+        Reader::from_memory(bytes),
+        Codec::FloatPcm,
+        Container::Raw,
+        Some(metadata),
+    )
+}
+
+// キューに積む。前のメッセージがまだ残っていたら、サーバーで設定した長さの無音を間に挟む
+// generation は合成を始めたときの値で、その間に /flush されていたら積まない
+// tracks はひとつのメッセージとして間をあけずに続けて積む
+// message_ids はもとになったメッセージで、合成中に消されていたら積まない
+async fn enqueue(
+    ctx: &Context,
+    handler: &Handler,
+    guild_id: GuildId,
+    tracks: Vec<(Track, TrackHandle)>,
+    generation: u64,
+    announcement: bool,
+    message_ids: &[MessageId],
+) -> Result<()> {
+    let guild_config = handler
+        .settings
+        .guild_config(&handler.database, guild_id.0 as i64)
+        .await;
+    let gap = guild_config.message_gap_ms;
+    let gain = MixPolicy::from_guild_config(&guild_config).tts;
+    let priority = if announcement {
+        Priority::of(&guild_config)
+    } else {
+        Priority::Queue
+    };
     let manager = songbird::get(ctx)
         .await
         .ok_or_else(|| anyhow!("Songbird Voice client placed in at initialisation."))?
         .clone();
     if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
-        let mut source = songbird::ffmpeg(&path).await?;
-        source.metadata.source_url = Some(path.to_string_lossy().to_string());
-        handler.enqueue_source(source);
+        let mut call = handler_lock.lock().await;
+        if tracks.is_empty()
+            || generation != handler.playback.generation(guild_id).await
+            || handler.playback.is_deleted(guild_id, message_ids).await
+        {
+            return Ok(());
+        }
+        // 割り込むお知らせの前には無音をはさまない
+        if gap > 0 && !call.queue().is_empty() && priority == Priority::Queue {
+            call.enqueue_source(silence(Duration::from_millis(gap as u64)));
+        }
+        for (mut track, track_handle) in tracks {
+            if !handler
+                .playback
+                .register(guild_id, &track_handle, generation)
+                .await
+            {
+                return Ok(());
+            }
+            handler
+                .playback
+                .attach(guild_id, message_ids, &track_handle)
+                .await;
+            let volume = track.volume() * gain;
+            track.set_volume(volume);
+            call.enqueue(track);
+            if priority != Priority::Queue {
+                handler
+                    .playback
+                    .mark_announcement(guild_id, &track_handle)
+                    .await;
+                let queued = call.queue().current_queue();
+                // いま積んだ最後のトラックを除いて入れる位置を決める
+                let flags = handler
+                    .playback
+                    .announcement_flags(guild_id, &queued[..queued.len() - 1])
+                    .await;
+                let position = priority.position(&flags);
+                call.queue().modify_queue(|queue| {
+                    if let Some(track) = queue.pop_back() {
+                        queue.insert(position, track);
+                    }
+                });
+                // 読んでいたトラックは一時停止し、お知らせが終わるとキューが続きから流す
+                if position == 0 && queued.len() > 1 {
+                    queued[0].pause().ok();
+                    track_handle.play().ok();
+                }
+            }
+        }
+        metrics().message_read();
     }
+    handler.activity.touch(guild_id).await;
     Ok(())
 }
+
+#[test]
+fn silence_test() {
+    use std::io::Read;
+
+    let mut input = silence(Duration::from_millis(300));
+    assert_eq!(input.metadata.duration, Some(Duration::from_millis(300)));
+    let mut bytes = Vec::new();
+    input.reader.read_to_end(&mut bytes).unwrap();
+    // 48kHz の f32 で 0.3 秒分
+    assert_eq!(bytes.len(), 14400 * 4);
+    assert!(bytes.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn resample_test() {
+    // 24kHz -> 48kHz でフレーム数が倍になり、間は線形補間される
+    let mono = vec![0.0, 1.0, 0.0];
+    assert_eq!(
+        resample(&mono, 1, 24000, 48000),
+        vec![0.0, 0.5, 1.0, 0.5, 0.0, 0.0]
+    );
+
+    let stereo = vec![0.0, 1.0, 1.0, 0.0];
+    assert_eq!(
+        resample(&stereo, 2, 24000, 48000),
+        vec![0.0, 1.0, 0.5, 0.5, 1.0, 0.0, 1.0, 0.0]
+    );
+
+    assert_eq!(resample(&mono, 1, 48000, 48000), mono);
+}
+
+#[cfg(test)]
+fn make_wav(samples: &[i16]) -> Vec<u8> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 24000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut buf = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut buf, spec).unwrap();
+    for &s in samples {
+        writer.write_sample(s).unwrap();
+    }
+    writer.finalize().unwrap();
+    buf.into_inner()
+}
+
+#[test]
+fn wav_validation_test() {
+    let ok = make_wav(&[0, 1000, -1000, 2000]);
+    assert!(wav_to_input(&ok).is_ok());
+
+    let empty = make_wav(&[]);
+    assert!(wav_to_input(&empty).is_err());
+
+    let clipped = make_wav(&[i16::MAX, i16::MIN, i16::MAX, 0]);
+    assert!(wav_to_input(&clipped).is_err());
+
+    let corrupt = b"RIFF\x00\x00\x00\x00WAVE".to_vec();
+    assert!(wav_to_input(&corrupt).is_err());
+}
+
+#[test]
+fn webhook_embed_test() {
+    // 通知 bot の webhook は本文が空で、埋め込みだけを送ってくる
+    let msg: Message = serde_json::from_value(serde_json::json!({
+        "id": "1",
+        "channel_id": "1",
+        "guild_id": "1",
+        "webhook_id": "3",
+        "author": {"id": "3", "username": "GitHub", "discriminator": "0000", "avatar": null, "bot": true},
+        "attachments": [],
+        "content": "",
+        "edited_timestamp": null,
+        "embeds": [{"type": "rich", "title": "新しいリリース", "description": "v1.2.0", "fields": []}],
+        "mention_everyone": false,
+        "mention_roles": [],
+        "mentions": [],
+        "pinned": false,
+        "timestamp": "2022-01-01T00:00:00+00:00",
+        "tts": false,
+        "type": 0,
+    }))
+    .unwrap();
+    let nick = author_nick(&msg);
+    assert_eq!(nick, None);
+    assert_eq!(
+        NameReading::Nickname.author_name(None, nick, &msg.author.name),
+        Some("GitHub".to_string())
+    );
+    assert_eq!(embed_text(&msg.embeds, true, true), "新しいリリース v1.2.0");
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use tracing::info;
+
+use super::db::{SpeakerDB, UserConfig, VoiceRemapDB, VoiceType};
+use crate::handler::Generators;
+
+// 使えなくなった声の代わり。voice, default は (generator_type, voice_type)
+// 声が残っていれば None。エンジンが応答せず話者がひとつもないときも、戻ってくるかもしれないので None
+// default が使えればそれ、なければ最初の声にする
+pub fn remap_voice(
+    speakers: &[VoiceType],
+    voice: (i64, i64),
+    default: (i64, i64),
+) -> Option<(i64, i64)> {
+    let generator_name = |generator_type: i64| -> Option<&'static str> {
+        Generators::try_from(generator_type as u8)
+            .ok()
+            .map(|generator| generator.into())
+    };
+    let exists = |(generator_type, voice_type): (i64, i64)| {
+        generator_name(generator_type).is_some_and(|name| {
+            speakers
+                .iter()
+                .any(|speaker| speaker.generator_type == name && speaker.style_id == voice_type)
+        })
+    };
+    let responded = generator_name(voice.0).is_none_or(|name| {
+        speakers
+            .iter()
+            .any(|speaker| speaker.generator_type == name)
+    });
+    if exists(voice) || !responded {
+        return None;
+    }
+    if exists(default) {
+        return Some(default);
+    }
+    let speaker = speakers.first()?;
+    let generator = Generators::try_from(speaker.generator_type.as_str()).ok()?;
+    Some((generator as i64, speaker.style_id))
+}
+
+// エンジンの更新でなくなった声を使っている人を、デフォルトの声に戻す。戻した人は次のコマンドで知らせる
+// 戻した人数を返す
+pub async fn reconcile_voices(database: &sqlx::SqlitePool) -> Result<usize> {
+    let speakers = database.get_all_speakers().await?;
+    let default = UserConfig::from_user_id(0);
+    let default = (default.generator_type, default.voice_type);
+    let mut count = 0;
+    for (user_id, generator_type, voice_type) in database.get_user_voices().await? {
+        if let Some((generator_type, voice_type)) =
+            remap_voice(&speakers, (generator_type, voice_type), default)
+        {
+            database
+                .remap_user_voice(user_id, generator_type, voice_type)
+                .await?;
+            count += 1;
+        }
+    }
+    if count > 0 {
+        info!("remapped {} users whose voice no longer exists", count);
+    }
+    Ok(count)
+}
+
+#[test]
+fn remap_voice_test() {
+    let voice = |style_id, generator_type: &str| VoiceType {
+        id: style_id,
+        name: "name".to_string(),
+        style_id,
+        style_name: "style".to_string(),
+        generator_type: generator_type.to_string(),
+    };
+    let speakers = vec![voice(3, "VOICEVOX"), voice(1, "COEIROINK")];
+    assert_eq!(remap_voice(&speakers, (1, 3), (0, 1)), None);
+    assert_eq!(remap_voice(&speakers, (1, 8), (0, 1)), Some((0, 1)));
+    // デフォルトの声もなければ最初の声
+    assert_eq!(remap_voice(&speakers, (1, 8), (0, 5)), Some((1, 3)));
+    // 応答しなかったエンジンの声はそのまま
+    assert_eq!(remap_voice(&speakers, (2, 8), (0, 1)), None);
+    assert_eq!(remap_voice(&[], (1, 8), (0, 1)), None);
+    // ない種類のエンジンの声は戻す
+    assert_eq!(remap_voice(&speakers, (9, 1), (0, 1)), Some((0, 1)));
+}
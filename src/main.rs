@@ -1,57 +1,66 @@
+#![allow(special_module_name)]
 mod commands;
 mod handler;
 mod lib;
-use dotenv::dotenv;
+use serenity::client::bridge::gateway::ShardManager;
 use serenity::client::ClientBuilder;
+use serenity::framework::StandardFramework;
 use serenity::http::Http;
-use serenity::{async_trait, framework::StandardFramework};
-use songbird::{Event, EventContext, SerenityInit};
+use serenity::prelude::TypeMapKey;
+use songbird::{driver::DecodeMode, serenity::SongbirdKey, SerenityInit};
 
-use std::path::Path;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 
 use crate::handler::Handler;
 use crate::lib::db::SpeakerDB;
+use crate::lib::legacy::{legacy_dict_exists, legacy_dict_path};
+use crate::lib::{
+    activity::{run_idle_leave, ActivityTracker},
+    app_config::{config, set_config, Config},
+    health::{run_health_check, EngineHealth},
+    hosting::{run_quota_reset, HostingLimits},
+    metrics::run_metrics_log,
+    music::Music,
+    playback::Playback,
+    read_channel::ReadChannels,
+    retention::run_pruner,
+    settings::SettingsCache,
+    shutdown::{wait_for_signal, Shutdown},
+    speak::{run_speak, SpeakArgs},
+    startup::{connect_database, wait_for_engines},
+    status_server::{run_status_server, StatusServer},
+    supervisor::Supervisor,
+    temp::{clear_temp_dir, prepare_temp_dir, run_temp_janitor, temp_dir},
+    voice_remap::reconcile_voices,
+};
+
+pub struct ShardManagerContainer;
+
+impl TypeMapKey for ShardManagerContainer {
+    type Value = Arc<Mutex<ShardManager>>;
+}
 
 #[derive(Debug)]
 pub struct Dict {
     word: String,
     read_word: String,
-}
-
-struct TrackEndNotifier;
-
-#[async_trait]
-impl songbird::EventHandler for TrackEndNotifier {
-    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        if let EventContext::Track(track_list) = ctx {
-            for (_, handle) in track_list.iter() {
-                let path = handle.metadata().source_url.as_ref().unwrap();
-                tracing::info!("played file path: {:?}", path);
-                if !path.ends_with(".wav") {
-                    std::fs::remove_file(Path::new(handle.metadata().source_url.as_ref().unwrap()))
-                        .unwrap();
-                }
-            }
-        }
-        None
-    }
+    priority: i64,
 }
 
 #[tokio::main]
 async fn main() {
+    match Config::load() {
+        Ok(config) => set_config(config),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
     tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
+        .with_max_level(config().log_level.parse().unwrap_or(tracing::Level::INFO))
         .init();
-    dotenv().ok();
-    let database = sqlx::sqlite::SqlitePoolOptions::new()
-        .max_connections(10)
-        .connect_with(
-            sqlx::sqlite::SqliteConnectOptions::new()
-                .filename("database.sqlite")
-                .create_if_missing(true),
-        )
+    let database = connect_database()
         .await
         .expect("Couldn't connect to database");
 
@@ -59,30 +68,163 @@ async fn main() {
         .run(&database)
         .await
         .expect("Couldn't run database migrations");
+    // --speak のときは discord につながずに WAV を書き出して終わる
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match SpeakArgs::parse(&args) {
+        Ok(Some(speak)) => {
+            if let Err(e) = run_speak(&database, &speak).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+    // エンジンより先に起動しても話者を取れるよう、応答するまで待つ
+    wait_for_engines().await;
     let _ = database.insert_speaker_data().await;
-    let application_id = std::env::var("APP_ID").unwrap().parse().unwrap();
-    let token = std::env::var("DISCORD_TOKEN").expect("environment variable not found");
+    if let Err(e) = reconcile_voices(&database).await {
+        tracing::warn!("failed to remap voices: {}", e);
+    }
+    if legacy_dict_exists() {
+        tracing::warn!(
+            "{} was found. Run /migrate_legacy_dict <guild> to import it into that guild's dictionary.",
+            legacy_dict_path()
+        );
+    }
+    if let Err(e) = config().require_discord() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    let application_id = config().app_id;
+    let token = &config().discord_token;
     let framework = StandardFramework::new();
+    let read_channels = ReadChannels::default();
+    let engine_health = EngineHealth::default();
+    let supervisor = Supervisor::default();
+    let activity = ActivityTracker::default();
+    let music = Music::default();
+    let playback = Playback::new(music.clone());
+    let settings = SettingsCache::default();
+    let hosting = HostingLimits::from_config(config());
+    // 文字起こしするときだけ、受け取った声を PCM にする
+    let songbird_config = match config().stt_url {
+        Some(_) => songbird::Config::default().decode_mode(DecodeMode::Decode),
+        None => songbird::Config::default(),
+    };
     let mut client =
-        ClientBuilder::new_with_http(Http::new_with_token_application_id(&token, application_id))
+        ClientBuilder::new_with_http(Http::new_with_token_application_id(token, application_id))
             .event_handler(Handler {
-                database,
-                read_channel_id: Arc::new(Mutex::new(None)),
+                database: database.clone(),
+                read_channels: read_channels.clone(),
+                engine_health: engine_health.clone(),
+                reaction_counter: Default::default(),
+                join_queue: Default::default(),
+                supervisor: supervisor.clone(),
+                activity: activity.clone(),
+                playback: playback.clone(),
+                music: music.clone(),
+                greetings: Default::default(),
+                moves: Default::default(),
+                rotation: Default::default(),
+                threads: Default::default(),
+                recent: Default::default(),
+                batches: Default::default(),
+                hosting: hosting.clone(),
+                long_read: Default::default(),
+                dict_adds: Default::default(),
+                opus_cache: Default::default(),
+                read_delay: Default::default(),
+                settings: settings.clone(),
             })
             .framework(framework)
-            .register_songbird()
+            .register_songbird_from_config(songbird_config)
             .await
             .expect("Err creating client");
-    std::fs::create_dir("temp").ok();
+    client
+        .data
+        .write()
+        .await
+        .insert::<ShardManagerContainer>(client.shard_manager.clone());
+    if let Err(e) = prepare_temp_dir() {
+        eprintln!("{}: {}", temp_dir().display(), e);
+        std::process::exit(2);
+    }
+    supervisor.spawn("temp_janitor", run_temp_janitor);
+    let http = client.cache_and_http.http.clone();
+    {
+        let (http, read_channels) = (http.clone(), read_channels.clone());
+        let engine_health = engine_health.clone();
+        supervisor.spawn("health_check", move || {
+            run_health_check(engine_health.clone(), http.clone(), read_channels.clone())
+        });
+    }
+    let manager = client
+        .data
+        .read()
+        .await
+        .get::<SongbirdKey>()
+        .cloned()
+        .expect("Songbird Voice client placed in at initialisation.");
+    // metrics_log_interval 秒ごとに数えた値をログに出す
+    if let Some(secs) = config().metrics_log_interval.filter(|secs| *secs > 0) {
+        supervisor.spawn("metrics_log", move || {
+            run_metrics_log(Duration::from_secs(secs))
+        });
+    }
+    // 監視用の HTTP サーバーは status_addr を設定したときだけ立てる
+    if let Some(addr) = config().status_addr {
+        let server = StatusServer {
+            cache: client.cache_and_http.cache.clone(),
+            manager: manager.clone(),
+            playback,
+            music,
+            engine_health,
+            settings,
+            supervisor: supervisor.clone(),
+        };
+        supervisor.spawn("status_server", move || {
+            run_status_server(addr, server.clone())
+        });
+    }
+    {
+        let database = database.clone();
+        supervisor.spawn("pruner", move || run_pruner(database.clone()));
+    }
+    if config().daily_char_quota.is_some() {
+        let database = database.clone();
+        supervisor.spawn("quota_reset", move || {
+            run_quota_reset(hosting.clone(), database.clone())
+        });
+    }
+    let shutdown = Shutdown {
+        cache: client.cache_and_http.cache.clone(),
+        manager: manager.clone(),
+        shard_manager: client.shard_manager.clone(),
+        database: database.clone(),
+    };
+    supervisor.spawn("idle_leave", move || {
+        run_idle_leave(
+            database.clone(),
+            activity.clone(),
+            manager.clone(),
+            http.clone(),
+            read_channels.clone(),
+        )
+    });
 
     tokio::spawn(async move {
+        // サーバー数に応じて discord が勧める数のシャードで動かす
         let _ = client
-            .start()
+            .start_autosharded()
             .await
             .map_err(|why| tracing::info!("Client ended: {:?}", why));
     });
-    tokio::signal::ctrl_c().await.unwrap();
-    std::fs::remove_dir_all("temp").unwrap();
-    std::fs::create_dir("temp").unwrap();
-    tracing::info!("Ctrl-C received, shutting down...");
+    wait_for_signal().await;
+    shutdown.run().await;
+    clear_temp_dir();
 }